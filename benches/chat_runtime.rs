@@ -0,0 +1,81 @@
+//! Room message throughput of the local chat runtime with many rooms and connections.
+//!
+//! `cargo bench --bench chat_runtime` runs the same load against one shard, which behaves like
+//! the former single runtime lock, and against sharded runtimes. Shards only pay off with
+//! several worker threads, so run it on a multi-core machine.
+
+use std::{sync::Arc, time::Instant};
+
+use axes::{
+    config::ChatConfig,
+    handlers::chat::{ChatCommand, ChatState},
+};
+
+const ROOMS: usize = 1_000;
+const CONNECTIONS: usize = 5_000;
+const MESSAGES_PER_CONNECTION: usize = 40;
+const SHARD_COUNTS: [usize; 4] = [1, 4, 16, 64];
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    println!(
+        "{ROOMS} rooms, {CONNECTIONS} connections, {} room messages per run",
+        CONNECTIONS * MESSAGES_PER_CONNECTION
+    );
+    // Warms the allocator and the scheduler so the first measured run is not penalised.
+    run(1).await;
+    for runtime_shards in SHARD_COUNTS {
+        let messages_per_sec = run(runtime_shards).await;
+        println!("{runtime_shards:>3} shards: {messages_per_sec:>10.0} messages/s");
+    }
+}
+
+async fn run(runtime_shards: usize) -> f64 {
+    let chat_state = Arc::new(
+        ChatState::default().with_config(ChatConfig { runtime_shards, ..ChatConfig::default() }),
+    );
+    let mut users = Vec::with_capacity(CONNECTIONS);
+    for connection in 0..CONNECTIONS {
+        let user_id = format!("user-{connection}");
+        let (session_user, outbound) = chat_state.register_connection(&user_id, &user_id).await;
+        let room_id = format!("room-{}", connection % ROOMS);
+        chat_state
+            .process_message(
+                &session_user,
+                ChatCommand::JoinRoom { room_id: room_id.clone(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+        users.push((session_user, outbound, room_id));
+    }
+
+    let started = Instant::now();
+    let tasks = users
+        .into_iter()
+        .map(|(session_user, outbound, room_id)| {
+            let chat_state = chat_state.clone();
+            tokio::spawn(async move {
+                for message in 0..MESSAGES_PER_CONNECTION {
+                    chat_state
+                        .process_message(
+                            &session_user,
+                            ChatCommand::SendRoomMessage {
+                                room_id: room_id.clone(),
+                                content: format!("message {message}"),
+                                reply_to: None,
+                            },
+                        )
+                        .await
+                        .expect("send should succeed");
+                    // Keep queues short, as the socket writer would.
+                    while outbound.try_pop().is_some() {}
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.expect("sender task should finish");
+    }
+
+    (CONNECTIONS * MESSAGES_PER_CONNECTION) as f64 / started.elapsed().as_secs_f64()
+}
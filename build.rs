@@ -11,6 +11,11 @@ fn build_grpc_protos() -> Result<(), Box<dyn std::error::Error>> {
     unsafe { std::env::set_var("PROTOC", protoc_path) };
 
     tonic_prost_build::compile_protos("./protos/greeter.proto")?;
+    // The generated client's `connect` constructor would clash with the `Connect` rpc, so chat
+    // clients build their channel themselves and use `ChatClient::new`.
+    tonic_prost_build::configure()
+        .build_transport(false)
+        .compile_protos(&["./protos/chat.proto"], &["./protos"])?;
 
     Ok(())
 }
@@ -0,0 +1,14 @@
+-- Chat room messages persisted by the API's background history writer.
+-- Versions continue across room restarts, so (RoomId, Version) identifies a message.
+
+CREATE TABLE IF NOT EXISTS "chat_room_messages" (
+    "RoomId" varchar(64) NOT NULL,
+    "Version" bigint NOT NULL,
+    "MessageId" varchar(64) NOT NULL,
+    "SenderId" varchar(128) NOT NULL,
+    "SenderName" varchar(128) NOT NULL,
+    "Content" text NOT NULL,
+    "SentAt" bigint NOT NULL,
+    "CreatedAtUtc" timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT "PK_chat_room_messages" PRIMARY KEY ("RoomId", "Version")
+);
@@ -0,0 +1,10 @@
+-- Edited and deleted chat messages keep their room version; deletes leave a tombstone row.
+
+ALTER TABLE "chat_room_messages"
+ADD COLUMN IF NOT EXISTS "EditedAt" bigint NULL;
+
+ALTER TABLE "chat_room_messages"
+ADD COLUMN IF NOT EXISTS "DeletedAt" bigint NULL;
+
+CREATE INDEX IF NOT EXISTS "IX_chat_room_messages_message_id"
+ON "chat_room_messages" ("RoomId", "MessageId");
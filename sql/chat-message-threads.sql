@@ -0,0 +1,8 @@
+-- Thread replies point at the root message of their thread by message id.
+
+ALTER TABLE "chat_room_messages"
+ADD COLUMN IF NOT EXISTS "ReplyTo" varchar(64) NULL;
+
+CREATE INDEX IF NOT EXISTS "IX_chat_room_messages_reply_to"
+ON "chat_room_messages" ("RoomId", "ReplyTo", "Version")
+WHERE "ReplyTo" IS NOT NULL;
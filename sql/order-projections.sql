@@ -0,0 +1,29 @@
+-- CQRS read models rebuilt from the retained outbox history, see `projections-worker rebuild`.
+-- Projections are written on the primary and replicated, handlers read them from the replica.
+
+CREATE TABLE IF NOT EXISTS "projection_inbox_messages" (
+    "MessageId" uuid NOT NULL,
+    "Consumer" varchar(128) NOT NULL,
+    "ProcessedAtUtc" timestamptz NOT NULL,
+    CONSTRAINT "PK_projection_inbox_messages" PRIMARY KEY ("MessageId", "Consumer")
+);
+
+CREATE TABLE IF NOT EXISTS "order_sku_daily_stats" (
+    "Sku" varchar(64) NOT NULL,
+    "Day" date NOT NULL,
+    "OrdersCreated" bigint NOT NULL DEFAULT 0,
+    "QuantityOrdered" bigint NOT NULL DEFAULT 0,
+    "OrdersConfirmed" bigint NOT NULL DEFAULT 0,
+    "OrdersRejected" bigint NOT NULL DEFAULT 0,
+    "UpdatedAtUtc" timestamptz NOT NULL,
+    CONSTRAINT "PK_order_sku_daily_stats" PRIMARY KEY ("Sku", "Day")
+);
+
+CREATE INDEX IF NOT EXISTS "IX_order_sku_daily_stats_day"
+ON "order_sku_daily_stats" ("Day", "Sku");
+
+CREATE INDEX IF NOT EXISTS "IX_order_outbox_messages_replay"
+ON "order_outbox_messages" ("EventType", "Id");
+
+CREATE INDEX IF NOT EXISTS "IX_inventory_outbox_messages_replay"
+ON "inventory_outbox_messages" ("EventType", "Id");
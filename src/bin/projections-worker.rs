@@ -0,0 +1,68 @@
+use anyhow::Context;
+use axes::orders::{
+    INVENTORY_RESULT_EVENT_TYPE, InventoryResultEvent, KafkaTopic, ORDER_CREATED_EVENT_TYPE,
+    OrderCreatedEvent, PROJECTIONS_WORKER_CONSUMER,
+    projections::{ProjectedEvent, Projection, project_event, rebuild_projection},
+    runtime::{WorkerContext, WorkerRuntime},
+};
+use tracing::info;
+
+const USAGE: &str = "usage: projections-worker [run | rebuild <projection>]";
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let runtime = WorkerRuntime::new("projections-worker", PROJECTIONS_WORKER_CONSUMER);
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] | ["run"] => {
+            runtime
+                .handle(
+                    KafkaTopic::OrderCreated,
+                    ORDER_CREATED_EVENT_TYPE,
+                    |ctx, event: OrderCreatedEvent| async move {
+                        project_all(&ctx, &ProjectedEvent::OrderCreated(event)).await
+                    },
+                )
+                .handle(
+                    KafkaTopic::InventoryResult,
+                    INVENTORY_RESULT_EVENT_TYPE,
+                    |ctx, event: InventoryResultEvent| async move {
+                        project_all(&ctx, &ProjectedEvent::InventoryResult(event)).await
+                    },
+                )
+                .run()
+                .await
+        }
+        ["rebuild", name] => {
+            let projection = Projection::parse(name).with_context(|| {
+                let known = Projection::ALL.map(Projection::name).join(", ");
+                format!("unknown projection {name}, expected one of: {known}")
+            })?;
+            runtime
+                .run_task(|ctx| async move {
+                    let summary = rebuild_projection(&ctx.pool, projection).await?;
+                    info!(
+                        projection = projection.name(),
+                        events_replayed = summary.events_replayed,
+                        "projection rebuilt"
+                    );
+                    Ok(())
+                })
+                .await
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+}
+
+async fn project_all(ctx: &WorkerContext, event: &ProjectedEvent) -> anyhow::Result<()> {
+    for projection in Projection::ALL {
+        project_event(&ctx.pool, projection, event).await?;
+    }
+    Ok(())
+}
@@ -1,6 +1,10 @@
-use config::File;
+use std::{collections::BTreeMap, fmt};
+
+use config::{Environment, File};
 use serde::Deserialize;
 
+const REDACTED: &str = "<redacted>";
+
 /// Postgre Config
 #[derive(Deserialize, Clone, Debug, Default)]
 pub struct PostgreConfig {
@@ -14,23 +18,418 @@ pub struct RedisConfig {
     pub url: Option<String>,
 }
 
+/// Worker runtime Config
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WorkerConfig {
+    pub max_connections: u32,
+    pub acquire_timeout_secs: u64,
+    pub outbox_batch_size: i64,
+    pub outbox_poll_interval_ms: u64,
+    pub admin_addr: String,
+    pub retry: RetryConfig,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 5,
+            acquire_timeout_secs: 3,
+            outbox_batch_size: 50,
+            outbox_poll_interval_ms: 500,
+            admin_addr: "0.0.0.0:9090".to_string(),
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+/// Consumer retry Config for transient handler failures
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Total time one message may spend retrying, kept well below the consumer's
+    /// `max.poll.interval.ms` since the consume loop does not poll while retrying.
+    pub max_retry_ms: u64,
+    /// Times a message that ran out of retries is handled again, with its partition paused in
+    /// between, before it is parked.
+    pub max_redeliveries: u32,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff_ms: 200,
+            max_backoff_ms: 10_000,
+            max_retry_ms: 60_000,
+            max_redeliveries: 3,
+        }
+    }
+}
+
+/// Chat websocket Config
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ChatConfig {
+    /// Events buffered per connection before the slow consumer policy kicks in.
+    pub outbound_queue_capacity: usize,
+    pub slow_consumer_policy: SlowConsumerPolicy,
+    /// How often the server pings each socket.
+    pub heartbeat_interval_secs: u64,
+    /// How long a ping may go unanswered before the connection is considered dead.
+    pub pong_timeout_secs: u64,
+    /// How long a client may stay silent, pongs aside, before it is disconnected.
+    pub idle_timeout_secs: u64,
+    /// Independently locked partitions rooms are spread over on each instance.
+    pub runtime_shards: usize,
+    /// Users allowed to inspect live connections and close connections or rooms.
+    pub admin_user_ids: Vec<String>,
+    /// How long a shutdown waits for connections to flush their events and close.
+    pub shutdown_drain_secs: u64,
+    /// Reconnect delay suggested to clients when the server shuts down.
+    pub shutdown_reconnect_after_ms: u64,
+    /// Message writes waiting for Postgres before new ones are shed.
+    pub history_queue_capacity: usize,
+    pub rate_limit: ChatRateLimitConfig,
+    pub filters: ChatFilterConfig,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            outbound_queue_capacity: 256,
+            slow_consumer_policy: SlowConsumerPolicy::DropOldest,
+            heartbeat_interval_secs: 30,
+            pong_timeout_secs: 10,
+            idle_timeout_secs: 600,
+            runtime_shards: 16,
+            admin_user_ids: Vec::new(),
+            shutdown_drain_secs: 10,
+            shutdown_reconnect_after_ms: 1000,
+            history_queue_capacity: 10_000,
+            rate_limit: ChatRateLimitConfig::default(),
+            filters: ChatFilterConfig::default(),
+        }
+    }
+}
+
+/// Chat command rate limits, token buckets per connection and per user on each instance
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ChatRateLimitConfig {
+    /// Room and direct messages, including edits and deletes.
+    pub messages: ChatRateLimit,
+    /// Room joins and creations.
+    pub joins: ChatRateLimit,
+    /// Room syncs and history pages.
+    pub syncs: ChatRateLimit,
+    /// Rate limited commands in a row before the connection is closed.
+    pub max_violations: u32,
+}
+
+impl Default for ChatRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            messages: ChatRateLimit {
+                connection_burst: 10,
+                connection_per_sec: 5.0,
+                user_burst: 20,
+                user_per_sec: 10.0,
+            },
+            joins: ChatRateLimit {
+                connection_burst: 10,
+                connection_per_sec: 1.0,
+                user_burst: 20,
+                user_per_sec: 2.0,
+            },
+            syncs: ChatRateLimit {
+                connection_burst: 10,
+                connection_per_sec: 2.0,
+                user_burst: 20,
+                user_per_sec: 4.0,
+            },
+            max_violations: 20,
+        }
+    }
+}
+
+/// Content filters run on room messages and edits before they are committed
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ChatFilterConfig {
+    /// Matched case-insensitively against whole words.
+    pub banned_words: Vec<String>,
+    pub banned_word_action: ChatBannedWordAction,
+    pub link_policy: ChatLinkPolicy,
+    /// Domains, and their subdomains, links may always point to.
+    pub allowed_link_domains: Vec<String>,
+    /// Distinct `@user` mentions allowed in one message; zero allows any number.
+    pub max_mentions: usize,
+}
+
+impl Default for ChatFilterConfig {
+    fn default() -> Self {
+        Self {
+            banned_words: Vec::new(),
+            banned_word_action: ChatBannedWordAction::Mask,
+            link_policy: ChatLinkPolicy::Allow,
+            allowed_link_domains: Vec::new(),
+            max_mentions: 20,
+        }
+    }
+}
+
+/// What to do with a message containing a banned word.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatBannedWordAction {
+    /// Replace the word's letters with `*`.
+    Mask,
+    /// Send it, but flag it for moderator review.
+    Flag,
+    Reject,
+}
+
+/// What to do with a message linking outside the allowed domains.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatLinkPolicy {
+    Allow,
+    Flag,
+    Reject,
+}
+
+/// A burst of zero disables that bucket.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ChatRateLimit {
+    pub connection_burst: u32,
+    pub connection_per_sec: f64,
+    /// Shared by all of a user's connections.
+    pub user_burst: u32,
+    pub user_per_sec: f64,
+}
+
+/// What to do when a connection's outbound queue is full.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowConsumerPolicy {
+    /// Drop the oldest queued event to make room.
+    DropOldest,
+    /// Merge presence updates per room, dropping the oldest presence or event if still full.
+    CoalescePresence,
+    /// Close the connection with a slow consumer close code.
+    Disconnect,
+}
+
+impl SlowConsumerPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::DropOldest => "drop_oldest",
+            Self::CoalescePresence => "coalesce_presence",
+            Self::Disconnect => "disconnect",
+        }
+    }
+}
+
+/// Kafka Config
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct KafkaConfig {
+    pub brokers: String,
+    pub client_id: Option<String>,
+    pub order_created_topic: String,
+    pub inventory_result_topic: String,
+    pub security: KafkaSecurityConfig,
+    pub producer: KafkaProducerConfig,
+    pub consumer: KafkaConsumerConfig,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        Self {
+            brokers: "localhost:9092".to_string(),
+            client_id: None,
+            order_created_topic: "orders.created.v1".to_string(),
+            inventory_result_topic: "inventory.result.v1".to_string(),
+            security: KafkaSecurityConfig::default(),
+            producer: KafkaProducerConfig::default(),
+            consumer: KafkaConsumerConfig::default(),
+        }
+    }
+}
+
+/// Kafka SASL/SSL Config, mapped onto the librdkafka `security.*`, `sasl.*` and `ssl.*` keys
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct KafkaSecurityConfig {
+    pub protocol: Option<String>,
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    pub ssl_ca_location: Option<String>,
+    pub ssl_certificate_location: Option<String>,
+    pub ssl_key_location: Option<String>,
+    pub ssl_key_password: Option<String>,
+}
+
+impl fmt::Debug for KafkaSecurityConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaSecurityConfig")
+            .field("protocol", &self.protocol)
+            .field("sasl_mechanism", &self.sasl_mechanism)
+            .field("sasl_username", &self.sasl_username)
+            .field("sasl_password", &self.sasl_password.as_ref().map(|_| REDACTED))
+            .field("ssl_ca_location", &self.ssl_ca_location)
+            .field("ssl_certificate_location", &self.ssl_certificate_location)
+            .field("ssl_key_location", &self.ssl_key_location)
+            .field("ssl_key_password", &self.ssl_key_password.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+/// Kafka producer Config, `properties` are passed to librdkafka as-is and win over typed fields
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct KafkaProducerConfig {
+    pub acks: String,
+    pub enable_idempotence: bool,
+    pub compression_type: Option<String>,
+    pub linger_ms: Option<u64>,
+    pub message_timeout_ms: u64,
+    pub properties: BTreeMap<String, String>,
+}
+
+impl Default for KafkaProducerConfig {
+    fn default() -> Self {
+        Self {
+            acks: "all".to_string(),
+            enable_idempotence: true,
+            compression_type: None,
+            linger_ms: None,
+            message_timeout_ms: 5000,
+            properties: BTreeMap::new(),
+        }
+    }
+}
+
+impl fmt::Debug for KafkaProducerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaProducerConfig")
+            .field("acks", &self.acks)
+            .field("enable_idempotence", &self.enable_idempotence)
+            .field("compression_type", &self.compression_type)
+            .field("linger_ms", &self.linger_ms)
+            .field("message_timeout_ms", &self.message_timeout_ms)
+            .field("properties", &redacted_properties(&self.properties))
+            .finish()
+    }
+}
+
+/// Kafka consumer Config, `properties` are passed to librdkafka as-is and win over typed fields
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct KafkaConsumerConfig {
+    pub auto_offset_reset: String,
+    pub properties: BTreeMap<String, String>,
+}
+
+impl Default for KafkaConsumerConfig {
+    fn default() -> Self {
+        Self { auto_offset_reset: "earliest".to_string(), properties: BTreeMap::new() }
+    }
+}
+
+impl fmt::Debug for KafkaConsumerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaConsumerConfig")
+            .field("auto_offset_reset", &self.auto_offset_reset)
+            .field("properties", &redacted_properties(&self.properties))
+            .finish()
+    }
+}
+
+/// Whether a librdkafka property carries a credential and must not be logged.
+pub fn is_secret_kafka_property(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    ["password", "secret", "jaas", "token", "key.pem"]
+        .iter()
+        .any(|marker| key.contains(marker))
+}
+
+fn redacted_properties(properties: &BTreeMap<String, String>) -> BTreeMap<&str, &str> {
+    properties
+        .iter()
+        .map(|(key, value)| {
+            let value = if is_secret_kafka_property(key) { REDACTED } else { value.as_str() };
+            (key.as_str(), value)
+        })
+        .collect()
+}
+
 /// App Config
 #[derive(Deserialize, Clone)]
 pub struct AppConfig {
     pub pg: PostgreConfig,
     pub redis: RedisConfig,
+    #[serde(default)]
+    pub worker: WorkerConfig,
+    #[serde(default)]
+    pub kafka: KafkaConfig,
+    #[serde(default)]
+    pub chat: ChatConfig,
 }
 
 impl AppConfig {
+    /// Loads `./settings/{ENVIRONMENT}.toml`, then applies the legacy `AXES_KAFKA_*` variables and
+    /// `AXES__SECTION__KEY` env overrides (e.g. `AXES__KAFKA__SECURITY__SASL_PASSWORD`), the
+    /// latter winning when both set a key.
     pub fn new() -> Result<Self, config::ConfigError> {
         let environment = std::env::var("ENVIRONMENT").unwrap_or_else(|_| "development".into());
 
-        let cfg = config::Config::builder()
+        config::Config::builder()
             .add_source(File::with_name(&format!("./settings/{}", environment)))
-            .build()?;
+            .add_source(legacy_kafka_env_source(|key| std::env::var(key).ok())?)
+            .add_source(env_overrides())
+            .build()?
+            .try_deserialize()
+    }
+}
 
-        cfg.try_deserialize()
+pub fn env_overrides() -> Environment {
+    Environment::with_prefix("AXES")
+        .separator("__")
+        .try_parsing(true)
+}
+
+/// The legacy Kafka variables as a config source, layered below `env_overrides()`.
+pub fn legacy_kafka_env_source<F>(lookup: F) -> Result<config::Config, config::ConfigError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut builder = config::Config::builder();
+    for (key, value) in legacy_kafka_env_overrides(lookup) {
+        builder = builder.set_override(key, value)?;
     }
+    builder.build()
+}
+
+/// Maps the pre-`[kafka]` env variables onto their config keys so existing deployments keep
+/// working.
+pub fn legacy_kafka_env_overrides<F>(lookup: F) -> Vec<(&'static str, String)>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    [
+        ("AXES_KAFKA_BROKERS", "kafka.brokers"),
+        ("AXES_KAFKA_ORDER_CREATED_TOPIC", "kafka.order_created_topic"),
+        ("AXES_KAFKA_INVENTORY_RESULT_TOPIC", "kafka.inventory_result_topic"),
+    ]
+    .into_iter()
+    .filter_map(|(env_key, config_key)| lookup(env_key).map(|value| (config_key, value)))
+    .collect()
 }
 
 impl PostgreConfig {
@@ -56,7 +455,22 @@ impl PostgreConfig {
 
 #[cfg(test)]
 mod tests {
-    use super::{AppConfig, PostgreConfig};
+    use std::collections::HashMap;
+
+    use super::{
+        AppConfig, ChatBannedWordAction, ChatLinkPolicy, PostgreConfig, SlowConsumerPolicy,
+        env_overrides, legacy_kafka_env_overrides,
+    };
+
+    fn base_config() -> config::ConfigBuilder<config::builder::DefaultState> {
+        config::Config::builder()
+            .set_override("pg.write_url", "postgresql://writer:5432/axes")
+            .expect("write url should be set")
+            .set_override("pg.read_url", "postgresql://reader:5433/axes")
+            .expect("read url should be set")
+            .set_override("redis.url", "redis://127.0.0.1:6379")
+            .expect("redis url should be set")
+    }
 
     #[test]
     fn app_config_deserializes_read_write_postgres_urls() {
@@ -76,6 +490,174 @@ mod tests {
         assert_eq!(cfg.pg.read_url.as_deref(), Some("postgresql://reader:5433/axes"));
     }
 
+    #[test]
+    fn worker_config_defaults_when_section_is_missing() {
+        let cfg = base_config()
+            .build()
+            .expect("config should build")
+            .try_deserialize::<AppConfig>()
+            .expect("config should deserialize");
+
+        assert_eq!(cfg.worker.max_connections, 5);
+        assert_eq!(cfg.worker.outbox_batch_size, 50);
+        assert_eq!(cfg.worker.outbox_poll_interval_ms, 500);
+        assert_eq!(cfg.worker.admin_addr, "0.0.0.0:9090");
+    }
+
+    #[test]
+    fn chat_config_defaults_and_parses_slow_consumer_policy() {
+        let cfg = base_config()
+            .build()
+            .expect("config should build")
+            .try_deserialize::<AppConfig>()
+            .expect("config should deserialize");
+
+        assert_eq!(cfg.chat.outbound_queue_capacity, 256);
+        assert_eq!(cfg.chat.slow_consumer_policy, SlowConsumerPolicy::DropOldest);
+        assert_eq!(cfg.chat.heartbeat_interval_secs, 30);
+        assert_eq!(cfg.chat.pong_timeout_secs, 10);
+        assert_eq!(cfg.chat.idle_timeout_secs, 600);
+        assert_eq!(cfg.chat.runtime_shards, 16);
+        assert!(cfg.chat.admin_user_ids.is_empty());
+        assert_eq!(cfg.chat.shutdown_drain_secs, 10);
+        assert_eq!(cfg.chat.shutdown_reconnect_after_ms, 1000);
+        assert_eq!(cfg.chat.history_queue_capacity, 10_000);
+        assert!(cfg.chat.filters.banned_words.is_empty());
+        assert_eq!(cfg.chat.filters.banned_word_action, ChatBannedWordAction::Mask);
+        assert_eq!(cfg.chat.filters.link_policy, ChatLinkPolicy::Allow);
+        assert_eq!(cfg.chat.filters.max_mentions, 20);
+        assert_eq!(cfg.chat.rate_limit.messages.connection_burst, 10);
+        assert_eq!(cfg.chat.rate_limit.max_violations, 20);
+
+        let cfg = base_config()
+            .set_override("chat.slow_consumer_policy", "coalesce_presence")
+            .expect("policy should be set")
+            .build()
+            .expect("config should build")
+            .try_deserialize::<AppConfig>()
+            .expect("config should deserialize");
+
+        assert_eq!(cfg.chat.slow_consumer_policy, SlowConsumerPolicy::CoalescePresence);
+
+        let cfg = base_config()
+            .set_override("chat.rate_limit.joins.user_per_sec", 0.5)
+            .expect("join rate should be set")
+            .set_override("chat.rate_limit.joins.user_burst", 3)
+            .expect("join burst should be set")
+            .set_override("chat.rate_limit.joins.connection_burst", 2)
+            .expect("join burst should be set")
+            .set_override("chat.rate_limit.joins.connection_per_sec", 0.25)
+            .expect("join rate should be set")
+            .build()
+            .expect("config should build")
+            .try_deserialize::<AppConfig>()
+            .expect("config should deserialize");
+
+        assert_eq!(cfg.chat.rate_limit.joins.user_per_sec, 0.5);
+        assert_eq!(cfg.chat.rate_limit.joins.connection_burst, 2);
+        assert_eq!(cfg.chat.rate_limit.syncs.connection_burst, 10);
+    }
+
+    #[test]
+    fn worker_config_allows_partial_overrides() {
+        let cfg = base_config()
+            .set_override("worker.max_connections", 20)
+            .expect("max connections should be set")
+            .set_override("worker.outbox_batch_size", 200)
+            .expect("batch size should be set")
+            .build()
+            .expect("config should build")
+            .try_deserialize::<AppConfig>()
+            .expect("config should deserialize");
+
+        assert_eq!(cfg.worker.max_connections, 20);
+        assert_eq!(cfg.worker.outbox_batch_size, 200);
+        assert_eq!(cfg.worker.acquire_timeout_secs, 3);
+    }
+
+    #[test]
+    fn kafka_config_uses_expected_defaults() {
+        let cfg = base_config()
+            .build()
+            .expect("config should build")
+            .try_deserialize::<AppConfig>()
+            .expect("config should deserialize");
+
+        assert_eq!(cfg.kafka.brokers, "localhost:9092");
+        assert_eq!(cfg.kafka.order_created_topic, "orders.created.v1");
+        assert_eq!(cfg.kafka.inventory_result_topic, "inventory.result.v1");
+        assert_eq!(cfg.kafka.producer.acks, "all");
+        assert!(cfg.kafka.producer.enable_idempotence);
+        assert_eq!(cfg.kafka.producer.message_timeout_ms, 5000);
+        assert_eq!(cfg.kafka.consumer.auto_offset_reset, "earliest");
+    }
+
+    #[test]
+    fn kafka_config_reads_env_overrides() {
+        let env = HashMap::from([
+            ("AXES__KAFKA__BROKERS".to_string(), "kafka-1:9092,kafka-2:9092".to_string()),
+            ("AXES__KAFKA__SECURITY__SASL_PASSWORD".to_string(), "s3cret".to_string()),
+            ("AXES__KAFKA__PRODUCER__LINGER_MS".to_string(), "20".to_string()),
+            ("AXES_HTTP_ADDR".to_string(), "0.0.0.0:7878".to_string()),
+        ]);
+        let cfg = base_config()
+            .add_source(env_overrides().source(Some(env)))
+            .build()
+            .expect("config should build")
+            .try_deserialize::<AppConfig>()
+            .expect("config should deserialize");
+
+        assert_eq!(cfg.kafka.brokers, "kafka-1:9092,kafka-2:9092");
+        assert_eq!(cfg.kafka.security.sasl_password.as_deref(), Some("s3cret"));
+        assert_eq!(cfg.kafka.producer.linger_ms, Some(20));
+    }
+
+    #[test]
+    fn kafka_config_maps_legacy_env_variables() {
+        let overrides = legacy_kafka_env_overrides(|key| {
+            (key == "AXES_KAFKA_BROKERS").then(|| "kafka-1:9092".to_string())
+        });
+
+        assert_eq!(overrides, vec![("kafka.brokers", "kafka-1:9092".to_string())]);
+    }
+
+    #[test]
+    fn kafka_config_redacts_secrets_in_debug_output() {
+        let cfg = base_config()
+            .add_source(config::File::from_str(
+                r#"
+                [kafka.security]
+                sasl_password = "s3cret"
+
+                [kafka.producer.properties]
+                "sasl.oauthbearer.token" = "t0ken"
+
+                [kafka.consumer.properties]
+                "fetch.min.bytes" = "1024"
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .expect("config should build")
+            .try_deserialize::<AppConfig>()
+            .expect("config should deserialize");
+
+        assert_eq!(
+            cfg.kafka
+                .consumer
+                .properties
+                .get("fetch.min.bytes")
+                .map(String::as_str),
+            Some("1024")
+        );
+
+        let rendered = format!("{:?}", cfg.kafka);
+        assert!(!rendered.contains("s3cret"));
+        assert!(!rendered.contains("t0ken"));
+        assert!(rendered.contains("<redacted>"));
+        assert!(rendered.contains("1024"));
+    }
+
     #[test]
     fn postgres_config_requires_both_read_and_write_urls() {
         let cfg = PostgreConfig {
@@ -45,9 +45,18 @@ where
 }
 
 pub async fn connect_pool(url: &str, role: &str) -> anyhow::Result<PgPool> {
+    connect_pool_with(url, role, 5, Duration::from_secs(3)).await
+}
+
+pub async fn connect_pool_with(
+    url: &str,
+    role: &str,
+    max_connections: u32,
+    acquire_timeout: Duration,
+) -> anyhow::Result<PgPool> {
     PgPoolOptions::new()
-        .max_connections(5)
-        .acquire_timeout(Duration::from_secs(3))
+        .max_connections(max_connections)
+        .acquire_timeout(acquire_timeout)
         .connect(url)
         .await
         .map_err(|error| anyhow::anyhow!("can't connect to {role} database: {error}"))
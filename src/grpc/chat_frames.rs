@@ -0,0 +1,441 @@
+//! Conversions between the typed `chat.v1` frames and the websocket chat's commands and events.
+
+use super::chat::{self, CommandFrame, EventFrame, command_frame::Command, event_frame::Event};
+use crate::handlers::chat::{
+    ChatCommand, ChatEmptyPayload, ChatErrorPayload, ChatEvent, ChatLeftRoomNotice,
+    ChatPresenceChange, ChatRoomHistory, ChatRoomMessage, ChatRoomSnapshot, ChatUserSummary,
+    direct::{ChatDirectConversation, ChatDirectMessage, ChatDirectMessageAck, ChatDirectUnread},
+    directory::{ChatConnectionClosed, ChatRoomClosed},
+    edits::{ChatMessageDeleted, ChatMessageEdited},
+    moderation::{ChatModerationAction, ChatModerationNotice},
+    presence::{ChatPresenceStatus, ChatStatusChange, ChatUserStatus},
+    reactions::{ChatMessageReactions, ChatReaction, ChatReactionChanged},
+    receipts::{ChatReadPosition, ChatReadReceipt, ChatTypingNotice},
+    shutdown::ChatServerShutdown,
+    sync::ChatRoomDelta,
+    threads::{ChatThread, ChatThreadSummary},
+};
+
+/// The command a frame carries, `None` when it carries none or an unspecified status.
+pub fn command_from_frame(frame: CommandFrame) -> Option<ChatCommand> {
+    let command = match frame.command? {
+        Command::JoinRoom(chat::JoinRoom { room_id, since_version }) => {
+            ChatCommand::JoinRoom { room_id, since_version }
+        }
+        Command::CreateRoom(chat::CreateRoom { room_id, private }) => {
+            ChatCommand::CreateRoom { room_id, private }
+        }
+        Command::LeaveRoom(chat::RoomRef { room_id }) => ChatCommand::LeaveRoom { room_id },
+        Command::SendRoomMessage(chat::SendRoomMessage { room_id, content, reply_to }) => {
+            ChatCommand::SendRoomMessage { room_id, content, reply_to }
+        }
+        Command::EditRoomMessage(chat::EditRoomMessage { room_id, message_id, content }) => {
+            ChatCommand::EditRoomMessage { room_id, message_id, content }
+        }
+        Command::DeleteRoomMessage(chat::MessageRef { room_id, message_id }) => {
+            ChatCommand::DeleteRoomMessage { room_id, message_id }
+        }
+        Command::LoadThread(chat::MessageRef { room_id, message_id }) => {
+            ChatCommand::LoadThread { room_id, message_id }
+        }
+        Command::React(chat::ReactionRef { room_id, message_id, emoji }) => {
+            ChatCommand::React { room_id, message_id, emoji }
+        }
+        Command::Unreact(chat::ReactionRef { room_id, message_id, emoji }) => {
+            ChatCommand::Unreact { room_id, message_id, emoji }
+        }
+        Command::SyncRoomState(chat::JoinRoom { room_id, since_version }) => {
+            ChatCommand::SyncRoomState { room_id, since_version }
+        }
+        Command::Typing(chat::RoomRef { room_id }) => ChatCommand::Typing { room_id },
+        Command::MarkRead(chat::MarkRead { room_id, version }) => {
+            ChatCommand::MarkRead { room_id, version }
+        }
+        Command::LoadHistory(chat::LoadHistory { room_id, before, limit }) => {
+            ChatCommand::LoadHistory {
+                room_id,
+                before,
+                limit: limit.map(|limit| usize::try_from(limit).unwrap_or(usize::MAX)),
+            }
+        }
+        Command::SendDirectMessage(chat::SendDirectMessage { to_user_id, content }) => {
+            ChatCommand::SendDirectMessage { to_user_id, content }
+        }
+        Command::MarkDirectRead(chat::UserRef { user_id }) => {
+            ChatCommand::MarkDirectRead { user_id }
+        }
+        Command::InviteToRoom(chat::MemberRef { room_id, user_id }) => {
+            ChatCommand::InviteToRoom { room_id, user_id }
+        }
+        Command::AddModerator(chat::MemberRef { room_id, user_id }) => {
+            ChatCommand::AddModerator { room_id, user_id }
+        }
+        Command::RemoveModerator(chat::MemberRef { room_id, user_id }) => {
+            ChatCommand::RemoveModerator { room_id, user_id }
+        }
+        Command::KickMember(chat::MemberRef { room_id, user_id }) => {
+            ChatCommand::KickMember { room_id, user_id }
+        }
+        Command::MuteMember(chat::MuteMember { room_id, user_id, duration_secs }) => {
+            ChatCommand::MuteMember { room_id, user_id, duration_secs }
+        }
+        Command::UnmuteMember(chat::MemberRef { room_id, user_id }) => {
+            ChatCommand::UnmuteMember { room_id, user_id }
+        }
+        Command::BanMember(chat::MemberRef { room_id, user_id }) => {
+            ChatCommand::BanMember { room_id, user_id }
+        }
+        Command::UnbanMember(chat::MemberRef { room_id, user_id }) => {
+            ChatCommand::UnbanMember { room_id, user_id }
+        }
+        Command::SetStatus(set_status) => {
+            let status = match set_status.status() {
+                chat::PresenceStatus::Unspecified => return None,
+                chat::PresenceStatus::Online => ChatPresenceStatus::Online,
+                chat::PresenceStatus::Away => ChatPresenceStatus::Away,
+                chat::PresenceStatus::Offline => ChatPresenceStatus::Offline,
+            };
+            ChatCommand::SetStatus { status }
+        }
+        Command::Ping(chat::Empty {}) => ChatCommand::Ping(ChatEmptyPayload::default()),
+    };
+    Some(command)
+}
+
+impl From<ChatEvent> for EventFrame {
+    fn from(event: ChatEvent) -> Self {
+        let event = match event {
+            ChatEvent::Connected { user_id, user_name } => {
+                Event::Connected(chat::Connected { user_id, user_name })
+            }
+            ChatEvent::JoinedRoom(snapshot) => Event::JoinedRoom(snapshot.into()),
+            ChatEvent::LeftRoom(notice) => Event::LeftRoom(notice.into()),
+            ChatEvent::RoomMessage(message) => Event::RoomMessage(message.into()),
+            ChatEvent::MessageEdited(edited) => Event::MessageEdited(edited.into()),
+            ChatEvent::MessageDeleted(deleted) => Event::MessageDeleted(deleted.into()),
+            ChatEvent::ReactionChanged(changed) => Event::ReactionChanged(changed.into()),
+            ChatEvent::RoomState(snapshot) => Event::RoomState(snapshot.into()),
+            ChatEvent::RoomDelta(delta) => Event::RoomDelta(delta.into()),
+            ChatEvent::RoomHistory(history) => Event::RoomHistory(history.into()),
+            ChatEvent::Thread(thread) => Event::Thread(thread.into()),
+            ChatEvent::PresenceChanged(presence) => Event::PresenceChanged(presence.into()),
+            ChatEvent::StatusChanged(change) => Event::StatusChanged(change.into()),
+            ChatEvent::UserStatus(status) => Event::UserStatus(status.into()),
+            ChatEvent::Typing(notice) => Event::Typing(notice.into()),
+            ChatEvent::ReadReceipt(receipt) => Event::ReadReceipt(receipt.into()),
+            ChatEvent::RoomModeration(notice) => Event::RoomModeration(notice.into()),
+            ChatEvent::RoomClosed(closed) => Event::RoomClosed(closed.into()),
+            ChatEvent::ConnectionClosed(closed) => Event::ConnectionClosed(closed.into()),
+            ChatEvent::ServerShutdown(shutdown) => Event::ServerShutdown(shutdown.into()),
+            ChatEvent::DirectMessage(message) => Event::DirectMessage(message.into()),
+            ChatEvent::DirectMessageSent(ack) => Event::DirectMessageSent(ack.into()),
+            ChatEvent::DirectUnread(unread) => Event::DirectUnread(unread.into()),
+            ChatEvent::Pong(ChatEmptyPayload {}) => Event::Pong(chat::Empty {}),
+            ChatEvent::Error(error) => Event::Error(error.into()),
+        };
+        Self { event: Some(event) }
+    }
+}
+
+fn status(status: ChatPresenceStatus) -> i32 {
+    match status {
+        ChatPresenceStatus::Online => chat::PresenceStatus::Online,
+        ChatPresenceStatus::Away => chat::PresenceStatus::Away,
+        ChatPresenceStatus::Offline => chat::PresenceStatus::Offline,
+    }
+    .into()
+}
+
+fn messages(messages: Vec<ChatRoomMessage>) -> Vec<chat::RoomMessage> {
+    messages.into_iter().map(Into::into).collect()
+}
+
+fn members(members: Vec<ChatUserSummary>) -> Vec<chat::UserSummary> {
+    members.into_iter().map(Into::into).collect()
+}
+
+fn reactions(reactions: Vec<ChatReaction>) -> Vec<chat::Reaction> {
+    reactions
+        .into_iter()
+        .map(|ChatReaction { emoji, count, user_ids }| chat::Reaction { emoji, count, user_ids })
+        .collect()
+}
+
+impl From<ChatUserSummary> for chat::UserSummary {
+    fn from(member: ChatUserSummary) -> Self {
+        Self { user_id: member.user_id, user_name: member.user_name, status: status(member.status) }
+    }
+}
+
+impl From<ChatRoomMessage> for chat::RoomMessage {
+    fn from(message: ChatRoomMessage) -> Self {
+        Self {
+            room_id: message.room_id,
+            message_id: message.message_id,
+            sender_id: message.sender_id,
+            sender_name: message.sender_name,
+            content: message.content,
+            sent_at: message.sent_at,
+            version: message.version,
+            edited_at: message.edited_at,
+            deleted_at: message.deleted_at,
+            mentions: message.mentions,
+            reply_to: message.reply_to,
+        }
+    }
+}
+
+impl From<ChatRoomSnapshot> for chat::RoomSnapshot {
+    fn from(snapshot: ChatRoomSnapshot) -> Self {
+        Self {
+            room_id: snapshot.room_id,
+            members: members(snapshot.members),
+            recent_messages: messages(snapshot.recent_messages),
+            version: snapshot.version,
+            read_positions: snapshot
+                .read_positions
+                .into_iter()
+                .map(|ChatReadPosition { user_id, version }| chat::ReadPosition {
+                    user_id,
+                    version,
+                })
+                .collect(),
+            unread_count: snapshot.unread_count,
+            delta_unavailable: snapshot.delta_unavailable,
+            owner_id: snapshot.owner_id,
+            moderator_ids: snapshot.moderator_ids,
+            private: snapshot.private,
+            threads: snapshot.threads.into_iter().map(Into::into).collect(),
+            reactions: snapshot.reactions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ChatThreadSummary> for chat::ThreadSummary {
+    fn from(thread: ChatThreadSummary) -> Self {
+        Self {
+            root_message_id: thread.root_message_id,
+            reply_count: thread.reply_count,
+            last_reply: Some(thread.last_reply.into()),
+        }
+    }
+}
+
+impl From<ChatMessageReactions> for chat::MessageReactions {
+    fn from(message: ChatMessageReactions) -> Self {
+        Self {
+            message_id: message.message_id,
+            message_version: message.message_version,
+            reactions: reactions(message.reactions),
+        }
+    }
+}
+
+impl From<ChatLeftRoomNotice> for chat::LeftRoom {
+    fn from(notice: ChatLeftRoomNotice) -> Self {
+        Self { room_id: notice.room_id, version: notice.version }
+    }
+}
+
+impl From<ChatMessageEdited> for chat::MessageEdited {
+    fn from(edited: ChatMessageEdited) -> Self {
+        Self {
+            room_id: edited.room_id,
+            message_id: edited.message_id,
+            message_version: edited.message_version,
+            content: edited.content,
+            edited_at: edited.edited_at,
+            version: edited.version,
+        }
+    }
+}
+
+impl From<ChatMessageDeleted> for chat::MessageDeleted {
+    fn from(deleted: ChatMessageDeleted) -> Self {
+        Self {
+            room_id: deleted.room_id,
+            message_id: deleted.message_id,
+            message_version: deleted.message_version,
+            deleted_at: deleted.deleted_at,
+            version: deleted.version,
+        }
+    }
+}
+
+impl From<ChatReactionChanged> for chat::ReactionChanged {
+    fn from(changed: ChatReactionChanged) -> Self {
+        Self {
+            room_id: changed.room_id,
+            message_id: changed.message_id,
+            message_version: changed.message_version,
+            emoji: changed.emoji,
+            user_id: changed.user_id,
+            added: changed.added,
+            reactions: reactions(changed.reactions),
+            version: changed.version,
+        }
+    }
+}
+
+impl From<ChatRoomDelta> for chat::RoomDelta {
+    fn from(delta: ChatRoomDelta) -> Self {
+        Self {
+            room_id: delta.room_id,
+            since_version: delta.since_version,
+            version: delta.version,
+            events: delta.events.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ChatRoomHistory> for chat::RoomHistory {
+    fn from(history: ChatRoomHistory) -> Self {
+        Self {
+            room_id: history.room_id,
+            messages: messages(history.messages),
+            next_before: history.next_before,
+        }
+    }
+}
+
+impl From<ChatThread> for chat::Thread {
+    fn from(thread: ChatThread) -> Self {
+        Self {
+            room_id: thread.room_id,
+            root_message_id: thread.root_message_id,
+            root: thread.root.map(Into::into),
+            replies: messages(thread.replies),
+            reply_count: thread.reply_count,
+        }
+    }
+}
+
+impl From<ChatPresenceChange> for chat::PresenceChanged {
+    fn from(presence: ChatPresenceChange) -> Self {
+        Self {
+            room_id: presence.room_id,
+            joined_members: members(presence.joined_members),
+            left_members: members(presence.left_members),
+            version: presence.version,
+        }
+    }
+}
+
+impl From<ChatStatusChange> for chat::StatusChanged {
+    fn from(change: ChatStatusChange) -> Self {
+        Self {
+            room_id: change.room_id,
+            user_id: change.user_id,
+            status: status(change.status),
+            version: change.version,
+        }
+    }
+}
+
+impl From<ChatUserStatus> for chat::UserStatus {
+    fn from(user_status: ChatUserStatus) -> Self {
+        Self { user_id: user_status.user_id, status: status(user_status.status) }
+    }
+}
+
+impl From<ChatTypingNotice> for chat::TypingNotice {
+    fn from(notice: ChatTypingNotice) -> Self {
+        Self {
+            room_id: notice.room_id,
+            user_id: notice.user_id,
+            user_name: notice.user_name,
+            expires_in_ms: notice.expires_in_ms,
+        }
+    }
+}
+
+impl From<ChatReadReceipt> for chat::ReadReceipt {
+    fn from(receipt: ChatReadReceipt) -> Self {
+        Self { room_id: receipt.room_id, user_id: receipt.user_id, version: receipt.version }
+    }
+}
+
+impl From<ChatModerationNotice> for chat::RoomModeration {
+    fn from(notice: ChatModerationNotice) -> Self {
+        let action = match notice.action {
+            ChatModerationAction::Invite => chat::ModerationAction::Invite,
+            ChatModerationAction::AddModerator => chat::ModerationAction::AddModerator,
+            ChatModerationAction::RemoveModerator => chat::ModerationAction::RemoveModerator,
+            ChatModerationAction::Kick => chat::ModerationAction::Kick,
+            ChatModerationAction::Mute => chat::ModerationAction::Mute,
+            ChatModerationAction::Unmute => chat::ModerationAction::Unmute,
+            ChatModerationAction::Ban => chat::ModerationAction::Ban,
+            ChatModerationAction::Unban => chat::ModerationAction::Unban,
+        };
+        Self {
+            room_id: notice.room_id,
+            action: action.into(),
+            user_id: notice.user_id,
+            moderator_id: notice.moderator_id,
+            muted_until: notice.muted_until,
+        }
+    }
+}
+
+impl From<ChatRoomClosed> for chat::RoomClosed {
+    fn from(closed: ChatRoomClosed) -> Self {
+        Self { room_id: closed.room_id, reason: closed.reason, version: closed.version }
+    }
+}
+
+impl From<ChatConnectionClosed> for chat::ConnectionClosed {
+    fn from(closed: ChatConnectionClosed) -> Self {
+        Self { reason: closed.reason }
+    }
+}
+
+impl From<ChatServerShutdown> for chat::ServerShutdown {
+    fn from(shutdown: ChatServerShutdown) -> Self {
+        Self { reconnect_after_ms: shutdown.reconnect_after_ms }
+    }
+}
+
+impl From<ChatDirectMessage> for chat::DirectMessage {
+    fn from(message: ChatDirectMessage) -> Self {
+        Self {
+            message_id: message.message_id,
+            sender_id: message.sender_id,
+            sender_name: message.sender_name,
+            recipient_id: message.recipient_id,
+            content: message.content,
+            sent_at: message.sent_at,
+        }
+    }
+}
+
+impl From<ChatDirectMessageAck> for chat::DirectMessageSent {
+    fn from(ack: ChatDirectMessageAck) -> Self {
+        Self {
+            message_id: ack.message_id,
+            to_user_id: ack.to_user_id,
+            delivered: ack.delivered,
+            sent_at: ack.sent_at,
+        }
+    }
+}
+
+impl From<ChatDirectUnread> for chat::DirectUnread {
+    fn from(unread: ChatDirectUnread) -> Self {
+        Self {
+            conversations: unread
+                .conversations
+                .into_iter()
+                .map(|ChatDirectConversation { user_id, unread_count }| chat::DirectConversation {
+                    user_id,
+                    unread_count,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ChatErrorPayload> for chat::Error {
+    fn from(error: ChatErrorPayload) -> Self {
+        Self { code: error.code, message: error.message, retry_after_ms: error.retry_after_ms }
+    }
+}
@@ -0,0 +1,177 @@
+use std::{pin::Pin, sync::Arc, time::Duration};
+
+use futures_util::{Stream, StreamExt, stream};
+use tonic::{Request, Response, Status, Streaming, metadata::MetadataMap};
+use tracing::debug;
+
+use crate::{
+    grpc::{
+        chat::{
+            CommandFrame, EventFrame,
+            chat_server::{Chat, ChatServer},
+        },
+        chat_frames::command_from_frame,
+    },
+    handlers::chat::{
+        ChatError, ChatErrorPayload, ChatEvent, ChatSessionUser, ChatState, auth,
+        outbound::{ChatCloseReason, ChatOutbound},
+    },
+    utils::jwt_auth::{self, Claims},
+};
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<EventFrame, Status>> + Send>>;
+
+/// Bridges `chat.v1.Chat` streams onto the same `ChatState` the websocket handler uses.
+pub struct ChatSvc {
+    chat_state: Arc<ChatState>,
+}
+
+#[tonic::async_trait]
+impl Chat for ChatSvc {
+    type ConnectStream = EventStream;
+
+    async fn connect(
+        &self,
+        req: Request<Streaming<CommandFrame>>,
+    ) -> Result<Response<Self::ConnectStream>, Status> {
+        let claims = authenticate(req.metadata())?;
+        let user_id = claims.sub.trim().to_string();
+        if user_id.is_empty() {
+            return Err(Status::unauthenticated("invalid token"));
+        }
+        let session_ttl = auth::session_remaining(&claims);
+        if self.chat_state.is_shutting_down() {
+            return Err(Status::unavailable(ChatError::ShuttingDown.to_string()));
+        }
+
+        let (session_user, outbound) = self
+            .chat_state
+            .register_connection(&user_id, &user_id)
+            .await;
+        tokio::spawn(self.chat_state.track_session(run_commands(
+            self.chat_state.clone(),
+            req.into_inner(),
+            session_user,
+            outbound.clone(),
+            session_ttl,
+        )));
+        Ok(Response::new(Box::pin(events(outbound))))
+    }
+}
+
+pub fn router(chat_state: Arc<ChatState>) -> ChatServer<ChatSvc> {
+    ChatServer::new(ChatSvc { chat_state })
+}
+
+/// Reads the client's commands until it hangs up, idles out or the session is closed, then leaves
+/// its rooms. Dead transports are caught by HTTP/2 keepalive pings, see `super::serve`.
+async fn run_commands(
+    chat_state: Arc<ChatState>,
+    mut commands: Streaming<CommandFrame>,
+    session_user: ChatSessionUser,
+    outbound: Arc<ChatOutbound>,
+    session_ttl: Duration,
+) {
+    let connection_id = session_user.connection_id.clone();
+    let heartbeat = chat_state.heartbeat();
+    let mut rate_limits = chat_state.rate_limits(&session_user);
+    chat_state.welcome(&session_user).await;
+
+    let session_expiry = tokio::time::sleep(session_ttl);
+    tokio::pin!(session_expiry);
+    let mut close_reason = None;
+    loop {
+        let frame = tokio::select! {
+            _ = &mut session_expiry => {
+                chat_state
+                    .send_to_connection(&connection_id, ChatError::SessionExpired.to_event())
+                    .await;
+                close_reason = Some(ChatCloseReason::SessionExpired);
+                break;
+            }
+            // Slow consumer overflow, the event stream ends with its status.
+            _ = outbound.closed() => break,
+            _ = chat_state.shutting_down() => {
+                chat_state
+                    .send_to_connection(&connection_id, chat_state.shutdown_notice())
+                    .await;
+                close_reason = Some(ChatCloseReason::GoingAway);
+                break;
+            }
+            _ = chat_state.expire_connection(&connection_id, &outbound, &heartbeat) => break,
+            frame = commands.next() => match frame {
+                Some(Ok(frame)) => frame,
+                Some(Err(error)) => {
+                    debug!(error = %error, "grpc chat receive failed");
+                    break;
+                }
+                None => break,
+            },
+        };
+
+        heartbeat.activity();
+        match command_from_frame(frame) {
+            Some(command) => {
+                if let Err(reason) = chat_state
+                    .receive_command(&session_user, &mut rate_limits, command)
+                    .await
+                {
+                    close_reason = Some(reason);
+                    break;
+                }
+            }
+            None => {
+                debug!("invalid grpc chat command");
+                chat_state
+                    .send_to_connection(
+                        &connection_id,
+                        ChatEvent::Error(ChatErrorPayload {
+                            code: "invalid_message".to_string(),
+                            message: "invalid chat command".to_string(),
+                            retry_after_ms: None,
+                        }),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    outbound.close(close_reason);
+    chat_state.unregister_connection(&connection_id).await;
+}
+
+/// Queued events of the connection, ending with an error status when the server closed it.
+fn events(outbound: Arc<ChatOutbound>) -> impl Stream<Item = Result<EventFrame, Status>> {
+    stream::unfold(Some(outbound), |outbound| async move {
+        let outbound = outbound?;
+        let Some(event) = outbound.pop().await else {
+            let status = outbound.close_reason().map(close_status)?;
+            return Some((Err(status), None));
+        };
+        Some((Ok(EventFrame::from(event)), Some(outbound)))
+    })
+}
+
+pub fn close_status(reason: ChatCloseReason) -> Status {
+    match reason {
+        ChatCloseReason::SessionExpired => Status::unauthenticated(reason.as_str()),
+        ChatCloseReason::SlowConsumer | ChatCloseReason::RateLimited => {
+            Status::resource_exhausted(reason.as_str())
+        }
+        ChatCloseReason::HeartbeatTimeout | ChatCloseReason::IdleTimeout => {
+            Status::deadline_exceeded(reason.as_str())
+        }
+        ChatCloseReason::ClosedByAdmin => Status::aborted(reason.as_str()),
+        ChatCloseReason::GoingAway => Status::unavailable(reason.as_str()),
+    }
+}
+
+/// Resolves the caller from an `authorization: Bearer <jwt>` metadata entry.
+fn authenticate(metadata: &MetadataMap) -> Result<Claims, Status> {
+    let token = metadata
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+    jwt_auth::decode_claims(token.trim()).map_err(|_| Status::unauthenticated("invalid token"))
+}
@@ -1,16 +1,27 @@
+pub mod chat_frames;
+pub mod chat_impl;
 pub mod greeter_impl;
 
 pub mod greeter {
     tonic::include_proto!("greeter.v1"); // The string specified here must match the proto package name
 }
 
+pub mod chat {
+    tonic::include_proto!("chat.v1");
+}
+
 pub async fn serve(
     grpc_addr: std::net::SocketAddr,
+    chat_state: std::sync::Arc<crate::handlers::chat::ChatState>,
     token: tokio_util::sync::CancellationToken,
 ) -> Result<(), tonic::transport::Error> {
+    let (keepalive_interval, keepalive_timeout) = chat_state.keepalive();
     tonic::transport::Server::builder()
+        .http2_keepalive_interval(Some(keepalive_interval))
+        .http2_keepalive_timeout(Some(keepalive_timeout))
         .layer(crate::utils::observability::grpc_observability_layer())
         .add_service(greeter_impl::router())
+        .add_service(chat_impl::router(chat_state))
         .serve_with_shutdown(grpc_addr, async move { token.cancelled().await })
         .await
 }
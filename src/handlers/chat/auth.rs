@@ -0,0 +1,108 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{Json, extract::State, http::HeaderMap, response::IntoResponse};
+use chrono::Utc;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult, AuthError},
+    route::AppState,
+    utils::jwt_auth::{self, Claims},
+};
+
+/// Subprotocol chat clients offer next to their `bearer.<jwt>` entry; the server echoes it, or
+/// another one from `codec::CHAT_PROTOCOLS`, so the token itself is never reflected in the
+/// handshake response.
+pub const CHAT_PROTOCOL: &str = "axes.chat.v1";
+const BEARER_PROTOCOL_PREFIX: &str = "bearer.";
+const TICKET_TTL_SECONDS: u64 = 30;
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ChatTicket {
+    pub ticket: String,
+    pub expires_in: u64,
+}
+
+/// Issues a single-use ticket for clients that cannot set websocket headers, e.g. browsers.
+pub async fn issue_ticket(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let ticket = Uuid::new_v4().simple().to_string();
+    let mut conn = state
+        .redis_client
+        .get_multiplexed_async_connection()
+        .await?;
+    let _: () = redis::cmd("SET")
+        .arg(ticket_key(&ticket))
+        .arg(serde_json::to_string(&claims).map_err(anyhow::Error::from)?)
+        .arg("EX")
+        .arg(TICKET_TTL_SECONDS)
+        .query_async(&mut conn)
+        .await?;
+
+    Ok(Json(ChatTicket { ticket, expires_in: TICKET_TTL_SECONDS }))
+}
+
+/// Resolves the connecting user from a `bearer.<jwt>` subprotocol or a ticket, in that order.
+pub async fn authenticate(
+    state: &AppState,
+    headers: &HeaderMap,
+    ticket: Option<&str>,
+) -> AppResult<Claims> {
+    let protocols = headers
+        .get_all(axum::http::header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    if let Some(token) = bearer_from_protocols(&protocols) {
+        return Ok(jwt_auth::decode_claims(token)?);
+    }
+
+    match ticket.map(str::trim).filter(|ticket| !ticket.is_empty()) {
+        Some(ticket) => redeem_ticket(state, ticket).await,
+        None => Err(AuthError::MissingCredential.into()),
+    }
+}
+
+pub fn bearer_from_protocols(protocols: &str) -> Option<&str> {
+    protocols
+        .split(',')
+        .map(str::trim)
+        .find_map(|protocol| protocol.strip_prefix(BEARER_PROTOCOL_PREFIX))
+        .filter(|token| !token.is_empty())
+}
+
+/// Time left before the session's token expires, zero when it already has.
+pub fn session_ttl(claims: &Claims, now: i64) -> Duration {
+    Duration::from_secs(claims.exp.saturating_sub(now.max(0) as u64))
+}
+
+pub fn session_remaining(claims: &Claims) -> Duration {
+    session_ttl(claims, Utc::now().timestamp())
+}
+
+async fn redeem_ticket(state: &AppState, ticket: &str) -> AppResult<Claims> {
+    let mut conn = state
+        .redis_client
+        .get_multiplexed_async_connection()
+        .await?;
+    // GETDEL makes tickets single use even when several instances race on the same one.
+    let claims: Option<String> = redis::cmd("GETDEL")
+        .arg(ticket_key(ticket))
+        .query_async(&mut conn)
+        .await?;
+    let claims = claims.ok_or(AuthError::InvalidToken)?;
+    let claims: Claims = serde_json::from_str(&claims).map_err(|_| AuthError::InvalidToken)?;
+
+    if session_remaining(&claims).is_zero() {
+        return Err(AppError::from(AuthError::InvalidToken));
+    }
+    Ok(claims)
+}
+
+fn ticket_key(ticket: &str) -> String {
+    format!("chat:ticket:{ticket}")
+}
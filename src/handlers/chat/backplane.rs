@@ -0,0 +1,1749 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use chrono::Utc;
+use futures_util::StreamExt;
+use redis::{Script, aio::MultiplexedConnection};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use super::{
+    ChatError, ChatEvent, ChatPresenceChange, ChatRoomMessage, ChatRoomSnapshot, ChatSessionUser,
+    ChatState, ChatUserSummary, MAX_RECENT_MESSAGES,
+    direct::{ChatDirectUnread, direct_unread},
+    directory::{ChatConnectionSummary, ChatRoomClosed, ChatRoomSummary, member_count},
+    edits::{ChatMessageChange, ChatMessageModified},
+    filters::{ChatFlaggedMessage, MAX_FLAGGED_MESSAGES, extract_mentions},
+    moderation::ChatRoomAccess,
+    presence::{ChatPresenceStatus, ChatStatusChange},
+    reactions::{
+        ChatMessageReactions, ChatReactionChanged, MAX_REACTED_MESSAGES, MAX_REACTIONS_PER_MESSAGE,
+    },
+    receipts::{ChatReadMark, ChatReadReceipt, read_positions, unread_count},
+    sort_members,
+    sync::{ChatRoomEventLog, MAX_ROOM_EVENTS, StoredRoomEvent},
+    threads::{ChatThreadSummary, MAX_THREADS},
+};
+
+const CHANNEL_PREFIX: &str = "chat:events:";
+const DIRECT_CHANNEL_PREFIX: &str = "chat:direct:";
+const DISCONNECT_CHANNEL: &str = "chat:control:disconnect";
+/// Rooms by last activity in unix seconds, for the room directory.
+const ROOM_INDEX_KEY: &str = "chat:rooms";
+/// Live connections of every instance, leased like room members, and their summaries.
+const CONNECTION_LEASES_KEY: &str = "chat:{connections}:leases";
+const CONNECTION_DETAILS_KEY: &str = "chat:{connections}:details";
+/// Members not refreshed within this window are treated as gone, e.g. after a pod crash.
+const MEMBER_TTL: Duration = Duration::from_secs(30);
+const MEMBER_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+const ROOM_KEY_TTL_SECONDS: u64 = 604_800;
+const UNREAD_KEY_TTL_SECONDS: u64 = 2_592_000;
+const RESUBSCRIBE_BACKOFF: Duration = Duration::from_secs(1);
+const ACCESS_UPDATE_ATTEMPTS: usize = 5;
+
+/// Appends a version bumping event to the room's bounded log, see `StoredRoomEvent`.
+const LOG_ROOM_EVENT: &str = r#"
+local function log_event(eventsKey, entry, maxEvents)
+    redis.call('RPUSH', eventsKey, entry)
+    redis.call('LTRIM', eventsKey, -maxEvents, -1)
+end
+"#;
+
+/// Counts the member connections of a user, whose summaries sit in the details hash.
+const USER_CONNECTIONS: &str = r#"
+local function user_connections(detailsKey, userId)
+    local count = 0
+    for _, summary in ipairs(redis.call('HVALS', detailsKey)) do
+        if cjson.decode(summary)['user_id'] == userId then
+            count = count + 1
+        end
+    end
+    return count
+end
+"#;
+
+/// Removes members whose lease expired, bumping the version once when any user lost their last
+/// connection. Returns one summary per user who left.
+const PRUNE_EXPIRED_MEMBERS: &str = r#"
+local function prune_expired(versionKey, membersKey, detailsKey, eventsKey, now, maxEvents)
+    local expired = redis.call('ZRANGEBYSCORE', membersKey, '-inf', now)
+    local summaries = {}
+    for _, connectionId in ipairs(expired) do
+        local summary = redis.call('HGET', detailsKey, connectionId)
+        if summary then
+            table.insert(summaries, summary)
+        end
+        redis.call('ZREM', membersKey, connectionId)
+        redis.call('HDEL', detailsKey, connectionId)
+    end
+    local left, seen = {}, {}
+    for _, summary in ipairs(summaries) do
+        local userId = cjson.decode(summary)['user_id']
+        if not seen[userId] and user_connections(detailsKey, userId) == 0 then
+            seen[userId] = true
+            table.insert(left, summary)
+        end
+    end
+    if #left > 0 then
+        local version = redis.call('INCR', versionKey)
+        log_event(eventsKey, '{"version":' .. version .. ',"left":[' .. table.concat(left, ',') .. ']}', maxEvents)
+        return version, left
+    end
+    return 0, left
+end
+"#;
+
+const JOIN_ROOM_SCRIPT: &str = r#"
+local versionKey, membersKey, detailsKey, recentKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4]
+local countKey, readsKey, eventsKey, threadsKey, reactionsKey = KEYS[5], KEYS[6], KEYS[7], KEYS[8], KEYS[9]
+local connectionId, summary, now, expiresAt = ARGV[1], ARGV[2], ARGV[3], ARGV[4]
+local floor, ttl, userId, maxEvents = tonumber(ARGV[5]), tonumber(ARGV[6]), ARGV[7], tonumber(ARGV[8])
+
+if tonumber(redis.call('GET', versionKey) or '0') < floor then
+    redis.call('SET', versionKey, floor)
+    -- Versions skipped by the jump were never logged, so older entries cannot be replayed.
+    redis.call('DEL', eventsKey)
+end
+local prunedVersion, expired = prune_expired(versionKey, membersKey, detailsKey, eventsKey, now, maxEvents)
+
+-- Peers only see the user join with their first connection.
+local joined = 0
+if not redis.call('ZSCORE', membersKey, connectionId) and user_connections(detailsKey, userId) == 0 then
+    local joinedVersion = redis.call('INCR', versionKey)
+    log_event(eventsKey, '{"version":' .. joinedVersion .. ',"joined":[' .. summary .. ']}', maxEvents)
+    joined = 1
+end
+redis.call('ZADD', membersKey, expiresAt, connectionId)
+redis.call('HSET', detailsKey, connectionId, summary)
+local version = tonumber(redis.call('GET', versionKey))
+local messageCount = tonumber(redis.call('GET', countKey) or '0')
+-- First-time members start caught up rather than with the whole room unread.
+redis.call('HSETNX', readsKey, userId, version .. ':' .. messageCount)
+for _, key in ipairs(KEYS) do
+    redis.call('EXPIRE', key, ttl)
+end
+
+return {version, joined, prunedVersion, expired, redis.call('HVALS', detailsKey), redis.call('LRANGE', recentKey, 0, -1), messageCount, redis.call('HGETALL', readsKey), redis.call('HVALS', threadsKey), redis.call('HVALS', reactionsKey)}
+"#;
+
+const LEAVE_ROOM_SCRIPT: &str = r#"
+local versionKey, membersKey, detailsKey, eventsKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4]
+local connectionId, maxEvents = ARGV[1], tonumber(ARGV[2])
+
+if not redis.call('ZSCORE', membersKey, connectionId) then
+    return {0, '', 0}
+end
+local summary = redis.call('HGET', detailsKey, connectionId) or ''
+redis.call('ZREM', membersKey, connectionId)
+redis.call('HDEL', detailsKey, connectionId)
+-- Peers only see the user leave with their last connection.
+if summary ~= '' and user_connections(detailsKey, cjson.decode(summary)['user_id']) > 0 then
+    return {tonumber(redis.call('GET', versionKey) or '0'), summary, 0}
+end
+local version = redis.call('INCR', versionKey)
+log_event(eventsKey, '{"version":' .. version .. ',"left":[' .. summary .. ']}', maxEvents)
+return {version, summary, 1}
+"#;
+
+/// Appends a message under the next room version. A reply names the message it answers, found
+/// in the recent list, among the thread summaries or passed in as the stored copy loaded from
+/// history, and is moved to the root of that message's thread. Replies with a status, then the
+/// stored message.
+const APPEND_MESSAGE_SCRIPT: &str = r#"
+local versionKey, membersKey, recentKey, countKey, readsKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4], KEYS[5]
+local eventsKey, threadsKey = KEYS[6], KEYS[7]
+local connectionId, message, maxRecent, ttl = ARGV[1], ARGV[2], tonumber(ARGV[3]), tonumber(ARGV[4])
+local userId, maxEvents, stored, maxThreads = ARGV[5], tonumber(ARGV[6]), ARGV[7], tonumber(ARGV[8])
+
+if not redis.call('ZSCORE', membersKey, connectionId) then
+    return {'not_in_room'}
+end
+local decoded = cjson.decode(message)
+local replyTo = decoded['reply_to']
+if replyTo then
+    local target = nil
+    for _, encoded in ipairs(redis.call('LRANGE', recentKey, 0, -1)) do
+        local recent = cjson.decode(encoded)
+        if recent['message_id'] == replyTo then
+            target = recent
+            break
+        end
+    end
+    if not target and redis.call('HEXISTS', threadsKey, replyTo) == 1 then
+        target = {message_id = replyTo}
+    end
+    if not target and stored ~= '' then
+        target = cjson.decode(stored)
+    end
+    if not target or target['message_id'] ~= replyTo or (target['deleted_at'] and target['deleted_at'] ~= cjson.null) then
+        return {'not_found'}
+    end
+    -- Replying to a reply joins the thread it belongs to.
+    if target['reply_to'] and target['reply_to'] ~= cjson.null then
+        replyTo = target['reply_to']
+    end
+    decoded['reply_to'] = replyTo
+end
+local version = redis.call('INCR', versionKey)
+decoded['version'] = version
+local encoded = cjson.encode(decoded)
+redis.call('RPUSH', recentKey, encoded)
+redis.call('LTRIM', recentKey, -maxRecent, -1)
+log_event(eventsKey, '{"version":' .. version .. ',"message":' .. encoded .. '}', maxEvents)
+-- Senders have read everything up to their own message.
+local messageCount = redis.call('INCR', countKey)
+redis.call('HSET', readsKey, userId, version .. ':' .. messageCount)
+if replyTo then
+    local summary = redis.call('HGET', threadsKey, replyTo)
+    local replyCount = summary and cjson.decode(summary)['reply_count'] or 0
+    redis.call('HSET', threadsKey, replyTo, cjson.encode({root_message_id = replyTo, reply_count = replyCount + 1, last_reply = decoded}))
+    -- Drops the least recently replied to thread once there are too many.
+    if redis.call('HLEN', threadsKey) > maxThreads then
+        local stalest, stalestVersion = nil, nil
+        for _, encodedSummary in ipairs(redis.call('HVALS', threadsKey)) do
+            local thread = cjson.decode(encodedSummary)
+            if not stalestVersion or thread['last_reply']['version'] < stalestVersion then
+                stalest, stalestVersion = thread['root_message_id'], thread['last_reply']['version']
+            end
+        end
+        redis.call('HDEL', threadsKey, stalest)
+    end
+    redis.call('EXPIRE', threadsKey, ttl)
+end
+redis.call('EXPIRE', versionKey, ttl)
+redis.call('EXPIRE', recentKey, ttl)
+redis.call('EXPIRE', countKey, ttl)
+redis.call('EXPIRE', readsKey, ttl)
+redis.call('EXPIRE', eventsKey, ttl)
+return {'ok', encoded}
+"#;
+
+const ROOM_SNAPSHOT_SCRIPT: &str = r#"
+local versionKey, membersKey, detailsKey, recentKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4]
+local countKey, readsKey, eventsKey, threadsKey, reactionsKey = KEYS[5], KEYS[6], KEYS[7], KEYS[8], KEYS[9]
+local prunedVersion, expired = prune_expired(versionKey, membersKey, detailsKey, eventsKey, ARGV[1], tonumber(ARGV[2]))
+local version = tonumber(redis.call('GET', versionKey) or '0')
+local messageCount = tonumber(redis.call('GET', countKey) or '0')
+return {version, prunedVersion, expired, redis.call('HVALS', detailsKey), redis.call('LRANGE', recentKey, 0, -1), messageCount, redis.call('HGETALL', readsKey), redis.call('HVALS', threadsKey), redis.call('HVALS', reactionsKey)}
+"#;
+
+/// Moves a user's read position forward, counting newer messages from the recent window.
+/// Returns the clamped version, or false when the position did not advance.
+const MARK_READ_SCRIPT: &str = r#"
+local versionKey, recentKey, countKey, readsKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4]
+local userId = ARGV[1]
+
+local version = math.min(tonumber(ARGV[2]), tonumber(redis.call('GET', versionKey) or '0'))
+local existing = redis.call('HGET', readsKey, userId)
+if existing and tonumber(string.match(existing, '^(%d+):')) >= version then
+    return false
+end
+local newer = 0
+for _, encoded in ipairs(redis.call('LRANGE', recentKey, 0, -1)) do
+    if cjson.decode(encoded)['version'] > version then
+        newer = newer + 1
+    end
+end
+local messageCount = math.max(tonumber(redis.call('GET', countKey) or '0') - newer, 0)
+redis.call('HSET', readsKey, userId, version .. ':' .. messageCount)
+return version
+"#;
+
+/// Edits or deletes a message of the caller, found in the recent list or passed in as the
+/// stored copy loaded from history. Keeps its thread's last reply current and drops the
+/// reactions of deleted messages. Replies with a status, then the changed message and event.
+const MODIFY_MESSAGE_SCRIPT: &str = r#"
+local versionKey, membersKey, recentKey, eventsKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4]
+local threadsKey, reactionsKey = KEYS[5], KEYS[6]
+local connectionId, userId, messageId, action = ARGV[1], ARGV[2], ARGV[3], ARGV[4]
+local content, now, stored, maxEvents = ARGV[5], tonumber(ARGV[6]), ARGV[7], tonumber(ARGV[8])
+local moderator, mentions = ARGV[9], ARGV[10]
+
+if not redis.call('ZSCORE', membersKey, connectionId) then
+    return {'not_in_room'}
+end
+local index, message = nil, nil
+for i, encoded in ipairs(redis.call('LRANGE', recentKey, 0, -1)) do
+    local decoded = cjson.decode(encoded)
+    if decoded['message_id'] == messageId then
+        index, message = i - 1, decoded
+        break
+    end
+end
+if not message and stored ~= '' then
+    message = cjson.decode(stored)
+end
+if not message or (message['deleted_at'] and message['deleted_at'] ~= cjson.null) then
+    return {'not_found'}
+end
+-- Moderators may delete, but not edit, other users' messages.
+if message['sender_id'] ~= userId and not (action == 'delete' and moderator == '1') then
+    return {'forbidden'}
+end
+
+local version = redis.call('INCR', versionKey)
+local change = {room_id = message['room_id'], message_id = messageId, message_version = message['version'], version = version}
+local kind
+if action == 'edit' then
+    message['content'] = content
+    message['edited_at'] = now
+    -- An empty table would encode as an object, so no mentions leaves the field out.
+    message['mentions'] = mentions ~= '' and cjson.decode(mentions) or nil
+    change['content'] = content
+    change['edited_at'] = now
+    kind = 'edited'
+else
+    message['content'] = ''
+    message['deleted_at'] = now
+    message['mentions'] = nil
+    change['deleted_at'] = now
+    kind = 'deleted'
+end
+local encodedMessage = cjson.encode(message)
+if index then
+    redis.call('LSET', recentKey, index, encodedMessage)
+end
+local replyTo = message['reply_to']
+if replyTo and replyTo ~= cjson.null then
+    local summary = redis.call('HGET', threadsKey, replyTo)
+    if summary then
+        local thread = cjson.decode(summary)
+        if thread['last_reply']['message_id'] == messageId then
+            thread['last_reply'] = message
+            redis.call('HSET', threadsKey, replyTo, cjson.encode(thread))
+        end
+    end
+end
+if action == 'delete' then
+    redis.call('HDEL', reactionsKey, messageId)
+end
+local encodedChange = cjson.encode(change)
+log_event(eventsKey, '{"version":' .. version .. ',"' .. kind .. '":' .. encodedChange .. '}', maxEvents)
+return {'ok', encodedMessage, encodedChange}
+"#;
+
+/// Adds or removes the caller's reaction to a message, found among the reacted messages, in the
+/// recent list or passed in as the stored copy loaded from history. Replies with a status, then
+/// the change event.
+const REACT_SCRIPT: &str = r#"
+local versionKey, membersKey, recentKey, reactionsKey, eventsKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4], KEYS[5]
+local connectionId, roomId, userId, messageId, emoji = ARGV[1], ARGV[2], ARGV[3], ARGV[4], ARGV[5]
+local added, stored, maxEvents, maxReactions = ARGV[6] == '1', ARGV[7], tonumber(ARGV[8]), tonumber(ARGV[9])
+local maxMessages, ttl = tonumber(ARGV[10]), tonumber(ARGV[11])
+
+if not redis.call('ZSCORE', membersKey, connectionId) then
+    return {'not_in_room'}
+end
+local existing = redis.call('HGET', reactionsKey, messageId)
+local entry
+if existing then
+    entry = cjson.decode(existing)
+elseif not added then
+    return {'unchanged'}
+else
+    local message = nil
+    for _, encoded in ipairs(redis.call('LRANGE', recentKey, 0, -1)) do
+        local recent = cjson.decode(encoded)
+        if recent['message_id'] == messageId then
+            message = recent
+            break
+        end
+    end
+    if not message and stored ~= '' then
+        message = cjson.decode(stored)
+    end
+    if not message or message['message_id'] ~= messageId or (message['deleted_at'] and message['deleted_at'] ~= cjson.null) then
+        return {'not_found'}
+    end
+    entry = {message_id = messageId, message_version = message['version'], reactions = {}}
+end
+
+local reactionIndex = nil
+for i, reaction in ipairs(entry['reactions']) do
+    if reaction['emoji'] == emoji then
+        reactionIndex = i
+        break
+    end
+end
+local userIndex = nil
+if reactionIndex then
+    for i, reacted in ipairs(entry['reactions'][reactionIndex]['user_ids']) do
+        if reacted == userId then
+            userIndex = i
+            break
+        end
+    end
+end
+if added then
+    if userIndex then
+        return {'unchanged'}
+    elseif reactionIndex then
+        local reaction = entry['reactions'][reactionIndex]
+        table.insert(reaction['user_ids'], userId)
+        reaction['count'] = #reaction['user_ids']
+    elseif #entry['reactions'] >= maxReactions then
+        return {'too_many'}
+    else
+        table.insert(entry['reactions'], {emoji = emoji, count = 1, user_ids = {userId}})
+    end
+else
+    if not userIndex then
+        return {'unchanged'}
+    end
+    local reaction = entry['reactions'][reactionIndex]
+    table.remove(reaction['user_ids'], userIndex)
+    reaction['count'] = #reaction['user_ids']
+    if reaction['count'] == 0 then
+        table.remove(entry['reactions'], reactionIndex)
+    end
+end
+
+local version = redis.call('INCR', versionKey)
+local change = {room_id = roomId, message_id = messageId, message_version = entry['message_version'], emoji = emoji, user_id = userId, added = added, version = version}
+-- An empty table would encode as an object, so messages left without reactions are dropped.
+if #entry['reactions'] == 0 then
+    redis.call('HDEL', reactionsKey, messageId)
+else
+    change['reactions'] = entry['reactions']
+    redis.call('HSET', reactionsKey, messageId, cjson.encode(entry))
+end
+if redis.call('HLEN', reactionsKey) > maxMessages then
+    local oldest, oldestVersion = nil, nil
+    for _, encoded in ipairs(redis.call('HVALS', reactionsKey)) do
+        local reacted = cjson.decode(encoded)
+        if reacted['message_id'] ~= messageId and (not oldestVersion or reacted['message_version'] < oldestVersion) then
+            oldest, oldestVersion = reacted['message_id'], reacted['message_version']
+        end
+    end
+    redis.call('HDEL', reactionsKey, oldest)
+end
+redis.call('EXPIRE', reactionsKey, ttl)
+local encodedChange = cjson.encode(change)
+log_event(eventsKey, '{"version":' .. version .. ',"reaction":' .. encodedChange .. '}', maxEvents)
+return {'ok', encodedChange}
+"#;
+
+/// Stores the default access rules unless the room already has some. Access rules never expire,
+/// so an idle room keeps its owner, bans and privacy.
+const ENSURE_ROOM_ACCESS_SCRIPT: &str = r#"
+redis.call('SET', KEYS[1], ARGV[1], 'NX')
+return redis.call('GET', KEYS[1])
+"#;
+
+/// Replaces the access rules only if nobody changed them since they were read.
+const UPDATE_ROOM_ACCESS_SCRIPT: &str = r#"
+if (redis.call('GET', KEYS[1]) or '') ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Takes every connection of a user out of the room under one new version.
+const REMOVE_USER_SCRIPT: &str = r#"
+local versionKey, membersKey, detailsKey, eventsKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4]
+local userId, maxEvents = ARGV[1], tonumber(ARGV[2])
+
+local summaries = {}
+local details = redis.call('HGETALL', detailsKey)
+for i = 1, #details, 2 do
+    if cjson.decode(details[i + 1])['user_id'] == userId then
+        -- The user is listed once however many connections they had.
+        if #summaries == 0 then
+            table.insert(summaries, details[i + 1])
+        end
+        redis.call('ZREM', membersKey, details[i])
+        redis.call('HDEL', detailsKey, details[i])
+    end
+end
+if #summaries == 0 then
+    return {0, summaries}
+end
+local version = redis.call('INCR', versionKey)
+log_event(eventsKey, '{"version":' .. version .. ',"left":[' .. table.concat(summaries, ',') .. ']}', maxEvents)
+return {version, summaries}
+"#;
+
+/// Sets a user's status on each of their member connections, bumping the version when it
+/// changed. Returns the version, or 0 when nothing changed.
+const SET_STATUS_SCRIPT: &str = r#"
+local versionKey, detailsKey, eventsKey = KEYS[1], KEYS[2], KEYS[3]
+local roomId, userId, status, maxEvents = ARGV[1], ARGV[2], ARGV[3], tonumber(ARGV[4])
+
+local changed = false
+local details = redis.call('HGETALL', detailsKey)
+for i = 1, #details, 2 do
+    local summary = cjson.decode(details[i + 1])
+    if summary['user_id'] == userId and (summary['status'] or 'online') ~= status then
+        summary['status'] = status
+        redis.call('HSET', detailsKey, details[i], cjson.encode(summary))
+        changed = true
+    end
+end
+if not changed then
+    return 0
+end
+local version = redis.call('INCR', versionKey)
+local change = cjson.encode({room_id = roomId, user_id = userId, status = status, version = version})
+log_event(eventsKey, '{"version":' .. version .. ',"status":' .. change .. '}', maxEvents)
+return version
+"#;
+
+/// Drops a user connection lease, and the user's status with their last connection.
+const UNREGISTER_USER_CONNECTION_SCRIPT: &str = r#"
+local connectionsKey, statusKey = KEYS[1], KEYS[2]
+local connectionId, now = ARGV[1], ARGV[2]
+
+redis.call('ZREM', connectionsKey, connectionId)
+redis.call('ZREMRANGEBYSCORE', connectionsKey, '-inf', now)
+if redis.call('ZCARD', connectionsKey) == 0 then
+    redis.call('DEL', statusKey)
+end
+return 1
+"#;
+
+/// Removes every member of a room under one new version. Returns the version, or 0 when the
+/// room had no members.
+const CLOSE_ROOM_SCRIPT: &str = r#"
+local versionKey, membersKey, detailsKey, eventsKey = KEYS[1], KEYS[2], KEYS[3], KEYS[4]
+local closed, maxEvents = ARGV[1], tonumber(ARGV[2])
+
+if redis.call('ZCARD', membersKey) == 0 then
+    return 0
+end
+redis.call('DEL', membersKey, detailsKey)
+local version = redis.call('INCR', versionKey)
+local decoded = cjson.decode(closed)
+decoded['version'] = version
+log_event(eventsKey, '{"version":' .. version .. ',"closed":' .. cjson.encode(decoded) .. '}', maxEvents)
+return version
+"#;
+
+/// Forgets connections whose lease expired and returns the summaries of the live ones.
+const LIVE_CONNECTIONS_SCRIPT: &str = r#"
+local leasesKey, detailsKey = KEYS[1], KEYS[2]
+for _, connectionId in ipairs(redis.call('ZRANGEBYSCORE', leasesKey, '-inf', ARGV[1])) do
+    redis.call('ZREM', leasesKey, connectionId)
+    redis.call('HDEL', detailsKey, connectionId)
+end
+return redis.call('HVALS', detailsKey)
+"#;
+
+/// Counts a direct message as unread unless the recipient holds a live connection lease.
+const RECORD_DIRECT_MESSAGE_SCRIPT: &str = r#"
+local connectionsKey, unreadKey = KEYS[1], KEYS[2]
+local senderId, now, ttl = ARGV[1], ARGV[2], tonumber(ARGV[3])
+
+redis.call('ZREMRANGEBYSCORE', connectionsKey, '-inf', now)
+if redis.call('ZCARD', connectionsKey) > 0 then
+    return 1
+end
+redis.call('HINCRBY', unreadKey, senderId, 1)
+redis.call('EXPIRE', unreadKey, ttl)
+return 0
+"#;
+
+/// Version, joined flag, pruned version, expired, members, recent messages, message count, reads,
+/// threads, reactions.
+type JoinReply = (
+    u64,
+    u8,
+    u64,
+    Vec<String>,
+    Vec<String>,
+    Vec<String>,
+    u64,
+    Vec<String>,
+    Vec<String>,
+    Vec<String>,
+);
+/// Version, pruned version, expired, members, recent messages, message count, reads, threads,
+/// reactions.
+type SnapshotReply =
+    (u64, u64, Vec<String>, Vec<String>, Vec<String>, u64, Vec<String>, Vec<String>, Vec<String>);
+
+/// A room event fanned out to every instance, which delivers it to its local room members.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatBackplaneEnvelope {
+    pub room_id: String,
+    pub event: ChatEvent,
+    /// Connection that already got its own reply, e.g. the joiner of a presence change.
+    pub exclude_connection_id: Option<String>,
+}
+
+/// An admin's request to close a connection, fanned out to every instance; the one holding the
+/// connection closes it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatDisconnectEnvelope {
+    pub connection_id: String,
+    pub reason: Option<String>,
+}
+
+/// A direct message fanned out to every instance, which delivers it to the user's connections.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatDirectEnvelope {
+    pub user_id: String,
+    pub event: ChatEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatBackplaneJoin {
+    pub snapshot: ChatRoomSnapshot,
+    pub joined_newly: bool,
+    pub expired: Option<ChatPresenceChange>,
+}
+
+/// A member connection removed from a room; `presence` is set when it was the user's last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatBackplaneLeave {
+    pub version: u64,
+    pub presence: Option<ChatPresenceChange>,
+}
+
+/// Redis backed room state shared by all API instances: versions, member leases and recent
+/// messages live in Redis, and room events are fanned out over pub/sub.
+#[derive(Debug)]
+pub struct ChatBackplane {
+    client: redis::Client,
+    connection: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl ChatBackplane {
+    pub fn new(client: redis::Client) -> Self {
+        Self { client, connection: Mutex::default() }
+    }
+
+    pub async fn join_room(
+        &self,
+        room_id: &str,
+        connection_id: &str,
+        member: &ChatUserSummary,
+        version_floor: u64,
+    ) -> anyhow::Result<ChatBackplaneJoin> {
+        let now = Utc::now().timestamp_millis();
+        let script = Script::new(&format!(
+            "{LOG_ROOM_EVENT}{USER_CONNECTIONS}{PRUNE_EXPIRED_MEMBERS}{JOIN_ROOM_SCRIPT}"
+        ));
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(members_key(room_id))
+            .key(details_key(room_id))
+            .key(recent_key(room_id))
+            .key(message_count_key(room_id))
+            .key(reads_key(room_id))
+            .key(events_key(room_id))
+            .key(threads_key(room_id))
+            .key(reactions_key(room_id))
+            .arg(connection_id)
+            .arg(serde_json::to_string(member)?)
+            .arg(now)
+            .arg(now + MEMBER_TTL.as_millis() as i64)
+            .arg(version_floor)
+            .arg(ROOM_KEY_TTL_SECONDS)
+            .arg(&member.user_id)
+            .arg(MAX_ROOM_EVENTS);
+        let reply: JoinReply = self.invoke(&invocation).await?;
+        let (
+            version,
+            joined,
+            pruned_version,
+            expired,
+            members,
+            recent,
+            message_count,
+            reads,
+            threads,
+            reactions,
+        ) = reply;
+
+        let reads = StoredReads { message_count, marks: read_marks(&reads) };
+        let mut snapshot =
+            room_snapshot(room_id, version, &members, &recent, &reads, Some(&member.user_id))?;
+        annotate_snapshot(&mut snapshot, &threads, &reactions)?;
+        Ok(ChatBackplaneJoin {
+            snapshot,
+            joined_newly: joined == 1,
+            expired: expired_presence(room_id, pruned_version, &expired)?,
+        })
+    }
+
+    /// Removes a member connection, `None` if it was not a member.
+    pub async fn leave_room(
+        &self,
+        room_id: &str,
+        connection_id: &str,
+        fallback: &ChatUserSummary,
+    ) -> anyhow::Result<Option<ChatBackplaneLeave>> {
+        let script = Script::new(&format!("{LOG_ROOM_EVENT}{USER_CONNECTIONS}{LEAVE_ROOM_SCRIPT}"));
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(members_key(room_id))
+            .key(details_key(room_id))
+            .key(events_key(room_id))
+            .arg(connection_id)
+            .arg(MAX_ROOM_EVENTS);
+        let (version, summary, left): (u64, String, u8) = self.invoke(&invocation).await?;
+        if version == 0 {
+            return Ok(None);
+        }
+
+        let presence = (left == 1).then(|| ChatPresenceChange {
+            room_id: room_id.to_string(),
+            joined_members: Vec::new(),
+            left_members: vec![serde_json::from_str(&summary).unwrap_or_else(|_| fallback.clone())],
+            version,
+        });
+        Ok(Some(ChatBackplaneLeave { version, presence }))
+    }
+
+    /// Sets the user's status in one room, `None` when it already had it or the user is gone.
+    pub async fn set_status(
+        &self,
+        room_id: &str,
+        user_id: &str,
+        status: ChatPresenceStatus,
+    ) -> anyhow::Result<Option<ChatStatusChange>> {
+        let script = Script::new(&format!("{LOG_ROOM_EVENT}{SET_STATUS_SCRIPT}"));
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(details_key(room_id))
+            .key(events_key(room_id))
+            .arg(room_id)
+            .arg(user_id)
+            .arg(status.as_str())
+            .arg(MAX_ROOM_EVENTS);
+        let version: u64 = self.invoke(&invocation).await?;
+
+        Ok((version > 0).then(|| ChatStatusChange {
+            room_id: room_id.to_string(),
+            user_id: user_id.to_string(),
+            status,
+            version,
+        }))
+    }
+
+    /// The status the user last set, online when none is stored.
+    pub async fn user_status(&self, user_id: &str) -> anyhow::Result<ChatPresenceStatus> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<Option<String>> = redis::cmd("GET")
+            .arg(user_status_key(user_id))
+            .query_async(&mut conn)
+            .await;
+        self.check(result)
+            .await?
+            .map(|stored| serde_json::from_str(&stored).context("invalid chat user status"))
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    /// Stores the user's status for joins on any instance, until their last connection closes.
+    pub async fn set_user_status(
+        &self,
+        user_id: &str,
+        status: ChatPresenceStatus,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = redis::cmd("SET")
+            .arg(user_status_key(user_id))
+            .arg(serde_json::to_string(&status)?)
+            .arg("EX")
+            .arg(ROOM_KEY_TTL_SECONDS)
+            .query_async(&mut conn)
+            .await;
+        self.check(result).await
+    }
+
+    /// Takes every connection of `user_id` out of the room, `None` when it had none in it.
+    pub async fn remove_user(
+        &self,
+        room_id: &str,
+        user_id: &str,
+    ) -> anyhow::Result<Option<ChatPresenceChange>> {
+        let script = Script::new(&format!("{LOG_ROOM_EVENT}{REMOVE_USER_SCRIPT}"));
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(members_key(room_id))
+            .key(details_key(room_id))
+            .key(events_key(room_id))
+            .arg(user_id)
+            .arg(MAX_ROOM_EVENTS);
+        let (version, removed): (u64, Vec<String>) = self.invoke(&invocation).await?;
+
+        expired_presence(room_id, version, &removed)
+    }
+
+    pub async fn room_access(&self, room_id: &str) -> anyhow::Result<Option<ChatRoomAccess>> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<Option<String>> = redis::cmd("GET")
+            .arg(access_key(room_id))
+            .query_async(&mut conn)
+            .await;
+        self.check(result)
+            .await?
+            .map(|stored| serde_json::from_str(&stored).context("invalid chat room access"))
+            .transpose()
+    }
+
+    /// The room's access rules, stored as `default` when the room has none yet.
+    pub async fn ensure_room_access(
+        &self,
+        room_id: &str,
+        default: &ChatRoomAccess,
+    ) -> anyhow::Result<ChatRoomAccess> {
+        let script = Script::new(ENSURE_ROOM_ACCESS_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(access_key(room_id))
+            .arg(serde_json::to_string(default)?);
+        let stored: String = self.invoke(&invocation).await?;
+        serde_json::from_str(&stored).context("invalid chat room access")
+    }
+
+    /// Stores the access rules of a new room, false when the room already has some.
+    pub async fn create_room_access(
+        &self,
+        room_id: &str,
+        access: &ChatRoomAccess,
+    ) -> anyhow::Result<bool> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<Option<String>> = redis::cmd("SET")
+            .arg(access_key(room_id))
+            .arg(serde_json::to_string(access)?)
+            .arg("NX")
+            .query_async(&mut conn)
+            .await;
+        Ok(self.check(result).await?.is_some())
+    }
+
+    /// Read-modify-write of the access rules, retried when another instance changed them in
+    /// between. The outer error is a backplane failure, the inner one a rejected change.
+    pub async fn update_room_access(
+        &self,
+        room_id: &str,
+        mut update: impl FnMut(&mut ChatRoomAccess) -> Result<(), ChatError>,
+    ) -> anyhow::Result<Result<ChatRoomAccess, ChatError>> {
+        let script = Script::new(UPDATE_ROOM_ACCESS_SCRIPT);
+        for _ in 0..ACCESS_UPDATE_ATTEMPTS {
+            let mut conn = self.connection().await?;
+            let result: redis::RedisResult<Option<String>> = redis::cmd("GET")
+                .arg(access_key(room_id))
+                .query_async(&mut conn)
+                .await;
+            let Some(current) = self.check(result).await? else {
+                return Ok(Err(ChatError::NotRoomModerator));
+            };
+            let mut access: ChatRoomAccess =
+                serde_json::from_str(&current).context("invalid chat room access")?;
+            if let Err(error) = update(&mut access) {
+                return Ok(Err(error));
+            }
+
+            let mut invocation = script.prepare_invoke();
+            invocation
+                .key(access_key(room_id))
+                .arg(&current)
+                .arg(serde_json::to_string(&access)?);
+            let updated: bool = self.invoke(&invocation).await?;
+            if updated {
+                return Ok(Ok(access));
+            }
+        }
+
+        anyhow::bail!("chat room access kept changing concurrently")
+    }
+
+    /// Assigns the next room version to a message and appends it to the shared recent list.
+    /// Replies to a message outside the recent window need its `stored` copy. The outer error is
+    /// a backplane failure, the inner one a rejected message.
+    pub async fn append_message(
+        &self,
+        connection_id: &str,
+        message: &ChatRoomMessage,
+        stored: Option<&ChatRoomMessage>,
+    ) -> anyhow::Result<Result<ChatRoomMessage, ChatError>> {
+        let script = Script::new(&format!("{LOG_ROOM_EVENT}{APPEND_MESSAGE_SCRIPT}"));
+        let stored = stored.map(serde_json::to_string).transpose()?;
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(&message.room_id))
+            .key(members_key(&message.room_id))
+            .key(recent_key(&message.room_id))
+            .key(message_count_key(&message.room_id))
+            .key(reads_key(&message.room_id))
+            .key(events_key(&message.room_id))
+            .key(threads_key(&message.room_id))
+            .arg(connection_id)
+            .arg(serde_json::to_string(message)?)
+            .arg(MAX_RECENT_MESSAGES)
+            .arg(ROOM_KEY_TTL_SECONDS)
+            .arg(&message.sender_id)
+            .arg(MAX_ROOM_EVENTS)
+            .arg(stored.unwrap_or_default())
+            .arg(MAX_THREADS);
+        let reply: Vec<String> = self.invoke(&invocation).await?;
+
+        match reply.as_slice() {
+            [status, stored] if status == "ok" => {
+                Ok(Ok(serde_json::from_str(stored).context("invalid stored chat message")?))
+            }
+            [status] if status == "not_found" => Ok(Err(ChatError::MessageNotFound {
+                message_id: message.reply_to.clone().unwrap_or_default(),
+            })),
+            _ => Ok(Err(ChatError::NotInRoom { room_id: message.room_id.clone() })),
+        }
+    }
+
+    /// Keeps a flagged message for the room's moderators.
+    pub async fn flag_message(&self, flagged: &ChatFlaggedMessage) -> anyhow::Result<()> {
+        let key = flagged_key(&flagged.message.room_id);
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = redis::pipe()
+            .atomic()
+            .cmd("LPUSH")
+            .arg(&key)
+            .arg(serde_json::to_string(flagged)?)
+            .ignore()
+            .cmd("LTRIM")
+            .arg(&key)
+            .arg(0)
+            .arg(MAX_FLAGGED_MESSAGES - 1)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(ROOM_KEY_TTL_SECONDS)
+            .ignore()
+            .query_async(&mut conn)
+            .await;
+        self.check(result).await
+    }
+
+    /// Flagged messages of a room, the newest first.
+    pub async fn flagged_messages(&self, room_id: &str) -> anyhow::Result<Vec<ChatFlaggedMessage>> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<Vec<String>> = redis::cmd("LRANGE")
+            .arg(flagged_key(room_id))
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut conn)
+            .await;
+        self.check(result)
+            .await?
+            .iter()
+            .map(|flagged| serde_json::from_str(flagged))
+            .collect::<Result<Vec<_>, _>>()
+            .context("invalid flagged chat message")
+    }
+
+    /// Applies an edit or delete under a new room version. The outer error is a backplane
+    /// failure, the inner one a rejected change.
+    pub async fn modify_message(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        message_id: &str,
+        change: &ChatMessageChange,
+        stored: Option<&ChatRoomMessage>,
+        moderator: bool,
+    ) -> anyhow::Result<Result<ChatMessageModified, ChatError>> {
+        let script = Script::new(&format!("{LOG_ROOM_EVENT}{MODIFY_MESSAGE_SCRIPT}"));
+        let content = match change {
+            ChatMessageChange::Edit { content } => content.as_str(),
+            ChatMessageChange::Delete => "",
+        };
+        let mentions = extract_mentions(content);
+        let mentions =
+            if mentions.is_empty() { String::new() } else { serde_json::to_string(&mentions)? };
+        let stored = stored.map(serde_json::to_string).transpose()?;
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(members_key(room_id))
+            .key(recent_key(room_id))
+            .key(events_key(room_id))
+            .key(threads_key(room_id))
+            .key(reactions_key(room_id))
+            .arg(&session_user.connection_id)
+            .arg(&session_user.user_id)
+            .arg(message_id)
+            .arg(change.as_str())
+            .arg(content)
+            .arg(Utc::now().timestamp())
+            .arg(stored.unwrap_or_default())
+            .arg(MAX_ROOM_EVENTS)
+            .arg(if moderator { "1" } else { "0" })
+            .arg(mentions);
+        let reply: Vec<String> = self.invoke(&invocation).await?;
+
+        match reply.as_slice() {
+            [status, message, event] if status == "ok" => {
+                let message: ChatRoomMessage =
+                    serde_json::from_str(message).context("invalid stored chat message")?;
+                let event = match change {
+                    ChatMessageChange::Edit { .. } => ChatEvent::MessageEdited(
+                        serde_json::from_str(event).context("invalid chat message edit")?,
+                    ),
+                    ChatMessageChange::Delete => ChatEvent::MessageDeleted(
+                        serde_json::from_str(event).context("invalid chat message delete")?,
+                    ),
+                };
+                Ok(Ok(ChatMessageModified { message, event }))
+            }
+            [status] if status == "not_in_room" => {
+                Ok(Err(ChatError::NotInRoom { room_id: room_id.to_string() }))
+            }
+            [status] if status == "forbidden" => Ok(Err(ChatError::Forbidden)),
+            _ => Ok(Err(ChatError::MessageNotFound { message_id: message_id.to_string() })),
+        }
+    }
+
+    /// Adds or removes a reaction under a new room version, `None` when nothing changed. The outer
+    /// error is a backplane failure, the inner one a rejected reaction.
+    pub async fn react(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        message_id: &str,
+        emoji: &str,
+        added: bool,
+        stored: Option<&ChatRoomMessage>,
+    ) -> anyhow::Result<Result<Option<ChatReactionChanged>, ChatError>> {
+        let script = Script::new(&format!("{LOG_ROOM_EVENT}{REACT_SCRIPT}"));
+        let stored = stored.map(serde_json::to_string).transpose()?;
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(members_key(room_id))
+            .key(recent_key(room_id))
+            .key(reactions_key(room_id))
+            .key(events_key(room_id))
+            .arg(&session_user.connection_id)
+            .arg(room_id)
+            .arg(&session_user.user_id)
+            .arg(message_id)
+            .arg(emoji)
+            .arg(if added { "1" } else { "0" })
+            .arg(stored.unwrap_or_default())
+            .arg(MAX_ROOM_EVENTS)
+            .arg(MAX_REACTIONS_PER_MESSAGE)
+            .arg(MAX_REACTED_MESSAGES)
+            .arg(ROOM_KEY_TTL_SECONDS);
+        let reply: Vec<String> = self.invoke(&invocation).await?;
+
+        match reply.as_slice() {
+            [status, changed] if status == "ok" => {
+                Ok(Ok(Some(serde_json::from_str(changed).context("invalid chat reaction change")?)))
+            }
+            [status] if status == "unchanged" => Ok(Ok(None)),
+            [status] if status == "not_in_room" => {
+                Ok(Err(ChatError::NotInRoom { room_id: room_id.to_string() }))
+            }
+            [status] if status == "too_many" => {
+                Ok(Err(ChatError::TooManyReactions { max_reactions: MAX_REACTIONS_PER_MESSAGE }))
+            }
+            _ => Ok(Err(ChatError::MessageNotFound { message_id: message_id.to_string() })),
+        }
+    }
+
+    /// Current room state; `unread_count` is computed for `user_id` when given.
+    pub async fn room_snapshot(
+        &self,
+        room_id: &str,
+        user_id: Option<&str>,
+    ) -> anyhow::Result<(ChatRoomSnapshot, Option<ChatPresenceChange>)> {
+        let script = Script::new(&format!(
+            "{LOG_ROOM_EVENT}{USER_CONNECTIONS}{PRUNE_EXPIRED_MEMBERS}{ROOM_SNAPSHOT_SCRIPT}"
+        ));
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(members_key(room_id))
+            .key(details_key(room_id))
+            .key(recent_key(room_id))
+            .key(message_count_key(room_id))
+            .key(reads_key(room_id))
+            .key(events_key(room_id))
+            .key(threads_key(room_id))
+            .key(reactions_key(room_id))
+            .arg(Utc::now().timestamp_millis())
+            .arg(MAX_ROOM_EVENTS);
+        let reply: SnapshotReply = self.invoke(&invocation).await?;
+        let (
+            version,
+            pruned_version,
+            expired,
+            members,
+            recent,
+            message_count,
+            reads,
+            threads,
+            reactions,
+        ) = reply;
+
+        let reads = StoredReads { message_count, marks: read_marks(&reads) };
+        let mut snapshot = room_snapshot(room_id, version, &members, &recent, &reads, user_id)?;
+        annotate_snapshot(&mut snapshot, &threads, &reactions)?;
+        Ok((snapshot, expired_presence(room_id, pruned_version, &expired)?))
+    }
+
+    /// The room's shared event log, read after a snapshot at `version`.
+    pub async fn room_event_log(
+        &self,
+        room_id: &str,
+        version: u64,
+    ) -> anyhow::Result<ChatRoomEventLog> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<Vec<String>> = redis::cmd("LRANGE")
+            .arg(events_key(room_id))
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut conn)
+            .await;
+        let entries = self
+            .check(result)
+            .await?
+            .iter()
+            .map(|entry| {
+                serde_json::from_str::<StoredRoomEvent>(entry)
+                    .map(|stored| stored.into_event(room_id))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .context("invalid stored chat room event")?;
+        Ok(ChatRoomEventLog::from_entries(entries, version))
+    }
+
+    /// Moves the user's read position forward, `None` when it did not advance.
+    pub async fn mark_read(
+        &self,
+        room_id: &str,
+        user_id: &str,
+        version: u64,
+    ) -> anyhow::Result<Option<ChatReadReceipt>> {
+        let script = Script::new(MARK_READ_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(recent_key(room_id))
+            .key(message_count_key(room_id))
+            .key(reads_key(room_id))
+            .arg(user_id)
+            .arg(version);
+        let version: Option<u64> = self.invoke(&invocation).await?;
+
+        Ok(version.map(|version| ChatReadReceipt {
+            room_id: room_id.to_string(),
+            user_id: user_id.to_string(),
+            version,
+        }))
+    }
+
+    /// Extends the leases of members connected to this instance, pruned members stay gone.
+    pub async fn refresh_members(&self, memberships: &[(String, String)]) -> anyhow::Result<()> {
+        if memberships.is_empty() {
+            return Ok(());
+        }
+
+        let expires_at = Utc::now().timestamp_millis() + MEMBER_TTL.as_millis() as i64;
+        let mut pipe = redis::pipe();
+        for (room_id, connection_id) in memberships {
+            pipe.cmd("ZADD")
+                .arg(members_key(room_id))
+                .arg("XX")
+                .arg(expires_at)
+                .arg(connection_id)
+                .ignore();
+        }
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = pipe.query_async(&mut conn).await;
+        self.check(result).await
+    }
+
+    /// Leases a user connection so other instances know the user is online and admins can
+    /// list it.
+    pub async fn register_user_connection(
+        &self,
+        summary: &ChatConnectionSummary,
+    ) -> anyhow::Result<()> {
+        let expires_at = Utc::now().timestamp_millis() + MEMBER_TTL.as_millis() as i64;
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = redis::pipe()
+            .cmd("ZADD")
+            .arg(user_connections_key(&summary.user_id))
+            .arg(expires_at)
+            .arg(&summary.connection_id)
+            .ignore()
+            .cmd("PEXPIRE")
+            .arg(user_connections_key(&summary.user_id))
+            .arg(MEMBER_TTL.as_millis() as u64)
+            .ignore()
+            .cmd("ZADD")
+            .arg(CONNECTION_LEASES_KEY)
+            .arg(expires_at)
+            .arg(&summary.connection_id)
+            .ignore()
+            .cmd("HSET")
+            .arg(CONNECTION_DETAILS_KEY)
+            .arg(&summary.connection_id)
+            .arg(serde_json::to_string(summary)?)
+            .ignore()
+            .query_async(&mut conn)
+            .await;
+        self.check(result).await
+    }
+
+    pub async fn unregister_user_connection(
+        &self,
+        user_id: &str,
+        connection_id: &str,
+    ) -> anyhow::Result<()> {
+        let script = Script::new(UNREGISTER_USER_CONNECTION_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(user_connections_key(user_id))
+            .key(user_status_key(user_id))
+            .arg(connection_id)
+            .arg(Utc::now().timestamp_millis());
+        self.invoke::<()>(&invocation).await?;
+
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = redis::pipe()
+            .cmd("ZREM")
+            .arg(CONNECTION_LEASES_KEY)
+            .arg(connection_id)
+            .ignore()
+            .cmd("HDEL")
+            .arg(CONNECTION_DETAILS_KEY)
+            .arg(connection_id)
+            .ignore()
+            .query_async(&mut conn)
+            .await;
+        self.check(result).await
+    }
+
+    /// Summaries of the live connections of every instance.
+    pub async fn live_connections(&self) -> anyhow::Result<Vec<ChatConnectionSummary>> {
+        let script = Script::new(LIVE_CONNECTIONS_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(CONNECTION_LEASES_KEY)
+            .key(CONNECTION_DETAILS_KEY)
+            .arg(Utc::now().timestamp_millis());
+        let summaries: Vec<String> = self.invoke(&invocation).await?;
+        summaries
+            .iter()
+            .map(|summary| serde_json::from_str(summary))
+            .collect::<Result<Vec<_>, _>>()
+            .context("invalid chat connection summary")
+    }
+
+    /// Whether any instance holds a live lease for the connection.
+    pub async fn connection_exists(&self, connection_id: &str) -> anyhow::Result<bool> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<Option<i64>> = redis::cmd("ZSCORE")
+            .arg(CONNECTION_LEASES_KEY)
+            .arg(connection_id)
+            .query_async(&mut conn)
+            .await;
+        let expires_at = self.check(result).await?;
+        Ok(expires_at.is_some_and(|expires_at| expires_at > Utc::now().timestamp_millis()))
+    }
+
+    /// Records activity in a room for the directory's ordering.
+    pub async fn touch_room(&self, room_id: &str) -> anyhow::Result<()> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = redis::cmd("ZADD")
+            .arg(ROOM_INDEX_KEY)
+            .arg(Utc::now().timestamp())
+            .arg(room_id)
+            .query_async(&mut conn)
+            .await;
+        self.check(result).await
+    }
+
+    /// Up to `limit` of the most recently active rooms that still have members, with their
+    /// access rules. Rooms idle for longer than room state is kept are dropped from the index.
+    pub async fn room_directory(
+        &self,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(ChatRoomSummary, Option<ChatRoomAccess>)>> {
+        let mut conn = self.connection().await?;
+        let stale_before = Utc::now().timestamp() - ROOM_KEY_TTL_SECONDS as i64;
+        let result: redis::RedisResult<Vec<(String, i64)>> = redis::pipe()
+            .cmd("ZREMRANGEBYSCORE")
+            .arg(ROOM_INDEX_KEY)
+            .arg("-inf")
+            .arg(stale_before)
+            .ignore()
+            .cmd("ZREVRANGE")
+            .arg(ROOM_INDEX_KEY)
+            .arg(0)
+            .arg(limit.saturating_sub(1))
+            .arg("WITHSCORES")
+            .query_async::<(Vec<(String, i64)>,)>(&mut conn)
+            .await
+            .map(|(rooms,)| rooms);
+        let indexed = self.check(result).await?;
+        if indexed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut pipe = redis::pipe();
+        for (room_id, _) in &indexed {
+            pipe.cmd("HVALS")
+                .arg(details_key(room_id))
+                .cmd("GET")
+                .arg(access_key(room_id));
+        }
+        let result: redis::RedisResult<Vec<redis::Value>> = pipe.query_async(&mut conn).await;
+        let replies = self.check(result).await?;
+
+        let mut rooms = Vec::new();
+        let mut replies = replies.into_iter();
+        for (room_id, last_activity_at) in indexed {
+            let (Some(members), Some(access)) = (replies.next(), replies.next()) else {
+                break;
+            };
+            let members: Vec<String> = redis::from_redis_value(members)?;
+            let members = members
+                .iter()
+                .map(|member| serde_json::from_str(member))
+                .collect::<Result<Vec<ChatUserSummary>, _>>()
+                .context("invalid chat member summary")?;
+            if members.is_empty() {
+                continue;
+            }
+            let access: Option<String> = redis::from_redis_value(access)?;
+            let access = access
+                .map(|stored| serde_json::from_str::<ChatRoomAccess>(&stored))
+                .transpose()
+                .context("invalid chat room access")?;
+            rooms.push((
+                ChatRoomSummary {
+                    room_id,
+                    member_count: member_count(&members),
+                    last_activity_at,
+                    private: access.as_ref().is_some_and(|access| access.private),
+                },
+                access,
+            ));
+        }
+        Ok(rooms)
+    }
+
+    /// Takes every member out of the room, `None` when it had none.
+    pub async fn close_room(
+        &self,
+        room_id: &str,
+        reason: Option<String>,
+    ) -> anyhow::Result<Option<ChatRoomClosed>> {
+        let closed = ChatRoomClosed { room_id: room_id.to_string(), reason, version: 0 };
+        let script = Script::new(&format!("{LOG_ROOM_EVENT}{CLOSE_ROOM_SCRIPT}"));
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(version_key(room_id))
+            .key(members_key(room_id))
+            .key(details_key(room_id))
+            .key(events_key(room_id))
+            .arg(serde_json::to_string(&closed)?)
+            .arg(MAX_ROOM_EVENTS);
+        let version: u64 = self.invoke(&invocation).await?;
+        if version == 0 {
+            return Ok(None);
+        }
+
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = redis::cmd("ZREM")
+            .arg(ROOM_INDEX_KEY)
+            .arg(room_id)
+            .query_async(&mut conn)
+            .await;
+        self.check(result).await?;
+        Ok(Some(ChatRoomClosed { version, ..closed }))
+    }
+
+    /// Extends the leases of user connections on this instance.
+    pub async fn refresh_user_connections(
+        &self,
+        connections: &[(String, String)],
+    ) -> anyhow::Result<()> {
+        if connections.is_empty() {
+            return Ok(());
+        }
+
+        let expires_at = Utc::now().timestamp_millis() + MEMBER_TTL.as_millis() as i64;
+        let mut pipe = redis::pipe();
+        for (user_id, connection_id) in connections {
+            pipe.cmd("ZADD")
+                .arg(user_connections_key(user_id))
+                .arg(expires_at)
+                .arg(connection_id)
+                .ignore()
+                .cmd("PEXPIRE")
+                .arg(user_connections_key(user_id))
+                .arg(MEMBER_TTL.as_millis() as u64)
+                .ignore()
+                .cmd("ZADD")
+                .arg(CONNECTION_LEASES_KEY)
+                .arg("XX")
+                .arg(expires_at)
+                .arg(connection_id)
+                .ignore();
+        }
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = pipe.query_async(&mut conn).await;
+        self.check(result).await
+    }
+
+    /// Returns whether the recipient is online; offline recipients get the message counted as
+    /// unread from `sender_id`.
+    pub async fn record_direct_message(
+        &self,
+        recipient_id: &str,
+        sender_id: &str,
+    ) -> anyhow::Result<bool> {
+        let script = Script::new(RECORD_DIRECT_MESSAGE_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(user_connections_key(recipient_id))
+            .key(direct_unread_key(recipient_id))
+            .arg(sender_id)
+            .arg(Utc::now().timestamp_millis())
+            .arg(UNREAD_KEY_TTL_SECONDS);
+        let online: u8 = self.invoke(&invocation).await?;
+        Ok(online == 1)
+    }
+
+    pub async fn mark_direct_read(&self, user_id: &str, sender_id: &str) -> anyhow::Result<()> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = redis::cmd("HDEL")
+            .arg(direct_unread_key(user_id))
+            .arg(sender_id)
+            .query_async(&mut conn)
+            .await;
+        self.check(result).await
+    }
+
+    pub async fn direct_unread(&self, user_id: &str) -> anyhow::Result<ChatDirectUnread> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<Vec<(String, u64)>> = redis::cmd("HGETALL")
+            .arg(direct_unread_key(user_id))
+            .query_async(&mut conn)
+            .await;
+        Ok(direct_unread(self.check(result).await?))
+    }
+
+    pub async fn publish(&self, envelope: &ChatBackplaneEnvelope) -> anyhow::Result<()> {
+        let payload = serde_json::to_string(envelope)?;
+        self.publish_payload(&channel(&envelope.room_id), payload)
+            .await
+    }
+
+    pub async fn publish_direct(&self, envelope: &ChatDirectEnvelope) -> anyhow::Result<()> {
+        let payload = serde_json::to_string(envelope)?;
+        self.publish_payload(&direct_channel(&envelope.user_id), payload)
+            .await
+    }
+
+    pub async fn publish_disconnect(
+        &self,
+        envelope: &ChatDisconnectEnvelope,
+    ) -> anyhow::Result<()> {
+        let payload = serde_json::to_string(envelope)?;
+        self.publish_payload(DISCONNECT_CHANNEL, payload).await
+    }
+
+    async fn publish_payload(&self, channel: &str, payload: String) -> anyhow::Result<()> {
+        let mut conn = self.connection().await?;
+        let result: redis::RedisResult<()> = redis::cmd("PUBLISH")
+            .arg(channel)
+            .arg(payload)
+            .query_async(&mut conn)
+            .await;
+        self.check(result).await
+    }
+
+    async fn invoke<T: redis::FromRedisValue>(
+        &self,
+        invocation: &redis::ScriptInvocation<'_>,
+    ) -> anyhow::Result<T> {
+        let mut conn = self.connection().await?;
+        let result = invocation.invoke_async(&mut conn).await;
+        self.check(result).await
+    }
+
+    async fn connection(&self) -> anyhow::Result<MultiplexedConnection> {
+        let mut cached = self.connection.lock().await;
+        if let Some(conn) = cached.as_ref() {
+            return Ok(conn.clone());
+        }
+
+        let conn = self
+            .client
+            .get_multiplexed_async_connection()
+            .await
+            .context("redis is unreachable")?;
+        *cached = Some(conn.clone());
+        Ok(conn)
+    }
+
+    /// Drops the cached connection after I/O failures so the next call reconnects.
+    async fn check<T>(&self, result: redis::RedisResult<T>) -> anyhow::Result<T> {
+        if let Err(error) = &result
+            && (error.is_io_error() || error.is_connection_dropped())
+        {
+            self.connection.lock().await.take();
+        }
+        Ok(result?)
+    }
+}
+
+/// Runs the pub/sub subscriber and member lease refresher for `state` until the process exits.
+/// Events published while the subscriber reconnects are lost, clients recover with a room sync.
+pub fn spawn(state: Arc<ChatState>, client: redis::Client) {
+    let subscriber_state = state.clone();
+    tokio::spawn(async move {
+        loop {
+            if let Err(error) = subscribe(&subscriber_state, &client).await {
+                warn!(error = %error, "chat backplane subscription failed, resubscribing");
+            }
+            tokio::time::sleep(RESUBSCRIBE_BACKOFF).await;
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(MEMBER_REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(backplane) = state.backplane.as_ref() else {
+                return;
+            };
+            let memberships = state.local_memberships().await;
+            if let Err(error) = backplane.refresh_members(&memberships).await {
+                warn!(error = %error, "failed to refresh chat member leases");
+            }
+            let connections = state.local_user_connections().await;
+            if let Err(error) = backplane.refresh_user_connections(&connections).await {
+                warn!(error = %error, "failed to refresh chat user leases");
+            }
+        }
+    });
+}
+
+pub fn channel(room_id: &str) -> String {
+    format!("{CHANNEL_PREFIX}{room_id}")
+}
+
+pub fn direct_channel(user_id: &str) -> String {
+    format!("{DIRECT_CHANNEL_PREFIX}{user_id}")
+}
+
+async fn subscribe(state: &ChatState, client: &redis::Client) -> anyhow::Result<()> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.psubscribe(format!("{CHANNEL_PREFIX}*")).await?;
+    pubsub
+        .psubscribe(format!("{DIRECT_CHANNEL_PREFIX}*"))
+        .await?;
+    pubsub.subscribe(DISCONNECT_CHANNEL).await?;
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = match message.get_payload() {
+            Ok(payload) => payload,
+            Err(error) => {
+                warn!(error = %error, "invalid chat backplane payload");
+                continue;
+            }
+        };
+        if message.get_channel_name() == DISCONNECT_CHANNEL {
+            match serde_json::from_str::<ChatDisconnectEnvelope>(&payload) {
+                Ok(envelope) => {
+                    state.close_local_connection(&envelope.connection_id, envelope.reason);
+                }
+                Err(error) => warn!(error = %error, "failed to decode chat disconnect request"),
+            }
+            continue;
+        }
+        if message
+            .get_channel_name()
+            .starts_with(DIRECT_CHANNEL_PREFIX)
+        {
+            match serde_json::from_str::<ChatDirectEnvelope>(&payload) {
+                Ok(envelope) => state.deliver_direct_local(envelope).await,
+                Err(error) => warn!(error = %error, "failed to decode direct chat event"),
+            }
+            continue;
+        }
+        match serde_json::from_str::<ChatBackplaneEnvelope>(&payload) {
+            Ok(envelope) => state.deliver_local(envelope).await,
+            Err(error) => warn!(error = %error, "failed to decode chat backplane event"),
+        }
+    }
+
+    anyhow::bail!("chat backplane subscription closed")
+}
+
+/// Keys of one room share a hash tag so the scripts stay on a single cluster slot.
+fn version_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:version")
+}
+
+fn members_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:members")
+}
+
+fn details_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:member-details")
+}
+
+fn recent_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:recent")
+}
+
+fn events_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:events")
+}
+
+fn message_count_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:message-count")
+}
+
+fn reads_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:reads")
+}
+
+fn threads_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:threads")
+}
+
+fn reactions_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:reactions")
+}
+
+fn flagged_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:flagged")
+}
+
+fn access_key(room_id: &str) -> String {
+    format!("chat:{{{room_id}}}:access")
+}
+
+#[derive(Debug, Default)]
+struct StoredReads {
+    message_count: u64,
+    marks: BTreeMap<String, ChatReadMark>,
+}
+
+/// Parses a flattened `HGETALL` of `user_id -> "version:message_count"`, skipping bad entries.
+pub fn read_marks(fields: &[String]) -> BTreeMap<String, ChatReadMark> {
+    fields
+        .chunks_exact(2)
+        .filter_map(|pair| {
+            let (version, message_count) = pair[1].split_once(':')?;
+            let mark = ChatReadMark {
+                version: version.parse().ok()?,
+                message_count: message_count.parse().ok()?,
+            };
+            Some((pair[0].clone(), mark))
+        })
+        .collect()
+}
+
+/// Keys of one user share a hash tag so the direct message script stays on one cluster slot.
+fn user_connections_key(user_id: &str) -> String {
+    format!("chat:user:{{{user_id}}}:connections")
+}
+
+fn direct_unread_key(user_id: &str) -> String {
+    format!("chat:user:{{{user_id}}}:direct-unread")
+}
+
+fn user_status_key(user_id: &str) -> String {
+    format!("chat:user:{{{user_id}}}:status")
+}
+
+fn room_snapshot(
+    room_id: &str,
+    version: u64,
+    members: &[String],
+    recent: &[String],
+    reads: &StoredReads,
+    user_id: Option<&str>,
+) -> anyhow::Result<ChatRoomSnapshot> {
+    let members = members
+        .iter()
+        .map(|member| serde_json::from_str(member))
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid chat member summary")?;
+    let recent_messages = recent
+        .iter()
+        .map(|message| serde_json::from_str(message))
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid stored chat message")?;
+
+    let members = sort_members(members);
+    Ok(ChatRoomSnapshot {
+        room_id: room_id.to_string(),
+        read_positions: read_positions(&members, &reads.marks),
+        unread_count: user_id
+            .map(|user_id| unread_count(user_id, reads.message_count, &reads.marks))
+            .unwrap_or_default(),
+        members,
+        recent_messages,
+        version,
+        delta_unavailable: false,
+        owner_id: None,
+        moderator_ids: Vec::new(),
+        private: false,
+        threads: Vec::new(),
+        reactions: Vec::new(),
+    })
+}
+
+/// Adds the thread summaries and message reactions stored as `HVALS` of their hashes.
+fn annotate_snapshot(
+    snapshot: &mut ChatRoomSnapshot,
+    threads: &[String],
+    reactions: &[String],
+) -> anyhow::Result<()> {
+    let mut threads = threads
+        .iter()
+        .map(|thread| serde_json::from_str::<ChatThreadSummary>(thread))
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid stored chat thread summary")?;
+    threads.sort_by_key(|thread| thread.last_reply.version);
+    let mut reactions = reactions
+        .iter()
+        .map(|reactions| serde_json::from_str::<ChatMessageReactions>(reactions))
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid stored chat reactions")?;
+    reactions.sort_by_key(|reactions| reactions.message_version);
+
+    snapshot.threads = threads;
+    snapshot.reactions = reactions;
+    Ok(())
+}
+
+fn expired_presence(
+    room_id: &str,
+    version: u64,
+    expired: &[String],
+) -> anyhow::Result<Option<ChatPresenceChange>> {
+    if expired.is_empty() {
+        return Ok(None);
+    }
+
+    let left_members = expired
+        .iter()
+        .map(|member| serde_json::from_str(member))
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid chat member summary")?;
+    Ok(Some(ChatPresenceChange {
+        room_id: room_id.to_string(),
+        joined_members: Vec::new(),
+        left_members: sort_members(left_members),
+        version,
+    }))
+}
@@ -0,0 +1,55 @@
+use anyhow::{Context, bail};
+use axum::extract::ws::Message;
+
+use super::{ChatCommand, ChatEvent, auth::CHAT_PROTOCOL};
+
+/// Subprotocol for JSON text frames, the same wire format as `axes.chat.v1`.
+pub const CHAT_JSON_PROTOCOL: &str = "axes.chat.json";
+/// Subprotocol for MessagePack binary frames, for clients that need smaller payloads.
+pub const CHAT_MSGPACK_PROTOCOL: &str = "axes.chat.msgpack";
+
+/// Subprotocols the server accepts, most preferred first.
+pub const CHAT_PROTOCOLS: [&str; 3] = [CHAT_MSGPACK_PROTOCOL, CHAT_JSON_PROTOCOL, CHAT_PROTOCOL];
+
+/// How events and commands are framed on one socket, picked from the negotiated subprotocol.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChatWireFormat {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl ChatWireFormat {
+    /// JSON unless MessagePack was negotiated.
+    pub fn from_protocol(protocol: Option<&str>) -> Self {
+        match protocol {
+            Some(CHAT_MSGPACK_PROTOCOL) => Self::MessagePack,
+            _ => Self::Json,
+        }
+    }
+
+    /// JSON goes out as text frames, MessagePack as binary frames with named fields.
+    pub fn encode(self, event: &ChatEvent) -> anyhow::Result<Message> {
+        match self {
+            Self::Json => Ok(Message::Text(serde_json::to_string(event)?.into())),
+            Self::MessagePack => Ok(Message::Binary(rmp_serde::to_vec_named(event)?.into())),
+        }
+    }
+
+    /// Text frames are always JSON so clients can fall back to it for debugging; binary frames
+    /// use the negotiated format.
+    pub fn decode(self, frame: &Message) -> anyhow::Result<ChatCommand> {
+        match (self, frame) {
+            (_, Message::Text(text)) => {
+                serde_json::from_str(text).context("invalid json websocket message")
+            }
+            (Self::Json, Message::Binary(bytes)) => {
+                serde_json::from_slice(bytes).context("invalid json websocket message")
+            }
+            (Self::MessagePack, Message::Binary(bytes)) => {
+                rmp_serde::from_slice(bytes).context("invalid msgpack websocket message")
+            }
+            _ => bail!("not a data frame"),
+        }
+    }
+}
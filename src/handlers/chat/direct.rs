@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::{
+    ChatError, ChatEvent, ChatSessionUser, ChatState, backplane::ChatDirectEnvelope,
+    backplane_error, normalized_content,
+};
+
+const MAX_USER_ID_LEN: usize = 128;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatDirectMessage {
+    pub message_id: String,
+    pub sender_id: String,
+    pub sender_name: String,
+    pub recipient_id: String,
+    pub content: String,
+    pub sent_at: i64,
+}
+
+/// Reply to the sending connection; `delivered` is false when the recipient had no live
+/// connection and the message only bumped their unread count.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatDirectMessageAck {
+    pub message_id: String,
+    pub to_user_id: String,
+    pub delivered: bool,
+    pub sent_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatDirectConversation {
+    pub user_id: String,
+    pub unread_count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct ChatDirectUnread {
+    pub conversations: Vec<ChatDirectConversation>,
+}
+
+/// Direct messages that reached a user while they were offline, per sender.
+#[derive(Debug, Default)]
+pub struct ChatDirectUnreadCounts {
+    counts: HashMap<String, BTreeMap<String, u64>>,
+}
+
+impl ChatDirectUnreadCounts {
+    pub fn increment(&mut self, recipient_id: &str, sender_id: &str) {
+        *self
+            .counts
+            .entry(recipient_id.to_string())
+            .or_default()
+            .entry(sender_id.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn mark_read(&mut self, recipient_id: &str, sender_id: &str) {
+        if let Some(conversations) = self.counts.get_mut(recipient_id) {
+            conversations.remove(sender_id);
+            if conversations.is_empty() {
+                self.counts.remove(recipient_id);
+            }
+        }
+    }
+
+    pub fn summary(&self, recipient_id: &str) -> ChatDirectUnread {
+        direct_unread(
+            self.counts
+                .get(recipient_id)
+                .into_iter()
+                .flatten()
+                .map(|(user_id, count)| (user_id.clone(), *count)),
+        )
+    }
+}
+
+impl ChatState {
+    /// Delivers a direct message to every live connection of the recipient, on any instance when
+    /// the backplane is enabled, or counts it as unread when the recipient is offline.
+    pub(super) async fn send_direct_message(
+        &self,
+        session_user: &ChatSessionUser,
+        to_user_id: &str,
+        content: &str,
+    ) -> Result<(), ChatError> {
+        let to_user_id = normalized_user_id(to_user_id)?;
+        if to_user_id == session_user.user_id {
+            return Err(ChatError::InvalidRecipient);
+        }
+        let content = normalized_content(content)?;
+        let message = ChatDirectMessage {
+            message_id: Uuid::new_v4().to_string(),
+            sender_id: session_user.user_id.clone(),
+            sender_name: session_user.user_name.clone(),
+            recipient_id: to_user_id.clone(),
+            content,
+            sent_at: Utc::now().timestamp(),
+        };
+
+        let delivered = match &self.backplane {
+            Some(backplane) => {
+                let online = backplane
+                    .record_direct_message(&to_user_id, &session_user.user_id)
+                    .await
+                    .map_err(backplane_error)?;
+                if online {
+                    let envelope = ChatDirectEnvelope {
+                        user_id: to_user_id.clone(),
+                        event: ChatEvent::DirectMessage(message.clone()),
+                    };
+                    if let Err(error) = backplane.publish_direct(&envelope).await {
+                        warn!(error = %error, "failed to publish direct chat message");
+                    }
+                }
+                online
+            }
+            None => {
+                let mut connections = self.runtime.connections_mut();
+                let recipients = connections.user_connections(&to_user_id);
+                if recipients.is_empty() {
+                    connections
+                        .direct_unread
+                        .increment(&to_user_id, &session_user.user_id);
+                }
+                for recipient in &recipients {
+                    recipient.push(ChatEvent::DirectMessage(message.clone()));
+                }
+                !recipients.is_empty()
+            }
+        };
+
+        self.send_to_connection(
+            &session_user.connection_id,
+            ChatEvent::DirectMessageSent(ChatDirectMessageAck {
+                message_id: message.message_id,
+                to_user_id,
+                delivered,
+                sent_at: message.sent_at,
+            }),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Clears the unread count of one direct conversation and replies with what is left.
+    pub(super) async fn mark_direct_read(
+        &self,
+        session_user: &ChatSessionUser,
+        user_id: &str,
+    ) -> Result<(), ChatError> {
+        let user_id = normalized_user_id(user_id)?;
+        match &self.backplane {
+            Some(backplane) => backplane
+                .mark_direct_read(&session_user.user_id, &user_id)
+                .await
+                .map_err(backplane_error)?,
+            None => self
+                .runtime
+                .connections_mut()
+                .direct_unread
+                .mark_read(&session_user.user_id, &user_id),
+        }
+
+        self.send_direct_unread(session_user).await
+    }
+
+    /// Sends the user's unread direct conversations to one of their connections.
+    pub async fn send_direct_unread(
+        &self,
+        session_user: &ChatSessionUser,
+    ) -> Result<(), ChatError> {
+        let unread = match &self.backplane {
+            Some(backplane) => backplane
+                .direct_unread(&session_user.user_id)
+                .await
+                .map_err(backplane_error)?,
+            None => self
+                .runtime
+                .connections()
+                .direct_unread
+                .summary(&session_user.user_id),
+        };
+
+        self.send_to_connection(&session_user.connection_id, ChatEvent::DirectUnread(unread))
+            .await;
+        Ok(())
+    }
+
+    /// Delivers a backplane direct message to the recipient's connections on this instance.
+    /// Status updates of the user are applied to their rooms here first.
+    pub(super) async fn deliver_direct_local(&self, envelope: ChatDirectEnvelope) {
+        if let ChatEvent::UserStatus(update) = &envelope.event {
+            self.apply_user_status(update).await;
+        }
+        let recipients = self
+            .runtime
+            .connections()
+            .user_connections(&envelope.user_id);
+        for recipient in recipients {
+            recipient.push(envelope.event.clone());
+        }
+    }
+}
+
+pub fn direct_unread(conversations: impl IntoIterator<Item = (String, u64)>) -> ChatDirectUnread {
+    let mut conversations = conversations
+        .into_iter()
+        .filter(|(_, unread_count)| *unread_count > 0)
+        .map(|(user_id, unread_count)| ChatDirectConversation { user_id, unread_count })
+        .collect::<Vec<_>>();
+    conversations.sort_by(|left, right| left.user_id.cmp(&right.user_id));
+    ChatDirectUnread { conversations }
+}
+
+pub(super) fn normalized_user_id(user_id: &str) -> Result<String, ChatError> {
+    let user_id = user_id.trim();
+    if user_id.is_empty() || user_id.len() > MAX_USER_ID_LEN {
+        return Err(ChatError::InvalidRecipient);
+    }
+
+    Ok(user_id.to_string())
+}
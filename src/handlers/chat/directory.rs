@@ -0,0 +1,436 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    ChatError, ChatEvent, ChatHub, ChatState, ChatUserSummary,
+    backplane::{ChatBackplane, ChatDisconnectEnvelope},
+    backplane_error, chat_error_response,
+    history::ChatHistoryStats,
+    normalized_room_id,
+    outbound::ChatCloseReason,
+    sorted_members,
+};
+use crate::{error::AppResult, route::AppState, utils::jwt_auth::Claims};
+
+const MAX_REASON_LEN: usize = 200;
+/// Most rooms listed by the directory, the most recently active first.
+pub const MAX_DIRECTORY_ROOMS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatRoomSummary {
+    pub room_id: String,
+    /// Distinct users in the room, however many connections each has.
+    pub member_count: usize,
+    /// Unix seconds of the last message, join or leave.
+    pub last_activity_at: i64,
+    pub private: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatRoomDirectory {
+    pub rooms: Vec<ChatRoomSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatRoomMembers {
+    pub room_id: String,
+    pub members: Vec<ChatUserSummary>,
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatConnectionSummary {
+    pub connection_id: String,
+    pub user_id: String,
+    pub user_name: String,
+    pub connected_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatConnectionList {
+    pub connections: Vec<ChatConnectionSummary>,
+}
+
+/// Body of the admin close endpoints.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatCloseRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Sent to a connection an admin closed, right before its close frame.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatConnectionClosed {
+    pub reason: Option<String>,
+}
+
+/// Sent to the members of a room an admin closed; they are no longer in it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatRoomClosed {
+    pub room_id: String,
+    pub reason: Option<String>,
+    pub version: u64,
+}
+
+impl ChatHub {
+    /// Loaded rooms with their member counts; cheap enough to run under the shard lock.
+    pub fn room_summaries(&self) -> Vec<ChatRoomSummary> {
+        self.rooms
+            .iter()
+            .map(|(room_id, room)| ChatRoomSummary {
+                room_id: room_id.clone(),
+                member_count: room.presence.len(),
+                last_activity_at: room.last_activity_at,
+                private: self
+                    .room_access
+                    .get(room_id)
+                    .is_some_and(|access| access.private),
+            })
+            .collect()
+    }
+
+    pub fn room_members(&self, room_id: &str) -> Option<ChatRoomMembers> {
+        let room = self.rooms.get(room_id)?;
+        Some(ChatRoomMembers {
+            room_id: room_id.to_string(),
+            members: sorted_members(room),
+            version: room.version,
+        })
+    }
+
+    /// Takes every connection out of the room under one new version and drops the room, returning
+    /// the notice and the connections that were in it. Access rules outlive it like any emptied
+    /// room's.
+    pub fn close_room(
+        &mut self,
+        room_id: &str,
+        reason: Option<String>,
+    ) -> Option<(ChatRoomClosed, Vec<String>)> {
+        let mut room = self.rooms.remove(room_id)?;
+        room.version += 1;
+        let closed = ChatRoomClosed { room_id: room_id.to_string(), reason, version: room.version };
+        self.drop_room(room_id, room.version);
+
+        let connection_ids = room.members.into_keys().collect::<Vec<_>>();
+        for connection_id in &connection_ids {
+            if let Some(connection) = self.connections.get_mut(connection_id) {
+                connection.joined_rooms.remove(room_id);
+                if connection.joined_rooms.is_empty() {
+                    self.connections.remove(connection_id);
+                }
+            }
+        }
+        Some((closed, connection_ids))
+    }
+}
+
+impl ChatState {
+    pub fn is_admin(&self, user_id: &str) -> bool {
+        self.config
+            .admin_user_ids
+            .iter()
+            .any(|admin_id| admin_id == user_id)
+    }
+
+    /// Queue usage and dropped writes of this instance's history writer.
+    pub fn history_stats(&self) -> Result<ChatHistoryStats, ChatError> {
+        self.history
+            .as_ref()
+            .map(|history| history.stats())
+            .ok_or(ChatError::HistoryUnavailable)
+    }
+
+    /// Active rooms `user_id` may join, the most recently active first.
+    pub async fn room_directory(&self, user_id: &str) -> Result<Vec<ChatRoomSummary>, ChatError> {
+        let mut rooms = Vec::new();
+        match &self.backplane {
+            Some(backplane) => {
+                for (room, access) in backplane
+                    .room_directory(MAX_DIRECTORY_ROOMS)
+                    .await
+                    .map_err(backplane_error)?
+                {
+                    if access.is_none_or(|access| access.check_join(user_id).is_ok()) {
+                        rooms.push(room);
+                    }
+                }
+            }
+            None => {
+                for shard in self.runtime.shards() {
+                    let hub = shard.lock().await;
+                    rooms.extend(hub.room_summaries().into_iter().filter(|room| {
+                        hub.room_access(&room.room_id)
+                            .is_none_or(|access| access.check_join(user_id).is_ok())
+                    }));
+                }
+            }
+        }
+
+        rooms.sort_by(|left, right| {
+            right
+                .last_activity_at
+                .cmp(&left.last_activity_at)
+                .then_with(|| left.room_id.cmp(&right.room_id))
+        });
+        rooms.truncate(MAX_DIRECTORY_ROOMS);
+        Ok(rooms)
+    }
+
+    /// Moves a room to the front of the directory. Failing only misorders the directory, so it
+    /// is logged rather than returned.
+    pub(super) async fn touch_room(&self, backplane: &ChatBackplane, room_id: &str) {
+        if let Err(error) = backplane.touch_room(room_id).await {
+            warn!(error = %error, room_id, "failed to record chat room activity");
+        }
+    }
+
+    /// Current members of a room `user_id` may join.
+    pub async fn room_members(
+        &self,
+        user_id: &str,
+        room_id: &str,
+    ) -> Result<ChatRoomMembers, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let not_found = || ChatError::RoomNotFound { room_id: room_id.clone() };
+        let Some(backplane) = &self.backplane else {
+            let hub = self.runtime.room(&room_id).lock().await;
+            if let Some(access) = hub.room_access(&room_id) {
+                access.check_join(user_id)?;
+            }
+            return hub.room_members(&room_id).ok_or_else(not_found);
+        };
+
+        if let Some(access) = backplane
+            .room_access(&room_id)
+            .await
+            .map_err(backplane_error)?
+        {
+            access.check_join(user_id)?;
+        }
+        let (snapshot, expired) = backplane
+            .room_snapshot(&room_id, None)
+            .await
+            .map_err(backplane_error)?;
+        if let Some(expired) = expired {
+            self.publish(&room_id, ChatEvent::PresenceChanged(expired), None)
+                .await;
+        }
+        if snapshot.members.is_empty() {
+            return Err(not_found());
+        }
+        Ok(ChatRoomMembers { room_id, members: snapshot.members, version: snapshot.version })
+    }
+
+    /// Live connections, across every instance when the backplane is enabled.
+    pub async fn live_connections(&self) -> Result<Vec<ChatConnectionSummary>, ChatError> {
+        let mut connections = match &self.backplane {
+            Some(backplane) => backplane
+                .live_connections()
+                .await
+                .map_err(backplane_error)?,
+            None => self.runtime.connections().summaries(),
+        };
+        connections.sort_by(|left, right| {
+            left.user_id
+                .cmp(&right.user_id)
+                .then_with(|| left.connection_id.cmp(&right.connection_id))
+        });
+        Ok(connections)
+    }
+
+    /// Tells a connection why it is being closed and closes it, on whichever instance holds it.
+    pub async fn close_connection(
+        &self,
+        connection_id: &str,
+        reason: Option<String>,
+    ) -> Result<(), ChatError> {
+        let Some(backplane) = &self.backplane else {
+            return if self.close_local_connection(connection_id, reason) {
+                Ok(())
+            } else {
+                Err(ChatError::ConnectionNotFound { connection_id: connection_id.to_string() })
+            };
+        };
+
+        if !backplane
+            .connection_exists(connection_id)
+            .await
+            .map_err(backplane_error)?
+        {
+            return Err(ChatError::ConnectionNotFound { connection_id: connection_id.to_string() });
+        }
+        let envelope = ChatDisconnectEnvelope { connection_id: connection_id.to_string(), reason };
+        backplane
+            .publish_disconnect(&envelope)
+            .await
+            .map_err(backplane_error)
+    }
+
+    /// Closes a connection of this instance, false when it has no such connection. Its transport
+    /// task sees the queue close and unregisters it.
+    pub(super) fn close_local_connection(
+        &self,
+        connection_id: &str,
+        reason: Option<String>,
+    ) -> bool {
+        let Some(outbound) = self.runtime.connection(connection_id) else {
+            return false;
+        };
+        outbound.push(ChatEvent::ConnectionClosed(ChatConnectionClosed { reason }));
+        outbound.close(Some(ChatCloseReason::ClosedByAdmin));
+        true
+    }
+
+    /// Removes every member from a room and tells them why.
+    pub async fn close_room(
+        &self,
+        room_id: &str,
+        reason: Option<String>,
+    ) -> Result<ChatRoomClosed, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let Some(backplane) = &self.backplane else {
+            let (closed, dispatches) = {
+                let mut hub = self.runtime.room(&room_id).lock().await;
+                let (closed, connection_ids) = hub
+                    .close_room(&room_id, reason)
+                    .ok_or_else(|| ChatError::RoomNotFound { room_id: room_id.clone() })?;
+                let dispatches = self
+                    .runtime
+                    .dispatches(connection_ids, ChatEvent::RoomClosed(closed.clone()));
+                (closed, dispatches)
+            };
+            for dispatch in dispatches {
+                dispatch.sender.push(dispatch.event);
+            }
+            return Ok(closed);
+        };
+
+        let closed = backplane
+            .close_room(&room_id, reason)
+            .await
+            .map_err(backplane_error)?
+            .ok_or_else(|| ChatError::RoomNotFound { room_id: room_id.clone() })?;
+        self.publish(&room_id, ChatEvent::RoomClosed(closed.clone()), None)
+            .await;
+        Ok(closed)
+    }
+}
+
+pub async fn list_rooms(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let rooms = state
+        .chat_service
+        .room_directory(claims.sub.trim())
+        .await
+        .map_err(chat_error_response)?;
+
+    Ok(Json(ChatRoomDirectory { rooms }))
+}
+
+pub async fn room_members(
+    State(state): State<Arc<AppState>>,
+    Path(room_id): Path<String>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let members = state
+        .chat_service
+        .room_members(claims.sub.trim(), &room_id)
+        .await
+        .map_err(chat_error_response)?;
+
+    Ok(Json(members))
+}
+
+pub async fn admin_connections(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let chat_state = admin(&state, &claims)?;
+    let connections = chat_state
+        .live_connections()
+        .await
+        .map_err(chat_error_response)?;
+
+    Ok(Json(ChatConnectionList { connections }))
+}
+
+pub async fn admin_close_connection(
+    State(state): State<Arc<AppState>>,
+    Path(connection_id): Path<String>,
+    claims: Claims,
+    Json(request): Json<ChatCloseRequest>,
+) -> AppResult<impl IntoResponse> {
+    let chat_state = admin(&state, &claims)?;
+    let reason = normalized_reason(request.reason.as_deref())?;
+    chat_state
+        .close_connection(connection_id.trim(), reason)
+        .await
+        .map_err(chat_error_response)?;
+    warn!(admin_id = claims.sub, connection_id, "chat connection closed by admin");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn admin_close_room(
+    State(state): State<Arc<AppState>>,
+    Path(room_id): Path<String>,
+    claims: Claims,
+    Json(request): Json<ChatCloseRequest>,
+) -> AppResult<impl IntoResponse> {
+    let chat_state = admin(&state, &claims)?;
+    let reason = normalized_reason(request.reason.as_deref())?;
+    let closed = chat_state
+        .close_room(&room_id, reason)
+        .await
+        .map_err(chat_error_response)?;
+    warn!(admin_id = claims.sub, room_id = closed.room_id, "chat room closed by admin");
+
+    Ok(Json(closed))
+}
+
+pub async fn admin_history(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let chat_state = admin(&state, &claims)?;
+    let stats = chat_state.history_stats().map_err(chat_error_response)?;
+
+    Ok(Json(stats))
+}
+
+fn admin<'a>(state: &'a AppState, claims: &Claims) -> AppResult<&'a ChatState> {
+    if !state.chat_service.is_admin(claims.sub.trim()) {
+        return Err(chat_error_response(ChatError::AdminRequired));
+    }
+    Ok(&state.chat_service)
+}
+
+/// Trimmed close reason, `None` when blank.
+pub fn normalized_reason(reason: Option<&str>) -> AppResult<Option<String>> {
+    let Some(reason) = reason.map(str::trim).filter(|reason| !reason.is_empty()) else {
+        return Ok(None);
+    };
+    if reason.chars().count() > MAX_REASON_LEN {
+        return Err(chat_error_response(ChatError::ContentTooLong { max_len: MAX_REASON_LEN }));
+    }
+    Ok(Some(reason.to_string()))
+}
+
+/// Distinct users among member summaries, for directory member counts.
+pub fn member_count(members: &[ChatUserSummary]) -> usize {
+    members
+        .iter()
+        .map(|member| member.user_id.as_str())
+        .collect::<BTreeSet<_>>()
+        .len()
+}
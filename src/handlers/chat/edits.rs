@@ -0,0 +1,308 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    ChatError, ChatEvent, ChatHub, ChatRoomAccess, ChatRoomMessage, ChatSessionUser, ChatState,
+    backplane_error,
+    filters::{ChatContentFlag, ChatFlaggedMessage, extract_mentions},
+    normalized_room_id,
+};
+
+const MAX_MESSAGE_ID_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatMessageEdited {
+    pub room_id: String,
+    pub message_id: String,
+    /// Version the message was sent at, which stays its position in the room.
+    pub message_version: u64,
+    pub content: String,
+    pub edited_at: i64,
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatMessageDeleted {
+    pub room_id: String,
+    pub message_id: String,
+    pub message_version: u64,
+    pub deleted_at: i64,
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatMessageChange {
+    Edit { content: String },
+    Delete,
+}
+
+impl ChatMessageChange {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Edit { .. } => "edit",
+            Self::Delete => "delete",
+        }
+    }
+
+    /// Applies the change in place; deletes leave a tombstone with empty content.
+    pub fn apply(&self, message: &mut ChatRoomMessage, now: i64) {
+        match self {
+            Self::Edit { content } => {
+                message.content = content.clone();
+                message.edited_at = Some(now);
+                message.mentions = extract_mentions(content);
+            }
+            Self::Delete => {
+                message.content = String::new();
+                message.deleted_at = Some(now);
+                message.mentions.clear();
+            }
+        }
+    }
+
+    /// Event announcing the change of `message`, already applied, at room `version`.
+    pub fn event(&self, message: &ChatRoomMessage, version: u64) -> ChatEvent {
+        match self {
+            Self::Edit { content } => ChatEvent::MessageEdited(ChatMessageEdited {
+                room_id: message.room_id.clone(),
+                message_id: message.message_id.clone(),
+                message_version: message.version,
+                content: content.clone(),
+                edited_at: message.edited_at.unwrap_or_default(),
+                version,
+            }),
+            Self::Delete => ChatEvent::MessageDeleted(ChatMessageDeleted {
+                room_id: message.room_id.clone(),
+                message_id: message.message_id.clone(),
+                message_version: message.version,
+                deleted_at: message.deleted_at.unwrap_or_default(),
+                version,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessageModified {
+    pub message: ChatRoomMessage,
+    pub event: ChatEvent,
+}
+
+impl ChatHub {
+    /// Edits or deletes a message of the sender; moderators may also delete other users'
+    /// messages. Messages no longer retained in memory can be passed in as `stored`, e.g. after
+    /// loading them from the history table.
+    pub fn modify_message(
+        &mut self,
+        connection_id: &str,
+        room_id: &str,
+        message_id: &str,
+        change: &ChatMessageChange,
+        stored: Option<ChatRoomMessage>,
+        now: i64,
+    ) -> Result<ChatMessageModified, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let access = self.room_access.get(&room_id);
+        let room = self
+            .rooms
+            .get_mut(&room_id)
+            .ok_or_else(|| ChatError::NotInRoom { room_id: room_id.clone() })?;
+        let Some(member) = room.members.get(connection_id) else {
+            return Err(ChatError::NotInRoom { room_id });
+        };
+
+        let retained = room
+            .recent_messages
+            .iter()
+            .position(|message| message.message_id == message_id);
+        let mut message = match (retained, stored) {
+            (Some(index), _) => room.recent_messages[index].clone(),
+            (None, Some(stored)) if stored.room_id == room_id => stored,
+            _ => return Err(ChatError::MessageNotFound { message_id: message_id.to_string() }),
+        };
+        if message.message_id != message_id || message.deleted_at.is_some() {
+            return Err(ChatError::MessageNotFound { message_id: message_id.to_string() });
+        }
+        if !may_modify(&message, &member.user_id, change, access) {
+            return Err(ChatError::Forbidden);
+        }
+
+        room.version += 1;
+        change.apply(&mut message, now);
+        let event = change.event(&message, room.version);
+        if let Some(index) = retained {
+            room.recent_messages[index] = message.clone();
+        }
+        room.update_thread_reply(&message);
+        // Reactions go with the deleted content.
+        if *change == ChatMessageChange::Delete {
+            room.reactions.remove(message_id);
+        }
+        room.events.record(room.version, event.clone());
+
+        Ok(ChatMessageModified { message, event })
+    }
+}
+
+impl ChatState {
+    pub(super) async fn modify_room_message(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        message_id: &str,
+        change: ChatMessageChange,
+    ) -> Result<(), ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let message_id = normalized_message_id(message_id)?;
+        // Edits go through the same filters as new messages.
+        let (change, flags) = match change {
+            ChatMessageChange::Edit { content } => {
+                let filtered = self
+                    .filter_content(session_user, &room_id, &content)
+                    .await?;
+                (ChatMessageChange::Edit { content: filtered.content }, filtered.flags)
+            }
+            ChatMessageChange::Delete => (ChatMessageChange::Delete, Vec::new()),
+        };
+        if self.backplane.is_some() {
+            return self
+                .modify_distributed(session_user, &room_id, &message_id, &change, flags)
+                .await;
+        }
+
+        let now = Utc::now().timestamp();
+        let mut stored = None;
+        let mut looked_up = false;
+        loop {
+            let mut hub = self.runtime.room(&room_id).lock().await;
+            let modified = match hub.modify_message(
+                &session_user.connection_id,
+                &room_id,
+                &message_id,
+                &change,
+                stored.take(),
+                now,
+            ) {
+                Err(ChatError::MessageNotFound { .. }) if !looked_up && self.history.is_some() => {
+                    drop(hub);
+                    looked_up = true;
+                    stored = Some(self.stored_message(&room_id, &message_id).await?);
+                    continue;
+                }
+                modified => modified?,
+            };
+            if !flags.is_empty() {
+                hub.flag_message(ChatFlaggedMessage::new(modified.message.clone(), flags));
+            }
+            if let Some(history) = &self.history {
+                history.persist_update(modified.message);
+            }
+            for dispatch in self
+                .runtime
+                .room_dispatches(&hub, &room_id, modified.event, None)
+            {
+                dispatch.sender.push(dispatch.event);
+            }
+            return Ok(());
+        }
+    }
+
+    async fn modify_distributed(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        message_id: &str,
+        change: &ChatMessageChange,
+        flags: Vec<ChatContentFlag>,
+    ) -> Result<(), ChatError> {
+        let Some(backplane) = &self.backplane else {
+            return Ok(());
+        };
+        if !self
+            .runtime
+            .room(room_id)
+            .lock()
+            .await
+            .is_member(&session_user.connection_id, room_id)
+        {
+            return Err(ChatError::NotInRoom { room_id: room_id.to_string() });
+        }
+
+        let moderator = backplane
+            .room_access(room_id)
+            .await
+            .map_err(backplane_error)?
+            .is_some_and(|access| access.is_moderator(&session_user.user_id));
+        let mut stored = None;
+        let modified = loop {
+            let modified = backplane
+                .modify_message(
+                    session_user,
+                    room_id,
+                    message_id,
+                    change,
+                    stored.as_ref(),
+                    moderator,
+                )
+                .await
+                .map_err(backplane_error)?;
+            match modified {
+                Err(ChatError::MessageNotFound { .. })
+                    if stored.is_none() && self.history.is_some() =>
+                {
+                    stored = Some(self.stored_message(room_id, message_id).await?);
+                }
+                modified => break modified?,
+            }
+        };
+        if !flags.is_empty() {
+            self.record_flagged(ChatFlaggedMessage::new(modified.message.clone(), flags))
+                .await;
+        }
+        if let Some(history) = &self.history {
+            history.persist_update(modified.message);
+        }
+        self.publish(room_id, modified.event, None).await;
+        Ok(())
+    }
+
+    /// A message that fell out of the in-memory window, read from the history table.
+    pub(super) async fn stored_message(
+        &self,
+        room_id: &str,
+        message_id: &str,
+    ) -> Result<ChatRoomMessage, ChatError> {
+        let Some(history) = &self.history else {
+            return Err(ChatError::MessageNotFound { message_id: message_id.to_string() });
+        };
+        history
+            .find_message(room_id, message_id)
+            .await
+            .map_err(|error| {
+                warn!(error = %error, room_id, "failed to load chat message");
+                ChatError::HistoryUnavailable
+            })?
+            .ok_or_else(|| ChatError::MessageNotFound { message_id: message_id.to_string() })
+    }
+}
+
+pub(super) fn normalized_message_id(message_id: &str) -> Result<String, ChatError> {
+    let message_id = message_id.trim();
+    if message_id.is_empty() || message_id.len() > MAX_MESSAGE_ID_LEN {
+        return Err(ChatError::MessageNotFound { message_id: message_id.to_string() });
+    }
+
+    Ok(message_id.to_string())
+}
+
+fn may_modify(
+    message: &ChatRoomMessage,
+    user_id: &str,
+    change: &ChatMessageChange,
+    access: Option<&ChatRoomAccess>,
+) -> bool {
+    message.sender_id == user_id
+        || (*change == ChatMessageChange::Delete
+            && access.is_some_and(|access| access.is_moderator(user_id)))
+}
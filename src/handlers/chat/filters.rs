@@ -0,0 +1,474 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    fmt,
+    sync::Arc,
+};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use chrono::Utc;
+use futures_util::{FutureExt, future::BoxFuture};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    ChatError, ChatHub, ChatRoomMessage, ChatSessionUser, ChatState, backplane_error,
+    chat_error_response, normalized_content, normalized_room_id,
+};
+use crate::{
+    config::{ChatBannedWordAction, ChatFilterConfig, ChatLinkPolicy},
+    error::AppResult,
+    route::AppState,
+    utils::jwt_auth::Claims,
+};
+
+/// Flagged messages kept per room for moderators, the newest first.
+pub const MAX_FLAGGED_MESSAGES: usize = 200;
+const MAX_MENTION_LEN: usize = 64;
+
+/// The content a filter checks, as rewritten by the filters before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatFilterInput {
+    pub room_id: String,
+    pub sender_id: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatFilterAction {
+    Allow,
+    /// Replace the content, e.g. to mask words.
+    Rewrite(String),
+    /// Let the message through but keep it for moderator review.
+    Flag(String),
+    Reject(ChatError),
+}
+
+/// A check run on room messages and edits before they are committed. Filters run outside the
+/// room locks, so they may await.
+pub trait ChatContentFilter: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn check<'a>(&'a self, input: &'a ChatFilterInput) -> BoxFuture<'a, ChatFilterAction>;
+}
+
+/// Why a filter flagged a message.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatContentFlag {
+    pub filter: String,
+    pub reason: String,
+}
+
+/// Content that passed every filter, with the users it mentions and any flags raised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatFilteredContent {
+    pub content: String,
+    pub mentions: Vec<String>,
+    pub flags: Vec<ChatContentFlag>,
+}
+
+impl ChatFilteredContent {
+    /// Content no filter looked at.
+    pub fn unfiltered(content: String) -> Self {
+        Self { mentions: extract_mentions(&content), content, flags: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatFlaggedMessage {
+    pub message: ChatRoomMessage,
+    pub flags: Vec<ChatContentFlag>,
+    pub flagged_at: i64,
+}
+
+impl ChatFlaggedMessage {
+    pub fn new(message: ChatRoomMessage, flags: Vec<ChatContentFlag>) -> Self {
+        Self { message, flags, flagged_at: Utc::now().timestamp() }
+    }
+}
+
+/// Flagged messages per room, the newest first.
+pub(super) type ChatFlaggedLog = HashMap<String, VecDeque<ChatFlaggedMessage>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatFlaggedMessages {
+    pub room_id: String,
+    pub messages: Vec<ChatFlaggedMessage>,
+}
+
+/// The configured filters followed by custom ones, in the order they were added.
+#[derive(Clone, Default)]
+pub struct ChatContentFilters {
+    configured: Vec<Arc<dyn ChatContentFilter>>,
+    custom: Vec<Arc<dyn ChatContentFilter>>,
+}
+
+impl fmt::Debug for ChatContentFilters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.filters().map(|filter| filter.name()))
+            .finish()
+    }
+}
+
+impl ChatContentFilters {
+    /// Replaces the configured filters, keeping custom ones.
+    pub fn configure(&mut self, config: &ChatFilterConfig) {
+        self.configured.clear();
+        let banned_words = BannedWordFilter::new(&config.banned_words, config.banned_word_action);
+        if !banned_words.words.is_empty() {
+            self.configured.push(Arc::new(banned_words));
+        }
+        if config.link_policy != ChatLinkPolicy::Allow {
+            self.configured
+                .push(Arc::new(LinkFilter::new(config.link_policy, &config.allowed_link_domains)));
+        }
+        if config.max_mentions > 0 {
+            self.configured
+                .push(Arc::new(MentionFilter { max_mentions: config.max_mentions }));
+        }
+    }
+
+    pub fn push(&mut self, filter: Arc<dyn ChatContentFilter>) {
+        self.custom.push(filter);
+    }
+
+    /// Runs every filter in turn, stopping at the first rejection.
+    pub async fn run(
+        &self,
+        room_id: &str,
+        sender_id: &str,
+        content: String,
+    ) -> Result<ChatFilteredContent, ChatError> {
+        let mut input = ChatFilterInput {
+            room_id: room_id.to_string(),
+            sender_id: sender_id.to_string(),
+            content,
+        };
+        let mut flags = Vec::new();
+        for filter in self.filters() {
+            match filter.check(&input).await {
+                ChatFilterAction::Allow => {}
+                ChatFilterAction::Rewrite(content) => input.content = normalized_content(&content)?,
+                ChatFilterAction::Flag(reason) => {
+                    flags.push(ChatContentFlag { filter: filter.name().to_string(), reason });
+                }
+                ChatFilterAction::Reject(error) => return Err(error),
+            }
+        }
+
+        Ok(ChatFilteredContent { flags, ..ChatFilteredContent::unfiltered(input.content) })
+    }
+
+    fn filters(&self) -> impl Iterator<Item = &Arc<dyn ChatContentFilter>> {
+        self.configured.iter().chain(&self.custom)
+    }
+}
+
+/// Masks, flags or rejects whole words from a configured list, ignoring case.
+pub struct BannedWordFilter {
+    words: BTreeSet<String>,
+    action: ChatBannedWordAction,
+}
+
+impl BannedWordFilter {
+    pub fn new(words: &[String], action: ChatBannedWordAction) -> Self {
+        let words = words
+            .iter()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        Self { words, action }
+    }
+
+    fn banned_spans(&self, content: &str) -> Vec<(usize, usize)> {
+        word_spans(content)
+            .filter(|&(start, end)| self.words.contains(&content[start..end].to_lowercase()))
+            .collect()
+    }
+}
+
+impl ChatContentFilter for BannedWordFilter {
+    fn name(&self) -> &str {
+        "banned_words"
+    }
+
+    fn check<'a>(&'a self, input: &'a ChatFilterInput) -> BoxFuture<'a, ChatFilterAction> {
+        let spans = self.banned_spans(&input.content);
+        let action = match (spans.is_empty(), self.action) {
+            (true, _) => ChatFilterAction::Allow,
+            (false, ChatBannedWordAction::Reject) => {
+                ChatFilterAction::Reject(ChatError::BannedContent)
+            }
+            (false, ChatBannedWordAction::Flag) => {
+                ChatFilterAction::Flag(format!("{} banned word(s)", spans.len()))
+            }
+            (false, ChatBannedWordAction::Mask) => {
+                ChatFilterAction::Rewrite(mask_spans(&input.content, &spans))
+            }
+        };
+        futures_util::future::ready(action).boxed()
+    }
+}
+
+/// Applies the link policy to `http(s)://` and `www.` links outside the allowed domains.
+pub struct LinkFilter {
+    policy: ChatLinkPolicy,
+    allowed_domains: Vec<String>,
+}
+
+impl LinkFilter {
+    pub fn new(policy: ChatLinkPolicy, allowed_domains: &[String]) -> Self {
+        let allowed_domains = allowed_domains
+            .iter()
+            .map(|domain| domain.trim().trim_start_matches('.').to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect();
+        Self { policy, allowed_domains }
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        self.allowed_domains.iter().any(|domain| {
+            host == domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+}
+
+impl ChatContentFilter for LinkFilter {
+    fn name(&self) -> &str {
+        "links"
+    }
+
+    fn check<'a>(&'a self, input: &'a ChatFilterInput) -> BoxFuture<'a, ChatFilterAction> {
+        let blocked = link_hosts(&input.content)
+            .into_iter()
+            .find(|host| !self.is_allowed(host));
+        let action = match (blocked, self.policy) {
+            (None, _) | (_, ChatLinkPolicy::Allow) => ChatFilterAction::Allow,
+            (Some(host), ChatLinkPolicy::Flag) => {
+                ChatFilterAction::Flag(format!("links to {host}"))
+            }
+            (Some(_), ChatLinkPolicy::Reject) => {
+                ChatFilterAction::Reject(ChatError::LinkNotAllowed)
+            }
+        };
+        futures_util::future::ready(action).boxed()
+    }
+}
+
+/// Rejects messages mentioning too many distinct users.
+pub struct MentionFilter {
+    max_mentions: usize,
+}
+
+impl ChatContentFilter for MentionFilter {
+    fn name(&self) -> &str {
+        "mentions"
+    }
+
+    fn check<'a>(&'a self, input: &'a ChatFilterInput) -> BoxFuture<'a, ChatFilterAction> {
+        let action = if extract_mentions(&input.content).len() > self.max_mentions {
+            ChatFilterAction::Reject(ChatError::TooManyMentions { max_mentions: self.max_mentions })
+        } else {
+            ChatFilterAction::Allow
+        };
+        futures_util::future::ready(action).boxed()
+    }
+}
+
+/// Distinct `@user` mentions in order of first appearance. An `@` inside a word, as in an email
+/// address, is not a mention.
+pub fn extract_mentions(content: &str) -> Vec<String> {
+    let mut mentions = Vec::new();
+    let mut previous = None;
+    for (index, ch) in content.char_indices() {
+        let starts_mention = ch == '@' && !previous.is_some_and(is_mention_char);
+        previous = Some(ch);
+        if !starts_mention {
+            continue;
+        }
+
+        let rest = &content[index + 1..];
+        let end = rest
+            .find(|ch: char| !is_mention_char(ch))
+            .unwrap_or(rest.len());
+        let mention = rest[..end].trim_end_matches(['.', '-']);
+        if !mention.is_empty()
+            && mention.chars().count() <= MAX_MENTION_LEN
+            && !mentions.iter().any(|known| known == mention)
+        {
+            mentions.push(mention.to_string());
+        }
+    }
+    mentions
+}
+
+fn is_mention_char(ch: char) -> bool {
+    ch.is_alphanumeric() || matches!(ch, '_' | '.' | '-')
+}
+
+/// Byte ranges of the alphanumeric runs in `content`.
+fn word_spans(content: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut start = None;
+    content
+        .char_indices()
+        .chain([(content.len(), ' ')])
+        .filter_map(move |(index, ch)| match (ch.is_alphanumeric(), start) {
+            (true, None) => {
+                start = Some(index);
+                None
+            }
+            (false, Some(word_start)) => {
+                start = None;
+                Some((word_start, index))
+            }
+            _ => None,
+        })
+}
+
+fn mask_spans(content: &str, spans: &[(usize, usize)]) -> String {
+    let mut masked = String::with_capacity(content.len());
+    let mut last = 0;
+    for &(start, end) in spans {
+        masked.push_str(&content[last..start]);
+        masked.extend(content[start..end].chars().map(|_| '*'));
+        last = end;
+    }
+    masked.push_str(&content[last..]);
+    masked
+}
+
+/// Lower-cased hosts of the links in `content`.
+fn link_hosts(content: &str) -> Vec<String> {
+    content
+        .split_whitespace()
+        .filter_map(|token| {
+            let token = token.trim_start_matches(['(', '<', '"', '\'']);
+            let lower = token.to_lowercase();
+            let rest = ["https://", "http://"]
+                .iter()
+                .find_map(|scheme| lower.strip_prefix(scheme))
+                .or_else(|| lower.starts_with("www.").then_some(lower.as_str()))?;
+            let host = rest
+                .split(['/', '?', '#', ':'])
+                .next()?
+                .trim_end_matches(['.', ',', ')', '>', '"', '\'', '!', ';']);
+            (!host.is_empty()).then(|| host.to_string())
+        })
+        .collect()
+}
+
+impl ChatHub {
+    /// Keeps a flagged message for moderators. The list outlives the room, like its access rules.
+    pub fn flag_message(&mut self, flagged: ChatFlaggedMessage) {
+        let messages = self
+            .flagged_messages
+            .entry(flagged.message.room_id.clone())
+            .or_default();
+        messages.push_front(flagged);
+        messages.truncate(MAX_FLAGGED_MESSAGES);
+    }
+
+    pub fn flagged_messages(&self, room_id: &str) -> Vec<ChatFlaggedMessage> {
+        self.flagged_messages
+            .get(room_id)
+            .map(|messages| messages.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl ChatState {
+    /// Adds a filter that runs after the configured ones.
+    pub fn with_content_filter(mut self, filter: Arc<dyn ChatContentFilter>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Normalizes message content and runs it through the filters.
+    pub(super) async fn filter_content(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        content: &str,
+    ) -> Result<ChatFilteredContent, ChatError> {
+        let content = normalized_content(content)?;
+        self.filters
+            .run(room_id, &session_user.user_id, content)
+            .await
+    }
+
+    /// Keeps a message a filter flagged. The message is already out, so a failure to store the
+    /// flag is logged rather than returned.
+    pub(super) async fn record_flagged(&self, flagged: ChatFlaggedMessage) {
+        let room_id = flagged.message.room_id.clone();
+        match &self.backplane {
+            Some(backplane) => {
+                if let Err(error) = backplane.flag_message(&flagged).await {
+                    warn!(error = %error, room_id, "failed to store flagged chat message");
+                }
+            }
+            None => self
+                .runtime
+                .room(&room_id)
+                .lock()
+                .await
+                .flag_message(flagged),
+        }
+    }
+
+    /// Flagged messages of a room, for its moderators and chat admins.
+    pub async fn flagged_messages(
+        &self,
+        user_id: &str,
+        room_id: &str,
+    ) -> Result<ChatFlaggedMessages, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let Some(backplane) = &self.backplane else {
+            let hub = self.runtime.room(&room_id).lock().await;
+            if !self.is_admin(user_id)
+                && !hub
+                    .room_access(&room_id)
+                    .is_some_and(|access| access.is_moderator(user_id))
+            {
+                return Err(ChatError::NotRoomModerator);
+            }
+            let messages = hub.flagged_messages(&room_id);
+            return Ok(ChatFlaggedMessages { room_id, messages });
+        };
+
+        if !self.is_admin(user_id)
+            && !backplane
+                .room_access(&room_id)
+                .await
+                .map_err(backplane_error)?
+                .is_some_and(|access| access.is_moderator(user_id))
+        {
+            return Err(ChatError::NotRoomModerator);
+        }
+        let messages = backplane
+            .flagged_messages(&room_id)
+            .await
+            .map_err(backplane_error)?;
+        Ok(ChatFlaggedMessages { room_id, messages })
+    }
+}
+
+pub async fn flagged_messages(
+    State(state): State<Arc<AppState>>,
+    Path(room_id): Path<String>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let flagged = state
+        .chat_service
+        .flagged_messages(claims.sub.trim(), &room_id)
+        .await
+        .map_err(chat_error_response)?;
+
+    Ok(Json(flagged))
+}
@@ -0,0 +1,107 @@
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use tokio::{sync::Notify, time::Instant};
+
+use super::outbound::ChatCloseReason;
+use crate::config::ChatConfig;
+
+#[derive(Debug)]
+struct HeartbeatState {
+    last_activity: Instant,
+    /// When the oldest ping still waiting for an answer was sent.
+    awaiting_pong_since: Option<Instant>,
+    next_ping_at: Instant,
+}
+
+/// Per-connection liveness tracking. The writer sends pings when `ping_due` resolves and the
+/// reader records what the client sends; `expired` resolves once either deadline is missed.
+#[derive(Debug)]
+pub struct ChatHeartbeat {
+    interval: Duration,
+    pong_timeout: Duration,
+    idle_timeout: Duration,
+    state: Mutex<HeartbeatState>,
+    changed: Notify,
+}
+
+impl ChatHeartbeat {
+    pub fn new(config: &ChatConfig) -> Self {
+        let interval = Duration::from_secs(config.heartbeat_interval_secs.max(1));
+        let now = Instant::now();
+        Self {
+            interval,
+            pong_timeout: Duration::from_secs(config.pong_timeout_secs.max(1)),
+            idle_timeout: Duration::from_secs(config.idle_timeout_secs.max(1)),
+            state: Mutex::new(HeartbeatState {
+                last_activity: now,
+                awaiting_pong_since: None,
+                next_ping_at: now + interval,
+            }),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Resolves when the next server ping should be sent.
+    pub async fn ping_due(&self) {
+        let next_ping_at = self.lock().next_ping_at;
+        tokio::time::sleep_until(next_ping_at).await;
+    }
+
+    pub fn ping_sent(&self) {
+        let now = Instant::now();
+        {
+            let mut state = self.lock();
+            state.awaiting_pong_since = state.awaiting_pong_since.or(Some(now));
+            state.next_ping_at = now + self.interval;
+        }
+        self.changed.notify_waiters();
+    }
+
+    /// A pong proves the transport is alive but is not client activity.
+    pub fn pong_received(&self) {
+        self.lock().awaiting_pong_since = None;
+    }
+
+    /// Any other frame from the client, which also answers outstanding pings.
+    pub fn activity(&self) {
+        let mut state = self.lock();
+        state.last_activity = Instant::now();
+        state.awaiting_pong_since = None;
+    }
+
+    /// Resolves with the close reason once a ping went unanswered or the client went idle.
+    pub async fn expired(&self) -> ChatCloseReason {
+        loop {
+            let changed = self.changed.notified();
+            let (deadline, reason) = self.next_deadline();
+            if Instant::now() >= deadline {
+                return reason;
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = changed => {}
+            }
+        }
+    }
+
+    fn next_deadline(&self) -> (Instant, ChatCloseReason) {
+        let state = self.lock();
+        let idle = (state.last_activity + self.idle_timeout, ChatCloseReason::IdleTimeout);
+        match state.awaiting_pong_since {
+            Some(sent_at) if sent_at + self.pong_timeout < idle.0 => {
+                (sent_at + self.pong_timeout, ChatCloseReason::HeartbeatTimeout)
+            }
+            _ => idle,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HeartbeatState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row, postgres::PgRow};
+use tokio::sync::mpsc::{self, Receiver, Sender, error::TrySendError};
+use tracing::{error, warn};
+
+use super::{ChatRoomHistory, ChatRoomMessage, filters::extract_mentions};
+
+const WRITE_BATCH_SIZE: usize = 100;
+const WRITE_ATTEMPTS: u32 = 3;
+const WRITE_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Postgres backed chat history, written by a background task so sends never wait on the database.
+#[derive(Debug, Clone)]
+pub struct ChatHistory {
+    write_pool: PgPool,
+    read_pool: PgPool,
+    sender: Sender<ChatHistoryWrite>,
+    /// Writes shed because the queue was full or dropped after their retries ran out.
+    dropped_writes: Arc<AtomicU64>,
+}
+
+/// Write queue usage of the history writer, for the chat admin endpoints.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatHistoryStats {
+    pub queued_writes: usize,
+    pub queue_capacity: usize,
+    pub dropped_writes: u64,
+}
+
+/// Writes are applied in queue order, so an edit never lands before its message is inserted.
+#[derive(Debug, Clone)]
+enum ChatHistoryWrite {
+    Insert(ChatRoomMessage),
+    /// Stores the content and edit or delete timestamps of an already sent message.
+    Update(ChatRoomMessage),
+}
+
+impl ChatHistory {
+    /// Starts the background writer, must be called from within a tokio runtime. At most
+    /// `queue_capacity` writes wait for the database, later ones are shed and counted.
+    pub fn spawn(write_pool: PgPool, read_pool: PgPool, queue_capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(queue_capacity.max(1));
+        let dropped_writes = Arc::new(AtomicU64::new(0));
+        tokio::spawn(run_writer(write_pool.clone(), receiver, dropped_writes.clone()));
+        Self { write_pool, read_pool, sender, dropped_writes }
+    }
+
+    /// Queues a message for persistence without waiting for the write.
+    pub fn persist(&self, message: ChatRoomMessage) {
+        self.enqueue(ChatHistoryWrite::Insert(message));
+    }
+
+    /// Queues an edited or deleted message to overwrite its stored row.
+    pub fn persist_update(&self, message: ChatRoomMessage) {
+        self.enqueue(ChatHistoryWrite::Update(message));
+    }
+
+    /// Writes currently queued and dropped so far.
+    pub fn stats(&self) -> ChatHistoryStats {
+        ChatHistoryStats {
+            queued_writes: self.sender.max_capacity() - self.sender.capacity(),
+            queue_capacity: self.sender.max_capacity(),
+            dropped_writes: self.dropped_writes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sends run under room locks, so a full queue sheds the write instead of waiting.
+    fn enqueue(&self, write: ChatHistoryWrite) {
+        let message = match &write {
+            ChatHistoryWrite::Insert(message) | ChatHistoryWrite::Update(message) => message,
+        };
+        let (room_id, version) = (message.room_id.clone(), message.version);
+        match self.sender.try_send(write) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped_writes.fetch_add(1, Ordering::Relaxed);
+                warn!(room_id, version, "chat history queue is full, write was not persisted");
+            }
+            Err(TrySendError::Closed(_)) => {
+                self.dropped_writes.fetch_add(1, Ordering::Relaxed);
+                error!(room_id, version, "chat history writer stopped, write was not persisted");
+            }
+        }
+    }
+
+    /// A single message by id, read from the primary so recent writes are visible.
+    pub async fn find_message(
+        &self,
+        room_id: &str,
+        message_id: &str,
+    ) -> anyhow::Result<Option<ChatRoomMessage>> {
+        let row = sqlx::query(
+            r#"
+            SELECT "RoomId" AS room_id,
+                   "Version" AS version,
+                   "MessageId" AS message_id,
+                   "SenderId" AS sender_id,
+                   "SenderName" AS sender_name,
+                   "Content" AS content,
+                   "SentAt" AS sent_at,
+                   "EditedAt" AS edited_at,
+                   "DeletedAt" AS deleted_at,
+                   "ReplyTo" AS reply_to
+            FROM "chat_room_messages"
+            WHERE "RoomId" = $1 AND "MessageId" = $2
+            "#,
+        )
+        .bind(room_id)
+        .bind(message_id)
+        .fetch_optional(&self.write_pool)
+        .await?;
+
+        row.map(|row| message_from_row(&row)).transpose()
+    }
+
+    /// Highest persisted version of a room, read from the primary so a recreated room never
+    /// reuses a version.
+    pub async fn latest_version(&self, room_id: &str) -> anyhow::Result<u64> {
+        let version: Option<i64> = sqlx::query_scalar(
+            r#"SELECT MAX("Version") FROM "chat_room_messages" WHERE "RoomId" = $1"#,
+        )
+        .bind(room_id)
+        .fetch_one(&self.write_pool)
+        .await?;
+        Ok(version.unwrap_or_default().max(0) as u64)
+    }
+
+    /// Up to `limit` messages older than `before`, newest first.
+    pub async fn load_before(
+        &self,
+        room_id: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatRoomMessage>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT "RoomId" AS room_id,
+                   "Version" AS version,
+                   "MessageId" AS message_id,
+                   "SenderId" AS sender_id,
+                   "SenderName" AS sender_name,
+                   "Content" AS content,
+                   "SentAt" AS sent_at,
+                   "EditedAt" AS edited_at,
+                   "DeletedAt" AS deleted_at,
+                   "ReplyTo" AS reply_to
+            FROM "chat_room_messages"
+            WHERE "RoomId" = $1
+              AND ($2::INT8 IS NULL OR "Version" < $2)
+            ORDER BY "Version" DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(room_id)
+        .bind(before.map(|before| before.min(i64::MAX as u64) as i64))
+        .bind(limit as i64)
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        rows.iter().map(message_from_row).collect()
+    }
+
+    /// The latest `limit` replies of a thread, oldest first.
+    pub async fn load_thread(
+        &self,
+        room_id: &str,
+        root_message_id: &str,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ChatRoomMessage>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT "RoomId" AS room_id,
+                   "Version" AS version,
+                   "MessageId" AS message_id,
+                   "SenderId" AS sender_id,
+                   "SenderName" AS sender_name,
+                   "Content" AS content,
+                   "SentAt" AS sent_at,
+                   "EditedAt" AS edited_at,
+                   "DeletedAt" AS deleted_at,
+                   "ReplyTo" AS reply_to
+            FROM "chat_room_messages"
+            WHERE "RoomId" = $1 AND "ReplyTo" = $2
+            ORDER BY "Version" DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(room_id)
+        .bind(root_message_id)
+        .bind(limit as i64)
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        let mut replies = rows
+            .iter()
+            .map(message_from_row)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        replies.reverse();
+        Ok(replies)
+    }
+}
+
+fn message_from_row(row: &PgRow) -> anyhow::Result<ChatRoomMessage> {
+    // Mentions are not stored, they are extracted again from the content.
+    let content: String = row.try_get("content")?;
+    Ok(ChatRoomMessage {
+        room_id: row.try_get("room_id")?,
+        message_id: row.try_get("message_id")?,
+        sender_id: row.try_get("sender_id")?,
+        sender_name: row.try_get("sender_name")?,
+        mentions: extract_mentions(&content),
+        content,
+        sent_at: row.try_get("sent_at")?,
+        version: row.try_get::<i64, _>("version")?.max(0) as u64,
+        edited_at: row.try_get("edited_at")?,
+        deleted_at: row.try_get("deleted_at")?,
+        reply_to: row.try_get("reply_to")?,
+    })
+}
+
+/// Merges in-memory and persisted messages into one page of at most `limit` messages, oldest
+/// first. Callers fetch `limit + 1` stored rows so `next_before` is only set when more exist.
+pub fn merge_history_page(
+    room_id: String,
+    limit: usize,
+    cached: Vec<ChatRoomMessage>,
+    stored: Vec<ChatRoomMessage>,
+) -> ChatRoomHistory {
+    let merged = stored
+        .into_iter()
+        .chain(cached)
+        .map(|message| (message.version, message))
+        .collect::<BTreeMap<_, _>>();
+    let has_more = merged.len() > limit;
+    let messages = merged
+        .into_values()
+        .rev()
+        .take(limit)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>();
+    let next_before = if has_more { messages.first().map(|message| message.version) } else { None };
+
+    ChatRoomHistory { room_id, messages, next_before }
+}
+
+async fn run_writer(
+    pool: PgPool,
+    mut receiver: Receiver<ChatHistoryWrite>,
+    dropped_writes: Arc<AtomicU64>,
+) {
+    let mut batch = Vec::with_capacity(WRITE_BATCH_SIZE);
+    while receiver.recv_many(&mut batch, WRITE_BATCH_SIZE).await > 0 {
+        let mut inserts = Vec::with_capacity(batch.len());
+        for write in batch.drain(..) {
+            match write {
+                ChatHistoryWrite::Insert(message) => inserts.push(message),
+                ChatHistoryWrite::Update(message) => {
+                    flush_inserts(&pool, &mut inserts, &dropped_writes).await;
+                    with_retries("chat message change", 1, &dropped_writes, || {
+                        update_message(&pool, &message)
+                    })
+                    .await;
+                }
+            }
+        }
+        flush_inserts(&pool, &mut inserts, &dropped_writes).await;
+    }
+}
+
+async fn flush_inserts(
+    pool: &PgPool,
+    inserts: &mut Vec<ChatRoomMessage>,
+    dropped_writes: &AtomicU64,
+) {
+    if inserts.is_empty() {
+        return;
+    }
+    with_retries("chat messages", inserts.len(), dropped_writes, || insert_messages(pool, inserts))
+        .await;
+    inserts.clear();
+}
+
+async fn with_retries<F, Fut>(what: &str, count: usize, dropped_writes: &AtomicU64, mut write: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let mut attempt = 1;
+    while let Err(error) = write().await {
+        if attempt >= WRITE_ATTEMPTS {
+            error!(error = ?error, count, "dropping unpersisted {what}");
+            dropped_writes.fetch_add(count as u64, Ordering::Relaxed);
+            break;
+        }
+        warn!(error = %error, attempt, "failed to persist {what}, retrying");
+        tokio::time::sleep(WRITE_RETRY_BACKOFF * attempt).await;
+        attempt += 1;
+    }
+}
+
+async fn update_message(pool: &PgPool, message: &ChatRoomMessage) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE "chat_room_messages"
+        SET "Content" = $3, "EditedAt" = $4, "DeletedAt" = $5
+        WHERE "RoomId" = $1 AND "Version" = $2
+        "#,
+    )
+    .bind(&message.room_id)
+    .bind(message.version as i64)
+    .bind(&message.content)
+    .bind(message.edited_at)
+    .bind(message.deleted_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn insert_messages(pool: &PgPool, messages: &[ChatRoomMessage]) -> anyhow::Result<()> {
+    let mut room_ids = Vec::with_capacity(messages.len());
+    let mut versions = Vec::with_capacity(messages.len());
+    let mut message_ids = Vec::with_capacity(messages.len());
+    let mut sender_ids = Vec::with_capacity(messages.len());
+    let mut sender_names = Vec::with_capacity(messages.len());
+    let mut contents = Vec::with_capacity(messages.len());
+    let mut sent_ats = Vec::with_capacity(messages.len());
+    let mut reply_tos = Vec::with_capacity(messages.len());
+    for message in messages {
+        room_ids.push(message.room_id.clone());
+        versions.push(message.version as i64);
+        message_ids.push(message.message_id.clone());
+        sender_ids.push(message.sender_id.clone());
+        sender_names.push(message.sender_name.clone());
+        contents.push(message.content.clone());
+        sent_ats.push(message.sent_at);
+        reply_tos.push(message.reply_to.clone());
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO "chat_room_messages"
+            ("RoomId", "Version", "MessageId", "SenderId", "SenderName", "Content", "SentAt",
+             "ReplyTo")
+        SELECT * FROM UNNEST(
+            $1::VARCHAR[], $2::INT8[], $3::VARCHAR[], $4::VARCHAR[], $5::VARCHAR[], $6::TEXT[], $7::INT8[],
+            $8::VARCHAR[]
+        )
+        ON CONFLICT ("RoomId", "Version") DO NOTHING
+        "#,
+    )
+    .bind(room_ids)
+    .bind(versions)
+    .bind(message_ids)
+    .bind(sender_ids)
+    .bind(sender_names)
+    .bind(contents)
+    .bind(sent_ats)
+    .bind(reply_tos)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
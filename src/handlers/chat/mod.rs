@@ -1,37 +1,95 @@
+pub mod auth;
+pub mod backplane;
+pub mod codec;
+pub mod direct;
+pub mod directory;
+pub mod edits;
+pub mod filters;
+pub mod heartbeat;
+pub mod history;
+pub mod moderation;
+pub mod outbound;
+pub mod presence;
+pub mod rate_limit;
+pub mod reactions;
+pub mod receipts;
+pub mod runtime;
+pub mod shutdown;
+pub mod sync;
+pub mod threads;
+
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     sync::Arc,
+    time::Duration,
 };
 
 use axum::{
+    Json,
     extract::{
-        Query, State,
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
     },
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
 use chrono::Utc;
 use futures_util::{sink::SinkExt, stream::StreamExt};
 use serde::{Deserialize, Serialize};
-use tokio::sync::{
-    Mutex, mpsc,
-    mpsc::{UnboundedReceiver, UnboundedSender},
-};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tracing::{debug, warn};
-
+use uuid::Uuid;
+
+use self::{
+    backplane::{ChatBackplane, ChatBackplaneEnvelope},
+    codec::{CHAT_PROTOCOLS, ChatWireFormat},
+    direct::{ChatDirectMessage, ChatDirectMessageAck, ChatDirectUnread},
+    directory::{ChatConnectionClosed, ChatConnectionSummary, ChatRoomClosed},
+    edits::{ChatMessageChange, ChatMessageDeleted, ChatMessageEdited, normalized_message_id},
+    filters::{ChatContentFilters, ChatFilteredContent, ChatFlaggedLog, ChatFlaggedMessage},
+    heartbeat::ChatHeartbeat,
+    history::{ChatHistory, merge_history_page},
+    moderation::{ChatModerationAction, ChatModerationNotice, ChatRoomAccess},
+    outbound::{ChatCloseReason, ChatOutbound},
+    presence::{ChatPresenceStatus, ChatRoomPresence, ChatStatusChange, ChatUserStatus},
+    rate_limit::{ChatConnectionRateLimits, ChatRateLimiter},
+    reactions::{ChatMessageReactions, ChatReactionChanged},
+    receipts::{
+        ChatReadMark, ChatReadPosition, ChatReadReceipt, ChatTypingNotice, read_positions,
+        unread_count,
+    },
+    runtime::ChatRuntime,
+    shutdown::ChatServerShutdown,
+    sync::{ChatRoomDelta, ChatRoomEventLog, ChatRoomSync, room_sync},
+    threads::{ChatReplyTarget, ChatThread, ChatThreadSummary},
+};
 use crate::{
-    error::{AppError, AppResult},
+    config::ChatConfig,
+    error::{AppError, AppResult, AuthError},
     route::AppState,
+    utils::jwt_auth::Claims,
 };
 
 const MAX_ROOM_ID_LEN: usize = 64;
 const MAX_MESSAGE_LEN: usize = 500;
 const MAX_RECENT_MESSAGES: usize = 20;
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+const MAX_HISTORY_LIMIT: usize = 100;
+/// Dropped rooms whose version is remembered; the lowest floor is forgotten first and re-seeded
+/// from history when the room is joined again.
+const MAX_VERSION_FLOORS: usize = 10_000;
+const WRITER_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ChatConnectQuery {
-    pub user_id: String,
-    pub user_name: Option<String>,
+    /// Single-use ticket from `POST /api/chat/tickets`, for clients that cannot send headers.
+    pub ticket: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatHistoryQuery {
+    pub before: Option<u64>,
+    pub limit: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
@@ -41,6 +99,8 @@ pub struct ChatEmptyPayload {}
 pub struct ChatUserSummary {
     pub user_id: String,
     pub user_name: String,
+    #[serde(default)]
+    pub status: ChatPresenceStatus,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -59,6 +119,17 @@ pub struct ChatRoomMessage {
     pub content: String,
     pub sent_at: i64,
     pub version: u64,
+    #[serde(default)]
+    pub edited_at: Option<i64>,
+    /// Set on tombstones of deleted messages, whose content is cleared.
+    #[serde(default)]
+    pub deleted_at: Option<i64>,
+    /// Users mentioned as `@user_id`, in order of first appearance.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>,
+    /// Root message of the thread this message replies to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -67,6 +138,28 @@ pub struct ChatRoomSnapshot {
     pub members: Vec<ChatUserSummary>,
     pub recent_messages: Vec<ChatRoomMessage>,
     pub version: u64,
+    pub read_positions: Vec<ChatReadPosition>,
+    /// Messages the requesting user has not marked read.
+    pub unread_count: u64,
+    /// Set when a delta was asked for but the events since that version are gone.
+    pub delta_unavailable: bool,
+    pub owner_id: Option<String>,
+    pub moderator_ids: Vec<String>,
+    pub private: bool,
+    /// Summaries of the room's threads, also of roots no longer among the recent messages.
+    #[serde(default)]
+    pub threads: Vec<ChatThreadSummary>,
+    /// Reactions of every message that has any, also of ones no longer among the recent messages.
+    #[serde(default)]
+    pub reactions: Vec<ChatMessageReactions>,
+}
+
+/// One page of room history, oldest first; pass `next_before` back to fetch the previous page.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatRoomHistory {
+    pub room_id: String,
+    pub messages: Vec<ChatRoomMessage>,
+    pub next_before: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -87,15 +180,118 @@ pub struct ChatLeftRoomNotice {
 pub struct ChatErrorPayload {
     pub code: String,
     pub message: String,
+    /// Set on `rate_limited` errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(tag = "type", content = "payload", rename_all = "snake_case")]
 pub enum ChatCommand {
-    JoinRoom { room_id: String },
-    LeaveRoom { room_id: String },
-    SendRoomMessage { room_id: String, content: String },
-    SyncRoomState { room_id: String },
+    JoinRoom {
+        room_id: String,
+        since_version: Option<u64>,
+    },
+    /// Creates a room owned by the caller and joins it; private rooms only admit invited users.
+    CreateRoom {
+        room_id: String,
+        #[serde(default)]
+        private: bool,
+    },
+    LeaveRoom {
+        room_id: String,
+    },
+    /// Replying to a message starts its thread, or joins the thread it already belongs to.
+    SendRoomMessage {
+        room_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reply_to: Option<String>,
+    },
+    EditRoomMessage {
+        room_id: String,
+        message_id: String,
+        content: String,
+    },
+    DeleteRoomMessage {
+        room_id: String,
+        message_id: String,
+    },
+    /// Loads the thread a message starts or belongs to.
+    LoadThread {
+        room_id: String,
+        message_id: String,
+    },
+    React {
+        room_id: String,
+        message_id: String,
+        emoji: String,
+    },
+    Unreact {
+        room_id: String,
+        message_id: String,
+        emoji: String,
+    },
+    SyncRoomState {
+        room_id: String,
+        since_version: Option<u64>,
+    },
+    Typing {
+        room_id: String,
+    },
+    MarkRead {
+        room_id: String,
+        version: u64,
+    },
+    LoadHistory {
+        room_id: String,
+        before: Option<u64>,
+        limit: Option<usize>,
+    },
+    SendDirectMessage {
+        to_user_id: String,
+        content: String,
+    },
+    MarkDirectRead {
+        user_id: String,
+    },
+    InviteToRoom {
+        room_id: String,
+        user_id: String,
+    },
+    AddModerator {
+        room_id: String,
+        user_id: String,
+    },
+    RemoveModerator {
+        room_id: String,
+        user_id: String,
+    },
+    KickMember {
+        room_id: String,
+        user_id: String,
+    },
+    /// Mutes for `duration_secs`, at most a week, which is also the default.
+    MuteMember {
+        room_id: String,
+        user_id: String,
+        duration_secs: Option<u64>,
+    },
+    UnmuteMember {
+        room_id: String,
+        user_id: String,
+    },
+    BanMember {
+        room_id: String,
+        user_id: String,
+    },
+    UnbanMember {
+        room_id: String,
+        user_id: String,
+    },
+    SetStatus {
+        status: ChatPresenceStatus,
+    },
     Ping(ChatEmptyPayload),
 }
 
@@ -103,9 +299,29 @@ impl ChatCommand {
     pub fn event_type(&self) -> &'static str {
         match self {
             Self::JoinRoom { .. } => "join_room",
+            Self::CreateRoom { .. } => "create_room",
             Self::LeaveRoom { .. } => "leave_room",
             Self::SendRoomMessage { .. } => "send_room_message",
+            Self::EditRoomMessage { .. } => "edit_room_message",
+            Self::DeleteRoomMessage { .. } => "delete_room_message",
+            Self::LoadThread { .. } => "load_thread",
+            Self::React { .. } => "react",
+            Self::Unreact { .. } => "unreact",
             Self::SyncRoomState { .. } => "sync_room_state",
+            Self::Typing { .. } => "typing",
+            Self::MarkRead { .. } => "mark_read",
+            Self::LoadHistory { .. } => "load_history",
+            Self::SendDirectMessage { .. } => "send_direct_message",
+            Self::MarkDirectRead { .. } => "mark_direct_read",
+            Self::InviteToRoom { .. } => "invite_to_room",
+            Self::AddModerator { .. } => "add_moderator",
+            Self::RemoveModerator { .. } => "remove_moderator",
+            Self::KickMember { .. } => "kick_member",
+            Self::MuteMember { .. } => "mute_member",
+            Self::UnmuteMember { .. } => "unmute_member",
+            Self::BanMember { .. } => "ban_member",
+            Self::UnbanMember { .. } => "unban_member",
+            Self::SetStatus { .. } => "set_status",
             Self::Ping(_) => "ping",
         }
     }
@@ -118,8 +334,25 @@ pub enum ChatEvent {
     JoinedRoom(ChatRoomSnapshot),
     LeftRoom(ChatLeftRoomNotice),
     RoomMessage(ChatRoomMessage),
+    MessageEdited(ChatMessageEdited),
+    MessageDeleted(ChatMessageDeleted),
+    ReactionChanged(ChatReactionChanged),
     RoomState(ChatRoomSnapshot),
+    RoomDelta(ChatRoomDelta),
+    RoomHistory(ChatRoomHistory),
+    Thread(ChatThread),
     PresenceChanged(ChatPresenceChange),
+    StatusChanged(ChatStatusChange),
+    UserStatus(ChatUserStatus),
+    Typing(ChatTypingNotice),
+    ReadReceipt(ChatReadReceipt),
+    RoomModeration(ChatModerationNotice),
+    RoomClosed(ChatRoomClosed),
+    ConnectionClosed(ChatConnectionClosed),
+    ServerShutdown(ChatServerShutdown),
+    DirectMessage(ChatDirectMessage),
+    DirectMessageSent(ChatDirectMessageAck),
+    DirectUnread(ChatDirectUnread),
     Pong(ChatEmptyPayload),
     Error(ChatErrorPayload),
 }
@@ -132,8 +365,56 @@ pub enum ChatError {
     EmptyContent,
     #[error("room message content is too long")]
     ContentTooLong { max_len: usize },
+    #[error("direct message recipient is invalid")]
+    InvalidRecipient,
     #[error("connection is not in room {room_id}")]
     NotInRoom { room_id: String },
+    #[error("room message {message_id} was not found")]
+    MessageNotFound { message_id: String },
+    #[error("not allowed to change this room message")]
+    Forbidden,
+    #[error("room already exists")]
+    RoomExists,
+    #[error("room is private and needs an invite")]
+    InviteRequired,
+    #[error("banned from this room")]
+    BannedFromRoom,
+    #[error("muted in this room until {until}")]
+    Muted { until: i64 },
+    #[error("only room moderators may do this")]
+    NotRoomModerator,
+    #[error("only the room owner may do this")]
+    NotRoomOwner,
+    #[error("moderation target is invalid")]
+    InvalidModerationTarget,
+    #[error("too many commands, retry in {retry_after_ms} ms")]
+    RateLimited { retry_after_ms: u64 },
+    #[error("room {room_id} was not found")]
+    RoomNotFound { room_id: String },
+    #[error("connection {connection_id} was not found")]
+    ConnectionNotFound { connection_id: String },
+    #[error("only chat admins may do this")]
+    AdminRequired,
+    #[error("room message contains a banned word")]
+    BannedContent,
+    #[error("links to this site are not allowed")]
+    LinkNotAllowed,
+    #[error("room message mentions more than {max_mentions} users")]
+    TooManyMentions { max_mentions: usize },
+    #[error("room message was rejected: {reason}")]
+    ContentRejected { reason: String },
+    #[error("reaction must be a short emoji without spaces")]
+    InvalidReaction,
+    #[error("room message has more than {max_reactions} different reactions")]
+    TooManyReactions { max_reactions: usize },
+    #[error("room history is unavailable")]
+    HistoryUnavailable,
+    #[error("chat backplane is unavailable")]
+    BackplaneUnavailable,
+    #[error("chat session expired")]
+    SessionExpired,
+    #[error("chat server is shutting down")]
+    ShuttingDown,
 }
 
 impl ChatError {
@@ -142,7 +423,31 @@ impl ChatError {
             Self::InvalidRoomId => "invalid_room_id",
             Self::EmptyContent => "empty_content",
             Self::ContentTooLong { .. } => "content_too_long",
+            Self::InvalidRecipient => "invalid_recipient",
             Self::NotInRoom { .. } => "not_in_room",
+            Self::MessageNotFound { .. } => "message_not_found",
+            Self::Forbidden => "forbidden",
+            Self::RoomExists => "room_exists",
+            Self::InviteRequired => "invite_required",
+            Self::BannedFromRoom => "banned_from_room",
+            Self::Muted { .. } => "muted",
+            Self::NotRoomModerator => "not_room_moderator",
+            Self::NotRoomOwner => "not_room_owner",
+            Self::InvalidModerationTarget => "invalid_moderation_target",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::RoomNotFound { .. } => "room_not_found",
+            Self::ConnectionNotFound { .. } => "connection_not_found",
+            Self::AdminRequired => "admin_required",
+            Self::BannedContent => "banned_content",
+            Self::LinkNotAllowed => "link_not_allowed",
+            Self::TooManyMentions { .. } => "too_many_mentions",
+            Self::ContentRejected { .. } => "content_rejected",
+            Self::InvalidReaction => "invalid_reaction",
+            Self::TooManyReactions { .. } => "too_many_reactions",
+            Self::HistoryUnavailable => "history_unavailable",
+            Self::BackplaneUnavailable => "backplane_unavailable",
+            Self::SessionExpired => "session_expired",
+            Self::ShuttingDown => "shutting_down",
         }
     }
 
@@ -150,6 +455,10 @@ impl ChatError {
         ChatEvent::Error(ChatErrorPayload {
             code: self.code().to_string(),
             message: self.to_string(),
+            retry_after_ms: match self {
+                Self::RateLimited { retry_after_ms } => Some(*retry_after_ms),
+                _ => None,
+            },
         })
     }
 }
@@ -160,6 +469,9 @@ pub struct ChatJoinRoomResult {
     pub members: Vec<ChatUserSummary>,
     pub recent_messages: Vec<ChatRoomMessage>,
     pub version: u64,
+    pub read_positions: Vec<ChatReadPosition>,
+    pub unread_count: u64,
+    /// Set on the user's first connection in the room, when peers see them join.
     pub joined_newly: bool,
     pub peer_connection_ids: Vec<String>,
 }
@@ -180,15 +492,101 @@ struct ChatConnection {
 #[derive(Debug, Clone)]
 struct ChatRoom {
     version: u64,
+    /// Member connections, for routing room events.
     members: BTreeMap<String, ChatUserSummary>,
+    /// Members by user, so a user with several connections is listed once.
+    presence: BTreeMap<String, ChatRoomPresence>,
     recent_messages: Vec<ChatRoomMessage>,
+    /// Messages sent since the room was loaded, the base for unread counts.
+    message_count: u64,
+    /// Last read position per user, kept while the room is loaded.
+    read_marks: BTreeMap<String, ChatReadMark>,
+    /// Last typing notice per connection, for throttling.
+    typing: HashMap<String, tokio::time::Instant>,
+    events: ChatRoomEventLog,
+    /// Unix seconds of the last message, join or leave, for the room directory.
+    last_activity_at: i64,
+    /// Thread summaries by root message id, kept while the room is loaded.
+    threads: HashMap<String, ChatThreadSummary>,
+    /// Reactions by message id, kept while the room is loaded.
+    reactions: HashMap<String, ChatMessageReactions>,
+}
+
+impl ChatRoom {
+    fn new(version: u64) -> Self {
+        Self {
+            version,
+            members: BTreeMap::new(),
+            presence: BTreeMap::new(),
+            recent_messages: Vec::new(),
+            message_count: 0,
+            read_marks: BTreeMap::new(),
+            typing: HashMap::new(),
+            events: ChatRoomEventLog::new(version),
+            last_activity_at: Utc::now().timestamp(),
+            threads: HashMap::new(),
+            reactions: HashMap::new(),
+        }
+    }
+
+    fn snapshot(&self, room_id: &str, user_id: &str) -> ChatRoomSnapshot {
+        let members = sorted_members(self);
+        ChatRoomSnapshot {
+            room_id: room_id.to_string(),
+            read_positions: read_positions(&members, &self.read_marks),
+            unread_count: unread_count(user_id, self.message_count, &self.read_marks),
+            members,
+            recent_messages: self.recent_messages.clone(),
+            version: self.version,
+            delta_unavailable: false,
+            owner_id: None,
+            moderator_ids: Vec::new(),
+            private: false,
+            threads: self.thread_summaries(),
+            reactions: self.reaction_summaries(),
+        }
+    }
+
+    /// Adds a member connection, true when it is the user's first in the room.
+    fn add_member(&mut self, connection_id: &str, member: ChatUserSummary) -> bool {
+        self.last_activity_at = Utc::now().timestamp();
+        self.members
+            .insert(connection_id.to_string(), member.clone());
+        let presence = self
+            .presence
+            .entry(member.user_id.clone())
+            .or_insert(ChatRoomPresence { member, connections: 0 });
+        presence.connections += 1;
+        presence.connections == 1
+    }
+
+    /// Removes a member connection, returning the user once their last connection is gone.
+    fn remove_member(&mut self, connection_id: &str) -> Option<ChatUserSummary> {
+        self.typing.remove(connection_id);
+        let member = self.members.remove(connection_id)?;
+        self.last_activity_at = Utc::now().timestamp();
+        let presence = self.presence.get_mut(&member.user_id)?;
+        presence.connections -= 1;
+        if presence.connections > 0 {
+            return None;
+        }
+        self.presence
+            .remove(&member.user_id)
+            .map(|presence| presence.member)
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct ChatHub {
     rooms: HashMap<String, ChatRoom>,
     connections: HashMap<String, ChatConnection>,
-    next_message_id: u64,
+    /// Last version of rooms that were dropped or have persisted history, so a recreated room
+    /// keeps counting instead of reusing versions.
+    version_floors: HashMap<String, u64>,
+    room_access: HashMap<String, ChatRoomAccess>,
+    /// Statuses users set, applied when they join further rooms.
+    statuses: HashMap<String, ChatPresenceStatus>,
+    flagged_messages: ChatFlaggedLog,
 }
 
 impl ChatHub {
@@ -198,14 +596,24 @@ impl ChatHub {
         room_id: &str,
     ) -> Result<ChatJoinRoomResult, ChatError> {
         let room_id = normalized_room_id(room_id)?;
-        let room = self
-            .rooms
+        // The first member of a room nobody created explicitly becomes its owner.
+        self.room_access
             .entry(room_id.clone())
-            .or_insert_with(|| ChatRoom {
-                version: 0,
-                members: BTreeMap::new(),
-                recent_messages: Vec::new(),
-            });
+            .or_insert_with(|| ChatRoomAccess::new(&user.user_id, false))
+            .check_join(&user.user_id)?;
+        Ok(self.enter_room(user, room_id))
+    }
+
+    /// Adds a member whose access was already checked, leaving the room's access rules alone.
+    fn enter_room(&mut self, user: ChatSessionUser, room_id: String) -> ChatJoinRoomResult {
+        let status = self
+            .statuses
+            .get(&user.user_id)
+            .copied()
+            .unwrap_or_default();
+        let room = self.rooms.entry(room_id.clone()).or_insert_with(|| {
+            ChatRoom::new(self.version_floors.remove(&room_id).unwrap_or_default())
+        });
         let connection = self
             .connections
             .entry(user.connection_id.clone())
@@ -215,26 +623,40 @@ impl ChatHub {
             });
         connection.user = user.clone();
 
-        let joined_newly = if room.members.contains_key(&user.connection_id) {
-            false
-        } else {
+        let member = ChatUserSummary {
+            user_id: user.user_id.clone(),
+            user_name: user.user_name.clone(),
+            status,
+        };
+        let joined_newly = !room.members.contains_key(&user.connection_id)
+            && room.add_member(&user.connection_id, member.clone());
+        connection.joined_rooms.insert(room_id.clone());
+        if joined_newly {
             room.version += 1;
-            room.members.insert(
-                user.connection_id.clone(),
-                ChatUserSummary {
-                    user_id: user.user_id.clone(),
-                    user_name: user.user_name.clone(),
-                },
+            room.events.record(
+                room.version,
+                ChatEvent::PresenceChanged(ChatPresenceChange {
+                    room_id: room_id.clone(),
+                    joined_members: vec![member],
+                    left_members: Vec::new(),
+                    version: room.version,
+                }),
             );
-            connection.joined_rooms.insert(room_id.clone());
-            true
-        };
+        }
+        if !room.read_marks.contains_key(&user.user_id) {
+            // First-time members start caught up rather than with the whole room unread.
+            let mark = room.read_mark_at(room.version);
+            room.read_marks.insert(user.user_id.clone(), mark);
+        }
+        let snapshot = room.snapshot(&room_id, &user.user_id);
 
-        Ok(ChatJoinRoomResult {
+        ChatJoinRoomResult {
             room_id: room_id.clone(),
-            members: sorted_members(room),
-            recent_messages: room.recent_messages.clone(),
-            version: room.version,
+            members: snapshot.members,
+            recent_messages: snapshot.recent_messages,
+            version: snapshot.version,
+            read_positions: snapshot.read_positions,
+            unread_count: snapshot.unread_count,
             joined_newly,
             peer_connection_ids: room
                 .members
@@ -242,14 +664,14 @@ impl ChatHub {
                 .filter(|connection_id| *connection_id != &user.connection_id)
                 .cloned()
                 .collect(),
-        })
+        }
     }
 
     pub fn leave_room(
         &mut self,
         connection_id: &str,
         room_id: &str,
-    ) -> Result<(ChatLeftRoomNotice, ChatPresenceChange, Vec<String>), ChatError> {
+    ) -> Result<(ChatLeftRoomNotice, Option<ChatPresenceChange>, Vec<String>), ChatError> {
         let room_id = normalized_room_id(room_id)?;
         let connection = self
             .connections
@@ -259,26 +681,36 @@ impl ChatHub {
             .rooms
             .get_mut(&room_id)
             .ok_or_else(|| ChatError::NotInRoom { room_id: room_id.clone() })?;
-        let Some(left_member) = room.members.remove(connection_id) else {
+        if !room.members.contains_key(connection_id) {
             return Err(ChatError::NotInRoom { room_id });
-        };
+        }
 
         connection.joined_rooms.remove(&room_id);
-        room.version += 1;
-        let version = room.version;
+        // Peers only see the user leave with their last connection.
+        let presence = room.remove_member(connection_id).map(|left_member| {
+            room.version += 1;
+            let presence = ChatPresenceChange {
+                room_id: room_id.clone(),
+                joined_members: Vec::new(),
+                left_members: vec![left_member],
+                version: room.version,
+            };
+            room.events
+                .record(room.version, ChatEvent::PresenceChanged(presence.clone()));
+            presence
+        });
         let peer_connection_ids = room.members.keys().cloned().collect::<Vec<_>>();
-        let left_notice = ChatLeftRoomNotice { room_id: room_id.clone(), version };
-        let presence = ChatPresenceChange {
-            room_id: room_id.clone(),
-            joined_members: Vec::new(),
-            left_members: vec![left_member],
-            version,
-        };
+        let left_notice = ChatLeftRoomNotice { room_id: room_id.clone(), version: room.version };
 
         if room.members.is_empty() {
-            self.rooms.remove(&room_id);
+            let version = room.version;
+            self.drop_room(&room_id, version);
         }
-        if connection.joined_rooms.is_empty() {
+        if self
+            .connections
+            .get(connection_id)
+            .is_some_and(|connection| connection.joined_rooms.is_empty())
+        {
             self.connections.remove(connection_id);
         }
 
@@ -291,8 +723,25 @@ impl ChatHub {
         room_id: &str,
         content: &str,
     ) -> Result<ChatSendRoomMessageResult, ChatError> {
-        let room_id = normalized_room_id(room_id)?;
         let content = normalized_content(content)?;
+        self.send_filtered_message(
+            connection_id,
+            room_id,
+            ChatFilteredContent::unfiltered(content),
+            None,
+        )
+    }
+
+    /// Sends content that already went through the filters, keeping the message for moderators
+    /// when a filter flagged it.
+    pub fn send_filtered_message(
+        &mut self,
+        connection_id: &str,
+        room_id: &str,
+        filtered: ChatFilteredContent,
+        reply_to: Option<ChatReplyTarget>,
+    ) -> Result<ChatSendRoomMessageResult, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
         let connection = self
             .connections
             .get(connection_id)
@@ -305,29 +754,43 @@ impl ChatHub {
         if !room.members.contains_key(connection_id) {
             return Err(ChatError::NotInRoom { room_id });
         }
+        if let Some(access) = self.room_access.get(&room_id) {
+            access.check_send(&connection.user.user_id, Utc::now().timestamp())?;
+        }
+
+        let reply_to = reply_to
+            .map(|target| room.thread_root(&room_id, &target))
+            .transpose()?;
 
         room.version += 1;
-        self.next_message_id += 1;
         let message = ChatRoomMessage {
-            room_id: room_id.clone(),
-            message_id: format!("msg-{}", self.next_message_id),
-            sender_id: connection.user.user_id.clone(),
-            sender_name: connection.user.user_name.clone(),
-            content,
-            sent_at: Utc::now().timestamp(),
             version: room.version,
+            reply_to,
+            ..new_room_message(&room_id, &connection.user, filtered.content, filtered.mentions)
         };
         room.recent_messages.push(message.clone());
         if room.recent_messages.len() > MAX_RECENT_MESSAGES {
             let drop_len = room.recent_messages.len() - MAX_RECENT_MESSAGES;
             room.recent_messages.drain(0..drop_len);
         }
+        room.record_reply(&message);
+        room.message_count += 1;
+        room.last_activity_at = message.sent_at;
+        room.events
+            .record(room.version, ChatEvent::RoomMessage(message.clone()));
+        room.typing.remove(connection_id);
+        // Senders have read everything up to their own message.
+        room.read_marks.insert(
+            connection.user.user_id.clone(),
+            ChatReadMark { version: room.version, message_count: room.message_count },
+        );
+
+        let recipient_connection_ids = room.members.keys().cloned().collect();
+        if !filtered.flags.is_empty() {
+            self.flag_message(ChatFlaggedMessage::new(message.clone(), filtered.flags));
+        }
 
-        Ok(ChatSendRoomMessageResult {
-            room_id,
-            message,
-            recipient_connection_ids: room.members.keys().cloned().collect(),
-        })
+        Ok(ChatSendRoomMessageResult { room_id, message, recipient_connection_ids })
     }
 
     pub fn sync_room_state(
@@ -340,51 +803,153 @@ impl ChatHub {
             .rooms
             .get(&room_id)
             .ok_or_else(|| ChatError::NotInRoom { room_id: room_id.clone() })?;
-        if !room.members.contains_key(connection_id) {
+        let Some(member) = room.members.get(connection_id) else {
             return Err(ChatError::NotInRoom { room_id });
-        }
+        };
 
-        Ok(ChatRoomSnapshot {
-            room_id,
-            members: sorted_members(room),
-            recent_messages: room.recent_messages.clone(),
-            version: room.version,
-        })
+        let mut snapshot = room.snapshot(&room_id, &member.user_id);
+        if let Some(access) = self.room_access.get(&room_id) {
+            access.describe(&mut snapshot);
+        }
+        Ok(snapshot)
     }
 
+    /// Takes a closed connection out of its rooms, returning a change for each room the user
+    /// has no other connection in.
     pub fn disconnect(&mut self, connection_id: &str) -> Vec<ChatPresenceChange> {
         let Some(connection) = self.connections.remove(connection_id) else {
             return Vec::new();
         };
 
-        let left_member = ChatUserSummary {
-            user_id: connection.user.user_id.clone(),
-            user_name: connection.user.user_name.clone(),
-        };
         let mut changes = Vec::new();
+        let mut emptied = Vec::new();
         for room_id in connection.joined_rooms {
-            if let Some(room) = self.rooms.get_mut(&room_id) {
-                room.members.remove(connection_id);
+            if let Some(room) = self.rooms.get_mut(&room_id)
+                && let Some(left_member) = room.remove_member(connection_id)
+            {
                 room.version += 1;
-                changes.push(ChatPresenceChange {
+                let presence = ChatPresenceChange {
                     room_id: room_id.clone(),
                     joined_members: Vec::new(),
-                    left_members: vec![left_member.clone()],
+                    left_members: vec![left_member],
                     version: room.version,
-                });
+                };
+                room.events
+                    .record(room.version, ChatEvent::PresenceChanged(presence.clone()));
+                changes.push(presence);
                 if room.members.is_empty() {
-                    self.rooms.remove(&room_id);
+                    emptied.push((room_id, room.version));
                 }
             }
         }
+        for (room_id, version) in emptied {
+            self.drop_room(&room_id, version);
+        }
 
         changes
     }
 
+    /// Answers a join or sync of a member, as a delta since `since_version` when possible.
+    pub fn room_sync(
+        &self,
+        connection_id: &str,
+        room_id: &str,
+        since_version: Option<u64>,
+    ) -> Result<ChatRoomSync, ChatError> {
+        let snapshot = self.sync_room_state(connection_id, room_id)?;
+        let log = &self.rooms[&snapshot.room_id].events;
+        Ok(room_sync(snapshot, since_version, log))
+    }
+
     pub fn room_exists(&self, room_id: &str) -> bool {
         self.rooms.contains_key(room_id)
     }
 
+    /// `(room_id, connection_id)` pairs of every room membership held by this hub.
+    pub fn memberships(&self) -> Vec<(String, String)> {
+        self.rooms
+            .iter()
+            .flat_map(|(room_id, room)| {
+                room.members
+                    .keys()
+                    .map(|connection_id| (room_id.clone(), connection_id.clone()))
+            })
+            .collect()
+    }
+
+    /// Rooms of a connection with the member it joined them as.
+    pub fn connection_rooms(&self, connection_id: &str) -> Vec<(String, ChatUserSummary)> {
+        self.connections
+            .get(connection_id)
+            .into_iter()
+            .flat_map(|connection| &connection.joined_rooms)
+            .filter_map(|room_id| {
+                let member = self.rooms.get(room_id)?.members.get(connection_id)?;
+                Some((room_id.clone(), member.clone()))
+            })
+            .collect()
+    }
+
+    pub fn is_member(&self, connection_id: &str, room_id: &str) -> bool {
+        self.rooms
+            .get(room_id)
+            .is_some_and(|room| room.members.contains_key(connection_id))
+    }
+
+    /// Raises the version a not yet loaded room starts from, loaded rooms already count past it.
+    pub fn seed_room_version(&mut self, room_id: &str, version: u64) {
+        if self.rooms.contains_key(room_id) {
+            return;
+        }
+        self.raise_version_floor(room_id, version);
+    }
+
+    /// Forgets a room whose last member left. Its version is kept as a floor and its access rules
+    /// until the room is closed, so it keeps its owner, bans and privacy.
+    fn drop_room(&mut self, room_id: &str, version: u64) {
+        self.rooms.remove(room_id);
+        if let Some(access) = self.room_access.get_mut(room_id) {
+            access.forget_expired_mutes(Utc::now().timestamp());
+        }
+        self.raise_version_floor(room_id, version);
+    }
+
+    fn raise_version_floor(&mut self, room_id: &str, version: u64) {
+        if version == 0 {
+            return;
+        }
+        let floor = self.version_floors.entry(room_id.to_string()).or_default();
+        *floor = (*floor).max(version);
+        if self.version_floors.len() > MAX_VERSION_FLOORS
+            && let Some(lowest) = self
+                .version_floors
+                .iter()
+                .filter(|(floor_room_id, _)| *floor_room_id != room_id)
+                .min_by_key(|(_, floor)| **floor)
+                .map(|(floor_room_id, _)| floor_room_id.clone())
+        {
+            self.version_floors.remove(&lowest);
+        }
+    }
+
+    /// In-memory messages of a room older than `before`, oldest first.
+    pub fn recent_messages_before(
+        &self,
+        room_id: &str,
+        before: Option<u64>,
+    ) -> Vec<ChatRoomMessage> {
+        self.rooms
+            .get(room_id)
+            .map(|room| {
+                room.recent_messages
+                    .iter()
+                    .filter(|message| before.is_none_or(|before| message.version < before))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     fn chat_member_connection_ids(&self, room_id: &str) -> Vec<String> {
         self.rooms
             .get(room_id)
@@ -393,54 +958,107 @@ impl ChatHub {
     }
 }
 
-#[derive(Debug, Default)]
-struct ChatRuntime {
-    hub: ChatHub,
-    connections: HashMap<String, UnboundedSender<ChatEvent>>,
-    next_connection_id: u64,
-}
-
 #[derive(Debug, Default)]
 pub struct ChatState {
-    runtime: Mutex<ChatRuntime>,
+    runtime: ChatRuntime,
+    history: Option<ChatHistory>,
+    /// When set, room state is shared through Redis and room events reach peers via pub/sub.
+    backplane: Option<ChatBackplane>,
+    config: ChatConfig,
+    rate_limiter: ChatRateLimiter,
+    filters: ChatContentFilters,
+    /// Cancelled when the server starts shutting down, so sessions close themselves.
+    shutdown: CancellationToken,
+    sessions: TaskTracker,
 }
 
 #[derive(Debug)]
 struct ChatDispatch {
-    sender: UnboundedSender<ChatEvent>,
+    sender: Arc<ChatOutbound>,
     event: ChatEvent,
 }
 
 impl ChatState {
+    pub fn with_history(mut self, history: ChatHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub fn with_backplane(mut self, backplane: ChatBackplane) -> Self {
+        self.backplane = Some(backplane);
+        self
+    }
+
+    pub fn with_config(mut self, config: ChatConfig) -> Self {
+        self.runtime = ChatRuntime::new(config.runtime_shards);
+        self.filters.configure(&config.filters);
+        self.config = config;
+        self
+    }
+
     pub async fn register_connection(
         &self,
         user_id: &str,
         user_name: &str,
-    ) -> (ChatSessionUser, UnboundedReceiver<ChatEvent>) {
-        let (sender, receiver) = mpsc::unbounded_channel();
-        let mut runtime = self.runtime.lock().await;
-        runtime.next_connection_id += 1;
-        let connection_id = format!("chat-{}", runtime.next_connection_id);
-        runtime.connections.insert(connection_id.clone(), sender);
+    ) -> (ChatSessionUser, Arc<ChatOutbound>) {
+        let outbound = Arc::new(ChatOutbound::new(
+            self.config.outbound_queue_capacity,
+            self.config.slow_consumer_policy,
+        ));
+        // Connection ids double as backplane member ids, so they must be unique across instances.
+        let session_user = ChatSessionUser {
+            connection_id: format!("chat-{}", Uuid::new_v4().simple()),
+            user_id: user_id.to_string(),
+            user_name: user_name.to_string(),
+        };
+        let summary = ChatConnectionSummary {
+            connection_id: session_user.connection_id.clone(),
+            user_id: user_id.to_string(),
+            user_name: user_name.to_string(),
+            connected_at: Utc::now().timestamp(),
+        };
+        self.runtime
+            .connections_mut()
+            .insert(summary.clone(), outbound.clone());
+        if let Some(backplane) = &self.backplane
+            && let Err(error) = backplane.register_user_connection(&summary).await
+        {
+            warn!(error = %error, user_id, "failed to register chat user connection");
+        }
 
+        (session_user, outbound)
+    }
+
+    pub fn heartbeat(&self) -> ChatHeartbeat {
+        ChatHeartbeat::new(&self.config)
+    }
+
+    /// HTTP/2 keepalive ping interval and timeout of gRPC sessions, the websocket ping settings.
+    pub fn keepalive(&self) -> (Duration, Duration) {
         (
-            ChatSessionUser {
-                connection_id,
-                user_id: user_id.to_string(),
-                user_name: user_name.to_string(),
-            },
-            receiver,
+            Duration::from_secs(self.config.heartbeat_interval_secs.max(1)),
+            Duration::from_secs(self.config.pong_timeout_secs.max(1)),
         )
     }
 
-    pub async fn send_to_connection(&self, connection_id: &str, event: ChatEvent) {
-        let sender = {
-            let runtime = self.runtime.lock().await;
-            runtime.connections.get(connection_id).cloned()
-        };
+    /// Waits for the connection to miss a heartbeat or idle out, then closes it and leaves its
+    /// rooms so peers see the member go.
+    pub async fn expire_connection(
+        &self,
+        connection_id: &str,
+        outbound: &ChatOutbound,
+        heartbeat: &ChatHeartbeat,
+    ) -> ChatCloseReason {
+        let reason = heartbeat.expired().await;
+        debug!(connection_id, reason = reason.as_str(), "expiring chat connection");
+        outbound.close(Some(reason));
+        self.unregister_connection(connection_id).await;
+        reason
+    }
 
-        if let Some(sender) = sender {
-            let _ = sender.send(event);
+    pub async fn send_to_connection(&self, connection_id: &str, event: ChatEvent) {
+        if let Some(sender) = self.runtime.connection(connection_id) {
+            sender.push(event);
         }
     }
 
@@ -449,235 +1067,1001 @@ impl ChatState {
         session_user: &ChatSessionUser,
         message: ChatCommand,
     ) -> Result<(), ChatError> {
-        let dispatches = {
-            let mut runtime = self.runtime.lock().await;
-            match message {
-                ChatCommand::JoinRoom { room_id } => {
-                    let joined = runtime.hub.join_room(session_user.clone(), &room_id)?;
-                    let mut dispatches = dispatch_to_connection(
-                        runtime
-                            .connections
-                            .get(&session_user.connection_id)
-                            .cloned(),
-                        ChatEvent::JoinedRoom(ChatRoomSnapshot {
-                            room_id: joined.room_id.clone(),
-                            members: joined.members.clone(),
-                            recent_messages: joined.recent_messages.clone(),
-                            version: joined.version,
-                        }),
+        let message = match message {
+            ChatCommand::CreateRoom { room_id, private } => {
+                self.create_room(session_user, &room_id, private).await?;
+                ChatCommand::JoinRoom { room_id, since_version: None }
+            }
+            message => message,
+        };
+        let message = match message {
+            ChatCommand::LoadHistory { room_id, before, limit } => {
+                let is_member = self
+                    .runtime
+                    .room(&room_id)
+                    .lock()
+                    .await
+                    .is_member(&session_user.connection_id, &normalized_room_id(&room_id)?);
+                if !is_member {
+                    return Err(ChatError::NotInRoom { room_id });
+                }
+
+                let page = self
+                    .room_history(&session_user.user_id, &room_id, before, limit)
+                    .await?;
+                self.send_to_connection(&session_user.connection_id, ChatEvent::RoomHistory(page))
+                    .await;
+                return Ok(());
+            }
+            ChatCommand::SendDirectMessage { to_user_id, content } => {
+                return self
+                    .send_direct_message(session_user, &to_user_id, &content)
+                    .await;
+            }
+            ChatCommand::MarkDirectRead { user_id } => {
+                return self.mark_direct_read(session_user, &user_id).await;
+            }
+            ChatCommand::SetStatus { status } => {
+                return self.set_status(session_user, status).await;
+            }
+            ChatCommand::InviteToRoom { room_id, user_id } => {
+                return self
+                    .moderate_room(
+                        session_user,
+                        &room_id,
+                        &user_id,
+                        ChatModerationAction::Invite,
+                        None,
                     )
-                    .into_iter()
-                    .collect::<Vec<_>>();
-
-                    if joined.joined_newly {
-                        let presence = ChatEvent::PresenceChanged(ChatPresenceChange {
-                            room_id: joined.room_id,
-                            joined_members: vec![ChatUserSummary {
-                                user_id: session_user.user_id.clone(),
-                                user_name: session_user.user_name.clone(),
-                            }],
-                            left_members: Vec::new(),
-                            version: joined.version,
-                        });
-                        dispatches.extend(joined.peer_connection_ids.into_iter().filter_map(
-                            |connection_id| {
-                                runtime
-                                    .connections
-                                    .get(&connection_id)
-                                    .cloned()
-                                    .map(|sender| ChatDispatch { sender, event: presence.clone() })
-                            },
-                        ));
-                    }
+                    .await;
+            }
+            ChatCommand::AddModerator { room_id, user_id } => {
+                return self
+                    .moderate_room(
+                        session_user,
+                        &room_id,
+                        &user_id,
+                        ChatModerationAction::AddModerator,
+                        None,
+                    )
+                    .await;
+            }
+            ChatCommand::RemoveModerator { room_id, user_id } => {
+                return self
+                    .moderate_room(
+                        session_user,
+                        &room_id,
+                        &user_id,
+                        ChatModerationAction::RemoveModerator,
+                        None,
+                    )
+                    .await;
+            }
+            ChatCommand::KickMember { room_id, user_id } => {
+                return self
+                    .moderate_room(
+                        session_user,
+                        &room_id,
+                        &user_id,
+                        ChatModerationAction::Kick,
+                        None,
+                    )
+                    .await;
+            }
+            ChatCommand::MuteMember { room_id, user_id, duration_secs } => {
+                return self
+                    .moderate_room(
+                        session_user,
+                        &room_id,
+                        &user_id,
+                        ChatModerationAction::Mute,
+                        duration_secs,
+                    )
+                    .await;
+            }
+            ChatCommand::UnmuteMember { room_id, user_id } => {
+                return self
+                    .moderate_room(
+                        session_user,
+                        &room_id,
+                        &user_id,
+                        ChatModerationAction::Unmute,
+                        None,
+                    )
+                    .await;
+            }
+            ChatCommand::BanMember { room_id, user_id } => {
+                return self
+                    .moderate_room(
+                        session_user,
+                        &room_id,
+                        &user_id,
+                        ChatModerationAction::Ban,
+                        None,
+                    )
+                    .await;
+            }
+            ChatCommand::UnbanMember { room_id, user_id } => {
+                return self
+                    .moderate_room(
+                        session_user,
+                        &room_id,
+                        &user_id,
+                        ChatModerationAction::Unban,
+                        None,
+                    )
+                    .await;
+            }
+            ChatCommand::EditRoomMessage { room_id, message_id, content } => {
+                return self
+                    .modify_room_message(
+                        session_user,
+                        &room_id,
+                        &message_id,
+                        ChatMessageChange::Edit { content },
+                    )
+                    .await;
+            }
+            ChatCommand::DeleteRoomMessage { room_id, message_id } => {
+                return self
+                    .modify_room_message(
+                        session_user,
+                        &room_id,
+                        &message_id,
+                        ChatMessageChange::Delete,
+                    )
+                    .await;
+            }
+            ChatCommand::LoadThread { room_id, message_id } => {
+                let thread = self
+                    .room_thread(session_user, &room_id, &message_id)
+                    .await?;
+                self.send_to_connection(&session_user.connection_id, ChatEvent::Thread(thread))
+                    .await;
+                return Ok(());
+            }
+            ChatCommand::React { room_id, message_id, emoji } => {
+                return self
+                    .react_to_message(session_user, &room_id, &message_id, &emoji, true)
+                    .await;
+            }
+            ChatCommand::Unreact { room_id, message_id, emoji } => {
+                return self
+                    .react_to_message(session_user, &room_id, &message_id, &emoji, false)
+                    .await;
+            }
+            message if self.backplane.is_some() => {
+                return self.process_distributed(session_user, message).await;
+            }
+            ChatCommand::JoinRoom { room_id, since_version } => {
+                self.seed_room_version(&room_id).await;
+                ChatCommand::JoinRoom { room_id, since_version }
+            }
+            message => message,
+        };
 
-                    dispatches
+        let dispatches = match message {
+            ChatCommand::JoinRoom { room_id, since_version } => {
+                let mut hub = self.runtime.room(&room_id).lock().await;
+                let joined = hub.join_room(session_user.clone(), &room_id)?;
+                let reply = match hub.room_sync(
+                    &session_user.connection_id,
+                    &joined.room_id,
+                    since_version,
+                )? {
+                    ChatRoomSync::Delta(delta) => ChatEvent::RoomDelta(delta),
+                    ChatRoomSync::Snapshot(snapshot) => ChatEvent::JoinedRoom(snapshot),
+                };
+                let mut dispatches = self
+                    .runtime
+                    .dispatches([session_user.connection_id.clone()], reply);
+
+                let member = joined
+                    .members
+                    .iter()
+                    .find(|member| member.user_id == session_user.user_id);
+                if joined.joined_newly
+                    && let Some(member) = member
+                {
+                    let presence = ChatEvent::PresenceChanged(ChatPresenceChange {
+                        room_id: joined.room_id,
+                        joined_members: vec![member.clone()],
+                        left_members: Vec::new(),
+                        version: joined.version,
+                    });
+                    dispatches.extend(
+                        self.runtime
+                            .dispatches(joined.peer_connection_ids, presence),
+                    );
                 }
-                ChatCommand::LeaveRoom { room_id } => {
-                    let (left_notice, presence, peer_connection_ids) = runtime
-                        .hub
-                        .leave_room(&session_user.connection_id, &room_id)?;
-                    let mut dispatches = dispatch_to_connection(
-                        runtime
-                            .connections
-                            .get(&session_user.connection_id)
-                            .cloned(),
-                        ChatEvent::LeftRoom(left_notice),
-                    )
+
+                dispatches
+            }
+            ChatCommand::LeaveRoom { room_id } => {
+                let (left_notice, presence, peer_connection_ids) = self
+                    .runtime
+                    .room(&room_id)
+                    .lock()
+                    .await
+                    .leave_room(&session_user.connection_id, &room_id)?;
+                let mut dispatches = self.runtime.dispatches(
+                    [session_user.connection_id.clone()],
+                    ChatEvent::LeftRoom(left_notice),
+                );
+                if let Some(presence) = presence {
+                    dispatches.extend(
+                        self.runtime
+                            .dispatches(peer_connection_ids, ChatEvent::PresenceChanged(presence)),
+                    );
+                }
+                dispatches
+            }
+            ChatCommand::SendRoomMessage { room_id, content, reply_to } => {
+                let room_id = normalized_room_id(&room_id)?;
+                let filtered = self
+                    .filter_content(session_user, &room_id, &content)
+                    .await?;
+                let sent = self
+                    .send_local_message(session_user, &room_id, filtered, reply_to)
+                    .await?;
+                if let Some(history) = &self.history {
+                    history.persist(sent.message.clone());
+                }
+                self.runtime
+                    .dispatches(sent.recipient_connection_ids, ChatEvent::RoomMessage(sent.message))
+            }
+            ChatCommand::SyncRoomState { room_id, since_version } => {
+                let sync = self.runtime.room(&room_id).lock().await.room_sync(
+                    &session_user.connection_id,
+                    &room_id,
+                    since_version,
+                )?;
+                let reply = match sync {
+                    ChatRoomSync::Delta(delta) => ChatEvent::RoomDelta(delta),
+                    ChatRoomSync::Snapshot(snapshot) => ChatEvent::RoomState(snapshot),
+                };
+                self.runtime
+                    .dispatches([session_user.connection_id.clone()], reply)
+            }
+            ChatCommand::Typing { room_id } => {
+                let hub = &mut *self.runtime.room(&room_id).lock().await;
+                match hub.start_typing(
+                    &session_user.connection_id,
+                    &room_id,
+                    tokio::time::Instant::now(),
+                )? {
+                    Some(notice) => self.runtime.room_dispatches(
+                        hub,
+                        &notice.room_id.clone(),
+                        ChatEvent::Typing(notice),
+                        Some(&session_user.connection_id),
+                    ),
+                    None => Vec::new(),
+                }
+            }
+            ChatCommand::MarkRead { room_id, version } => {
+                let hub = &mut *self.runtime.room(&room_id).lock().await;
+                match hub.mark_read(&session_user.connection_id, &room_id, version)? {
+                    Some(receipt) => self.runtime.room_dispatches(
+                        hub,
+                        &receipt.room_id.clone(),
+                        ChatEvent::ReadReceipt(receipt),
+                        Some(&session_user.connection_id),
+                    ),
+                    None => Vec::new(),
+                }
+            }
+            // Answered above without holding a room lock across async lookups.
+            ChatCommand::LoadHistory { .. }
+            | ChatCommand::SendDirectMessage { .. }
+            | ChatCommand::MarkDirectRead { .. }
+            | ChatCommand::EditRoomMessage { .. }
+            | ChatCommand::DeleteRoomMessage { .. }
+            | ChatCommand::LoadThread { .. }
+            | ChatCommand::React { .. }
+            | ChatCommand::Unreact { .. }
+            | ChatCommand::CreateRoom { .. }
+            | ChatCommand::InviteToRoom { .. }
+            | ChatCommand::AddModerator { .. }
+            | ChatCommand::RemoveModerator { .. }
+            | ChatCommand::KickMember { .. }
+            | ChatCommand::MuteMember { .. }
+            | ChatCommand::UnmuteMember { .. }
+            | ChatCommand::BanMember { .. }
+            | ChatCommand::UnbanMember { .. }
+            | ChatCommand::SetStatus { .. } => Vec::new(),
+            ChatCommand::Ping(_) => self.runtime.dispatches(
+                [session_user.connection_id.clone()],
+                ChatEvent::Pong(ChatEmptyPayload::default()),
+            ),
+        };
+
+        for dispatch in dispatches {
+            dispatch.sender.push(dispatch.event);
+        }
+
+        Ok(())
+    }
+
+    /// First events of every connection: who it is connected as and its unread direct messages.
+    pub async fn welcome(&self, session_user: &ChatSessionUser) {
+        let connection_id = &session_user.connection_id;
+        self.send_to_connection(
+            connection_id,
+            ChatEvent::Connected {
+                user_id: session_user.user_id.clone(),
+                user_name: session_user.user_name.clone(),
+            },
+        )
+        .await;
+        if let Err(error) = self.send_direct_unread(session_user).await {
+            self.send_to_connection(connection_id, error.to_event())
+                .await;
+        }
+    }
+
+    /// Runs a command from a client transport through its rate limits, answering failures with an
+    /// error event. Errs with the close reason once the client keeps exceeding its limits.
+    pub async fn receive_command(
+        &self,
+        session_user: &ChatSessionUser,
+        rate_limits: &mut ChatConnectionRateLimits,
+        command: ChatCommand,
+    ) -> Result<(), ChatCloseReason> {
+        let result = match rate_limits.check(&command) {
+            Ok(()) => self.process_message(session_user, command).await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            self.send_to_connection(&session_user.connection_id, error.to_event())
+                .await;
+        }
+        if rate_limits.exhausted() {
+            return Err(ChatCloseReason::RateLimited);
+        }
+        Ok(())
+    }
+
+    /// Pages backwards through the history of a room `user_id` may join, merging persisted
+    /// messages with ones still queued for the history writer.
+    pub async fn room_history(
+        &self,
+        user_id: &str,
+        room_id: &str,
+        before: Option<u64>,
+        limit: Option<usize>,
+    ) -> Result<ChatRoomHistory, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let limit = sanitized_history_limit(limit);
+        let cached = match &self.backplane {
+            Some(backplane) => {
+                if let Some(access) = backplane
+                    .room_access(&room_id)
+                    .await
+                    .map_err(backplane_error)?
+                {
+                    access.check_join(user_id)?;
+                }
+                let (snapshot, _) = backplane
+                    .room_snapshot(&room_id, None)
+                    .await
+                    .map_err(backplane_error)?;
+                snapshot
+                    .recent_messages
                     .into_iter()
-                    .collect::<Vec<_>>();
-                    let presence_event = ChatEvent::PresenceChanged(presence);
-                    dispatches.extend(peer_connection_ids.into_iter().filter_map(
-                        |connection_id| {
-                            runtime
-                                .connections
-                                .get(&connection_id)
-                                .cloned()
-                                .map(|sender| ChatDispatch {
-                                    sender,
-                                    event: presence_event.clone(),
-                                })
-                        },
-                    ));
-                    dispatches
+                    .filter(|message| before.is_none_or(|before| message.version < before))
+                    .collect()
+            }
+            None => {
+                let hub = self.runtime.room(&room_id).lock().await;
+                if let Some(access) = hub.room_access(&room_id) {
+                    access.check_join(user_id)?;
                 }
-                ChatCommand::SendRoomMessage { room_id, content } => {
-                    let sent = runtime.hub.send_room_message(
-                        &session_user.connection_id,
+                hub.recent_messages_before(&room_id, before)
+            }
+        };
+        let stored = match &self.history {
+            Some(history) => history
+                .load_before(&room_id, before, limit + 1)
+                .await
+                .map_err(|error| {
+                    warn!(error = %error, room_id, "failed to load chat history");
+                    ChatError::HistoryUnavailable
+                })?,
+            None => Vec::new(),
+        };
+
+        Ok(merge_history_page(room_id, limit, cached, stored))
+    }
+
+    /// Makes sure a room that is about to be recreated continues after its persisted versions.
+    async fn seed_room_version(&self, room_id: &str) {
+        let Ok(room_id) = normalized_room_id(room_id) else {
+            return;
+        };
+        let version = self.persisted_room_version(&room_id).await;
+        self.runtime
+            .room(&room_id)
+            .lock()
+            .await
+            .seed_room_version(&room_id, version);
+    }
+
+    /// Latest persisted version of a room not loaded on this instance, zero when unknown.
+    async fn persisted_room_version(&self, room_id: &str) -> u64 {
+        let Some(history) = &self.history else {
+            return 0;
+        };
+        if self.runtime.room(room_id).lock().await.room_exists(room_id) {
+            return 0;
+        }
+
+        history
+            .latest_version(room_id)
+            .await
+            .unwrap_or_else(|error| {
+                warn!(error = %error, room_id, "failed to load latest chat room version");
+                0
+            })
+    }
+
+    /// Handles room commands against the Redis backplane; the local hub only tracks which rooms
+    /// this instance's connections are in, so published room events can be routed to them.
+    async fn process_distributed(
+        &self,
+        session_user: &ChatSessionUser,
+        message: ChatCommand,
+    ) -> Result<(), ChatError> {
+        let Some(backplane) = &self.backplane else {
+            return Ok(());
+        };
+        let connection_id = &session_user.connection_id;
+        let member = ChatUserSummary {
+            user_id: session_user.user_id.clone(),
+            user_name: session_user.user_name.clone(),
+            status: ChatPresenceStatus::default(),
+        };
+
+        match message {
+            ChatCommand::JoinRoom { room_id, since_version } => {
+                let room_id = normalized_room_id(&room_id)?;
+                // The first member of a room nobody created explicitly becomes its owner.
+                let access = backplane
+                    .ensure_room_access(&room_id, &ChatRoomAccess::new(&member.user_id, false))
+                    .await
+                    .map_err(backplane_error)?;
+                access.check_join(&member.user_id)?;
+                let member = ChatUserSummary {
+                    status: backplane
+                        .user_status(&member.user_id)
+                        .await
+                        .map_err(backplane_error)?,
+                    ..member
+                };
+                let version_floor = self.persisted_room_version(&room_id).await;
+                let mut joined = backplane
+                    .join_room(&room_id, connection_id, &member, version_floor)
+                    .await
+                    .map_err(backplane_error)?;
+                // Access lives on the backplane, the local hub only routes the room's events.
+                self.runtime
+                    .room(&room_id)
+                    .lock()
+                    .await
+                    .enter_room(session_user.clone(), room_id.clone());
+                self.touch_room(backplane, &room_id).await;
+                access.describe(&mut joined.snapshot);
+                let reply = match self
+                    .distributed_room_sync(backplane, joined.snapshot.clone(), since_version)
+                    .await?
+                {
+                    ChatRoomSync::Delta(delta) => ChatEvent::RoomDelta(delta),
+                    ChatRoomSync::Snapshot(snapshot) => ChatEvent::JoinedRoom(snapshot),
+                };
+                self.send_to_connection(connection_id, reply).await;
+
+                if let Some(expired) = joined.expired {
+                    self.publish(&room_id, ChatEvent::PresenceChanged(expired), None)
+                        .await;
+                }
+                if joined.joined_newly {
+                    let presence = ChatPresenceChange {
+                        room_id: room_id.clone(),
+                        joined_members: vec![member],
+                        left_members: Vec::new(),
+                        version: joined.snapshot.version,
+                    };
+                    self.publish(
                         &room_id,
-                        &content,
-                    )?;
-                    let event = ChatEvent::RoomMessage(sent.message);
-                    sent.recipient_connection_ids
-                        .into_iter()
-                        .filter_map(|connection_id| {
-                            runtime
-                                .connections
-                                .get(&connection_id)
-                                .cloned()
-                                .map(|sender| ChatDispatch { sender, event: event.clone() })
-                        })
-                        .collect()
+                        ChatEvent::PresenceChanged(presence),
+                        Some(connection_id.clone()),
+                    )
+                    .await;
+                }
+            }
+            ChatCommand::LeaveRoom { room_id } => {
+                let room_id = normalized_room_id(&room_id)?;
+                self.runtime
+                    .room(&room_id)
+                    .lock()
+                    .await
+                    .leave_room(connection_id, &room_id)?;
+                let left = backplane
+                    .leave_room(&room_id, connection_id, &member)
+                    .await
+                    .map_err(backplane_error)?
+                    .ok_or_else(|| ChatError::NotInRoom { room_id: room_id.clone() })?;
+                self.touch_room(backplane, &room_id).await;
+                self.send_to_connection(
+                    connection_id,
+                    ChatEvent::LeftRoom(ChatLeftRoomNotice {
+                        room_id: room_id.clone(),
+                        version: left.version,
+                    }),
+                )
+                .await;
+                if let Some(presence) = left.presence {
+                    self.publish(&room_id, ChatEvent::PresenceChanged(presence), None)
+                        .await;
+                }
+            }
+            ChatCommand::SendRoomMessage { room_id, content, reply_to } => {
+                let room_id = normalized_room_id(&room_id)?;
+                let reply_to = reply_to
+                    .map(|message_id| normalized_message_id(&message_id))
+                    .transpose()?;
+                if !self
+                    .runtime
+                    .room(&room_id)
+                    .lock()
+                    .await
+                    .is_member(connection_id, &room_id)
+                {
+                    return Err(ChatError::NotInRoom { room_id });
+                }
+                if let Some(access) = backplane
+                    .room_access(&room_id)
+                    .await
+                    .map_err(backplane_error)?
+                {
+                    access.check_send(&member.user_id, Utc::now().timestamp())?;
+                }
+
+                let filtered = self
+                    .filter_content(session_user, &room_id, &content)
+                    .await?;
+                let message = ChatRoomMessage {
+                    reply_to,
+                    ..new_room_message(&room_id, session_user, filtered.content, filtered.mentions)
+                };
+                // A reply to a message outside the recent window retries with its stored copy.
+                let mut stored = None;
+                let message = loop {
+                    let appended = backplane
+                        .append_message(connection_id, &message, stored.as_ref())
+                        .await
+                        .map_err(backplane_error)?;
+                    match appended {
+                        Err(ChatError::MessageNotFound { message_id })
+                            if stored.is_none() && self.history.is_some() =>
+                        {
+                            stored = Some(self.stored_message(&room_id, &message_id).await?);
+                        }
+                        appended => break appended?,
+                    }
+                };
+                if let Some(history) = &self.history {
+                    history.persist(message.clone());
+                }
+                if !filtered.flags.is_empty() {
+                    self.record_flagged(ChatFlaggedMessage::new(message.clone(), filtered.flags))
+                        .await;
+                }
+                self.touch_room(backplane, &room_id).await;
+                self.publish(&room_id, ChatEvent::RoomMessage(message), None)
+                    .await;
+            }
+            ChatCommand::SyncRoomState { room_id, since_version } => {
+                let room_id = normalized_room_id(&room_id)?;
+                if !self
+                    .runtime
+                    .room(&room_id)
+                    .lock()
+                    .await
+                    .is_member(connection_id, &room_id)
+                {
+                    return Err(ChatError::NotInRoom { room_id });
+                }
+
+                let (mut snapshot, expired) = backplane
+                    .room_snapshot(&room_id, Some(&session_user.user_id))
+                    .await
+                    .map_err(backplane_error)?;
+                if let Some(access) = backplane
+                    .room_access(&room_id)
+                    .await
+                    .map_err(backplane_error)?
+                {
+                    access.describe(&mut snapshot);
+                }
+                let reply = match self
+                    .distributed_room_sync(backplane, snapshot, since_version)
+                    .await?
+                {
+                    ChatRoomSync::Delta(delta) => ChatEvent::RoomDelta(delta),
+                    ChatRoomSync::Snapshot(snapshot) => ChatEvent::RoomState(snapshot),
+                };
+                self.send_to_connection(connection_id, reply).await;
+                if let Some(expired) = expired {
+                    self.publish(&room_id, ChatEvent::PresenceChanged(expired), None)
+                        .await;
+                }
+            }
+            ChatCommand::Typing { room_id } => {
+                // Throttling is per connection, so this instance's hub is enough to track it.
+                let notice = self.runtime.room(&room_id).lock().await.start_typing(
+                    connection_id,
+                    &room_id,
+                    tokio::time::Instant::now(),
+                )?;
+                if let Some(notice) = notice {
+                    let room_id = notice.room_id.clone();
+                    self.publish(&room_id, ChatEvent::Typing(notice), Some(connection_id.clone()))
+                        .await;
                 }
-                ChatCommand::SyncRoomState { room_id } => {
-                    let snapshot = runtime
-                        .hub
-                        .sync_room_state(&session_user.connection_id, &room_id)?;
-                    dispatch_to_connection(
-                        runtime
-                            .connections
-                            .get(&session_user.connection_id)
-                            .cloned(),
-                        ChatEvent::RoomState(snapshot),
+            }
+            ChatCommand::MarkRead { room_id, version } => {
+                let room_id = normalized_room_id(&room_id)?;
+                if !self
+                    .runtime
+                    .room(&room_id)
+                    .lock()
+                    .await
+                    .is_member(connection_id, &room_id)
+                {
+                    return Err(ChatError::NotInRoom { room_id });
+                }
+
+                let receipt = backplane
+                    .mark_read(&room_id, &session_user.user_id, version)
+                    .await
+                    .map_err(backplane_error)?;
+                if let Some(receipt) = receipt {
+                    self.publish(
+                        &room_id,
+                        ChatEvent::ReadReceipt(receipt),
+                        Some(connection_id.clone()),
                     )
-                    .into_iter()
-                    .collect()
+                    .await;
                 }
-                ChatCommand::Ping(_) => dispatch_to_connection(
-                    runtime
-                        .connections
-                        .get(&session_user.connection_id)
-                        .cloned(),
+            }
+            ChatCommand::LoadHistory { .. }
+            | ChatCommand::SendDirectMessage { .. }
+            | ChatCommand::MarkDirectRead { .. }
+            | ChatCommand::EditRoomMessage { .. }
+            | ChatCommand::DeleteRoomMessage { .. }
+            | ChatCommand::LoadThread { .. }
+            | ChatCommand::React { .. }
+            | ChatCommand::Unreact { .. }
+            | ChatCommand::CreateRoom { .. }
+            | ChatCommand::InviteToRoom { .. }
+            | ChatCommand::AddModerator { .. }
+            | ChatCommand::RemoveModerator { .. }
+            | ChatCommand::KickMember { .. }
+            | ChatCommand::MuteMember { .. }
+            | ChatCommand::UnmuteMember { .. }
+            | ChatCommand::BanMember { .. }
+            | ChatCommand::UnbanMember { .. }
+            | ChatCommand::SetStatus { .. } => {}
+            ChatCommand::Ping(_) => {
+                self.send_to_connection(
+                    connection_id,
                     ChatEvent::Pong(ChatEmptyPayload::default()),
                 )
-                .into_iter()
-                .collect(),
+                .await;
             }
-        };
-
-        for dispatch in dispatches {
-            let _ = dispatch.sender.send(dispatch.event);
         }
 
         Ok(())
     }
 
-    pub async fn unregister_connection(&self, connection_id: &str) {
+    /// Reads the shared event log only when a delta was asked for.
+    async fn distributed_room_sync(
+        &self,
+        backplane: &ChatBackplane,
+        snapshot: ChatRoomSnapshot,
+        since_version: Option<u64>,
+    ) -> Result<ChatRoomSync, ChatError> {
+        if since_version.is_none() {
+            return Ok(ChatRoomSync::Snapshot(snapshot));
+        }
+        let log = backplane
+            .room_event_log(&snapshot.room_id, snapshot.version)
+            .await
+            .map_err(backplane_error)?;
+        Ok(room_sync(snapshot, since_version, &log))
+    }
+
+    async fn publish(
+        &self,
+        room_id: &str,
+        event: ChatEvent,
+        exclude_connection_id: Option<String>,
+    ) {
+        let Some(backplane) = &self.backplane else {
+            return;
+        };
+        let envelope =
+            ChatBackplaneEnvelope { room_id: room_id.to_string(), event, exclude_connection_id };
+        if let Err(error) = backplane.publish(&envelope).await {
+            warn!(error = %error, room_id, "failed to publish chat room event");
+        }
+    }
+
+    /// Delivers a backplane room event to the members of that room connected to this instance.
+    async fn deliver_local(&self, envelope: ChatBackplaneEnvelope) {
         let dispatches = {
-            let mut runtime = self.runtime.lock().await;
-            runtime.connections.remove(connection_id);
-            runtime
-                .hub
-                .disconnect(connection_id)
-                .into_iter()
-                .flat_map(|presence| {
-                    let event = ChatEvent::PresenceChanged(presence.clone());
-                    runtime
-                        .hub
-                        .chat_member_connection_ids(&presence.room_id)
-                        .into_iter()
-                        .filter_map(|peer_connection_id| {
-                            runtime
-                                .connections
-                                .get(&peer_connection_id)
-                                .cloned()
-                                .map(|sender| ChatDispatch { sender, event: event.clone() })
-                        })
-                        .collect::<Vec<_>>()
-                })
-                .collect::<Vec<_>>()
+            let mut hub = self.runtime.room(&envelope.room_id).lock().await;
+            let dispatches = self.runtime.room_dispatches(
+                &hub,
+                &envelope.room_id,
+                envelope.event.clone(),
+                envelope.exclude_connection_id.as_deref(),
+            );
+            // Kicked and banned users stop receiving the room's events on every instance.
+            if let ChatEvent::RoomModeration(notice) = &envelope.event
+                && notice.action.removes_member()
+            {
+                hub.remove_user(&envelope.room_id, &notice.user_id);
+            }
+            if let ChatEvent::RoomClosed(closed) = &envelope.event {
+                hub.close_room(&envelope.room_id, closed.reason.clone());
+            }
+            dispatches
         };
 
         for dispatch in dispatches {
-            let _ = dispatch.sender.send(dispatch.event);
+            dispatch.sender.push(dispatch.event);
         }
     }
-}
 
+    async fn local_memberships(&self) -> Vec<(String, String)> {
+        let mut memberships = Vec::new();
+        for shard in self.runtime.shards() {
+            memberships.extend(shard.lock().await.memberships());
+        }
+        memberships
+    }
+
+    /// `(user_id, connection_id)` of every connection on this instance.
+    async fn local_user_connections(&self) -> Vec<(String, String)> {
+        self.runtime.connections().users()
+    }
+
+    /// Takes a closed connection out of every room it joined, shard by shard.
+    async fn leave_all_rooms(&self, connection_id: &str) -> Vec<ChatPresenceChange> {
+        let mut changes = Vec::new();
+        for shard in self.runtime.shards() {
+            changes.extend(shard.lock().await.disconnect(connection_id));
+        }
+        changes
+    }
+
+    pub async fn unregister_connection(&self, connection_id: &str) {
+        if let Some(backplane) = &self.backplane {
+            let user_id = self.runtime.connections_mut().remove(connection_id);
+            // Other instances may hold more connections of the user, so the backplane decides
+            // whether the user left each room.
+            let mut left_rooms = Vec::new();
+            for shard in self.runtime.shards() {
+                let mut hub = shard.lock().await;
+                left_rooms.extend(hub.connection_rooms(connection_id));
+                hub.disconnect(connection_id);
+            }
+            if let Some(user_id) = user_id
+                && let Err(error) = backplane
+                    .unregister_user_connection(&user_id, connection_id)
+                    .await
+            {
+                warn!(error = %error, user_id, "failed to unregister chat user connection");
+            }
+            for (room_id, member) in left_rooms {
+                match backplane.leave_room(&room_id, connection_id, &member).await {
+                    Ok(Some(left)) => {
+                        self.touch_room(backplane, &room_id).await;
+                        if let Some(presence) = left.presence {
+                            self.publish(&room_id, ChatEvent::PresenceChanged(presence), None)
+                                .await;
+                        }
+                    }
+                    Ok(None) => {}
+                    // The member lease expires on its own and peers see it leave on next prune.
+                    Err(error) => warn!(error = %error, room_id, "failed to leave chat room"),
+                }
+            }
+            return;
+        }
+
+        let user_id = self.runtime.connections_mut().remove(connection_id);
+        if let Some(user_id) = user_id
+            && self
+                .runtime
+                .connections()
+                .user_connections(&user_id)
+                .is_empty()
+        {
+            for shard in self.runtime.shards() {
+                shard.lock().await.forget_status(&user_id);
+            }
+        }
+        for presence in self.leave_all_rooms(connection_id).await {
+            let dispatches = {
+                let hub = self.runtime.room(&presence.room_id).lock().await;
+                self.runtime.room_dispatches(
+                    &hub,
+                    &presence.room_id.clone(),
+                    ChatEvent::PresenceChanged(presence),
+                    None,
+                )
+            };
+            for dispatch in dispatches {
+                dispatch.sender.push(dispatch.event);
+            }
+        }
+    }
+}
 pub async fn connect(
     chat_socket: WebSocketUpgrade,
+    headers: HeaderMap,
     Query(query): Query<ChatConnectQuery>,
     State(state): State<Arc<AppState>>,
 ) -> AppResult<impl IntoResponse> {
-    let user_id = query.user_id.trim().to_string();
+    let claims = auth::authenticate(&state, &headers, query.ticket.as_deref()).await?;
+    let user_id = claims.sub.trim().to_string();
     if user_id.is_empty() {
-        return Err(AppError::new("user_id is required"));
+        return Err(AuthError::InvalidToken.into());
     }
-    let user_name = query
-        .user_name
-        .as_deref()
-        .unwrap_or(&user_id)
-        .trim()
-        .to_string();
-    if user_name.is_empty() {
-        return Err(AppError::new("user_name is invalid"));
+    let session_ttl = auth::session_remaining(&claims);
+    if state.chat_service.is_shutting_down() {
+        return Err(chat_error_response(ChatError::ShuttingDown));
     }
 
+    let chat_socket = chat_socket.protocols(CHAT_PROTOCOLS);
+    let wire_format = ChatWireFormat::from_protocol(
+        chat_socket
+            .selected_protocol()
+            .and_then(|protocol| protocol.to_str().ok()),
+    );
+
     Ok(chat_socket.on_upgrade(move |socket| async move {
-        let (session_user, receiver) = state
+        let (session_user, outbound) = state
             .chat_service
-            .register_connection(&user_id, &user_name)
+            .register_connection(&user_id, &user_id)
+            .await;
+        state
+            .chat_service
+            .track_session(run_socket(
+                state.chat_service.clone(),
+                socket,
+                wire_format,
+                session_user,
+                outbound,
+                session_ttl,
+            ))
             .await;
-        run_socket(state.chat_service.clone(), socket, session_user, receiver).await;
     }))
 }
 
+pub async fn room_messages(
+    State(state): State<Arc<AppState>>,
+    Path(room_id): Path<String>,
+    Query(query): Query<ChatHistoryQuery>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let page = state
+        .chat_service
+        .room_history(claims.sub.trim(), &room_id, query.before, query.limit)
+        .await
+        .map_err(chat_error_response)?;
+
+    Ok((StatusCode::OK, Json(page)))
+}
+
 async fn run_socket(
     chat_state: Arc<ChatState>,
     socket: WebSocket,
+    wire_format: ChatWireFormat,
     session_user: ChatSessionUser,
-    mut outgoing_rx: UnboundedReceiver<ChatEvent>,
+    outbound: Arc<ChatOutbound>,
+    session_ttl: Duration,
 ) {
     let connection_id = session_user.connection_id.clone();
     let (mut socket_sender, mut socket_receiver) = socket.split();
-
-    let writer = tokio::spawn(async move {
-        while let Some(event) = outgoing_rx.recv().await {
-            let payload = match serde_json::to_string(&event) {
-                Ok(payload) => payload,
+    let heartbeat = Arc::new(chat_state.heartbeat());
+    let mut rate_limits = chat_state.rate_limits(&session_user);
+
+    let writer_outbound = outbound.clone();
+    let writer_heartbeat = heartbeat.clone();
+    let mut writer = tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                event = writer_outbound.pop() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = writer_heartbeat.ping_due() => {
+                    if socket_sender.send(Message::Ping(Default::default())).await.is_err() {
+                        return;
+                    }
+                    writer_heartbeat.ping_sent();
+                    continue;
+                }
+            };
+            let frame = match wire_format.encode(&event) {
+                Ok(frame) => frame,
                 Err(error) => {
                     warn!(error = %error, "failed to serialize websocket event");
                     continue;
                 }
             };
 
-            if socket_sender
-                .send(Message::Text(payload.into()))
-                .await
-                .is_err()
-            {
-                break;
+            if socket_sender.send(frame).await.is_err() {
+                return;
             }
         }
+
+        if let Some(reason) = writer_outbound.close_reason() {
+            let frame = CloseFrame { code: reason.code(), reason: reason.as_str().into() };
+            let _ = socket_sender.send(Message::Close(Some(frame))).await;
+        }
     });
 
-    chat_state
-        .send_to_connection(
-            &connection_id,
-            ChatEvent::Connected {
-                user_id: session_user.user_id.clone(),
-                user_name: session_user.user_name.clone(),
+    chat_state.welcome(&session_user).await;
+
+    let session_expiry = tokio::time::sleep(session_ttl);
+    tokio::pin!(session_expiry);
+    let mut close_reason = None;
+    loop {
+        let result = tokio::select! {
+            _ = &mut session_expiry => {
+                chat_state
+                    .send_to_connection(&connection_id, ChatError::SessionExpired.to_event())
+                    .await;
+                close_reason = Some(ChatCloseReason::SessionExpired);
+                break;
+            }
+            // Slow consumer overflow, the writer sends the close frame.
+            _ = outbound.closed() => break,
+            _ = chat_state.shutting_down() => {
+                chat_state
+                    .send_to_connection(&connection_id, chat_state.shutdown_notice())
+                    .await;
+                close_reason = Some(ChatCloseReason::GoingAway);
+                break;
+            }
+            _ = chat_state.expire_connection(&connection_id, &outbound, &heartbeat) => break,
+            result = socket_receiver.next() => match result {
+                Some(result) => result,
+                None => break,
             },
-        )
-        .await;
+        };
+
+        match &result {
+            Ok(Message::Pong(_)) => heartbeat.pong_received(),
+            Ok(_) => heartbeat.activity(),
+            Err(_) => {}
+        }
 
-    while let Some(result) = socket_receiver.next().await {
         match result {
-            Ok(Message::Text(text)) => match serde_json::from_str::<ChatCommand>(&text) {
+            Ok(frame @ (Message::Text(_) | Message::Binary(_))) => match wire_format.decode(&frame)
+            {
                 Ok(message) => {
-                    if let Err(error) = chat_state.process_message(&session_user, message).await {
-                        chat_state
-                            .send_to_connection(&connection_id, error.to_event())
-                            .await;
+                    if let Err(reason) = chat_state
+                        .receive_command(&session_user, &mut rate_limits, message)
+                        .await
+                    {
+                        close_reason = Some(reason);
+                        break;
                     }
                 }
                 Err(error) => {
@@ -688,6 +2072,7 @@ async fn run_socket(
                             ChatEvent::Error(ChatErrorPayload {
                                 code: "invalid_message".to_string(),
                                 message: "invalid websocket message".to_string(),
+                                retry_after_ms: None,
                             }),
                         )
                         .await;
@@ -702,24 +2087,83 @@ async fn run_socket(
         }
     }
 
+    outbound.close(close_reason);
+    if outbound.close_reason().is_some() {
+        let _ = tokio::time::timeout(WRITER_DRAIN_TIMEOUT, &mut writer).await;
+    }
     chat_state.unregister_connection(&connection_id).await;
     writer.abort();
 }
 
-fn dispatch_to_connection(
-    sender: Option<UnboundedSender<ChatEvent>>,
-    event: ChatEvent,
-) -> Option<ChatDispatch> {
-    sender.map(|sender| ChatDispatch { sender, event })
+/// Maps a chat error to its HTTP status for the REST endpoints.
+pub fn chat_error_response(error: ChatError) -> AppError {
+    let status = match error {
+        ChatError::HistoryUnavailable
+        | ChatError::BackplaneUnavailable
+        | ChatError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+        ChatError::RoomNotFound { .. } | ChatError::ConnectionNotFound { .. } => {
+            StatusCode::NOT_FOUND
+        }
+        ChatError::AdminRequired
+        | ChatError::NotRoomModerator
+        | ChatError::InviteRequired
+        | ChatError::BannedFromRoom => StatusCode::FORBIDDEN,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    AppError::new(&error.to_string())
+        .with_status(status)
+        .with_details(serde_json::json!({ "code": error.code() }))
+}
+
+pub fn sanitized_history_limit(limit: Option<usize>) -> usize {
+    limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT)
+}
+
+fn backplane_error(error: anyhow::Error) -> ChatError {
+    warn!(error = %error, "chat backplane request failed");
+    ChatError::BackplaneUnavailable
+}
+
+fn new_room_message(
+    room_id: &str,
+    sender: &ChatSessionUser,
+    content: String,
+    mentions: Vec<String>,
+) -> ChatRoomMessage {
+    ChatRoomMessage {
+        room_id: room_id.to_string(),
+        message_id: Uuid::new_v4().to_string(),
+        sender_id: sender.user_id.clone(),
+        sender_name: sender.user_name.clone(),
+        content,
+        sent_at: Utc::now().timestamp(),
+        version: 0,
+        edited_at: None,
+        deleted_at: None,
+        mentions,
+        reply_to: None,
+    }
 }
 
 fn sorted_members(room: &ChatRoom) -> Vec<ChatUserSummary> {
-    let mut members = room.members.values().cloned().collect::<Vec<_>>();
+    sort_members(
+        room.presence
+            .values()
+            .map(|presence| presence.member.clone())
+            .collect(),
+    )
+}
+
+/// Orders members by user, keeping one entry per user.
+fn sort_members(mut members: Vec<ChatUserSummary>) -> Vec<ChatUserSummary> {
     members.sort_by(|left, right| {
         left.user_id
             .cmp(&right.user_id)
             .then(left.user_name.cmp(&right.user_name))
     });
+    members.dedup_by(|left, right| left.user_id == right.user_id);
     members
 }
 
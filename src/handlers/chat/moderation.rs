@@ -0,0 +1,399 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    ChatDispatch, ChatError, ChatEvent, ChatHub, ChatPresenceChange, ChatRoomSnapshot,
+    ChatSessionUser, ChatState, backplane::ChatDirectEnvelope, backplane_error,
+    direct::normalized_user_id, normalized_room_id,
+};
+
+/// Longest mute a moderator can hand out, one week.
+pub const MAX_MUTE_SECS: u64 = 604_800;
+
+/// Who runs a room and who may join or speak in it. Outlives the room's members, so bans and
+/// mutes still apply when an emptied room is joined again.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatRoomAccess {
+    pub owner_id: String,
+    #[serde(default)]
+    pub moderators: BTreeSet<String>,
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub invited: BTreeSet<String>,
+    #[serde(default)]
+    pub banned: BTreeSet<String>,
+    /// Unix seconds until which each muted user cannot send.
+    #[serde(default)]
+    pub muted: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatModerationAction {
+    Invite,
+    AddModerator,
+    RemoveModerator,
+    Kick,
+    Mute,
+    Unmute,
+    Ban,
+    Unban,
+}
+
+impl ChatModerationAction {
+    /// Kicks and bans take every connection of the user out of the room.
+    pub fn removes_member(self) -> bool {
+        matches!(self, Self::Kick | Self::Ban)
+    }
+}
+
+/// Broadcast to the room, and to an invited user directly.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatModerationNotice {
+    pub room_id: String,
+    pub action: ChatModerationAction,
+    pub user_id: String,
+    pub moderator_id: String,
+    pub muted_until: Option<i64>,
+}
+
+impl ChatRoomAccess {
+    pub fn new(owner_id: &str, private: bool) -> Self {
+        Self {
+            owner_id: owner_id.to_string(),
+            moderators: BTreeSet::new(),
+            private,
+            invited: BTreeSet::new(),
+            banned: BTreeSet::new(),
+            muted: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_moderator(&self, user_id: &str) -> bool {
+        self.owner_id == user_id || self.moderators.contains(user_id)
+    }
+
+    pub fn check_join(&self, user_id: &str) -> Result<(), ChatError> {
+        if self.banned.contains(user_id) {
+            return Err(ChatError::BannedFromRoom);
+        }
+        if self.private && !self.invited.contains(user_id) && !self.is_moderator(user_id) {
+            return Err(ChatError::InviteRequired);
+        }
+
+        Ok(())
+    }
+
+    pub fn check_send(&self, user_id: &str, now: i64) -> Result<(), ChatError> {
+        match self.muted.get(user_id) {
+            Some(until) if *until > now => Err(ChatError::Muted { until: *until }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies a moderation action. Only the owner manages moderators, and moderators cannot act
+    /// on each other or on the owner.
+    pub fn apply(&mut self, notice: &ChatModerationNotice) -> Result<(), ChatError> {
+        let moderator_id = notice.moderator_id.as_str();
+        let user_id = notice.user_id.as_str();
+        let by_owner = moderator_id == self.owner_id;
+        if matches!(
+            notice.action,
+            ChatModerationAction::AddModerator | ChatModerationAction::RemoveModerator
+        ) && !by_owner
+        {
+            return Err(ChatError::NotRoomOwner);
+        }
+        if !self.is_moderator(moderator_id) {
+            return Err(ChatError::NotRoomModerator);
+        }
+        if user_id == moderator_id
+            || user_id == self.owner_id
+            || (self.moderators.contains(user_id) && !by_owner)
+        {
+            return Err(ChatError::InvalidModerationTarget);
+        }
+
+        match notice.action {
+            ChatModerationAction::Invite => {
+                self.invited.insert(user_id.to_string());
+            }
+            ChatModerationAction::AddModerator => {
+                self.moderators.insert(user_id.to_string());
+            }
+            ChatModerationAction::RemoveModerator => {
+                self.moderators.remove(user_id);
+            }
+            ChatModerationAction::Kick => {}
+            ChatModerationAction::Mute => {
+                self.muted
+                    .insert(user_id.to_string(), notice.muted_until.unwrap_or_default());
+            }
+            ChatModerationAction::Unmute => {
+                self.muted.remove(user_id);
+            }
+            ChatModerationAction::Ban => {
+                self.banned.insert(user_id.to_string());
+                self.moderators.remove(user_id);
+                self.invited.remove(user_id);
+                self.muted.remove(user_id);
+            }
+            ChatModerationAction::Unban => {
+                self.banned.remove(user_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops mutes that already ran out, e.g. once the room empties.
+    pub fn forget_expired_mutes(&mut self, now: i64) {
+        self.muted.retain(|_, until| *until > now);
+    }
+
+    /// Fills the role fields every member may see.
+    pub fn describe(&self, snapshot: &mut ChatRoomSnapshot) {
+        snapshot.owner_id = Some(self.owner_id.clone());
+        snapshot.moderator_ids = self.moderators.iter().cloned().collect();
+        snapshot.private = self.private;
+    }
+}
+
+impl ChatHub {
+    /// Registers a new room owned by `user_id`; joining still goes through `join_room`.
+    pub fn create_room(
+        &mut self,
+        user_id: &str,
+        room_id: &str,
+        private: bool,
+    ) -> Result<(), ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        if self.room_access.contains_key(&room_id) || self.rooms.contains_key(&room_id) {
+            return Err(ChatError::RoomExists);
+        }
+
+        self.room_access
+            .insert(room_id, ChatRoomAccess::new(user_id, private));
+        Ok(())
+    }
+
+    pub fn room_access(&self, room_id: &str) -> Option<&ChatRoomAccess> {
+        self.room_access.get(room_id)
+    }
+
+    /// Applies a moderation action issued from a member connection.
+    pub fn moderate(
+        &mut self,
+        connection_id: &str,
+        notice: &ChatModerationNotice,
+    ) -> Result<(), ChatError> {
+        let room_id = &notice.room_id;
+        let is_member = self
+            .rooms
+            .get(room_id)
+            .and_then(|room| room.members.get(connection_id))
+            .is_some_and(|member| member.user_id == notice.moderator_id);
+        if !is_member {
+            return Err(ChatError::NotInRoom { room_id: room_id.clone() });
+        }
+
+        self.room_access
+            .get_mut(room_id)
+            .ok_or(ChatError::NotRoomModerator)?
+            .apply(notice)
+    }
+
+    /// Takes every connection of a user out of the room under one new version, returning the
+    /// presence change and the removed connection ids.
+    pub fn remove_user(
+        &mut self,
+        room_id: &str,
+        user_id: &str,
+    ) -> Option<(ChatPresenceChange, Vec<String>)> {
+        let room = self.rooms.get_mut(room_id)?;
+        let connection_ids = room
+            .members
+            .iter()
+            .filter(|(_, member)| member.user_id == user_id)
+            .map(|(connection_id, _)| connection_id.clone())
+            .collect::<Vec<_>>();
+        if connection_ids.is_empty() {
+            return None;
+        }
+
+        let left_members = connection_ids
+            .iter()
+            .filter_map(|connection_id| room.remove_member(connection_id))
+            .collect::<Vec<_>>();
+        room.version += 1;
+        let presence = ChatPresenceChange {
+            room_id: room_id.to_string(),
+            joined_members: Vec::new(),
+            left_members,
+            version: room.version,
+        };
+        room.events
+            .record(room.version, ChatEvent::PresenceChanged(presence.clone()));
+        if room.members.is_empty() {
+            let version = room.version;
+            self.drop_room(room_id, version);
+        }
+        for connection_id in &connection_ids {
+            if let Some(connection) = self.connections.get_mut(connection_id) {
+                connection.joined_rooms.remove(room_id);
+                if connection.joined_rooms.is_empty() {
+                    self.connections.remove(connection_id);
+                }
+            }
+        }
+
+        Some((presence, connection_ids))
+    }
+}
+
+impl ChatState {
+    /// Creates a room owned by the caller, who then joins it like any other room.
+    pub(super) async fn create_room(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        private: bool,
+    ) -> Result<(), ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        // A room with stored history exists even while nobody is in it.
+        if self.persisted_room_version(&room_id).await > 0 {
+            return Err(ChatError::RoomExists);
+        }
+        match &self.backplane {
+            Some(backplane) => {
+                let access = ChatRoomAccess::new(&session_user.user_id, private);
+                let created = backplane
+                    .create_room_access(&room_id, &access)
+                    .await
+                    .map_err(backplane_error)?;
+                if !created {
+                    return Err(ChatError::RoomExists);
+                }
+                Ok(())
+            }
+            None => self.runtime.room(&room_id).lock().await.create_room(
+                &session_user.user_id,
+                &room_id,
+                private,
+            ),
+        }
+    }
+
+    pub(super) async fn moderate_room(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        user_id: &str,
+        action: ChatModerationAction,
+        duration_secs: Option<u64>,
+    ) -> Result<(), ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let user_id =
+            normalized_user_id(user_id).map_err(|_| ChatError::InvalidModerationTarget)?;
+        let muted_until = (action == ChatModerationAction::Mute).then(|| {
+            let duration_secs = duration_secs
+                .unwrap_or(MAX_MUTE_SECS)
+                .clamp(1, MAX_MUTE_SECS);
+            Utc::now().timestamp() + duration_secs as i64
+        });
+        let notice = ChatModerationNotice {
+            room_id,
+            action,
+            user_id,
+            moderator_id: session_user.user_id.clone(),
+            muted_until,
+        };
+
+        if self.backplane.is_some() {
+            return self.moderate_distributed(session_user, notice).await;
+        }
+
+        let dispatches = {
+            let mut hub = self.runtime.room(&notice.room_id).lock().await;
+            hub.moderate(&session_user.connection_id, &notice)?;
+            let event = ChatEvent::RoomModeration(notice.clone());
+            let mut dispatches =
+                self.runtime
+                    .room_dispatches(&hub, &notice.room_id, event.clone(), None);
+            if action == ChatModerationAction::Invite {
+                dispatches.extend(
+                    self.runtime
+                        .connections()
+                        .user_connections(&notice.user_id)
+                        .into_iter()
+                        .map(|sender| ChatDispatch { sender, event: event.clone() }),
+                );
+            }
+            if action.removes_member()
+                && let Some((presence, _)) = hub.remove_user(&notice.room_id, &notice.user_id)
+            {
+                dispatches.extend(self.runtime.room_dispatches(
+                    &hub,
+                    &notice.room_id,
+                    ChatEvent::PresenceChanged(presence),
+                    None,
+                ));
+            }
+            dispatches
+        };
+
+        for dispatch in dispatches {
+            dispatch.sender.push(dispatch.event);
+        }
+        Ok(())
+    }
+
+    async fn moderate_distributed(
+        &self,
+        session_user: &ChatSessionUser,
+        notice: ChatModerationNotice,
+    ) -> Result<(), ChatError> {
+        let Some(backplane) = &self.backplane else {
+            return Ok(());
+        };
+        let room_id = notice.room_id.clone();
+        if !self
+            .runtime
+            .room(&room_id)
+            .lock()
+            .await
+            .is_member(&session_user.connection_id, &room_id)
+        {
+            return Err(ChatError::NotInRoom { room_id });
+        }
+
+        backplane
+            .update_room_access(&room_id, |access| access.apply(&notice))
+            .await
+            .map_err(backplane_error)??;
+        let event = ChatEvent::RoomModeration(notice.clone());
+        if notice.action == ChatModerationAction::Invite {
+            let envelope =
+                ChatDirectEnvelope { user_id: notice.user_id.clone(), event: event.clone() };
+            if let Err(error) = backplane.publish_direct(&envelope).await {
+                warn!(error = %error, room_id, "failed to publish chat room invite");
+            }
+        }
+        self.publish(&room_id, event, None).await;
+
+        if notice.action.removes_member() {
+            let presence = backplane
+                .remove_user(&room_id, &notice.user_id)
+                .await
+                .map_err(backplane_error)?;
+            if let Some(presence) = presence {
+                self.publish(&room_id, ChatEvent::PresenceChanged(presence), None)
+                    .await;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, MutexGuard},
+};
+
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use super::{ChatEvent, ChatPresenceChange, ChatUserSummary};
+use crate::{config::SlowConsumerPolicy, utils::observability};
+
+/// Why the server is closing a chat connection, carried to the transport's close frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatCloseReason {
+    SessionExpired,
+    HeartbeatTimeout,
+    IdleTimeout,
+    SlowConsumer,
+    RateLimited,
+    ClosedByAdmin,
+    GoingAway,
+}
+
+impl ChatCloseReason {
+    /// Application close codes in the 4000-4999 range reserved for private use, and the
+    /// standard going away code for shutdowns.
+    pub fn code(self) -> u16 {
+        match self {
+            Self::GoingAway => 1001,
+            Self::SessionExpired => 4001,
+            Self::HeartbeatTimeout => 4002,
+            Self::IdleTimeout => 4003,
+            Self::ClosedByAdmin => 4004,
+            Self::SlowConsumer => 4008,
+            Self::RateLimited => 4029,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SessionExpired => "session expired",
+            Self::HeartbeatTimeout => "heartbeat timeout",
+            Self::IdleTimeout => "idle timeout",
+            Self::SlowConsumer => "slow consumer",
+            Self::RateLimited => "rate limited",
+            Self::ClosedByAdmin => "closed by admin",
+            Self::GoingAway => "server shutting down",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    Queued,
+    DroppedOldest,
+    Coalesced,
+    Overflowed,
+    Closed,
+}
+
+#[derive(Debug, Default)]
+struct OutboundQueue {
+    events: VecDeque<ChatEvent>,
+    closing: bool,
+    close_reason: Option<ChatCloseReason>,
+}
+
+/// Bounded per-connection event queue. Pushing never waits, so callers can enqueue while
+/// holding the chat runtime lock; a full queue is handled by the slow consumer policy.
+#[derive(Debug)]
+pub struct ChatOutbound {
+    queue: Mutex<OutboundQueue>,
+    ready: Notify,
+    closed: CancellationToken,
+    capacity: usize,
+    policy: SlowConsumerPolicy,
+}
+
+impl ChatOutbound {
+    pub fn new(capacity: usize, policy: SlowConsumerPolicy) -> Self {
+        Self {
+            queue: Mutex::default(),
+            ready: Notify::new(),
+            closed: CancellationToken::new(),
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+
+    pub fn push(&self, event: ChatEvent) -> PushOutcome {
+        let (outcome, depth) = {
+            let mut queue = self.lock();
+            let outcome = self.push_locked(&mut queue, event);
+            (outcome, queue.events.len())
+        };
+
+        let policy = self.policy.as_str();
+        match outcome {
+            PushOutcome::Closed => return outcome,
+            PushOutcome::DroppedOldest => {
+                observability::record_chat_event_dropped(policy, "oldest")
+            }
+            PushOutcome::Coalesced => observability::record_chat_event_dropped(policy, "coalesced"),
+            PushOutcome::Overflowed => {
+                observability::record_chat_event_dropped(policy, "overflow");
+                observability::record_chat_slow_consumer_disconnect();
+                self.closed.cancel();
+            }
+            PushOutcome::Queued => {}
+        }
+        observability::record_chat_queue_depth(policy, depth);
+        self.ready.notify_one();
+        outcome
+    }
+
+    /// Next event to write, `None` once the queue is closing and drained.
+    pub async fn pop(&self) -> Option<ChatEvent> {
+        loop {
+            let notified = self.ready.notified();
+            {
+                let mut queue = self.lock();
+                if let Some(event) = queue.events.pop_front() {
+                    return Some(event);
+                }
+                if queue.closing {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    pub fn try_pop(&self) -> Option<ChatEvent> {
+        self.lock().events.pop_front()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.lock().events.len()
+    }
+
+    /// Stops accepting events; queued ones are still written before the optional close reason.
+    pub fn close(&self, reason: Option<ChatCloseReason>) {
+        {
+            let mut queue = self.lock();
+            queue.closing = true;
+            queue.close_reason = queue.close_reason.or(reason);
+        }
+        self.closed.cancel();
+        self.ready.notify_one();
+    }
+
+    pub fn close_reason(&self) -> Option<ChatCloseReason> {
+        self.lock().close_reason
+    }
+
+    /// Resolves once the queue was closed, by the connection or a slow consumer overflow.
+    pub async fn closed(&self) {
+        self.closed.cancelled().await;
+    }
+
+    fn push_locked(&self, queue: &mut OutboundQueue, event: ChatEvent) -> PushOutcome {
+        if queue.closing {
+            return PushOutcome::Closed;
+        }
+        if queue.events.len() < self.capacity {
+            queue.events.push_back(event);
+            return PushOutcome::Queued;
+        }
+
+        match self.policy {
+            SlowConsumerPolicy::DropOldest => {
+                queue.events.pop_front();
+                queue.events.push_back(event);
+                PushOutcome::DroppedOldest
+            }
+            SlowConsumerPolicy::CoalescePresence => {
+                let event = match event {
+                    ChatEvent::PresenceChanged(presence) => {
+                        match coalesce_presence(&mut queue.events, presence) {
+                            Ok(()) => return PushOutcome::Coalesced,
+                            Err(presence) => ChatEvent::PresenceChanged(presence),
+                        }
+                    }
+                    event => event,
+                };
+                // Presence is recoverable with a room sync, so it goes before anything else.
+                let oldest_presence = queue
+                    .events
+                    .iter()
+                    .position(|event| matches!(event, ChatEvent::PresenceChanged(_)))
+                    .unwrap_or(0);
+                queue.events.remove(oldest_presence);
+                queue.events.push_back(event);
+                PushOutcome::DroppedOldest
+            }
+            SlowConsumerPolicy::Disconnect => {
+                // Nothing queued is worth writing to a client that is about to be dropped.
+                queue.events.clear();
+                queue.closing = true;
+                queue.close_reason = Some(ChatCloseReason::SlowConsumer);
+                PushOutcome::Overflowed
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, OutboundQueue> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Folds `presence` into the latest queued presence change of the same room.
+fn coalesce_presence(
+    events: &mut VecDeque<ChatEvent>,
+    presence: ChatPresenceChange,
+) -> Result<(), ChatPresenceChange> {
+    let queued = events.iter_mut().rev().find_map(|event| match event {
+        ChatEvent::PresenceChanged(queued) if queued.room_id == presence.room_id => Some(queued),
+        _ => None,
+    });
+    let Some(queued) = queued else {
+        return Err(presence);
+    };
+
+    merge_presence(queued, presence);
+    Ok(())
+}
+
+/// Net membership change of two consecutive presence updates: a member that joined and then
+/// left (or the reverse) cancels out.
+pub fn merge_presence(older: &mut ChatPresenceChange, newer: ChatPresenceChange) {
+    for member in newer.left_members {
+        if !remove_member(&mut older.joined_members, &member) {
+            older.left_members.push(member);
+        }
+    }
+    for member in newer.joined_members {
+        if !remove_member(&mut older.left_members, &member) {
+            older.joined_members.push(member);
+        }
+    }
+    older.version = older.version.max(newer.version);
+}
+
+fn remove_member(members: &mut Vec<ChatUserSummary>, member: &ChatUserSummary) -> bool {
+    let Some(index) = members.iter().position(|existing| existing == member) else {
+        return false;
+    };
+    members.remove(index);
+    true
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    ChatError, ChatEvent, ChatHub, ChatSessionUser, ChatState, ChatUserSummary,
+    backplane::ChatDirectEnvelope, backplane_error,
+};
+
+/// What a user shows their rooms; `offline` lets a connected user appear offline.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatPresenceStatus {
+    #[default]
+    Online,
+    Away,
+    Offline,
+}
+
+impl ChatPresenceStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Online => "online",
+            Self::Away => "away",
+            Self::Offline => "offline",
+        }
+    }
+}
+
+/// A member's status changed, versioned like other room events so deltas replay it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatStatusChange {
+    pub room_id: String,
+    pub user_id: String,
+    pub status: ChatPresenceStatus,
+    pub version: u64,
+}
+
+/// Sent to every connection of the user who set the status, so their other tabs follow it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatUserStatus {
+    pub user_id: String,
+    pub status: ChatPresenceStatus,
+}
+
+/// A user present in a room and how many of their connections are in it.
+#[derive(Debug, Clone)]
+pub(super) struct ChatRoomPresence {
+    pub(super) member: ChatUserSummary,
+    pub(super) connections: usize,
+}
+
+impl ChatHub {
+    /// Remembers the user's status for later joins and applies it to the rooms they are in,
+    /// returning a change for each room where it differed.
+    pub fn set_status(
+        &mut self,
+        user_id: &str,
+        status: ChatPresenceStatus,
+    ) -> Vec<ChatStatusChange> {
+        self.statuses.insert(user_id.to_string(), status);
+
+        let mut changes = Vec::new();
+        for (room_id, room) in &mut self.rooms {
+            let Some(presence) = room.presence.get_mut(user_id) else {
+                continue;
+            };
+            if presence.member.status == status {
+                continue;
+            }
+
+            presence.member.status = status;
+            room.version += 1;
+            let change = ChatStatusChange {
+                room_id: room_id.clone(),
+                user_id: user_id.to_string(),
+                status,
+                version: room.version,
+            };
+            room.events
+                .record(room.version, ChatEvent::StatusChanged(change.clone()));
+            changes.push(change);
+        }
+        changes.sort_by(|left, right| left.room_id.cmp(&right.room_id));
+        changes
+    }
+
+    /// Drops the status of a user whose last connection closed.
+    pub fn forget_status(&mut self, user_id: &str) {
+        self.statuses.remove(user_id);
+    }
+
+    /// Rooms the user has at least one connection in.
+    pub fn user_rooms(&self, user_id: &str) -> Vec<String> {
+        let mut room_ids = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| room.presence.contains_key(user_id))
+            .map(|(room_id, _)| room_id.clone())
+            .collect::<Vec<_>>();
+        room_ids.sort();
+        room_ids
+    }
+}
+
+impl ChatState {
+    /// Sets the caller's status in every room they are in and tells their connections. With the
+    /// backplane, each instance applies it to the rooms its own connections of the user are in.
+    pub(super) async fn set_status(
+        &self,
+        session_user: &ChatSessionUser,
+        status: ChatPresenceStatus,
+    ) -> Result<(), ChatError> {
+        let update = ChatUserStatus { user_id: session_user.user_id.clone(), status };
+        if let Some(backplane) = &self.backplane {
+            backplane
+                .set_user_status(&update.user_id, status)
+                .await
+                .map_err(backplane_error)?;
+            let envelope = ChatDirectEnvelope {
+                user_id: update.user_id.clone(),
+                event: ChatEvent::UserStatus(update),
+            };
+            return backplane
+                .publish_direct(&envelope)
+                .await
+                .map_err(backplane_error);
+        }
+
+        let mut dispatches = Vec::new();
+        for shard in self.runtime.shards() {
+            let mut hub = shard.lock().await;
+            for change in hub.set_status(&update.user_id, status) {
+                dispatches.extend(self.runtime.room_dispatches(
+                    &hub,
+                    &change.room_id.clone(),
+                    ChatEvent::StatusChanged(change),
+                    None,
+                ));
+            }
+        }
+        for sender in self.runtime.connections().user_connections(&update.user_id) {
+            sender.push(ChatEvent::UserStatus(update.clone()));
+        }
+        for dispatch in dispatches {
+            dispatch.sender.push(dispatch.event);
+        }
+
+        Ok(())
+    }
+
+    /// Applies a status published over the backplane to the rooms this instance's connections of
+    /// the user are in. Instances sharing a room race harmlessly, only the first one changes it.
+    pub(super) async fn apply_user_status(&self, update: &ChatUserStatus) {
+        let Some(backplane) = &self.backplane else {
+            return;
+        };
+        let mut room_ids = Vec::new();
+        for shard in self.runtime.shards() {
+            room_ids.extend(shard.lock().await.user_rooms(&update.user_id));
+        }
+
+        for room_id in room_ids {
+            match backplane
+                .set_status(&room_id, &update.user_id, update.status)
+                .await
+            {
+                Ok(Some(change)) => {
+                    self.publish(&room_id, ChatEvent::StatusChanged(change), None)
+                        .await;
+                }
+                Ok(None) => {}
+                Err(error) => warn!(error = %error, room_id, "failed to set chat member status"),
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::{ChatCommand, ChatError, ChatSessionUser, ChatState};
+use crate::config::{ChatRateLimit, ChatRateLimitConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRateKind {
+    Message,
+    Join,
+    Sync,
+}
+
+impl ChatRateKind {
+    /// The bucket a command draws from, `None` for commands that are not limited.
+    pub fn of(command: &ChatCommand) -> Option<Self> {
+        match command {
+            ChatCommand::SendRoomMessage { .. }
+            | ChatCommand::EditRoomMessage { .. }
+            | ChatCommand::DeleteRoomMessage { .. }
+            | ChatCommand::React { .. }
+            | ChatCommand::Unreact { .. }
+            | ChatCommand::SendDirectMessage { .. }
+            | ChatCommand::SetStatus { .. } => Some(Self::Message),
+            ChatCommand::JoinRoom { .. } | ChatCommand::CreateRoom { .. } => Some(Self::Join),
+            ChatCommand::SyncRoomState { .. }
+            | ChatCommand::LoadHistory { .. }
+            | ChatCommand::LoadThread { .. } => Some(Self::Sync),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    burst: f64,
+    per_sec: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: u32, per_sec: f64, now: Instant) -> Self {
+        let burst = f64::from(burst);
+        Self { burst, per_sec: per_sec.max(0.0), tokens: burst, updated_at: now }
+    }
+
+    /// How long until a token is available, zero when one is.
+    fn wait(&mut self, now: Instant) -> Duration {
+        if self.burst == 0.0 {
+            return Duration::ZERO;
+        }
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.burst);
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else if self.per_sec == 0.0 {
+            Duration::MAX
+        } else {
+            // Tiny rates wait longer than a `Duration` holds, which is forever in practice.
+            Duration::try_from_secs_f64((1.0 - self.tokens) / self.per_sec).unwrap_or(Duration::MAX)
+        }
+    }
+
+    fn take(&mut self) {
+        if self.burst > 0.0 {
+            self.tokens -= 1.0;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ChatRateBuckets {
+    messages: TokenBucket,
+    joins: TokenBucket,
+    syncs: TokenBucket,
+}
+
+impl ChatRateBuckets {
+    fn new(config: &ChatRateLimitConfig, limit: impl Fn(&ChatRateLimit) -> (u32, f64)) -> Self {
+        let now = Instant::now();
+        let bucket = |rate_limit: &ChatRateLimit| {
+            let (burst, per_sec) = limit(rate_limit);
+            TokenBucket::new(burst, per_sec, now)
+        };
+        Self {
+            messages: bucket(&config.messages),
+            joins: bucket(&config.joins),
+            syncs: bucket(&config.syncs),
+        }
+    }
+
+    fn bucket(&mut self, kind: ChatRateKind) -> &mut TokenBucket {
+        match kind {
+            ChatRateKind::Message => &mut self.messages,
+            ChatRateKind::Join => &mut self.joins,
+            ChatRateKind::Sync => &mut self.syncs,
+        }
+    }
+}
+
+type SharedBuckets = Arc<Mutex<ChatRateBuckets>>;
+
+/// Per-user buckets shared by the connections of each user on this instance. Entries go away
+/// with the user's last connection.
+#[derive(Debug, Default)]
+pub struct ChatRateLimiter {
+    users: Mutex<HashMap<String, Weak<Mutex<ChatRateBuckets>>>>,
+}
+
+impl ChatRateLimiter {
+    fn user_buckets(&self, user_id: &str, config: &ChatRateLimitConfig) -> SharedBuckets {
+        let mut users = lock(&self.users);
+        if let Some(buckets) = users.get(user_id).and_then(Weak::upgrade) {
+            return buckets;
+        }
+
+        users.retain(|_, buckets| buckets.strong_count() > 0);
+        let buckets = Arc::new(Mutex::new(ChatRateBuckets::new(config, |limit| {
+            (limit.user_burst, limit.user_per_sec)
+        })));
+        users.insert(user_id.to_string(), Arc::downgrade(&buckets));
+        buckets
+    }
+}
+
+/// Rate limits of one socket, checked by its reader before a command reaches the runtime.
+#[derive(Debug)]
+pub struct ChatConnectionRateLimits {
+    connection: ChatRateBuckets,
+    user: SharedBuckets,
+    max_violations: u32,
+    violations: u32,
+}
+
+impl ChatConnectionRateLimits {
+    /// Takes a token from both the connection's and the user's bucket, or neither when either
+    /// is empty.
+    pub fn check(&mut self, command: &ChatCommand) -> Result<(), ChatError> {
+        let Some(kind) = ChatRateKind::of(command) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut user = lock(&self.user);
+        let wait = self
+            .connection
+            .bucket(kind)
+            .wait(now)
+            .max(user.bucket(kind).wait(now));
+        if !wait.is_zero() {
+            self.violations += 1;
+            return Err(ChatError::RateLimited {
+                retry_after_ms: u64::try_from(wait.as_millis()).unwrap_or(u64::MAX).max(1),
+            });
+        }
+
+        self.connection.bucket(kind).take();
+        user.bucket(kind).take();
+        self.violations = 0;
+        Ok(())
+    }
+
+    /// Set once the client kept sending after too many rate limited commands in a row.
+    pub fn exhausted(&self) -> bool {
+        self.violations >= self.max_violations.max(1)
+    }
+}
+
+impl ChatState {
+    pub fn rate_limits(&self, session_user: &ChatSessionUser) -> ChatConnectionRateLimits {
+        let config = &self.config.rate_limit;
+        ChatConnectionRateLimits {
+            connection: ChatRateBuckets::new(config, |limit| {
+                (limit.connection_burst, limit.connection_per_sec)
+            }),
+            user: self
+                .rate_limiter
+                .user_buckets(&session_user.user_id, config),
+            max_violations: config.max_violations,
+            violations: 0,
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
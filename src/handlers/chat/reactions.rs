@@ -0,0 +1,319 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ChatError, ChatEvent, ChatHub, ChatRoom, ChatRoomMessage, ChatSessionUser, ChatState,
+    backplane_error, edits::normalized_message_id, normalized_room_id,
+};
+
+/// Longest reaction accepted, in characters.
+pub const MAX_REACTION_LEN: usize = 32;
+/// Distinct reactions one message may collect.
+pub const MAX_REACTIONS_PER_MESSAGE: usize = 20;
+/// Messages per room whose reactions are kept; the oldest message's are dropped first.
+pub const MAX_REACTED_MESSAGES: usize = 200;
+
+/// One emoji on a message and the users who reacted with it, in reaction order.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatReaction {
+    pub emoji: String,
+    pub count: u64,
+    pub user_ids: Vec<String>,
+}
+
+/// Reactions of one message, kept apart from the recent messages so they outlive the window.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatMessageReactions {
+    pub message_id: String,
+    pub message_version: u64,
+    pub reactions: Vec<ChatReaction>,
+}
+
+impl ChatMessageReactions {
+    pub fn new(message: &ChatRoomMessage) -> Self {
+        Self {
+            message_id: message.message_id.clone(),
+            message_version: message.version,
+            reactions: Vec::new(),
+        }
+    }
+
+    /// Adds or removes a user's reaction, false when it was already there or already gone.
+    pub fn toggle(&mut self, emoji: &str, user_id: &str, added: bool) -> Result<bool, ChatError> {
+        let index = self
+            .reactions
+            .iter()
+            .position(|reaction| reaction.emoji == emoji);
+        match (index, added) {
+            (Some(index), true) => {
+                let reaction = &mut self.reactions[index];
+                if reaction.user_ids.iter().any(|reacted| reacted == user_id) {
+                    return Ok(false);
+                }
+                reaction.user_ids.push(user_id.to_string());
+                reaction.count = reaction.user_ids.len() as u64;
+            }
+            (None, true) => {
+                if self.reactions.len() >= MAX_REACTIONS_PER_MESSAGE {
+                    return Err(ChatError::TooManyReactions {
+                        max_reactions: MAX_REACTIONS_PER_MESSAGE,
+                    });
+                }
+                self.reactions.push(ChatReaction {
+                    emoji: emoji.to_string(),
+                    count: 1,
+                    user_ids: vec![user_id.to_string()],
+                });
+            }
+            (Some(index), false) => {
+                let reaction = &mut self.reactions[index];
+                let Some(position) = reaction
+                    .user_ids
+                    .iter()
+                    .position(|reacted| reacted == user_id)
+                else {
+                    return Ok(false);
+                };
+                reaction.user_ids.remove(position);
+                reaction.count = reaction.user_ids.len() as u64;
+                if reaction.user_ids.is_empty() {
+                    self.reactions.remove(index);
+                }
+            }
+            (None, false) => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// A reaction added or removed, with the message's reactions after the change.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatReactionChanged {
+    pub room_id: String,
+    pub message_id: String,
+    pub message_version: u64,
+    pub emoji: String,
+    pub user_id: String,
+    pub added: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<ChatReaction>,
+    pub version: u64,
+}
+
+impl ChatRoom {
+    /// Reactions of every message that has any, in message order.
+    pub(super) fn reaction_summaries(&self) -> Vec<ChatMessageReactions> {
+        let mut summaries = self.reactions.values().cloned().collect::<Vec<_>>();
+        summaries.sort_by_key(|summary| summary.message_version);
+        summaries
+    }
+}
+
+impl ChatHub {
+    /// Adds or removes the member's reaction to a message. Messages no longer retained in
+    /// memory can be passed in as `stored`. Returns `None` when nothing changed.
+    pub fn react(
+        &mut self,
+        connection_id: &str,
+        room_id: &str,
+        message_id: &str,
+        emoji: &str,
+        added: bool,
+        stored: Option<ChatRoomMessage>,
+    ) -> Result<Option<ChatReactionChanged>, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let emoji = normalized_emoji(emoji)?;
+        let room = self
+            .rooms
+            .get_mut(&room_id)
+            .ok_or_else(|| ChatError::NotInRoom { room_id: room_id.clone() })?;
+        let Some(member) = room.members.get(connection_id) else {
+            return Err(ChatError::NotInRoom { room_id });
+        };
+        let user_id = member.user_id.clone();
+        if added && let Some(access) = self.room_access.get(&room_id) {
+            access.check_send(&user_id, Utc::now().timestamp())?;
+        }
+
+        let mut reactions = match room.reactions.get(message_id) {
+            Some(reactions) => reactions.clone(),
+            None if !added => return Ok(None),
+            None => {
+                let retained = room
+                    .recent_messages
+                    .iter()
+                    .find(|message| message.message_id == message_id);
+                let message = match (retained, &stored) {
+                    (Some(message), _) => message,
+                    (None, Some(stored))
+                        if stored.room_id == room_id && stored.message_id == message_id =>
+                    {
+                        stored
+                    }
+                    _ => {
+                        return Err(ChatError::MessageNotFound {
+                            message_id: message_id.to_string(),
+                        });
+                    }
+                };
+                if message.deleted_at.is_some() {
+                    return Err(ChatError::MessageNotFound { message_id: message_id.to_string() });
+                }
+                ChatMessageReactions::new(message)
+            }
+        };
+        if !reactions.toggle(&emoji, &user_id, added)? {
+            return Ok(None);
+        }
+
+        room.version += 1;
+        let changed = ChatReactionChanged {
+            room_id,
+            message_id: message_id.to_string(),
+            message_version: reactions.message_version,
+            emoji,
+            user_id,
+            added,
+            reactions: reactions.reactions.clone(),
+            version: room.version,
+        };
+        if reactions.reactions.is_empty() {
+            room.reactions.remove(message_id);
+        } else {
+            room.reactions.insert(message_id.to_string(), reactions);
+        }
+        if room.reactions.len() > MAX_REACTED_MESSAGES
+            && let Some(oldest) = room
+                .reactions
+                .values()
+                .filter(|reactions| reactions.message_id != message_id)
+                .min_by_key(|reactions| reactions.message_version)
+                .map(|reactions| reactions.message_id.clone())
+        {
+            room.reactions.remove(&oldest);
+        }
+        room.events
+            .record(room.version, ChatEvent::ReactionChanged(changed.clone()));
+
+        Ok(Some(changed))
+    }
+}
+
+impl ChatState {
+    pub(super) async fn react_to_message(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        message_id: &str,
+        emoji: &str,
+        added: bool,
+    ) -> Result<(), ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let message_id = normalized_message_id(message_id)?;
+        let emoji = normalized_emoji(emoji)?;
+        if self.backplane.is_some() {
+            return self
+                .react_distributed(session_user, &room_id, &message_id, &emoji, added)
+                .await;
+        }
+
+        let mut stored = None;
+        let mut looked_up = false;
+        loop {
+            let mut hub = self.runtime.room(&room_id).lock().await;
+            let changed = match hub.react(
+                &session_user.connection_id,
+                &room_id,
+                &message_id,
+                &emoji,
+                added,
+                stored.take(),
+            ) {
+                Err(ChatError::MessageNotFound { .. }) if !looked_up && self.history.is_some() => {
+                    drop(hub);
+                    looked_up = true;
+                    stored = Some(self.stored_message(&room_id, &message_id).await?);
+                    continue;
+                }
+                changed => changed?,
+            };
+            if let Some(changed) = changed {
+                for dispatch in self.runtime.room_dispatches(
+                    &hub,
+                    &room_id,
+                    ChatEvent::ReactionChanged(changed),
+                    None,
+                ) {
+                    dispatch.sender.push(dispatch.event);
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    async fn react_distributed(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        message_id: &str,
+        emoji: &str,
+        added: bool,
+    ) -> Result<(), ChatError> {
+        let Some(backplane) = &self.backplane else {
+            return Ok(());
+        };
+        if !self
+            .runtime
+            .room(room_id)
+            .lock()
+            .await
+            .is_member(&session_user.connection_id, room_id)
+        {
+            return Err(ChatError::NotInRoom { room_id: room_id.to_string() });
+        }
+        if added
+            && let Some(access) = backplane
+                .room_access(room_id)
+                .await
+                .map_err(backplane_error)?
+        {
+            access.check_send(&session_user.user_id, Utc::now().timestamp())?;
+        }
+
+        let mut stored = None;
+        let changed = loop {
+            let changed = backplane
+                .react(session_user, room_id, message_id, emoji, added, stored.as_ref())
+                .await
+                .map_err(backplane_error)?;
+            match changed {
+                Err(ChatError::MessageNotFound { .. })
+                    if stored.is_none() && self.history.is_some() =>
+                {
+                    stored = Some(self.stored_message(room_id, message_id).await?);
+                }
+                changed => break changed?,
+            }
+        };
+        if let Some(changed) = changed {
+            self.publish(room_id, ChatEvent::ReactionChanged(changed), None)
+                .await;
+        }
+        Ok(())
+    }
+}
+
+/// Any short token without whitespace, so clients may react with emoji or shortcodes.
+pub fn normalized_emoji(emoji: &str) -> Result<String, ChatError> {
+    let emoji = emoji.trim();
+    if emoji.is_empty()
+        || emoji.chars().count() > MAX_REACTION_LEN
+        || emoji
+            .chars()
+            .any(|char| char.is_whitespace() || char.is_control())
+    {
+        return Err(ChatError::InvalidReaction);
+    }
+
+    Ok(emoji.to_string())
+}
@@ -0,0 +1,155 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use super::{ChatError, ChatHub, ChatRoom, ChatUserSummary, normalized_room_id};
+
+/// Minimum gap between two typing notices of the same connection.
+pub const TYPING_THROTTLE: Duration = Duration::from_secs(3);
+/// How long clients show a typing indicator unless it is refreshed or a message arrives.
+pub const TYPING_TTL: Duration = Duration::from_secs(6);
+
+/// Sent to room peers; clients clear it after `expires_in_ms` or when the user's message lands.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatTypingNotice {
+    pub room_id: String,
+    pub user_id: String,
+    pub user_name: String,
+    pub expires_in_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatReadReceipt {
+    pub room_id: String,
+    pub user_id: String,
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatReadPosition {
+    pub user_id: String,
+    pub version: u64,
+}
+
+/// A user's last read room version and how many messages the room had seen up to it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatReadMark {
+    pub version: u64,
+    pub message_count: u64,
+}
+
+impl ChatHub {
+    /// Returns the notice to broadcast, or `None` while the connection is still throttled.
+    pub fn start_typing(
+        &mut self,
+        connection_id: &str,
+        room_id: &str,
+        now: Instant,
+    ) -> Result<Option<ChatTypingNotice>, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let room = self
+            .rooms
+            .get_mut(&room_id)
+            .ok_or_else(|| ChatError::NotInRoom { room_id: room_id.clone() })?;
+        let Some(member) = room.members.get(connection_id) else {
+            return Err(ChatError::NotInRoom { room_id });
+        };
+
+        if room
+            .typing
+            .get(connection_id)
+            .is_some_and(|last| now.duration_since(*last) < TYPING_THROTTLE)
+        {
+            return Ok(None);
+        }
+        let notice = typing_notice(&room_id, member);
+        room.typing.insert(connection_id.to_string(), now);
+        Ok(Some(notice))
+    }
+
+    /// Moves the user's read position forward, returning the receipt to broadcast or `None` when
+    /// it did not advance. Versions past the room's current version are clamped.
+    pub fn mark_read(
+        &mut self,
+        connection_id: &str,
+        room_id: &str,
+        version: u64,
+    ) -> Result<Option<ChatReadReceipt>, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let room = self
+            .rooms
+            .get_mut(&room_id)
+            .ok_or_else(|| ChatError::NotInRoom { room_id: room_id.clone() })?;
+        let Some(member) = room.members.get(connection_id) else {
+            return Err(ChatError::NotInRoom { room_id });
+        };
+
+        let user_id = member.user_id.clone();
+        let version = version.min(room.version);
+        if room
+            .read_marks
+            .get(&user_id)
+            .is_some_and(|mark| mark.version >= version)
+        {
+            return Ok(None);
+        }
+        let mark = room.read_mark_at(version);
+        room.read_marks.insert(user_id.clone(), mark);
+        Ok(Some(ChatReadReceipt { room_id, user_id, version }))
+    }
+}
+
+impl ChatRoom {
+    /// Read mark for `version`, counting messages after it from the recent window. Older
+    /// positions with more than a window of newer messages are rounded up to the window.
+    pub(super) fn read_mark_at(&self, version: u64) -> ChatReadMark {
+        let newer = self
+            .recent_messages
+            .iter()
+            .filter(|message| message.version > version)
+            .count() as u64;
+        ChatReadMark { version, message_count: self.message_count.saturating_sub(newer) }
+    }
+}
+
+pub fn typing_notice(room_id: &str, member: &ChatUserSummary) -> ChatTypingNotice {
+    ChatTypingNotice {
+        room_id: room_id.to_string(),
+        user_id: member.user_id.clone(),
+        user_name: member.user_name.clone(),
+        expires_in_ms: TYPING_TTL.as_millis() as u64,
+    }
+}
+
+/// Read positions of the current members, ordered by user.
+pub fn read_positions(
+    members: &[ChatUserSummary],
+    read_marks: &BTreeMap<String, ChatReadMark>,
+) -> Vec<ChatReadPosition> {
+    let mut positions = members
+        .iter()
+        .filter_map(|member| {
+            read_marks
+                .get(&member.user_id)
+                .map(|mark| ChatReadPosition {
+                    user_id: member.user_id.clone(),
+                    version: mark.version,
+                })
+        })
+        .collect::<Vec<_>>();
+    positions.dedup_by(|left, right| left.user_id == right.user_id);
+    positions
+}
+
+/// Messages the user has not read yet; users without a read position have nothing unread.
+pub fn unread_count(
+    user_id: &str,
+    message_count: u64,
+    read_marks: &BTreeMap<String, ChatReadMark>,
+) -> u64 {
+    read_marks
+        .get(user_id)
+        .map(|mark| message_count.saturating_sub(mark.message_count))
+        .unwrap_or_default()
+}
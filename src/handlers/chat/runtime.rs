@@ -0,0 +1,160 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use tokio::sync::Mutex;
+
+use super::{
+    ChatDispatch, ChatEvent, ChatHub, direct::ChatDirectUnreadCounts,
+    directory::ChatConnectionSummary, outbound::ChatOutbound,
+};
+use crate::config::ChatConfig;
+
+/// Live connections of this instance and the users they belong to.
+#[derive(Debug, Default)]
+pub(super) struct ChatConnections {
+    outbound: HashMap<String, Arc<ChatOutbound>>,
+    /// Live connection ids of each user on this instance, for direct messages.
+    user_connections: HashMap<String, BTreeSet<String>>,
+    connection_users: HashMap<String, ChatConnectionSummary>,
+    pub(super) direct_unread: ChatDirectUnreadCounts,
+}
+
+impl ChatConnections {
+    pub(super) fn insert(&mut self, summary: ChatConnectionSummary, outbound: Arc<ChatOutbound>) {
+        self.outbound
+            .insert(summary.connection_id.clone(), outbound);
+        self.user_connections
+            .entry(summary.user_id.clone())
+            .or_default()
+            .insert(summary.connection_id.clone());
+        self.connection_users
+            .insert(summary.connection_id.clone(), summary);
+    }
+
+    /// Forgets a connection, returning the user it belonged to.
+    pub(super) fn remove(&mut self, connection_id: &str) -> Option<String> {
+        self.outbound.remove(connection_id);
+        let user_id = self.connection_users.remove(connection_id)?.user_id;
+        if let Some(connection_ids) = self.user_connections.get_mut(&user_id) {
+            connection_ids.remove(connection_id);
+            if connection_ids.is_empty() {
+                self.user_connections.remove(&user_id);
+            }
+        }
+        Some(user_id)
+    }
+
+    pub(super) fn get(&self, connection_id: &str) -> Option<Arc<ChatOutbound>> {
+        self.outbound.get(connection_id).cloned()
+    }
+
+    pub(super) fn user_connections(&self, user_id: &str) -> Vec<Arc<ChatOutbound>> {
+        self.user_connections
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|connection_id| self.get(connection_id))
+            .collect()
+    }
+
+    /// `(user_id, connection_id)` of every connection on this instance.
+    pub(super) fn users(&self) -> Vec<(String, String)> {
+        self.connection_users
+            .values()
+            .map(|summary| (summary.user_id.clone(), summary.connection_id.clone()))
+            .collect()
+    }
+
+    pub(super) fn summaries(&self) -> Vec<ChatConnectionSummary> {
+        self.connection_users.values().cloned().collect()
+    }
+
+    pub(super) fn outbounds(&self) -> Vec<Arc<ChatOutbound>> {
+        self.outbound.values().cloned().collect()
+    }
+}
+
+/// Rooms are spread over independently locked hubs by room id, so a busy room only holds up
+/// the rooms sharing its shard. Connection routing sits behind its own lock, which is only
+/// written on connect and disconnect.
+#[derive(Debug)]
+pub(super) struct ChatRuntime {
+    shards: Box<[Mutex<ChatHub>]>,
+    connections: RwLock<ChatConnections>,
+}
+
+impl Default for ChatRuntime {
+    fn default() -> Self {
+        Self::new(ChatConfig::default().runtime_shards)
+    }
+}
+
+impl ChatRuntime {
+    pub(super) fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Mutex::default()).collect(),
+            connections: RwLock::default(),
+        }
+    }
+
+    /// The hub owning a room, picked the same way for every spelling `normalized_room_id`
+    /// accepts.
+    pub(super) fn room(&self, room_id: &str) -> &Mutex<ChatHub> {
+        let mut hasher = DefaultHasher::new();
+        room_id.trim().hash(&mut hasher);
+        &self.shards[(hasher.finish() % self.shards.len() as u64) as usize]
+    }
+
+    pub(super) fn shards(&self) -> impl Iterator<Item = &Mutex<ChatHub>> {
+        self.shards.iter()
+    }
+
+    pub(super) fn connections(&self) -> RwLockReadGuard<'_, ChatConnections> {
+        self.connections
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(super) fn connections_mut(&self) -> RwLockWriteGuard<'_, ChatConnections> {
+        self.connections
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(super) fn connection(&self, connection_id: &str) -> Option<Arc<ChatOutbound>> {
+        self.connections().get(connection_id)
+    }
+
+    /// Dispatches of `event` to each of the connections on this instance.
+    pub(super) fn dispatches(
+        &self,
+        connection_ids: impl IntoIterator<Item = String>,
+        event: ChatEvent,
+    ) -> Vec<ChatDispatch> {
+        let connections = self.connections();
+        connection_ids
+            .into_iter()
+            .filter_map(|connection_id| connections.get(&connection_id))
+            .map(|sender| ChatDispatch { sender, event: event.clone() })
+            .collect()
+    }
+
+    /// Dispatches of `event` to the room's members on this instance, except `exclude`.
+    pub(super) fn room_dispatches(
+        &self,
+        hub: &ChatHub,
+        room_id: &str,
+        event: ChatEvent,
+        exclude: Option<&str>,
+    ) -> Vec<ChatDispatch> {
+        self.dispatches(
+            hub.chat_member_connection_ids(room_id)
+                .into_iter()
+                .filter(|connection_id| exclude != Some(connection_id.as_str())),
+            event,
+        )
+    }
+}
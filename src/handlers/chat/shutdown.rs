@@ -0,0 +1,59 @@
+use std::{future::Future, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio_util::{sync::CancellationToken, task::task_tracker::TrackedFuture};
+use tracing::{info, warn};
+
+use super::{ChatEvent, ChatState, outbound::ChatCloseReason};
+
+/// Sent to every connection when the server starts shutting down, right before its close frame.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatServerShutdown {
+    /// How long clients should wait before reconnecting, to reach another instance.
+    pub reconnect_after_ms: u64,
+}
+
+impl ChatState {
+    /// Counts a websocket or gRPC session until it ends, so shutdown can wait for it.
+    pub fn track_session<F: Future>(&self, session: F) -> TrackedFuture<F> {
+        self.sessions.track_future(session)
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Resolves once the server starts shutting down.
+    pub async fn shutting_down(&self) {
+        self.shutdown.cancelled().await;
+    }
+
+    pub fn shutdown_notice(&self) -> ChatEvent {
+        ChatEvent::ServerShutdown(ChatServerShutdown {
+            reconnect_after_ms: self.config.shutdown_reconnect_after_ms,
+        })
+    }
+
+    /// Waits for `token`, then tells every session to flush its events and close with a going
+    /// away code. Sessions still open after the drain window are closed without waiting further.
+    pub async fn drain_on_shutdown(&self, token: CancellationToken) {
+        token.cancelled().await;
+        info!(sessions = self.sessions.len(), "draining chat sessions");
+        self.shutdown.cancel();
+        self.sessions.close();
+
+        let drain_window = Duration::from_secs(self.config.shutdown_drain_secs);
+        if tokio::time::timeout(drain_window, self.sessions.wait())
+            .await
+            .is_err()
+        {
+            warn!(
+                sessions = self.sessions.len(),
+                "closing chat sessions left after the drain window"
+            );
+            for outbound in self.runtime.connections().outbounds() {
+                outbound.close(Some(ChatCloseReason::GoingAway));
+            }
+        }
+    }
+}
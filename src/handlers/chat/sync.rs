@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ChatEvent, ChatMessageDeleted, ChatMessageEdited, ChatPresenceChange, ChatRoomMessage,
+    ChatRoomSnapshot, ChatStatusChange, ChatUserSummary, directory::ChatRoomClosed,
+    reactions::ChatReactionChanged,
+};
+
+/// Room events kept for delta sync; clients further behind get a full snapshot.
+pub const MAX_ROOM_EVENTS: usize = 200;
+
+/// Room events after `since_version` up to `version`, oldest first.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatRoomDelta {
+    pub room_id: String,
+    pub since_version: u64,
+    pub version: u64,
+    pub events: Vec<ChatEvent>,
+}
+
+/// Bounded log of the version bumping events of one room: messages, their edits, deletes and
+/// reactions, presence and status changes.
+#[derive(Debug, Clone, Default)]
+pub struct ChatRoomEventLog {
+    entries: VecDeque<(u64, ChatEvent)>,
+    /// Every event after this version is still in the log.
+    floor: u64,
+}
+
+impl ChatRoomEventLog {
+    pub fn new(floor: u64) -> Self {
+        Self { entries: VecDeque::new(), floor }
+    }
+
+    /// Rebuilds a log from stored entries, oldest first; without any, nothing before `version`
+    /// can be replayed.
+    pub fn from_entries(entries: Vec<(u64, ChatEvent)>, version: u64) -> Self {
+        let floor = entries
+            .first()
+            .map(|(oldest, _)| oldest.saturating_sub(1))
+            .unwrap_or(version);
+        Self { entries: entries.into(), floor }
+    }
+
+    pub fn record(&mut self, version: u64, event: ChatEvent) {
+        self.entries.push_back((version, event));
+        while self.entries.len() > MAX_ROOM_EVENTS {
+            if let Some((evicted, _)) = self.entries.pop_front() {
+                self.floor = evicted;
+            }
+        }
+    }
+
+    /// Events after `since_version` up to `version`, or `None` when some were already evicted
+    /// or the client claims a version the room has not reached.
+    pub fn since(&self, since_version: u64, version: u64) -> Option<Vec<ChatEvent>> {
+        if since_version < self.floor || since_version > version {
+            return None;
+        }
+
+        Some(
+            self.entries
+                .iter()
+                .filter(|(entry_version, _)| {
+                    *entry_version > since_version && *entry_version <= version
+                })
+                .map(|(_, event)| event.clone())
+                .collect(),
+        )
+    }
+}
+
+/// Event log entry as stored by the backplane scripts, which only know the raw JSON pieces.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoredRoomEvent {
+    pub version: u64,
+    #[serde(default)]
+    pub joined: Vec<ChatUserSummary>,
+    #[serde(default)]
+    pub left: Vec<ChatUserSummary>,
+    #[serde(default)]
+    pub message: Option<ChatRoomMessage>,
+    #[serde(default)]
+    pub edited: Option<ChatMessageEdited>,
+    #[serde(default)]
+    pub deleted: Option<ChatMessageDeleted>,
+    #[serde(default)]
+    pub status: Option<ChatStatusChange>,
+    #[serde(default)]
+    pub closed: Option<ChatRoomClosed>,
+    #[serde(default)]
+    pub reaction: Option<ChatReactionChanged>,
+}
+
+impl StoredRoomEvent {
+    pub fn into_event(self, room_id: &str) -> (u64, ChatEvent) {
+        let event = match (
+            self.message,
+            self.edited,
+            self.deleted,
+            self.status,
+            self.closed,
+            self.reaction,
+        ) {
+            (Some(message), ..) => ChatEvent::RoomMessage(message),
+            (_, Some(edited), ..) => ChatEvent::MessageEdited(edited),
+            (_, _, Some(deleted), ..) => ChatEvent::MessageDeleted(deleted),
+            (_, _, _, Some(status), ..) => ChatEvent::StatusChanged(status),
+            (_, _, _, _, Some(closed), _) => ChatEvent::RoomClosed(closed),
+            (.., Some(reaction)) => ChatEvent::ReactionChanged(reaction),
+            _ => ChatEvent::PresenceChanged(ChatPresenceChange {
+                room_id: room_id.to_string(),
+                joined_members: self.joined,
+                left_members: self.left,
+                version: self.version,
+            }),
+        };
+        (self.version, event)
+    }
+}
+
+/// Reply to a join or sync that may ask for a delta.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatRoomSync {
+    Delta(ChatRoomDelta),
+    Snapshot(ChatRoomSnapshot),
+}
+
+/// The events since `since_version` when the log still has them, otherwise the full snapshot,
+/// flagged when a delta was asked for.
+pub fn room_sync(
+    mut snapshot: ChatRoomSnapshot,
+    since_version: Option<u64>,
+    log: &ChatRoomEventLog,
+) -> ChatRoomSync {
+    let Some(since_version) = since_version else {
+        return ChatRoomSync::Snapshot(snapshot);
+    };
+    match log.since(since_version, snapshot.version) {
+        Some(events) => ChatRoomSync::Delta(ChatRoomDelta {
+            room_id: snapshot.room_id,
+            since_version,
+            version: snapshot.version,
+            events,
+        }),
+        None => {
+            snapshot.delta_unavailable = true;
+            ChatRoomSync::Snapshot(snapshot)
+        }
+    }
+}
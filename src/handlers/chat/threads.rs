@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    ChatError, ChatHub, ChatRoom, ChatRoomMessage, ChatRoomSnapshot, ChatSendRoomMessageResult,
+    ChatSessionUser, ChatState, backplane_error, edits::normalized_message_id,
+    filters::ChatFilteredContent, history::merge_history_page, normalized_content,
+    normalized_room_id,
+};
+
+/// Thread summaries kept per room; the least recently replied to thread is dropped first.
+pub const MAX_THREADS: usize = 100;
+/// Latest replies returned when a thread is loaded.
+pub const MAX_THREAD_REPLIES: usize = 100;
+
+/// Reply count and latest reply of a thread, kept apart from the recent messages so threads
+/// whose root left the window are still summarized.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatThreadSummary {
+    pub root_message_id: String,
+    pub reply_count: u64,
+    pub last_reply: ChatRoomMessage,
+}
+
+/// A thread's root and latest replies, oldest first. `root` is unset when the root message is
+/// neither retained nor persisted.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatThread {
+    pub room_id: String,
+    pub root_message_id: String,
+    pub root: Option<ChatRoomMessage>,
+    pub replies: Vec<ChatRoomMessage>,
+    pub reply_count: u64,
+}
+
+/// The message a new message replies to, with its stored copy once it left the recent window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatReplyTarget {
+    pub message_id: String,
+    pub stored: Option<ChatRoomMessage>,
+}
+
+impl ChatReplyTarget {
+    pub fn new(message_id: impl Into<String>) -> Self {
+        Self { message_id: message_id.into(), stored: None }
+    }
+}
+
+impl ChatRoom {
+    /// Root of the thread a reply to `target` joins; replying to a reply joins its thread.
+    pub(super) fn thread_root(
+        &self,
+        room_id: &str,
+        target: &ChatReplyTarget,
+    ) -> Result<String, ChatError> {
+        let retained = self
+            .recent_messages
+            .iter()
+            .find(|message| message.message_id == target.message_id);
+        let message = match (retained, &target.stored) {
+            (Some(message), _) => message,
+            // Summarized threads outlive their root in the recent window.
+            (None, _) if self.threads.contains_key(&target.message_id) => {
+                return Ok(target.message_id.clone());
+            }
+            (None, Some(stored))
+                if stored.room_id == room_id && stored.message_id == target.message_id =>
+            {
+                stored
+            }
+            _ => {
+                return Err(ChatError::MessageNotFound { message_id: target.message_id.clone() });
+            }
+        };
+        if message.deleted_at.is_some() {
+            return Err(ChatError::MessageNotFound { message_id: target.message_id.clone() });
+        }
+
+        Ok(message
+            .reply_to
+            .clone()
+            .unwrap_or_else(|| message.message_id.clone()))
+    }
+
+    /// Counts a new reply into its thread's summary.
+    pub(super) fn record_reply(&mut self, reply: &ChatRoomMessage) {
+        let Some(root_message_id) = &reply.reply_to else {
+            return;
+        };
+        let summary = self
+            .threads
+            .entry(root_message_id.clone())
+            .or_insert_with(|| ChatThreadSummary {
+                root_message_id: root_message_id.clone(),
+                reply_count: 0,
+                last_reply: reply.clone(),
+            });
+        summary.reply_count += 1;
+        summary.last_reply = reply.clone();
+
+        if self.threads.len() > MAX_THREADS
+            && let Some(stalest) = self
+                .threads
+                .values()
+                .min_by_key(|summary| summary.last_reply.version)
+                .map(|summary| summary.root_message_id.clone())
+        {
+            self.threads.remove(&stalest);
+        }
+    }
+
+    /// Keeps a summary's last reply in step with its edits and deletes.
+    pub(super) fn update_thread_reply(&mut self, reply: &ChatRoomMessage) {
+        if let Some(summary) = reply
+            .reply_to
+            .as_ref()
+            .and_then(|root_message_id| self.threads.get_mut(root_message_id))
+            && summary.last_reply.message_id == reply.message_id
+        {
+            summary.last_reply = reply.clone();
+        }
+    }
+
+    /// Thread summaries, the least recently replied to first.
+    pub(super) fn thread_summaries(&self) -> Vec<ChatThreadSummary> {
+        let mut summaries = self.threads.values().cloned().collect::<Vec<_>>();
+        summaries.sort_by_key(|summary| summary.last_reply.version);
+        summaries
+    }
+}
+
+impl ChatHub {
+    /// Sends a reply into the thread of `reply_to`.
+    pub fn send_room_reply(
+        &mut self,
+        connection_id: &str,
+        room_id: &str,
+        content: &str,
+        reply_to: ChatReplyTarget,
+    ) -> Result<ChatSendRoomMessageResult, ChatError> {
+        let content = normalized_content(content)?;
+        self.send_filtered_message(
+            connection_id,
+            room_id,
+            ChatFilteredContent::unfiltered(content),
+            Some(reply_to),
+        )
+    }
+}
+
+impl ChatState {
+    /// Sends filtered content into a local room, loading the replied to message from history
+    /// when it left the recent window.
+    pub(super) async fn send_local_message(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        filtered: ChatFilteredContent,
+        reply_to: Option<String>,
+    ) -> Result<ChatSendRoomMessageResult, ChatError> {
+        let mut reply_to = reply_to
+            .map(|message_id| normalized_message_id(&message_id))
+            .transpose()?
+            .map(ChatReplyTarget::new);
+        loop {
+            let sent = self
+                .runtime
+                .room(room_id)
+                .lock()
+                .await
+                .send_filtered_message(
+                    &session_user.connection_id,
+                    room_id,
+                    filtered.clone(),
+                    reply_to.clone(),
+                );
+            match (sent, &mut reply_to) {
+                (Err(ChatError::MessageNotFound { .. }), Some(target))
+                    if target.stored.is_none() && self.history.is_some() =>
+                {
+                    target.stored = Some(self.stored_message(room_id, &target.message_id).await?);
+                }
+                (sent, _) => return sent,
+            }
+        }
+    }
+
+    /// Loads the thread `message_id` starts or belongs to, merging retained replies with
+    /// persisted ones.
+    pub async fn room_thread(
+        &self,
+        session_user: &ChatSessionUser,
+        room_id: &str,
+        message_id: &str,
+    ) -> Result<ChatThread, ChatError> {
+        let room_id = normalized_room_id(room_id)?;
+        let message_id = normalized_message_id(message_id)?;
+        let snapshot = match &self.backplane {
+            Some(backplane) => {
+                if !self
+                    .runtime
+                    .room(&room_id)
+                    .lock()
+                    .await
+                    .is_member(&session_user.connection_id, &room_id)
+                {
+                    return Err(ChatError::NotInRoom { room_id });
+                }
+                backplane
+                    .room_snapshot(&room_id, None)
+                    .await
+                    .map_err(backplane_error)?
+                    .0
+            }
+            None => self
+                .runtime
+                .room(&room_id)
+                .lock()
+                .await
+                .sync_room_state(&session_user.connection_id, &room_id)?,
+        };
+
+        let mut root_message_id = message_id;
+        let mut root = self.thread_message(&snapshot, &root_message_id).await?;
+        if let Some(thread_root) = root.as_ref().and_then(|message| message.reply_to.clone()) {
+            root = self.thread_message(&snapshot, &thread_root).await?;
+            root_message_id = thread_root;
+        }
+        let summary = snapshot
+            .threads
+            .iter()
+            .find(|summary| summary.root_message_id == root_message_id);
+        let cached = snapshot
+            .recent_messages
+            .iter()
+            .filter(|message| message.reply_to.as_ref() == Some(&root_message_id))
+            .cloned()
+            .collect();
+        let stored = match &self.history {
+            Some(history) => history
+                .load_thread(&room_id, &root_message_id, MAX_THREAD_REPLIES)
+                .await
+                .map_err(|error| {
+                    warn!(error = %error, room_id, "failed to load chat thread");
+                    ChatError::HistoryUnavailable
+                })?,
+            None => Vec::new(),
+        };
+        let replies =
+            merge_history_page(room_id.clone(), MAX_THREAD_REPLIES, cached, stored).messages;
+        if root.is_none() && summary.is_none() && replies.is_empty() {
+            return Err(ChatError::MessageNotFound { message_id: root_message_id });
+        }
+
+        // Summaries are dropped with their room, persisted replies still count.
+        let reply_count = summary
+            .map_or(0, |summary| summary.reply_count)
+            .max(replies.len() as u64);
+        Ok(ChatThread { room_id, root_message_id, root, replies, reply_count })
+    }
+
+    /// A message of the snapshot's room, from its recent window or else from history.
+    async fn thread_message(
+        &self,
+        snapshot: &ChatRoomSnapshot,
+        message_id: &str,
+    ) -> Result<Option<ChatRoomMessage>, ChatError> {
+        if let Some(message) = snapshot
+            .recent_messages
+            .iter()
+            .find(|message| message.message_id == message_id)
+        {
+            return Ok(Some(message.clone()));
+        }
+        if self.history.is_none() {
+            return Ok(None);
+        }
+        match self.stored_message(&snapshot.room_id, message_id).await {
+            Ok(message) => Ok(Some(message)),
+            Err(ChatError::MessageNotFound { .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
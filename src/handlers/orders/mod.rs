@@ -14,11 +14,13 @@ use uuid::Uuid;
 use crate::{
     error::{AppError, AppResult},
     orders::{
-        CreateOrderRequest, PrecheckDecision, RedisPrecheckOutcome, decide_order_creation,
-        redis_stock_key,
+        CreateOrderRequest, PrecheckDecision, RedisPrecheckOutcome, SagaStage,
+        decide_order_creation, redis_stock_key, saga_elapsed_seconds,
         store::{OrderRecord, get_order_by_id, insert_order_with_outbox},
+        utc_now,
     },
     route::AppState,
+    utils::observability,
 };
 
 #[derive(Debug, Serialize)]
@@ -46,15 +48,21 @@ pub async fn create(
     match redis_precheck(&state, &payload.sku, payload.quantity).await {
         PrecheckDecision::Allow => {}
         PrecheckDecision::Reject { status, reason } => {
+            observability::record_order_rejected(SagaStage::Precheck.as_str(), &reason);
             return Err(AppError::new("Insufficient stock")
                 .with_status(status)
-                .with_details(serde_json::json!({ "reason": reason }))
-                .into());
+                .with_details(serde_json::json!({ "reason": reason })));
         }
     }
 
     tracing::info!(db_role = "write", "handling order write request");
     let order = insert_order_with_outbox(&state.write_pool, &payload).await?;
+    observability::record_order_created();
+    observability::record_order_saga_duration(
+        SagaStage::Accepted.as_str(),
+        &order.status.as_str().to_ascii_lowercase(),
+        saga_elapsed_seconds(order.created_at_utc, utc_now()),
+    );
     Ok((StatusCode::CREATED, Json(to_order_response(order)?)))
 }
 
@@ -104,5 +112,8 @@ async fn redis_precheck(state: &Arc<AppState>, sku: &str, quantity: i32) -> Prec
         }
     };
 
-    decide_order_creation(outcome, quantity)
+    let outcome_label = outcome.as_str();
+    let decision = decide_order_creation(outcome, quantity);
+    observability::record_redis_precheck(outcome_label, decision.as_str());
+    decision
 }
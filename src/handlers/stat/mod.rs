@@ -1 +1,2 @@
 pub mod hot;
+pub mod orders;
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    error::{AppError, AppResult},
+    orders::{
+        projections::{
+            SkuDailyStats, SkuStatsSummary, confirmation_rate, list_sku_daily_stats,
+            list_sku_summaries,
+        },
+        utc_now,
+    },
+    route::AppState,
+};
+
+const DEFAULT_RANGE_DAYS: u64 = 7;
+const MAX_RANGE_DAYS: u64 = 92;
+const DEFAULT_SKU_LIMIT: i64 = 20;
+const MAX_SKU_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct OrderStatsQuery {
+    pub sku: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SkuDailyStatsItem {
+    pub sku: String,
+    pub day: String,
+    pub orders_created: i64,
+    pub quantity_ordered: i64,
+    pub orders_confirmed: i64,
+    pub orders_rejected: i64,
+    pub confirmation_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SkuStatsSummaryItem {
+    pub sku: String,
+    pub orders_created: i64,
+    pub quantity_ordered: i64,
+    pub orders_confirmed: i64,
+    pub orders_rejected: i64,
+    pub confirmation_rate: Option<f64>,
+}
+
+impl From<SkuDailyStats> for SkuDailyStatsItem {
+    fn from(stats: SkuDailyStats) -> Self {
+        Self {
+            confirmation_rate: confirmation_rate(stats.orders_confirmed, stats.orders_rejected),
+            sku: stats.sku,
+            day: stats.day.to_string(),
+            orders_created: stats.orders_created,
+            quantity_ordered: stats.quantity_ordered,
+            orders_confirmed: stats.orders_confirmed,
+            orders_rejected: stats.orders_rejected,
+        }
+    }
+}
+
+impl From<SkuStatsSummary> for SkuStatsSummaryItem {
+    fn from(summary: SkuStatsSummary) -> Self {
+        Self {
+            confirmation_rate: confirmation_rate(summary.orders_confirmed, summary.orders_rejected),
+            sku: summary.sku,
+            orders_created: summary.orders_created,
+            quantity_ordered: summary.quantity_ordered,
+            orders_confirmed: summary.orders_confirmed,
+            orders_rejected: summary.orders_rejected,
+        }
+    }
+}
+
+/// Per-SKU daily order counts from the `order_sku_daily_stats` projection.
+pub async fn daily(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OrderStatsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let (from, to) = resolve_date_range(query.from.as_deref(), query.to.as_deref(), today())?;
+    let sku = query
+        .sku
+        .as_deref()
+        .map(str::trim)
+        .filter(|sku| !sku.is_empty());
+
+    let items = list_sku_daily_stats(&state.read_pool, sku, from, to)
+        .await
+        .map_err(projection_query_error)?
+        .into_iter()
+        .map(SkuDailyStatsItem::from)
+        .collect::<Vec<_>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "sku": sku,
+            "items": items,
+        })),
+    ))
+}
+
+/// Per-SKU totals and confirmation rates over a date range, busiest SKUs first.
+pub async fn skus(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OrderStatsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let (from, to) = resolve_date_range(query.from.as_deref(), query.to.as_deref(), today())?;
+    let limit = sanitized_sku_limit(query.limit);
+
+    let items = list_sku_summaries(&state.read_pool, from, to, limit)
+        .await
+        .map_err(projection_query_error)?
+        .into_iter()
+        .map(SkuStatsSummaryItem::from)
+        .collect::<Vec<_>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "limit": limit,
+            "items": items,
+        })),
+    ))
+}
+
+/// Inclusive `[from, to]` range, defaulting to the last week and capped at `MAX_RANGE_DAYS`.
+pub(crate) fn resolve_date_range(
+    from: Option<&str>,
+    to: Option<&str>,
+    today: NaiveDate,
+) -> AppResult<(NaiveDate, NaiveDate)> {
+    let to = match to {
+        Some(to) => parse_day("to", to)?,
+        None => today,
+    };
+    let from = match from {
+        Some(from) => parse_day("from", from)?,
+        None => to - Days::new(DEFAULT_RANGE_DAYS - 1),
+    };
+
+    if from > to {
+        return Err(AppError::new("from must not be after to"));
+    }
+    if (to - from).num_days() >= MAX_RANGE_DAYS as i64 {
+        return Err(AppError::new("date range is too large").with_details(json!({
+            "max_days": MAX_RANGE_DAYS,
+        })));
+    }
+
+    Ok((from, to))
+}
+
+pub(crate) fn sanitized_sku_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_SKU_LIMIT).clamp(1, MAX_SKU_LIMIT)
+}
+
+fn parse_day(field: &str, value: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").map_err(|_| {
+        AppError::new("invalid date, expected YYYY-MM-DD").with_details(json!({
+            "field": field,
+            "value": value,
+        }))
+    })
+}
+
+fn today() -> NaiveDate {
+    utc_now().date_naive()
+}
+
+fn projection_query_error(error: anyhow::Error) -> AppError {
+    error!(error = ?error, "failed to query order projections");
+    AppError::internal("failed to query order stats")
+}
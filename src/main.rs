@@ -1,5 +1,8 @@
+use std::sync::Arc;
+
 use axes::{
-    route::route,
+    handlers::chat::ChatState,
+    route::{app_state, route},
     utils::{gracefully_shutdown::shutdown_token, observability},
 };
 
@@ -16,13 +19,16 @@ async fn main() -> Result<(), anyhow::Error> {
         .parse()?;
 
     let token = shutdown_token();
-    let router = route().await?;
+    let state = app_state().await?;
+    let chat_state = state.chat_service.clone();
+    let router = route(state);
 
     tracing::info!("http listening on http://{} grpc listening on http://{}", http_addr, grpc_addr);
 
     tokio::try_join!(
         run_http(http_addr, router, token.clone()),
-        run_grpc(grpc_addr, token.clone()),
+        run_grpc(grpc_addr, chat_state.clone(), token.clone()),
+        drain_chat(chat_state, token.clone()),
     )?;
 
     observability.shutdown()?;
@@ -44,8 +50,18 @@ async fn run_http(
 
 async fn run_grpc(
     grpc_addr: std::net::SocketAddr,
+    chat_state: Arc<ChatState>,
+    token: tokio_util::sync::CancellationToken,
+) -> anyhow::Result<()> {
+    axes::grpc::serve(grpc_addr, chat_state, token).await?;
+    Ok(())
+}
+
+/// Closes chat sessions on shutdown; the servers' graceful shutdown would wait on them forever.
+async fn drain_chat(
+    chat_state: Arc<ChatState>,
     token: tokio_util::sync::CancellationToken,
 ) -> anyhow::Result<()> {
-    axes::grpc::serve(grpc_addr, token).await?;
+    chat_state.drain_on_shutdown(token).await;
     Ok(())
 }
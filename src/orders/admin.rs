@@ -0,0 +1,341 @@
+use std::{
+    fmt::Write as _,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+    routing::get,
+};
+use rdkafka::{
+    Offset,
+    consumer::{Consumer, StreamConsumer},
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::warn;
+
+use super::store::{OutboxBacklog, OutboxSource};
+
+const KAFKA_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Consumed message counters shared between a worker's consume loop and its admin listener.
+#[derive(Debug, Default)]
+pub struct WorkerMessageCounters {
+    processed: AtomicU64,
+    failed: AtomicU64,
+    retried: AtomicU64,
+    redelivered: AtomicU64,
+    parked: AtomicU64,
+}
+
+impl WorkerMessageCounters {
+    pub fn record_processed(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_retried(&self) {
+        self.retried.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_redelivered(&self) {
+        self.redelivered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_parked(&self) {
+        self.parked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn retried(&self) -> u64 {
+        self.retried.load(Ordering::Relaxed)
+    }
+
+    pub fn redelivered(&self) -> u64 {
+        self.redelivered.load(Ordering::Relaxed)
+    }
+
+    pub fn parked(&self) -> u64 {
+        self.parked.load(Ordering::Relaxed)
+    }
+}
+
+pub struct WorkerAdminState {
+    pub worker: &'static str,
+    pub pool: Arc<PgPool>,
+    pub redis_client: Option<Arc<redis::Client>>,
+    pub consumer: Arc<StreamConsumer>,
+    pub outbox: Option<OutboxSource>,
+    pub counters: Arc<WorkerMessageCounters>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: i32,
+    pub lag: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkerMetricsSnapshot {
+    pub worker: String,
+    pub outbox_table: String,
+    pub outbox: Option<OutboxBacklog>,
+    pub consumer_lag: Vec<PartitionLag>,
+    pub messages_processed: u64,
+    pub messages_failed: u64,
+    pub messages_retried: u64,
+    pub messages_redelivered: u64,
+    pub messages_parked: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadinessResponse {
+    status: &'static str,
+    checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadinessCheck {
+    name: &'static str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Resolves the admin listener address, `AXES_WORKER_ADMIN_ADDR` wins over settings.
+pub fn admin_addr(configured: &str) -> anyhow::Result<std::net::SocketAddr> {
+    std::env::var("AXES_WORKER_ADMIN_ADDR")
+        .unwrap_or_else(|_| configured.to_string())
+        .parse()
+        .context("invalid worker admin address")
+}
+
+pub fn admin_router(state: Arc<WorkerAdminState>) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+pub async fn serve_admin(
+    addr: std::net::SocketAddr,
+    state: Arc<WorkerAdminState>,
+    token: tokio_util::sync::CancellationToken,
+) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind worker admin listener on {addr}"))?;
+    axum::serve(listener, admin_router(state))
+        .with_graceful_shutdown(async move { token.cancelled().await })
+        .await?;
+    Ok(())
+}
+
+async fn healthz() -> impl IntoResponse {
+    (StatusCode::OK, "ok")
+}
+
+async fn readyz(State(state): State<Arc<WorkerAdminState>>) -> impl IntoResponse {
+    let mut checks = vec![
+        readiness_check("postgres", check_postgres(&state.pool).await),
+        readiness_check("kafka", check_kafka(state.consumer.clone()).await),
+    ];
+    if let Some(redis_client) = state.redis_client.as_ref() {
+        checks.push(readiness_check("redis", check_redis(redis_client).await));
+    }
+
+    let ready = checks.iter().all(|check| check.ok);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (status, Json(ReadinessResponse { status: if ready { "ready" } else { "not_ready" }, checks }))
+}
+
+async fn metrics(State(state): State<Arc<WorkerAdminState>>) -> impl IntoResponse {
+    let outbox = match state.outbox {
+        Some(source) => match source.load_backlog(&state.pool).await {
+            Ok(backlog) => Some(backlog),
+            Err(error) => {
+                warn!(error = %error, worker = state.worker, "failed to load outbox backlog");
+                None
+            }
+        },
+        None => None,
+    };
+    let consumer_lag = match consumer_lag(state.consumer.clone()).await {
+        Ok(lag) => lag,
+        Err(error) => {
+            warn!(error = %error, worker = state.worker, "failed to compute consumer lag");
+            Vec::new()
+        }
+    };
+
+    let snapshot = WorkerMetricsSnapshot {
+        worker: state.worker.to_string(),
+        outbox_table: state
+            .outbox
+            .map(OutboxSource::table_name)
+            .unwrap_or_default()
+            .to_string(),
+        outbox,
+        consumer_lag,
+        messages_processed: state.counters.processed(),
+        messages_failed: state.counters.failed(),
+        messages_retried: state.counters.retried(),
+        messages_redelivered: state.counters.redelivered(),
+        messages_parked: state.counters.parked(),
+    };
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        render_metrics(&snapshot),
+    )
+}
+
+/// Renders a snapshot in the Prometheus text exposition format.
+pub fn render_metrics(snapshot: &WorkerMetricsSnapshot) -> String {
+    let worker = &snapshot.worker;
+    let table = &snapshot.outbox_table;
+    let mut out = String::new();
+
+    if let Some(outbox) = snapshot.outbox {
+        let _ = writeln!(out, "# TYPE axes_outbox_unpublished_messages gauge");
+        let _ = writeln!(
+            out,
+            "axes_outbox_unpublished_messages{{worker=\"{worker}\",table=\"{table}\"}} {}",
+            outbox.unpublished_count
+        );
+        let _ = writeln!(out, "# TYPE axes_outbox_oldest_unpublished_age_seconds gauge");
+        let _ = writeln!(
+            out,
+            "axes_outbox_oldest_unpublished_age_seconds{{worker=\"{worker}\",table=\"{table}\"}} {}",
+            outbox.oldest_unpublished_age_seconds.unwrap_or(0.0)
+        );
+    }
+
+    let _ = writeln!(out, "# TYPE axes_kafka_consumer_lag gauge");
+    for lag in &snapshot.consumer_lag {
+        let _ = writeln!(
+            out,
+            "axes_kafka_consumer_lag{{worker=\"{worker}\",topic=\"{}\",partition=\"{}\"}} {}",
+            lag.topic, lag.partition, lag.lag
+        );
+    }
+
+    let _ = writeln!(out, "# TYPE axes_worker_messages_processed_total counter");
+    let _ = writeln!(
+        out,
+        "axes_worker_messages_processed_total{{worker=\"{worker}\"}} {}",
+        snapshot.messages_processed
+    );
+    let _ = writeln!(out, "# TYPE axes_worker_messages_failed_total counter");
+    let _ = writeln!(
+        out,
+        "axes_worker_messages_failed_total{{worker=\"{worker}\"}} {}",
+        snapshot.messages_failed
+    );
+    let _ = writeln!(out, "# TYPE axes_worker_messages_retried_total counter");
+    let _ = writeln!(
+        out,
+        "axes_worker_messages_retried_total{{worker=\"{worker}\"}} {}",
+        snapshot.messages_retried
+    );
+    let _ = writeln!(out, "# TYPE axes_worker_messages_redelivered_total counter");
+    let _ = writeln!(
+        out,
+        "axes_worker_messages_redelivered_total{{worker=\"{worker}\"}} {}",
+        snapshot.messages_redelivered
+    );
+    let _ = writeln!(out, "# TYPE axes_worker_messages_parked_total counter");
+    let _ = writeln!(
+        out,
+        "axes_worker_messages_parked_total{{worker=\"{worker}\"}} {}",
+        snapshot.messages_parked
+    );
+
+    out
+}
+
+fn readiness_check(name: &'static str, result: anyhow::Result<()>) -> ReadinessCheck {
+    match result {
+        Ok(()) => ReadinessCheck { name, ok: true, error: None },
+        Err(error) => ReadinessCheck { name, ok: false, error: Some(format!("{error:#}")) },
+    }
+}
+
+async fn check_postgres(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .context("postgres is unreachable")?;
+    Ok(())
+}
+
+async fn check_redis(redis_client: &redis::Client) -> anyhow::Result<()> {
+    let mut conn = redis_client
+        .get_multiplexed_async_connection()
+        .await
+        .context("redis is unreachable")?;
+    let _: String = redis::cmd("PING")
+        .query_async(&mut conn)
+        .await
+        .context("redis ping failed")?;
+    Ok(())
+}
+
+async fn check_kafka(consumer: Arc<StreamConsumer>) -> anyhow::Result<()> {
+    // librdkafka metadata calls block, keep them off the runtime threads.
+    tokio::task::spawn_blocking(move || {
+        consumer
+            .fetch_metadata(None, KAFKA_PROBE_TIMEOUT)
+            .map(|_| ())
+            .context("kafka brokers are unreachable")
+    })
+    .await?
+}
+
+async fn consumer_lag(consumer: Arc<StreamConsumer>) -> anyhow::Result<Vec<PartitionLag>> {
+    tokio::task::spawn_blocking(move || {
+        let positions = consumer
+            .position()
+            .context("failed to read consumer positions")?;
+        let mut lags = Vec::new();
+        for element in positions.elements() {
+            // Partitions without a fetched position yet have nothing consumed to measure from.
+            let Offset::Offset(position) = element.offset() else {
+                continue;
+            };
+            let (_, high) = consumer
+                .fetch_watermarks(element.topic(), element.partition(), KAFKA_PROBE_TIMEOUT)
+                .context("failed to fetch kafka watermarks")?;
+            lags.push(PartitionLag {
+                topic: element.topic().to_string(),
+                partition: element.partition(),
+                lag: (high - position).max(0),
+            });
+        }
+        Ok(lags)
+    })
+    .await?
+}
@@ -3,8 +3,12 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::error::AppError;
+use crate::{config::KafkaConfig, error::AppError};
 
+pub mod admin;
+pub mod projections;
+pub mod retry;
+pub mod runtime;
 pub mod store;
 pub mod worker;
 
@@ -12,6 +16,7 @@ pub const ORDER_CREATED_EVENT_TYPE: &str = "OrderCreated";
 pub const INVENTORY_RESULT_EVENT_TYPE: &str = "InventoryResult";
 pub const ORDERS_WORKER_CONSUMER: &str = "axes-orders-worker";
 pub const INVENTORY_WORKER_CONSUMER: &str = "axes-inventory-worker";
+pub const PROJECTIONS_WORKER_CONSUMER: &str = "axes-projections-worker";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OrderStatus {
@@ -80,12 +85,62 @@ pub enum RedisPrecheckOutcome {
     Unavailable,
 }
 
+impl RedisPrecheckOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Known { .. } => "known",
+            Self::Missing => "missing",
+            Self::Unavailable => "unavailable",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PrecheckDecision {
     Allow,
     Reject { status: StatusCode, reason: String },
 }
 
+impl PrecheckDecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Reject { .. } => "reject",
+        }
+    }
+}
+
+/// Saga steps reported on the `orders.saga.duration` histogram, measured from `CreatedAtUtc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SagaStage {
+    /// API rejected the order before writing it.
+    Precheck,
+    /// API wrote the Pending order and its outbox event.
+    Accepted,
+    /// Inventory worker decided whether stock could be reserved.
+    Inventory,
+    /// Orders worker applied the final Confirmed/Rejected status.
+    Completed,
+}
+
+impl SagaStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Precheck => "precheck",
+            Self::Accepted => "accepted",
+            Self::Inventory => "inventory",
+            Self::Completed => "completed",
+        }
+    }
+}
+
+pub fn saga_elapsed_seconds(created_at_utc: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+    (now - created_at_utc)
+        .to_std()
+        .map(|elapsed| elapsed.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
 pub fn redis_stock_key(sku: &str) -> String {
     format!("demo:stock:{sku}")
 }
@@ -143,37 +198,17 @@ pub struct InventoryResultEvent {
     pub occurred_on_utc: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct KafkaSettings {
-    pub brokers: String,
-    pub order_created_topic: String,
-    pub inventory_result_topic: String,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KafkaTopic {
+    OrderCreated,
+    InventoryResult,
 }
 
-impl KafkaSettings {
-    pub fn from_env() -> Self {
-        Self::from_lookup(|key| std::env::var(key).ok())
-    }
-
-    pub fn from_map(values: &[(&str, &str)]) -> Self {
-        Self::from_lookup(|key| {
-            values
-                .iter()
-                .find(|(candidate, _)| *candidate == key)
-                .map(|(_, value)| (*value).to_string())
-        })
-    }
-
-    fn from_lookup<F>(lookup: F) -> Self
-    where
-        F: Fn(&str) -> Option<String>,
-    {
-        Self {
-            brokers: lookup("AXES_KAFKA_BROKERS").unwrap_or_else(|| "localhost:9092".to_string()),
-            order_created_topic: lookup("AXES_KAFKA_ORDER_CREATED_TOPIC")
-                .unwrap_or_else(|| "orders.created.v1".to_string()),
-            inventory_result_topic: lookup("AXES_KAFKA_INVENTORY_RESULT_TOPIC")
-                .unwrap_or_else(|| "inventory.result.v1".to_string()),
+impl KafkaTopic {
+    pub fn resolve(self, kafka: &KafkaConfig) -> &str {
+        match self {
+            Self::OrderCreated => &kafka.order_created_topic,
+            Self::InventoryResult => &kafka.inventory_result_topic,
         }
     }
 }
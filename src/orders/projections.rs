@@ -0,0 +1,360 @@
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{AssertSqlSafe, PgPool, Postgres, Row, Transaction};
+use uuid::Uuid;
+
+use super::{
+    INVENTORY_RESULT_EVENT_TYPE, InventoryResultEvent, ORDER_CREATED_EVENT_TYPE, OrderCreatedEvent,
+    store::{OutboxSource, insert_inbox_once},
+    utc_now,
+};
+
+const PROJECTION_INBOX_TABLE: &str = "projection_inbox_messages";
+const REPLAY_BATCH_SIZE: i64 = 500;
+
+/// Read models derived from the order saga events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// Per-SKU, per-day created/confirmed/rejected counters in `order_sku_daily_stats`.
+    OrderSkuDailyStats,
+}
+
+impl Projection {
+    pub const ALL: [Projection; 1] = [Projection::OrderSkuDailyStats];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OrderSkuDailyStats => "order_sku_daily_stats",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|projection| projection.name() == name.trim())
+    }
+
+    fn table_name(self) -> &'static str {
+        match self {
+            Self::OrderSkuDailyStats => "order_sku_daily_stats",
+        }
+    }
+
+    /// Inbox consumer name, so each projection deduplicates events independently.
+    fn consumer(self) -> String {
+        format!("projection:{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectedEvent {
+    OrderCreated(OrderCreatedEvent),
+    InventoryResult(InventoryResultEvent),
+}
+
+impl ProjectedEvent {
+    pub fn message_id(&self) -> Uuid {
+        match self {
+            Self::OrderCreated(event) => event.message_id,
+            Self::InventoryResult(event) => event.message_id,
+        }
+    }
+
+    /// Created counts land on the order's day, confirmations and rejections on the day the
+    /// inventory decision was made, which keeps every delta independent of arrival order.
+    pub fn sku_daily_delta(&self) -> anyhow::Result<SkuDailyDelta> {
+        match self {
+            Self::OrderCreated(event) => Ok(SkuDailyDelta {
+                sku: event.sku.clone(),
+                day: event_day(&event.occurred_on_utc)?,
+                orders_created: 1,
+                quantity_ordered: i64::from(event.quantity),
+                orders_confirmed: 0,
+                orders_rejected: 0,
+            }),
+            Self::InventoryResult(event) => Ok(SkuDailyDelta {
+                sku: event.sku.clone(),
+                day: event_day(&event.occurred_on_utc)?,
+                orders_created: 0,
+                quantity_ordered: 0,
+                orders_confirmed: i64::from(event.success),
+                orders_rejected: i64::from(!event.success),
+            }),
+        }
+    }
+
+    fn decode(event_type: &str, payload: &str) -> anyhow::Result<Option<Self>> {
+        let event = match event_type {
+            ORDER_CREATED_EVENT_TYPE => Self::OrderCreated(serde_json::from_str(payload)?),
+            INVENTORY_RESULT_EVENT_TYPE => Self::InventoryResult(serde_json::from_str(payload)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkuDailyDelta {
+    pub sku: String,
+    pub day: NaiveDate,
+    pub orders_created: i64,
+    pub quantity_ordered: i64,
+    pub orders_confirmed: i64,
+    pub orders_rejected: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkuDailyStats {
+    pub sku: String,
+    pub day: NaiveDate,
+    pub orders_created: i64,
+    pub quantity_ordered: i64,
+    pub orders_confirmed: i64,
+    pub orders_rejected: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkuStatsSummary {
+    pub sku: String,
+    pub orders_created: i64,
+    pub quantity_ordered: i64,
+    pub orders_confirmed: i64,
+    pub orders_rejected: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebuildSummary {
+    pub events_replayed: u64,
+}
+
+/// Share of decided orders that were confirmed, `None` until anything was decided.
+pub fn confirmation_rate(orders_confirmed: i64, orders_rejected: i64) -> Option<f64> {
+    let decided = orders_confirmed + orders_rejected;
+    (decided > 0).then(|| orders_confirmed as f64 / decided as f64)
+}
+
+/// Applies one event to a projection, returns `false` when it was already applied.
+pub async fn project_event(
+    pool: &PgPool,
+    projection: Projection,
+    event: &ProjectedEvent,
+) -> anyhow::Result<bool> {
+    let mut tx = pool.begin().await?;
+    // Take the projection table lock before the inbox row, in the same order as a rebuild,
+    // so a concurrent rebuild makes this wait instead of deadlocking.
+    let sql = format!(r#"LOCK TABLE "{}" IN ROW EXCLUSIVE MODE"#, projection.table_name());
+    sqlx::query(AssertSqlSafe(sql)).execute(&mut *tx).await?;
+    let applied = apply_event(&mut tx, projection, event).await?;
+    tx.commit().await?;
+    Ok(applied)
+}
+
+/// Truncates a projection and replays it from the retained outbox history in one transaction.
+pub async fn rebuild_projection(
+    pool: &PgPool,
+    projection: Projection,
+) -> anyhow::Result<RebuildSummary> {
+    let mut tx = pool.begin().await?;
+    let sql = format!(r#"TRUNCATE TABLE "{}""#, projection.table_name());
+    sqlx::query(AssertSqlSafe(sql)).execute(&mut *tx).await?;
+    sqlx::query(r#"DELETE FROM "projection_inbox_messages" WHERE "Consumer" = $1"#)
+        .bind(projection.consumer())
+        .execute(&mut *tx)
+        .await?;
+
+    let mut events_replayed = 0;
+    for (source, event_type) in [
+        (OutboxSource::Order, ORDER_CREATED_EVENT_TYPE),
+        (OutboxSource::Inventory, INVENTORY_RESULT_EVENT_TYPE),
+    ] {
+        let mut after_id = 0_i64;
+        loop {
+            let batch = load_replay_batch(&mut tx, source, event_type, after_id).await?;
+            let Some((last_id, _)) = batch.last() else {
+                break;
+            };
+            after_id = *last_id;
+
+            for (_, event) in &batch {
+                if apply_event(&mut tx, projection, event).await? {
+                    events_replayed += 1;
+                }
+            }
+        }
+    }
+
+    tx.commit().await?;
+    Ok(RebuildSummary { events_replayed })
+}
+
+pub async fn list_sku_daily_stats(
+    pool: &PgPool,
+    sku: Option<&str>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> anyhow::Result<Vec<SkuDailyStats>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT "Sku" AS sku,
+               "Day" AS day,
+               "OrdersCreated" AS orders_created,
+               "QuantityOrdered" AS quantity_ordered,
+               "OrdersConfirmed" AS orders_confirmed,
+               "OrdersRejected" AS orders_rejected
+        FROM "order_sku_daily_stats"
+        WHERE "Day" BETWEEN $1 AND $2
+          AND ($3::VARCHAR IS NULL OR "Sku" = $3)
+        ORDER BY "Day", "Sku"
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .bind(sku)
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(SkuDailyStats {
+                sku: row.try_get("sku")?,
+                day: row.try_get("day")?,
+                orders_created: row.try_get("orders_created")?,
+                quantity_ordered: row.try_get("quantity_ordered")?,
+                orders_confirmed: row.try_get("orders_confirmed")?,
+                orders_rejected: row.try_get("orders_rejected")?,
+            })
+        })
+        .collect()
+}
+
+pub async fn list_sku_summaries(
+    pool: &PgPool,
+    from: NaiveDate,
+    to: NaiveDate,
+    limit: i64,
+) -> anyhow::Result<Vec<SkuStatsSummary>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT "Sku" AS sku,
+               SUM("OrdersCreated")::INT8 AS orders_created,
+               SUM("QuantityOrdered")::INT8 AS quantity_ordered,
+               SUM("OrdersConfirmed")::INT8 AS orders_confirmed,
+               SUM("OrdersRejected")::INT8 AS orders_rejected
+        FROM "order_sku_daily_stats"
+        WHERE "Day" BETWEEN $1 AND $2
+        GROUP BY "Sku"
+        ORDER BY orders_created DESC, "Sku"
+        LIMIT $3
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(SkuStatsSummary {
+                sku: row.try_get("sku")?,
+                orders_created: row.try_get("orders_created")?,
+                quantity_ordered: row.try_get("quantity_ordered")?,
+                orders_confirmed: row.try_get("orders_confirmed")?,
+                orders_rejected: row.try_get("orders_rejected")?,
+            })
+        })
+        .collect()
+}
+
+async fn apply_event(
+    tx: &mut Transaction<'_, Postgres>,
+    projection: Projection,
+    event: &ProjectedEvent,
+) -> anyhow::Result<bool> {
+    let inserted =
+        insert_inbox_once(tx, PROJECTION_INBOX_TABLE, event.message_id(), &projection.consumer())
+            .await?;
+    if !inserted {
+        return Ok(false);
+    }
+
+    match projection {
+        Projection::OrderSkuDailyStats => {
+            upsert_sku_daily_delta(tx, &event.sku_daily_delta()?).await?;
+        }
+    }
+    Ok(true)
+}
+
+async fn upsert_sku_daily_delta(
+    tx: &mut Transaction<'_, Postgres>,
+    delta: &SkuDailyDelta,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO "order_sku_daily_stats"
+            ("Sku", "Day", "OrdersCreated", "QuantityOrdered", "OrdersConfirmed", "OrdersRejected", "UpdatedAtUtc")
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT ("Sku", "Day") DO UPDATE
+        SET "OrdersCreated" = "order_sku_daily_stats"."OrdersCreated" + EXCLUDED."OrdersCreated",
+            "QuantityOrdered" = "order_sku_daily_stats"."QuantityOrdered" + EXCLUDED."QuantityOrdered",
+            "OrdersConfirmed" = "order_sku_daily_stats"."OrdersConfirmed" + EXCLUDED."OrdersConfirmed",
+            "OrdersRejected" = "order_sku_daily_stats"."OrdersRejected" + EXCLUDED."OrdersRejected",
+            "UpdatedAtUtc" = EXCLUDED."UpdatedAtUtc"
+        "#,
+    )
+    .bind(&delta.sku)
+    .bind(delta.day)
+    .bind(delta.orders_created)
+    .bind(delta.quantity_ordered)
+    .bind(delta.orders_confirmed)
+    .bind(delta.orders_rejected)
+    .bind(utc_now())
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+async fn load_replay_batch(
+    tx: &mut Transaction<'_, Postgres>,
+    source: OutboxSource,
+    event_type: &str,
+    after_id: i64,
+) -> anyhow::Result<Vec<(i64, ProjectedEvent)>> {
+    let sql = format!(
+        r#"
+        SELECT "Id" AS id, "EventType" AS event_type, "Payload" AS payload
+        FROM "{}"
+        WHERE "EventType" = $1 AND "Id" > $2
+        ORDER BY "Id"
+        LIMIT $3
+        "#,
+        source.table_name()
+    );
+    let rows = sqlx::query(AssertSqlSafe(sql))
+        .bind(event_type)
+        .bind(after_id)
+        .bind(REPLAY_BATCH_SIZE)
+        .fetch_all(&mut **tx)
+        .await?;
+
+    let mut events = Vec::with_capacity(rows.len());
+    for row in rows {
+        let id: i64 = row.try_get("id")?;
+        let event_type: String = row.try_get("event_type")?;
+        let payload: String = row.try_get("payload")?;
+        let event = ProjectedEvent::decode(&event_type, &payload)
+            .with_context(|| format!("failed to decode outbox message {id} for replay"))?;
+        if let Some(event) = event {
+            events.push((id, event));
+        }
+    }
+    Ok(events)
+}
+
+fn event_day(occurred_on_utc: &str) -> anyhow::Result<NaiveDate> {
+    let occurred_on_utc = DateTime::parse_from_rfc3339(occurred_on_utc)
+        .with_context(|| format!("invalid event timestamp {occurred_on_utc}"))?;
+    Ok(occurred_on_utc.with_timezone(&Utc).date_naive())
+}
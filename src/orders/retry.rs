@@ -0,0 +1,159 @@
+use std::{collections::HashMap, time::Duration};
+
+use super::store::StoreError;
+use crate::config::RetryConfig;
+
+/// Postgres SQLSTATEs worth retrying: serialization failure, deadlock, lock not available,
+/// admin shutdown and cannot connect now.
+const RETRYABLE_SQLSTATES: [&str; 5] = ["40001", "40P01", "55P03", "57P01", "57P03"];
+
+/// How a failed handler attempt should be treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The worker's own pool is exhausted; says nothing about the message itself.
+    PoolTimeout,
+    /// Infrastructure hiccup or an ordering race that may resolve on its own.
+    Transient,
+    /// Logic or data error, retrying the same message will fail the same way.
+    Permanent,
+}
+
+impl FailureKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PoolTimeout => "pool_timeout",
+            Self::Transient => "transient",
+            Self::Permanent => "permanent",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    Retry { backoff: Duration },
+    GiveUp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_elapsed: Duration,
+    pub max_redeliveries: u32,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &RetryConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            max_elapsed: Duration::from_millis(config.max_retry_ms),
+            max_redeliveries: config.max_redeliveries,
+        }
+    }
+
+    /// Exponential backoff for the given 1-based attempt, capped at `max_backoff`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        self.initial_backoff
+            .saturating_mul(1_u32 << exponent)
+            .min(self.max_backoff)
+    }
+
+    /// Pool timeouts are retried without spending the attempt budget, since they reflect worker
+    /// capacity rather than the message. Either kind gives up once the next backoff would run
+    /// past `max_elapsed` since the first attempt.
+    pub fn decide(&self, attempt: u32, kind: FailureKind, elapsed: Duration) -> RetryDecision {
+        let backoff = self.backoff(attempt);
+        match kind {
+            FailureKind::Permanent => RetryDecision::GiveUp,
+            _ if elapsed.saturating_add(backoff) > self.max_elapsed => RetryDecision::GiveUp,
+            FailureKind::PoolTimeout => RetryDecision::Retry { backoff },
+            FailureKind::Transient if attempt < self.max_attempts => {
+                RetryDecision::Retry { backoff }
+            }
+            FailureKind::Transient => RetryDecision::GiveUp,
+        }
+    }
+}
+
+/// What to do with a message whose retries ran out on a failure that may still clear up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedeliveryDecision {
+    /// Pause its partition and handle it again once the backoff passed.
+    Pause { backoff: Duration },
+    /// Stop redelivering and commit past it.
+    Park,
+}
+
+/// Counts the redeliveries of the message each partition is stuck on.
+#[derive(Debug, Default)]
+pub struct RedeliveryTracker {
+    stuck: HashMap<(String, i32), (i64, u32)>,
+}
+
+impl RedeliveryTracker {
+    /// Records another round of exhausted retries, backing off a full `max_backoff` more each
+    /// time until `max_redeliveries` is spent.
+    pub fn record(
+        &mut self,
+        policy: &RetryPolicy,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> RedeliveryDecision {
+        let key = (topic.to_string(), partition);
+        let stuck = self.stuck.entry(key.clone()).or_insert((offset, 0));
+        if stuck.0 != offset {
+            *stuck = (offset, 0);
+        }
+        stuck.1 += 1;
+        if stuck.1 > policy.max_redeliveries {
+            self.stuck.remove(&key);
+            return RedeliveryDecision::Park;
+        }
+
+        RedeliveryDecision::Pause { backoff: policy.max_backoff.saturating_mul(stuck.1) }
+    }
+
+    /// Forgets a partition's message once it was handled.
+    pub fn clear(&mut self, topic: &str, partition: i32) {
+        self.stuck.remove(&(topic.to_string(), partition));
+    }
+}
+
+pub fn classify_error(error: &anyhow::Error) -> FailureKind {
+    for cause in error.chain() {
+        if let Some(error) = cause.downcast_ref::<sqlx::Error>() {
+            return classify_sqlx_error(error);
+        }
+        if let Some(error) = cause.downcast_ref::<StoreError>() {
+            return match error {
+                StoreError::OrderNotFound { .. } => FailureKind::Transient,
+            };
+        }
+    }
+
+    FailureKind::Permanent
+}
+
+fn classify_sqlx_error(error: &sqlx::Error) -> FailureKind {
+    match error {
+        sqlx::Error::PoolTimedOut => FailureKind::PoolTimeout,
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::Protocol(_)
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => FailureKind::Transient,
+        sqlx::Error::Database(error)
+            if error
+                .code()
+                .is_some_and(|code| RETRYABLE_SQLSTATES.contains(&code.as_ref())) =>
+        {
+            FailureKind::Transient
+        }
+        _ => FailureKind::Permanent,
+    }
+}
@@ -0,0 +1,553 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use rdkafka::{
+    Message, Offset,
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    message::BorrowedMessage,
+    topic_partition_list::TopicPartitionList,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::{
+    KafkaTopic,
+    admin::{WorkerAdminState, WorkerMessageCounters, admin_addr, serve_admin},
+    retry::{
+        FailureKind, RedeliveryDecision, RedeliveryTracker, RetryDecision, RetryPolicy,
+        classify_error,
+    },
+    store::OutboxSource,
+    worker::{
+        build_consumer, build_producer, decode_event, event_type_header, publish_outbox_loop,
+    },
+};
+use crate::{
+    config::{AppConfig, KafkaConfig, WorkerConfig},
+    db::connect_pool_with,
+    utils::{gracefully_shutdown::shutdown_token, observability},
+};
+
+const KAFKA_SEEK_TIMEOUT: Duration = Duration::from_secs(5);
+const COMMIT_ATTEMPTS: u32 = 3;
+
+pub type HandlerFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+type DecodeAndHandle =
+    Arc<dyn Fn(WorkerContext, &BorrowedMessage<'_>) -> Option<HandlerFuture> + Send + Sync>;
+
+/// Shared resources handed to every registered message handler.
+#[derive(Clone)]
+pub struct WorkerContext {
+    pub pool: Arc<PgPool>,
+    redis_client: Option<Arc<redis::Client>>,
+}
+
+impl WorkerContext {
+    pub fn redis(&self) -> anyhow::Result<&redis::Client> {
+        self.redis_client
+            .as_deref()
+            .context("worker was not configured with redis, call WorkerRuntime::with_redis")
+    }
+}
+
+struct RegisteredHandler {
+    topic: KafkaTopic,
+    event_type: &'static str,
+    handle: DecodeAndHandle,
+}
+
+/// Builder for a Kafka worker process: an optional outbox publisher plus consumers routed by
+/// topic and event type, sharing pool setup, the admin listener, observability and shutdown.
+pub struct WorkerRuntime {
+    name: &'static str,
+    group_id: &'static str,
+    outbox: Option<(OutboxSource, KafkaTopic)>,
+    uses_redis: bool,
+    handlers: Vec<RegisteredHandler>,
+}
+
+impl WorkerRuntime {
+    pub fn new(name: &'static str, group_id: &'static str) -> Self {
+        Self { name, group_id, outbox: None, uses_redis: false, handlers: Vec::new() }
+    }
+
+    pub fn with_redis(mut self) -> Self {
+        self.uses_redis = true;
+        self
+    }
+
+    pub fn publish_outbox(mut self, source: OutboxSource, topic: KafkaTopic) -> Self {
+        self.outbox = Some((source, topic));
+        self
+    }
+
+    /// Registers a handler for JSON events of `event_type` arriving on `topic`.
+    pub fn handle<T, F, Fut>(
+        mut self,
+        topic: KafkaTopic,
+        event_type: &'static str,
+        handler: F,
+    ) -> Self
+    where
+        T: serde::de::DeserializeOwned + 'static,
+        F: Fn(WorkerContext, T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let handle: DecodeAndHandle = Arc::new(move |ctx, message| {
+            let event = decode_event::<T>(message, event_type)?;
+            Some(Box::pin(handler(ctx, event)) as HandlerFuture)
+        });
+        self.handlers
+            .push(RegisteredHandler { topic, event_type, handle });
+        self
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let observability = observability::init_observability();
+        let result = match AppConfig::new().context("failed to load app config") {
+            Ok(config) => self.run_with_config(config).await,
+            Err(error) => Err(error),
+        };
+
+        observability.shutdown()?;
+        result
+    }
+
+    /// Runs a one-shot command against the worker's pool and redis setup instead of consuming.
+    pub async fn run_task<F, Fut>(self, task: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(WorkerContext) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        let observability = observability::init_observability();
+        let result = match AppConfig::new().context("failed to load app config") {
+            Ok(config) => match self.connect(&config).await {
+                Ok(ctx) => task(ctx).await,
+                Err(error) => Err(error),
+            },
+            Err(error) => Err(error),
+        };
+
+        observability.shutdown()?;
+        result
+    }
+
+    async fn connect(&self, config: &AppConfig) -> anyhow::Result<WorkerContext> {
+        let worker = &config.worker;
+        // Worker keeps using the primary because outbox/inbox processing needs strong consistency.
+        let pg_url = config
+            .pg
+            .required_write_url()
+            .context("Postgres write URL not found, check settings.")?;
+        let pool = connect_pool_with(
+            pg_url,
+            "write",
+            worker.max_connections,
+            Duration::from_secs(worker.acquire_timeout_secs),
+        )
+        .await
+        .with_context(|| format!("failed to connect postgres for {}", self.name))?;
+        let redis_client = if self.uses_redis {
+            let redis_url = config
+                .redis
+                .url
+                .as_deref()
+                .context("Redis URL not found, check settings.")?;
+            Some(Arc::new(redis::Client::open(redis_url).context("failed to create redis client")?))
+        } else {
+            None
+        };
+
+        Ok(WorkerContext { pool: Arc::new(pool), redis_client })
+    }
+
+    async fn run_with_config(self, config: AppConfig) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.handlers.is_empty(),
+            "{} has no message handlers registered",
+            self.name
+        );
+
+        let ctx = self.connect(&config).await?;
+        let pool = ctx.pool.clone();
+        let worker = config.worker;
+        let kafka = config.kafka;
+        let mut topics = self
+            .handlers
+            .iter()
+            .map(|handler| handler.topic.resolve(&kafka))
+            .collect::<Vec<_>>();
+        topics.sort_unstable();
+        topics.dedup();
+        let consumer = Arc::new(build_consumer(&kafka, self.group_id, &topics)?);
+        let routes = self
+            .handlers
+            .iter()
+            .map(|handler| (handler.topic.resolve(&kafka).to_string(), handler.event_type))
+            .collect::<Vec<_>>();
+        let handlers = self
+            .handlers
+            .into_iter()
+            .map(|handler| handler.handle)
+            .collect::<Vec<_>>();
+
+        let counters = Arc::new(WorkerMessageCounters::default());
+        let admin_state = Arc::new(WorkerAdminState {
+            worker: self.name,
+            pool: pool.clone(),
+            redis_client: ctx.redis_client.clone(),
+            consumer: consumer.clone(),
+            outbox: self.outbox.map(|(source, _)| source),
+            counters: counters.clone(),
+        });
+        let token = shutdown_token();
+
+        info!(worker = self.name, ?topics, kafka = ?kafka, "worker started");
+
+        tokio::try_join!(
+            publish_loop(&kafka, &worker, self.outbox, pool, token.clone()),
+            consume_loop(
+                ConsumeLoop {
+                    ctx,
+                    consumer,
+                    routes: &routes,
+                    handlers: &handlers,
+                    retry_policy: RetryPolicy::from_config(&worker.retry),
+                    counters,
+                },
+                token.clone(),
+            ),
+            serve_admin(admin_addr(&worker.admin_addr)?, admin_state, token),
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Picks the handler for a message: an exact topic/event type match, or the first handler on
+/// the topic when the message predates the `event_type` header.
+pub fn select_handler(
+    routes: &[(String, &'static str)],
+    topic: &str,
+    event_type: Option<&str>,
+) -> Option<usize> {
+    routes.iter().position(|(route_topic, route_event_type)| {
+        route_topic == topic && event_type.is_none_or(|event_type| event_type == *route_event_type)
+    })
+}
+
+async fn publish_loop(
+    kafka: &KafkaConfig,
+    worker: &WorkerConfig,
+    outbox: Option<(OutboxSource, KafkaTopic)>,
+    pool: Arc<PgPool>,
+    token: CancellationToken,
+) -> anyhow::Result<()> {
+    let Some((source, topic)) = outbox else {
+        token.cancelled().await;
+        return Ok(());
+    };
+    let batch_size = worker.outbox_batch_size;
+
+    publish_outbox_loop(
+        build_producer(kafka)?,
+        topic.resolve(kafka).to_string(),
+        Duration::from_millis(worker.outbox_poll_interval_ms),
+        token,
+        || {
+            let pool = pool.clone();
+            async move { source.list_unpublished(&pool, batch_size).await }
+        },
+        |id| {
+            let pool = pool.clone();
+            async move { source.mark_published(&pool, id).await }
+        },
+        |id, error| {
+            let pool = pool.clone();
+            async move { source.mark_failed(&pool, id, &error).await }
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandlingOutcome {
+    Processed,
+    Skipped,
+    /// Retries ran out on a failure that may still clear up.
+    Redeliver,
+    Cancelled,
+}
+
+struct ConsumeLoop<'a> {
+    ctx: WorkerContext,
+    consumer: Arc<StreamConsumer>,
+    routes: &'a [(String, &'static str)],
+    handlers: &'a [DecodeAndHandle],
+    retry_policy: RetryPolicy,
+    counters: Arc<WorkerMessageCounters>,
+}
+
+/// A partition held back until its stuck message is due for another round.
+struct PausedPartition {
+    topic: String,
+    partition: i32,
+    offset: i64,
+    resume_at: tokio::time::Instant,
+}
+
+async fn consume_loop(consume: ConsumeLoop<'_>, token: CancellationToken) -> anyhow::Result<()> {
+    let consumer = consume.consumer.clone();
+    let mut paused = Vec::<PausedPartition>::new();
+    let mut redeliveries = RedeliveryTracker::default();
+    loop {
+        let resume_at = paused.iter().map(|paused| paused.resume_at).min();
+        tokio::select! {
+            _ = token.cancelled() => return Ok(()),
+            _ = sleep_until(resume_at) => consume.resume_due(&mut paused),
+            message = consumer.recv() => {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(error) => {
+                        warn!(error = %error, "failed to receive kafka message");
+                        continue;
+                    }
+                };
+                // Fetched before the pause took effect, the seek on resume fetches it again.
+                if paused.iter().any(|paused| {
+                    paused.topic == message.topic() && paused.partition == message.partition()
+                }) {
+                    continue;
+                }
+
+                match consume.handle_with_retry(&message, &token).await? {
+                    HandlingOutcome::Processed => consume.counters.record_processed(),
+                    HandlingOutcome::Skipped => consume.counters.record_failed(),
+                    // Committing would leave the order stuck, so hold the partition back and
+                    // handle the message again until the redeliveries run out.
+                    HandlingOutcome::Redeliver => match redeliveries.record(
+                        &consume.retry_policy,
+                        message.topic(),
+                        message.partition(),
+                        message.offset(),
+                    ) {
+                        RedeliveryDecision::Pause { backoff } => {
+                            consume.counters.record_redelivered();
+                            paused.push(consume.pause(&message, backoff));
+                            continue;
+                        }
+                        RedeliveryDecision::Park => {
+                            let ids = MessageIds::decode(&message);
+                            error!(
+                                message_id = ?ids.message_id,
+                                order_id = ?ids.order_id,
+                                topic = message.topic(),
+                                partition = message.partition(),
+                                offset = message.offset(),
+                                "parking kafka message after repeated redeliveries"
+                            );
+                            consume.counters.record_parked();
+                        }
+                    },
+                    // Leave the offset uncommitted so the message is redelivered after restart.
+                    HandlingOutcome::Cancelled => return Ok(()),
+                }
+                redeliveries.clear(message.topic(), message.partition());
+                consume.commit(&message);
+            }
+        }
+    }
+}
+
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+impl ConsumeLoop<'_> {
+    /// Stops fetching the message's partition; other partitions keep being consumed meanwhile.
+    fn pause(&self, message: &BorrowedMessage<'_>, backoff: Duration) -> PausedPartition {
+        if let Err(error) = self
+            .consumer
+            .pause(&partition_list(message.topic(), message.partition()))
+        {
+            warn!(
+                error = %error,
+                topic = message.topic(),
+                partition = message.partition(),
+                "failed to pause kafka partition"
+            );
+        }
+        PausedPartition {
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+            resume_at: tokio::time::Instant::now() + backoff,
+        }
+    }
+
+    /// Rewinds and resumes the partitions that are due, retrying the ones that failed later.
+    fn resume_due(&self, paused: &mut Vec<PausedPartition>) {
+        let now = tokio::time::Instant::now();
+        paused.retain_mut(|paused| {
+            if paused.resume_at > now {
+                return true;
+            }
+            let Err(error) = self.rewind(paused) else {
+                return false;
+            };
+            warn!(
+                error = ?error,
+                topic = paused.topic,
+                partition = paused.partition,
+                "failed to resume kafka partition, retrying"
+            );
+            paused.resume_at = now + self.retry_policy.max_backoff;
+            true
+        });
+    }
+
+    fn rewind(&self, paused: &PausedPartition) -> anyhow::Result<()> {
+        self.consumer
+            .seek(
+                &paused.topic,
+                paused.partition,
+                Offset::Offset(paused.offset),
+                KAFKA_SEEK_TIMEOUT,
+            )
+            .context("failed to rewind kafka partition")?;
+        self.consumer
+            .resume(&partition_list(&paused.topic, paused.partition))
+            .context("failed to resume kafka partition")
+    }
+
+    /// Commits the message's offset, logging when it keeps failing since a later commit on the
+    /// partition covers it.
+    fn commit(&self, message: &BorrowedMessage<'_>) {
+        let mut mode = CommitMode::Async;
+        for attempt in 1..=COMMIT_ATTEMPTS {
+            let Err(error) = self.consumer.commit_message(message, mode) else {
+                return;
+            };
+            warn!(
+                error = %error,
+                attempt,
+                topic = message.topic(),
+                partition = message.partition(),
+                offset = message.offset(),
+                "failed to commit kafka offset"
+            );
+            mode = CommitMode::Sync;
+        }
+    }
+
+    async fn handle_with_retry(
+        &self,
+        message: &BorrowedMessage<'_>,
+        token: &CancellationToken,
+    ) -> anyhow::Result<HandlingOutcome> {
+        let event_type = event_type_header(message);
+        let Some(index) = select_handler(self.routes, message.topic(), event_type.as_deref())
+        else {
+            warn!(topic = message.topic(), ?event_type, "no handler registered for kafka message");
+            return Ok(HandlingOutcome::Skipped);
+        };
+
+        // Nothing is polled while retrying, so the policy's deadline keeps the consumer inside
+        // `max.poll.interval.ms`.
+        let started = Instant::now();
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let Some(handling) = self.handlers[index](self.ctx.clone(), message) else {
+                return Ok(HandlingOutcome::Skipped);
+            };
+            let error = match handling.await {
+                Ok(()) => return Ok(HandlingOutcome::Processed),
+                Err(error) => error,
+            };
+
+            let kind = classify_error(&error);
+            let RetryDecision::Retry { backoff } =
+                self.retry_policy.decide(attempt, kind, started.elapsed())
+            else {
+                let ids = MessageIds::decode(message);
+                if kind != FailureKind::Permanent {
+                    warn!(
+                        error = ?error,
+                        failure_kind = kind.as_str(),
+                        attempt,
+                        message_id = ?ids.message_id,
+                        order_id = ?ids.order_id,
+                        topic = message.topic(),
+                        partition = message.partition(),
+                        offset = message.offset(),
+                        "kafka message retries ran out, redelivering"
+                    );
+                    return Ok(HandlingOutcome::Redeliver);
+                }
+                error!(
+                    error = ?error,
+                    failure_kind = kind.as_str(),
+                    attempt,
+                    message_id = ?ids.message_id,
+                    order_id = ?ids.order_id,
+                    topic = message.topic(),
+                    partition = message.partition(),
+                    offset = message.offset(),
+                    "giving up on kafka message"
+                );
+                return Ok(HandlingOutcome::Skipped);
+            };
+
+            warn!(
+                error = %error,
+                failure_kind = kind.as_str(),
+                attempt,
+                backoff_ms = backoff.as_millis() as u64,
+                topic = message.topic(),
+                partition = message.partition(),
+                "kafka message handling failed, retrying"
+            );
+            self.counters.record_retried();
+
+            tokio::select! {
+                _ = token.cancelled() => return Ok(HandlingOutcome::Cancelled),
+                _ = tokio::time::sleep(backoff) => {}
+            }
+        }
+    }
+}
+
+fn partition_list(topic: &str, partition: i32) -> TopicPartitionList {
+    let mut partitions = TopicPartitionList::new();
+    partitions.add_partition(topic, partition);
+    partitions
+}
+
+/// Ids shared by every order event, read back for logging a message that was given up on.
+#[derive(Debug, Default, Deserialize)]
+struct MessageIds {
+    message_id: Option<Uuid>,
+    order_id: Option<Uuid>,
+}
+
+impl MessageIds {
+    fn decode(message: &BorrowedMessage<'_>) -> Self {
+        message
+            .payload()
+            .and_then(|payload| serde_json::from_slice(payload).ok())
+            .unwrap_or_default()
+    }
+}
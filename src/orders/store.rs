@@ -6,11 +6,19 @@ use uuid::Uuid;
 use super::{
     CreateOrderRequest, INVENTORY_RESULT_EVENT_TYPE, INVENTORY_WORKER_CONSUMER,
     InventoryResultEvent, ORDER_CREATED_EVENT_TYPE, ORDERS_WORKER_CONSUMER, OrderCreatedEvent,
-    OrderStatus, apply_inventory_result, determine_inventory_result, utc_now,
+    OrderStatus, SagaStage, apply_inventory_result, determine_inventory_result,
+    saga_elapsed_seconds, utc_now,
 };
+use crate::utils::observability;
 
 const OUTBOX_LOCK_SECONDS: i64 = 300;
 
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("order {order_id} not found")]
+    OrderNotFound { order_id: Uuid },
+}
+
 #[derive(Debug, Clone)]
 pub struct OrderRecord {
     pub id: Uuid,
@@ -27,9 +35,16 @@ pub struct OrderRecord {
 pub struct OutboxMessageRecord {
     pub id: i64,
     pub message_id: Uuid,
+    pub event_type: String,
     pub payload: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutboxBacklog {
+    pub unpublished_count: i64,
+    pub oldest_unpublished_age_seconds: Option<f64>,
+}
+
 pub async fn insert_order_with_outbox(
     pool: &PgPool,
     payload: &CreateOrderRequest,
@@ -123,38 +138,40 @@ pub async fn get_order_by_id(pool: &PgPool, order_id: Uuid) -> anyhow::Result<Op
     row.map(map_order_row).transpose()
 }
 
-pub async fn list_unpublished_order_outbox(
-    pool: &PgPool,
-    limit: i64,
-) -> anyhow::Result<Vec<OutboxMessageRecord>> {
-    list_unpublished_outbox(pool, "order_outbox_messages", limit).await
+/// Outbox table a worker publishes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxSource {
+    Order,
+    Inventory,
 }
 
-pub async fn list_unpublished_inventory_outbox(
-    pool: &PgPool,
-    limit: i64,
-) -> anyhow::Result<Vec<OutboxMessageRecord>> {
-    list_unpublished_outbox(pool, "inventory_outbox_messages", limit).await
-}
+impl OutboxSource {
+    pub fn table_name(self) -> &'static str {
+        match self {
+            Self::Order => "order_outbox_messages",
+            Self::Inventory => "inventory_outbox_messages",
+        }
+    }
 
-pub async fn mark_order_outbox_published(pool: &PgPool, id: i64) -> anyhow::Result<()> {
-    mark_outbox_published(pool, "order_outbox_messages", id).await
-}
+    pub async fn list_unpublished(
+        self,
+        pool: &PgPool,
+        limit: i64,
+    ) -> anyhow::Result<Vec<OutboxMessageRecord>> {
+        list_unpublished_outbox(pool, self.table_name(), limit).await
+    }
 
-pub async fn mark_inventory_outbox_published(pool: &PgPool, id: i64) -> anyhow::Result<()> {
-    mark_outbox_published(pool, "inventory_outbox_messages", id).await
-}
+    pub async fn mark_published(self, pool: &PgPool, id: i64) -> anyhow::Result<()> {
+        mark_outbox_published(pool, self.table_name(), id).await
+    }
 
-pub async fn mark_order_outbox_failed(pool: &PgPool, id: i64, error: &str) -> anyhow::Result<()> {
-    mark_outbox_failed(pool, "order_outbox_messages", id, error).await
-}
+    pub async fn mark_failed(self, pool: &PgPool, id: i64, error: &str) -> anyhow::Result<()> {
+        mark_outbox_failed(pool, self.table_name(), id, error).await
+    }
 
-pub async fn mark_inventory_outbox_failed(
-    pool: &PgPool,
-    id: i64,
-    error: &str,
-) -> anyhow::Result<()> {
-    mark_outbox_failed(pool, "inventory_outbox_messages", id, error).await
+    pub async fn load_backlog(self, pool: &PgPool) -> anyhow::Result<OutboxBacklog> {
+        load_outbox_backlog(pool, self.table_name()).await
+    }
 }
 
 pub async fn apply_inventory_result_message(
@@ -176,23 +193,38 @@ pub async fn apply_inventory_result_message(
     }
 
     let applied = apply_inventory_result(event.success, event.reason.clone());
-    let rows = sqlx::query(
+    let now = utc_now();
+    let created_at_utc = sqlx::query_scalar::<_, DateTime<Utc>>(
         r#"
         UPDATE "orders"
         SET "Status" = $2, "FailureReason" = $3, "UpdatedAtUtc" = $4
         WHERE "Id" = $1
+        RETURNING "CreatedAtUtc"
         "#,
     )
     .bind(event.order_id)
     .bind(applied.status.code())
-    .bind(applied.failure_reason)
-    .bind(utc_now())
-    .execute(&mut *tx)
+    .bind(applied.failure_reason.as_deref())
+    .bind(now)
+    .fetch_optional(&mut *tx)
     .await?;
 
-    anyhow::ensure!(rows.rows_affected() == 1, "order {} not found", event.order_id);
+    let Some(created_at_utc) = created_at_utc else {
+        // Dropping the transaction also rolls back the inbox row so a redelivery can apply it.
+        return Err(StoreError::OrderNotFound { order_id: event.order_id }.into());
+    };
 
     tx.commit().await?;
+
+    match applied.failure_reason.as_deref() {
+        None => observability::record_order_confirmed(),
+        Some(reason) => observability::record_order_rejected(SagaStage::Inventory.as_str(), reason),
+    }
+    observability::record_order_saga_duration(
+        SagaStage::Completed.as_str(),
+        &applied.status.as_str().to_ascii_lowercase(),
+        saga_elapsed_seconds(created_at_utc, now),
+    );
     Ok(true)
 }
 
@@ -269,6 +301,17 @@ pub async fn handle_order_created_message(
     .await?;
 
     tx.commit().await?;
+
+    observability::record_inventory_decrement(outbox_event.success, outbox_event.reason.as_deref());
+    // OrderCreated is stamped with the order's CreatedAtUtc by the API.
+    if let Ok(created_at_utc) = DateTime::parse_from_rfc3339(&event.occurred_on_utc) {
+        let outcome = if outbox_event.success { "reserved" } else { "unavailable" };
+        observability::record_order_saga_duration(
+            SagaStage::Inventory.as_str(),
+            outcome,
+            saga_elapsed_seconds(created_at_utc.with_timezone(&Utc), now),
+        );
+    }
     Ok(Some(event.sku.clone()))
 }
 
@@ -292,7 +335,7 @@ pub async fn load_inventory_stock_quantity(
         .context("failed to decode inventory stock quantity")
 }
 
-async fn insert_inbox_once(
+pub(super) async fn insert_inbox_once(
     tx: &mut Transaction<'_, Postgres>,
     table_name: &str,
     message_id: Uuid,
@@ -352,6 +395,7 @@ async fn list_unpublished_outbox(
         WHERE outbox."Id" = picked."Id"
         RETURNING outbox."Id" AS id,
                   outbox."MessageId" AS message_id,
+                  outbox."EventType" AS event_type,
                   outbox."Payload" AS payload
         "#
     );
@@ -366,12 +410,34 @@ async fn list_unpublished_outbox(
             Ok(OutboxMessageRecord {
                 id: row.try_get("id")?,
                 message_id: row.try_get("message_id")?,
+                event_type: row.try_get("event_type")?,
                 payload: row.try_get("payload")?,
             })
         })
         .collect()
 }
 
+async fn load_outbox_backlog(pool: &PgPool, table_name: &str) -> anyhow::Result<OutboxBacklog> {
+    let sql = format!(
+        r#"
+        SELECT COUNT(*) AS unpublished_count,
+               EXTRACT(EPOCH FROM (now() - MIN("OccurredOnUtc")))::FLOAT8 AS oldest_age_seconds
+        FROM "{table_name}"
+        WHERE "PublishedOnUtc" IS NULL
+        "#
+    );
+    let row = sqlx::query(AssertSqlSafe(sql)).fetch_one(pool).await?;
+
+    Ok(OutboxBacklog {
+        unpublished_count: row
+            .try_get("unpublished_count")
+            .context("failed to decode outbox unpublished count")?,
+        oldest_unpublished_age_seconds: row
+            .try_get("oldest_age_seconds")
+            .context("failed to decode outbox oldest age")?,
+    })
+}
+
 async fn mark_outbox_published(pool: &PgPool, table_name: &str, id: i64) -> anyhow::Result<()> {
     let sql = format!(
         r#"
@@ -4,42 +4,98 @@ use anyhow::Context;
 use rdkafka::{
     ClientConfig, Message,
     consumer::{Consumer, StreamConsumer},
+    message::{Header, Headers, OwnedHeaders},
     producer::{FutureProducer, FutureRecord},
 };
 use tokio::time::{MissedTickBehavior, interval};
 use tracing::{error, warn};
 
-use super::{KafkaSettings, store::OutboxMessageRecord};
+use super::store::OutboxMessageRecord;
+use crate::config::KafkaConfig;
 
-pub fn build_producer(kafka: &KafkaSettings) -> anyhow::Result<FutureProducer> {
-    ClientConfig::new()
-        .set("bootstrap.servers", &kafka.brokers)
-        .set("message.timeout.ms", "5000")
+/// Kafka header carrying the outbox `EventType`, used to route messages to handlers.
+pub const EVENT_TYPE_HEADER: &str = "event_type";
+
+pub fn build_producer(kafka: &KafkaConfig) -> anyhow::Result<FutureProducer> {
+    producer_client_config(kafka)
         .create()
         .context("failed to build kafka producer")
 }
 
 pub fn build_consumer(
-    kafka: &KafkaSettings,
+    kafka: &KafkaConfig,
     group_id: &str,
-    topic: &str,
+    topics: &[&str],
 ) -> anyhow::Result<StreamConsumer> {
-    let consumer: StreamConsumer = ClientConfig::new()
-        .set("group.id", group_id)
-        .set("bootstrap.servers", &kafka.brokers)
-        .set("enable.auto.commit", "false")
-        .set("auto.offset.reset", "earliest")
+    let consumer: StreamConsumer = consumer_client_config(kafka, group_id)
         .create()
         .context("failed to build kafka consumer")?;
     consumer
-        .subscribe(&[topic])
-        .context("failed to subscribe kafka topic")?;
+        .subscribe(topics)
+        .context("failed to subscribe kafka topics")?;
     Ok(consumer)
 }
 
+pub fn producer_client_config(kafka: &KafkaConfig) -> ClientConfig {
+    let producer = &kafka.producer;
+    let mut config = common_client_config(kafka);
+    config
+        .set("acks", &producer.acks)
+        .set("enable.idempotence", producer.enable_idempotence.to_string())
+        .set("message.timeout.ms", producer.message_timeout_ms.to_string());
+    if let Some(compression_type) = producer.compression_type.as_deref() {
+        config.set("compression.type", compression_type);
+    }
+    if let Some(linger_ms) = producer.linger_ms {
+        config.set("linger.ms", linger_ms.to_string());
+    }
+    for (key, value) in &producer.properties {
+        config.set(key, value);
+    }
+    config
+}
+
+pub fn consumer_client_config(kafka: &KafkaConfig, group_id: &str) -> ClientConfig {
+    let consumer = &kafka.consumer;
+    let mut config = common_client_config(kafka);
+    config
+        .set("group.id", group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", &consumer.auto_offset_reset);
+    for (key, value) in &consumer.properties {
+        config.set(key, value);
+    }
+    config
+}
+
+fn common_client_config(kafka: &KafkaConfig) -> ClientConfig {
+    let security = &kafka.security;
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", &kafka.brokers);
+
+    let optional = [
+        ("client.id", &kafka.client_id),
+        ("security.protocol", &security.protocol),
+        ("sasl.mechanism", &security.sasl_mechanism),
+        ("sasl.username", &security.sasl_username),
+        ("sasl.password", &security.sasl_password),
+        ("ssl.ca.location", &security.ssl_ca_location),
+        ("ssl.certificate.location", &security.ssl_certificate_location),
+        ("ssl.key.location", &security.ssl_key_location),
+        ("ssl.key.password", &security.ssl_key_password),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            config.set(key, value);
+        }
+    }
+    config
+}
+
 pub async fn publish_outbox_loop<ListFuture, ListFn, OkFuture, OkFn, ErrFuture, ErrFn>(
     producer: FutureProducer,
     topic: String,
+    poll_interval: Duration,
     token: tokio_util::sync::CancellationToken,
     list_messages: ListFn,
     mark_published: OkFn,
@@ -53,7 +109,7 @@ where
     ErrFn: Fn(i64, String) -> ErrFuture,
     ErrFuture: Future<Output = anyhow::Result<()>>,
 {
-    let mut ticker = interval(Duration::from_millis(500));
+    let mut ticker = interval(poll_interval);
     ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
     loop {
@@ -63,8 +119,15 @@ where
                 let messages = list_messages().await?;
                 for message in messages {
                     let key = message.message_id.to_string();
+                    let headers = OwnedHeaders::new().insert(Header {
+                        key: EVENT_TYPE_HEADER,
+                        value: Some(message.event_type.as_str()),
+                    });
                     match producer.send(
-                            FutureRecord::to(&topic).payload(&message.payload).key(&key),
+                            FutureRecord::to(&topic)
+                                .payload(&message.payload)
+                                .key(&key)
+                                .headers(headers),
                             Duration::from_secs(5),
                         ).await {
                         Ok(_) => mark_published(message.id).await?,
@@ -79,6 +142,16 @@ where
     }
 }
 
+pub fn event_type_header(message: &rdkafka::message::BorrowedMessage<'_>) -> Option<String> {
+    message.headers()?.iter().find_map(|header| {
+        (header.key == EVENT_TYPE_HEADER)
+            .then_some(header.value)
+            .flatten()
+            .and_then(|value| std::str::from_utf8(value).ok())
+            .map(str::to_string)
+    })
+}
+
 pub fn decode_event<T>(message: &rdkafka::message::BorrowedMessage<'_>, label: &str) -> Option<T>
 where
     T: serde::de::DeserializeOwned,
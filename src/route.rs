@@ -16,8 +16,7 @@ use tower_http::cors::{Any, CorsLayer};
 
 use crate::{
     db::connect_pool,
-    handlers::orders as order_handlers,
-    handlers::*,
+    handlers::{orders as order_handlers, *},
     utils::{jwt_auth::Claims, observability},
     *,
 };
@@ -29,7 +28,8 @@ pub struct AppState {
     pub chat_service: Arc<chat::ChatState>,
 }
 
-pub async fn route() -> Result<Router, anyhow::Error> {
+/// Connects the stores and builds the chat runtime shared by the HTTP and gRPC servers.
+pub async fn app_state() -> Result<Arc<AppState>, anyhow::Error> {
     // config init
     let cfg = config::AppConfig::new()
         .expect("Configuration initialization failed, check pg .env settings.");
@@ -41,24 +41,37 @@ pub async fn route() -> Result<Router, anyhow::Error> {
     let write_pool = connect_pool(write_pg_url, "write").await?;
     let read_pool = connect_pool(read_pg_url, "read").await?;
     let redis_client = Client::open(redis_url).expect("can't create redis client");
+    let chat_history = chat::history::ChatHistory::spawn(
+        write_pool.clone(),
+        read_pool.clone(),
+        cfg.chat.history_queue_capacity,
+    );
+    let chat_backplane = chat::backplane::ChatBackplane::new(redis_client.clone());
+    let chat_service = Arc::new(
+        chat::ChatState::default()
+            .with_config(cfg.chat.clone())
+            .with_history(chat_history)
+            .with_backplane(chat_backplane),
+    );
+    chat::backplane::spawn(chat_service.clone(), redis_client.clone());
 
+    Ok(Arc::new(AppState { write_pool, read_pool, redis_client, chat_service }))
+}
+
+pub fn route(state: Arc<AppState>) -> Router {
     // app init
-    Ok(Router::new()
+    Router::new()
         .route("/", get(index))
         .nest("/api/users", user_router())
         .nest("/api/auth", auth_router())
         .nest("/api/bakery", bakery_router())
         .nest("/api/orders", orders_router())
         .nest("/api/hot", hot_router())
+        .nest("/api/stat", stat_router())
         .nest("/api/chat", chat_router())
         .fallback(global_404)
         .layer(middleware::from_fn(global_405))
-        .with_state(Arc::new(AppState {
-            write_pool,
-            read_pool,
-            redis_client,
-            chat_service: Arc::new(chat::ChatState::default()),
-        }))
+        .with_state(state)
         .layer(tower_http::catch_panic::CatchPanicLayer::custom(|_err| {
             // _err: Box<dyn Any + Send>
             (
@@ -76,7 +89,7 @@ pub async fn route() -> Result<Router, anyhow::Error> {
                 .allow_headers(Any),
         )
         .layer(middleware::from_fn(auth::auth))
-        .layer(middleware::from_fn(observability::http_observability)))
+        .layer(middleware::from_fn(observability::http_observability))
 }
 
 async fn global_405(req: Request, next: Next) -> Response {
@@ -130,6 +143,13 @@ fn hot_router() -> Router<Arc<AppState>> {
         .route("/stock/{item_id}/claim", post(stat::hot::claim_stock))
 }
 
+fn stat_router() -> Router<Arc<AppState>> {
+    // /api/stat
+    Router::new()
+        .route("/orders/daily", get(stat::orders::daily))
+        .route("/orders/skus", get(stat::orders::skus))
+}
+
 fn orders_router() -> Router<Arc<AppState>> {
     Router::new()
         .route("/", post(order_handlers::create))
@@ -137,5 +157,18 @@ fn orders_router() -> Router<Arc<AppState>> {
 }
 
 fn chat_router() -> Router<Arc<AppState>> {
-    Router::new().route("/connect", get(chat::connect))
+    Router::new()
+        .route("/connect", get(chat::connect))
+        .route("/rooms/{room_id}/messages", get(chat::room_messages))
+        .route("/tickets", post(chat::auth::issue_ticket))
+        .route("/rooms", get(chat::directory::list_rooms))
+        .route("/rooms/{room_id}/members", get(chat::directory::room_members))
+        .route("/rooms/{room_id}/flagged", get(chat::filters::flagged_messages))
+        .route("/admin/connections", get(chat::directory::admin_connections))
+        .route(
+            "/admin/connections/{connection_id}/close",
+            post(chat::directory::admin_close_connection),
+        )
+        .route("/admin/rooms/{room_id}/close", post(chat::directory::admin_close_room))
+        .route("/admin/history", get(chat::directory::admin_history))
 }
@@ -1,17 +1,18 @@
 use serde_json::json;
 
-use crate::handlers::chat::{
-    ChatCommand, ChatError, ChatEvent, ChatHub, ChatSessionUser, ChatUserSummary,
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatError, ChatEvent, ChatHub, ChatRoomMessage, ChatUserSummary,
+        auth::{bearer_from_protocols, session_ttl},
+        backplane::{ChatBackplaneEnvelope, channel},
+        history::{ChatHistory, ChatHistoryStats, merge_history_page},
+        presence::ChatPresenceStatus,
+        sanitized_history_limit,
+    },
+    tests::chat_support::session_user,
+    utils::jwt_auth::{self, Claims},
 };
 
-fn session_user(id: &str, name: &str) -> ChatSessionUser {
-    ChatSessionUser {
-        connection_id: format!("conn-{id}"),
-        user_id: id.to_string(),
-        user_name: name.to_string(),
-    }
-}
-
 #[test]
 fn chat_wire_protocol_uses_tagged_json_messages() {
     let join_message: ChatCommand = serde_json::from_value(json!({
@@ -48,7 +49,11 @@ fn join_room_is_idempotent_and_returns_current_snapshot() {
     assert_eq!(joined.version, 1);
     assert_eq!(
         joined.members,
-        vec![ChatUserSummary { user_id: "u1".to_string(), user_name: "rc".to_string() }]
+        vec![ChatUserSummary {
+            user_id: "u1".to_string(),
+            user_name: "rc".to_string(),
+            status: ChatPresenceStatus::Online
+        }]
     );
     assert!(joined.recent_messages.is_empty());
 
@@ -116,7 +121,11 @@ fn disconnect_removes_members_and_drops_empty_rooms() {
     assert_eq!(disconnected[0].room_id, "lobby");
     assert_eq!(
         disconnected[0].left_members,
-        vec![ChatUserSummary { user_id: "u1".to_string(), user_name: "alice".to_string() }]
+        vec![ChatUserSummary {
+            user_id: "u1".to_string(),
+            user_name: "alice".to_string(),
+            status: ChatPresenceStatus::Online
+        }]
     );
 
     let remaining = hub
@@ -145,7 +154,222 @@ fn leave_room_removes_membership_and_reports_updated_version() {
 
     assert_eq!(left_notice.room_id, "lobby");
     assert_eq!(left_notice.version, 3);
+    let presence = presence.expect("alice's only connection should announce her leaving");
     assert_eq!(presence.version, 3);
     assert_eq!(presence.left_members[0].user_id, "u1");
     assert_eq!(peers, vec![bob.connection_id.clone()]);
 }
+
+fn room_message(version: u64) -> ChatRoomMessage {
+    ChatRoomMessage {
+        room_id: "lobby".to_string(),
+        message_id: format!("msg-{version}"),
+        sender_id: "u1".to_string(),
+        sender_name: "rc".to_string(),
+        content: format!("message {version}"),
+        sent_at: 1_700_000_000,
+        version,
+        edited_at: None,
+        deleted_at: None,
+        mentions: Vec::new(),
+        reply_to: None,
+    }
+}
+
+#[test]
+fn load_history_command_defaults_paging_fields() {
+    let command: ChatCommand = serde_json::from_value(json!({
+        "type": "load_history",
+        "payload": {
+            "room_id": "lobby"
+        }
+    }))
+    .expect("load_history message should deserialize");
+
+    assert_eq!(
+        command,
+        ChatCommand::LoadHistory { room_id: "lobby".to_string(), before: None, limit: None }
+    );
+    assert_eq!(command.event_type(), "load_history");
+    assert_eq!(sanitized_history_limit(None), 50);
+    assert_eq!(sanitized_history_limit(Some(0)), 1);
+    assert_eq!(sanitized_history_limit(Some(1_000)), 100);
+}
+
+#[test]
+fn recreated_room_continues_after_previous_versions() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+
+    hub.join_room(alice.clone(), "lobby").expect("alice joins");
+    hub.send_room_message(&alice.connection_id, "lobby", "hello")
+        .expect("message should be accepted");
+    hub.leave_room(&alice.connection_id, "lobby")
+        .expect("leave should succeed");
+    assert!(!hub.room_exists("lobby"));
+
+    let rejoined = hub
+        .join_room(alice.clone(), "lobby")
+        .expect("alice rejoins");
+    assert_eq!(rejoined.version, 4);
+
+    hub.seed_room_version("lobby", 100);
+    hub.seed_room_version("archive", 100);
+    let archive = hub
+        .join_room(alice, "archive")
+        .expect("alice joins archive");
+    assert_eq!(archive.version, 101);
+    assert_eq!(
+        hub.sync_room_state("conn-u1", "lobby")
+            .expect("loaded room ignores seeds")
+            .version,
+        4
+    );
+}
+
+#[test]
+fn recent_messages_before_filters_by_version() {
+    let mut hub = ChatHub::default();
+    let user = session_user("u1", "rc");
+    hub.join_room(user.clone(), "lobby")
+        .expect("join should succeed");
+    for idx in 0..3 {
+        hub.send_room_message(&user.connection_id, "lobby", &format!("msg-{idx}"))
+            .expect("message should be accepted");
+    }
+
+    let versions = |before| {
+        hub.recent_messages_before("lobby", before)
+            .into_iter()
+            .map(|message| message.version)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(versions(None), vec![2, 3, 4]);
+    assert_eq!(versions(Some(4)), vec![2, 3]);
+    assert!(hub.recent_messages_before("missing", None).is_empty());
+}
+
+#[test]
+fn history_page_merges_cached_and_stored_messages() {
+    let stored = vec![room_message(5), room_message(4), room_message(3), room_message(2)];
+    let cached = vec![room_message(5), room_message(6)];
+
+    let page = merge_history_page("lobby".to_string(), 3, cached, stored);
+
+    assert_eq!(
+        page.messages
+            .iter()
+            .map(|message| message.version)
+            .collect::<Vec<_>>(),
+        vec![4, 5, 6]
+    );
+    assert_eq!(page.next_before, Some(4));
+
+    let last_page = merge_history_page("lobby".to_string(), 3, Vec::new(), vec![room_message(1)]);
+    assert_eq!(last_page.messages.len(), 1);
+    assert_eq!(last_page.next_before, None);
+}
+
+#[tokio::test]
+async fn history_writes_beyond_the_queue_capacity_are_shed_and_counted() {
+    let pool = sqlx::postgres::PgPoolOptions::new()
+        .connect_lazy("postgres://chat@127.0.0.1:1/chat")
+        .expect("lazy pool should build");
+    let history = ChatHistory::spawn(pool.clone(), pool, 2);
+
+    for version in 1..=5 {
+        history.persist(room_message(version));
+    }
+
+    assert_eq!(
+        history.stats(),
+        ChatHistoryStats { queued_writes: 2, queue_capacity: 2, dropped_writes: 3 }
+    );
+}
+
+#[test]
+fn backplane_envelope_wraps_tagged_room_events() {
+    let envelope = ChatBackplaneEnvelope {
+        room_id: "lobby".to_string(),
+        event: ChatEvent::RoomMessage(room_message(7)),
+        exclude_connection_id: Some("chat-1".to_string()),
+    };
+
+    let encoded = serde_json::to_value(&envelope).expect("envelope should serialize");
+    assert_eq!(encoded["room_id"], "lobby");
+    assert_eq!(encoded["event"]["type"], "room_message");
+    assert_eq!(encoded["event"]["payload"]["version"], 7);
+
+    let decoded: ChatBackplaneEnvelope =
+        serde_json::from_value(encoded).expect("envelope should deserialize");
+    assert_eq!(decoded, envelope);
+    assert_eq!(channel("lobby"), "chat:events:lobby");
+}
+
+#[test]
+fn memberships_list_local_room_members() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    let bob = session_user("u2", "bob");
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice joins lobby");
+    hub.join_room(alice.clone(), "dev")
+        .expect("alice joins dev");
+    hub.join_room(bob.clone(), "lobby")
+        .expect("bob joins lobby");
+
+    let mut memberships = hub.memberships();
+    memberships.sort();
+
+    assert_eq!(
+        memberships,
+        vec![
+            ("dev".to_string(), alice.connection_id.clone()),
+            ("lobby".to_string(), alice.connection_id.clone()),
+            ("lobby".to_string(), bob.connection_id.clone()),
+        ]
+    );
+    assert!(hub.is_member(&bob.connection_id, "lobby"));
+    assert!(!hub.is_member(&bob.connection_id, "dev"));
+}
+
+#[test]
+fn bearer_token_is_read_from_websocket_subprotocols() {
+    assert_eq!(bearer_from_protocols("axes.chat.v1, bearer.abc.def.ghi"), Some("abc.def.ghi"));
+    assert_eq!(bearer_from_protocols("bearer.token,axes.chat.v1"), Some("token"));
+    assert_eq!(bearer_from_protocols("axes.chat.v1"), None);
+    assert_eq!(bearer_from_protocols("axes.chat.v1, bearer."), None);
+}
+
+#[test]
+fn session_ttl_counts_down_to_token_expiry() {
+    let claims = Claims { sub: "u1".to_string(), company: "axes".to_string(), exp: 1_000 };
+
+    assert_eq!(session_ttl(&claims, 940), std::time::Duration::from_secs(60));
+    assert!(session_ttl(&claims, 2_000).is_zero());
+}
+
+#[test]
+fn chat_tokens_are_validated_with_shared_keys() {
+    let claims = Claims {
+        sub: "u1".to_string(),
+        company: "axes".to_string(),
+        exp: (chrono::Utc::now().timestamp() + 300) as u64,
+    };
+    let token =
+        jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &jwt_auth::keys().encoding)
+            .expect("token should encode");
+
+    let decoded = jwt_auth::decode_claims(&token).expect("valid token should decode");
+    assert_eq!(decoded.sub, "u1");
+
+    let expired = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &Claims { exp: 1_000, ..claims },
+        &jwt_auth::keys().encoding,
+    )
+    .expect("token should encode");
+    assert!(jwt_auth::decode_claims(&expired).is_err());
+    assert!(jwt_auth::decode_claims("not-a-token").is_err());
+}
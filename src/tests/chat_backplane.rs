@@ -0,0 +1,272 @@
+//! Runs the backplane scripts against a real Redis, at `AXES_TEST_REDIS_URL` or on localhost:
+//! `cargo test chat_backplane -- --ignored`.
+
+use uuid::Uuid;
+
+use crate::{
+    handlers::chat::{
+        ChatError, ChatEvent, ChatRoomMessage, ChatSessionUser, ChatUserSummary,
+        backplane::ChatBackplane,
+        edits::ChatMessageChange,
+        moderation::{ChatModerationAction, ChatModerationNotice, ChatRoomAccess},
+        presence::ChatPresenceStatus,
+    },
+    tests::chat_support::session_user,
+};
+
+fn client() -> redis::Client {
+    let url = std::env::var("AXES_TEST_REDIS_URL")
+        .unwrap_or_else(|_| "redis://127.0.0.1:6379/".to_string());
+    redis::Client::open(url).expect("redis url should parse")
+}
+
+fn backplane() -> ChatBackplane {
+    ChatBackplane::new(client())
+}
+
+/// A fresh room per run, so leftovers of earlier runs never leak in.
+fn room_id() -> String {
+    format!("test-{}", Uuid::new_v4())
+}
+
+fn summary(user: &ChatSessionUser) -> ChatUserSummary {
+    ChatUserSummary {
+        user_id: user.user_id.clone(),
+        user_name: user.user_name.clone(),
+        status: ChatPresenceStatus::default(),
+    }
+}
+
+fn message(room_id: &str, sender: &ChatSessionUser, content: &str) -> ChatRoomMessage {
+    ChatRoomMessage {
+        room_id: room_id.to_string(),
+        message_id: Uuid::new_v4().to_string(),
+        sender_id: sender.user_id.clone(),
+        sender_name: sender.user_name.clone(),
+        content: content.to_string(),
+        sent_at: 1_700_000_000,
+        version: 0,
+        edited_at: None,
+        deleted_at: None,
+        mentions: Vec::new(),
+        reply_to: None,
+    }
+}
+
+async fn join(backplane: &ChatBackplane, room_id: &str, user: &ChatSessionUser) -> u64 {
+    backplane
+        .join_room(room_id, &user.connection_id, &summary(user), 0)
+        .await
+        .expect("join should reach redis")
+        .snapshot
+        .version
+}
+
+async fn append(
+    backplane: &ChatBackplane,
+    user: &ChatSessionUser,
+    message: &ChatRoomMessage,
+) -> ChatRoomMessage {
+    backplane
+        .append_message(&user.connection_id, message, None)
+        .await
+        .expect("append should reach redis")
+        .expect("member should be allowed to send")
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server"]
+async fn joins_share_members_and_bump_the_room_version() {
+    let backplane = backplane();
+    let room_id = room_id();
+    let (alice, bob) = (session_user("u1", "alice"), session_user("u2", "bob"));
+
+    let joined = backplane
+        .join_room(&room_id, &alice.connection_id, &summary(&alice), 0)
+        .await
+        .expect("join should reach redis");
+    assert!(joined.joined_newly);
+    assert_eq!(joined.snapshot.members, vec![summary(&alice)]);
+
+    let bob_version = join(&backplane, &room_id, &bob).await;
+    assert!(bob_version > joined.snapshot.version);
+    let again = backplane
+        .join_room(&room_id, &bob.connection_id, &summary(&bob), 0)
+        .await
+        .expect("rejoin should reach redis");
+    assert!(!again.joined_newly);
+    assert_eq!(again.snapshot.members.len(), 2);
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server"]
+async fn appends_assign_versions_and_reject_non_members() {
+    let backplane = backplane();
+    let room_id = room_id();
+    let (alice, mallory) = (session_user("u1", "alice"), session_user("u3", "mallory"));
+    let joined_at = join(&backplane, &room_id, &alice).await;
+
+    let first = append(&backplane, &alice, &message(&room_id, &alice, "hi")).await;
+    let second = append(&backplane, &alice, &message(&room_id, &alice, "again")).await;
+    assert_eq!(first.version, joined_at + 1);
+    assert_eq!(second.version, joined_at + 2);
+
+    let rejected = backplane
+        .append_message(&mallory.connection_id, &message(&room_id, &mallory, "hey"), None)
+        .await
+        .expect("append should reach redis");
+    assert_eq!(rejected, Err(ChatError::NotInRoom { room_id: room_id.clone() }));
+
+    let mut reply = message(&room_id, &alice, "reply");
+    reply.reply_to = Some("missing".to_string());
+    let orphan = backplane
+        .append_message(&alice.connection_id, &reply, None)
+        .await
+        .expect("append should reach redis");
+    assert_eq!(orphan, Err(ChatError::MessageNotFound { message_id: "missing".to_string() }));
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server"]
+async fn only_senders_and_moderators_modify_messages() {
+    let backplane = backplane();
+    let room_id = room_id();
+    let (alice, bob) = (session_user("u1", "alice"), session_user("u2", "bob"));
+    join(&backplane, &room_id, &alice).await;
+    join(&backplane, &room_id, &bob).await;
+    let sent = append(&backplane, &alice, &message(&room_id, &alice, "hi")).await;
+    let edit = ChatMessageChange::Edit { content: "hello @u2".to_string() };
+
+    let forbidden = backplane
+        .modify_message(&bob, &room_id, &sent.message_id, &edit, None, false)
+        .await
+        .expect("edit should reach redis");
+    assert_eq!(forbidden, Err(ChatError::Forbidden));
+
+    let edited = backplane
+        .modify_message(&alice, &room_id, &sent.message_id, &edit, None, false)
+        .await
+        .expect("edit should reach redis")
+        .expect("sender should be allowed to edit");
+    assert_eq!(edited.message.content, "hello @u2");
+    assert_eq!(edited.message.mentions, vec!["u2".to_string()]);
+    assert!(matches!(
+        edited.event,
+        ChatEvent::MessageEdited(event) if event.message_version == sent.version
+    ));
+
+    let deleted = backplane
+        .modify_message(&bob, &room_id, &sent.message_id, &ChatMessageChange::Delete, None, true)
+        .await
+        .expect("delete should reach redis")
+        .expect("moderator should be allowed to delete");
+    assert!(deleted.message.deleted_at.is_some());
+    assert!(deleted.message.content.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server"]
+async fn reactions_toggle_once_per_user() {
+    let backplane = backplane();
+    let room_id = room_id();
+    let (alice, bob) = (session_user("u1", "alice"), session_user("u2", "bob"));
+    join(&backplane, &room_id, &alice).await;
+    join(&backplane, &room_id, &bob).await;
+    let sent = append(&backplane, &alice, &message(&room_id, &alice, "hi")).await;
+
+    let added = backplane
+        .react(&bob, &room_id, &sent.message_id, "👍", true, None)
+        .await
+        .expect("reaction should reach redis")
+        .expect("member should be allowed to react")
+        .expect("first reaction should change the message");
+    assert_eq!(added.message_version, sent.version);
+    assert_eq!(added.reactions[0].user_ids, vec!["u2".to_string()]);
+
+    let repeated = backplane
+        .react(&bob, &room_id, &sent.message_id, "👍", true, None)
+        .await
+        .expect("reaction should reach redis")
+        .expect("member should be allowed to react");
+    assert_eq!(repeated, None);
+
+    let removed = backplane
+        .react(&bob, &room_id, &sent.message_id, "👍", false, None)
+        .await
+        .expect("reaction should reach redis")
+        .expect("member should be allowed to react")
+        .expect("removal should change the message");
+    assert!(!removed.added);
+    assert!(removed.reactions.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server"]
+async fn snapshots_carry_messages_reactions_and_unread_counts() {
+    let backplane = backplane();
+    let room_id = room_id();
+    let (alice, bob) = (session_user("u1", "alice"), session_user("u2", "bob"));
+    join(&backplane, &room_id, &alice).await;
+    join(&backplane, &room_id, &bob).await;
+    let sent = append(&backplane, &alice, &message(&room_id, &alice, "hi")).await;
+    let reacted = backplane
+        .react(&bob, &room_id, &sent.message_id, "🎉", true, None)
+        .await
+        .expect("reaction should reach redis")
+        .expect("member should be allowed to react")
+        .expect("first reaction should change the message");
+
+    let (snapshot, expired) = backplane
+        .room_snapshot(&room_id, Some("u2"))
+        .await
+        .expect("snapshot should reach redis");
+
+    assert_eq!(expired, None);
+    assert_eq!(snapshot.version, reacted.version);
+    assert_eq!(snapshot.members.len(), 2);
+    assert_eq!(snapshot.recent_messages, vec![sent.clone()]);
+    assert_eq!(snapshot.unread_count, 1);
+    assert_eq!(snapshot.reactions.len(), 1);
+    assert_eq!(snapshot.reactions[0].message_id, sent.message_id);
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server"]
+async fn access_rules_are_kept_without_expiry() {
+    let backplane = backplane();
+    let room_id = room_id();
+    let created = backplane
+        .create_room_access(&room_id, &ChatRoomAccess::new("u1", true))
+        .await
+        .expect("create should reach redis");
+    assert!(created);
+    let banned = backplane
+        .update_room_access(&room_id, |access| {
+            access.apply(&ChatModerationNotice {
+                room_id: room_id.clone(),
+                action: ChatModerationAction::Ban,
+                user_id: "u2".to_string(),
+                moderator_id: "u1".to_string(),
+                muted_until: None,
+            })
+        })
+        .await
+        .expect("update should reach redis")
+        .expect("owner should be allowed to ban");
+    let ensured = backplane
+        .ensure_room_access(&room_id, &ChatRoomAccess::new("u3", false))
+        .await
+        .expect("ensure should reach redis");
+    assert_eq!(ensured, banned);
+
+    let mut conn = client()
+        .get_multiplexed_async_connection()
+        .await
+        .expect("redis should accept connections");
+    let ttl: i64 = redis::cmd("TTL")
+        .arg(format!("chat:{{{room_id}}}:access"))
+        .query_async(&mut conn)
+        .await
+        .expect("ttl should be readable");
+    assert_eq!(ttl, -1);
+}
@@ -0,0 +1,84 @@
+use axum::extract::ws::Message;
+
+use crate::handlers::chat::{
+    ChatCommand, ChatEmptyPayload, ChatError, ChatEvent,
+    codec::{CHAT_JSON_PROTOCOL, CHAT_MSGPACK_PROTOCOL, ChatWireFormat},
+};
+
+fn send() -> ChatCommand {
+    ChatCommand::SendRoomMessage {
+        room_id: "lobby".to_string(),
+        content: "hi".to_string(),
+        reply_to: None,
+    }
+}
+
+#[test]
+fn wire_format_follows_negotiated_protocol() {
+    assert_eq!(ChatWireFormat::from_protocol(None), ChatWireFormat::Json);
+    assert_eq!(ChatWireFormat::from_protocol(Some("axes.chat.v1")), ChatWireFormat::Json);
+    assert_eq!(ChatWireFormat::from_protocol(Some(CHAT_JSON_PROTOCOL)), ChatWireFormat::Json);
+    assert_eq!(
+        ChatWireFormat::from_protocol(Some(CHAT_MSGPACK_PROTOCOL)),
+        ChatWireFormat::MessagePack
+    );
+}
+
+#[test]
+fn json_events_are_text_frames() {
+    let frame = ChatWireFormat::Json
+        .encode(&ChatEvent::Pong(ChatEmptyPayload::default()))
+        .expect("event should encode");
+    assert_eq!(frame, Message::Text(r#"{"type":"pong","payload":{}}"#.into()));
+}
+
+#[test]
+fn msgpack_events_round_trip_as_binary_frames() {
+    let event = ChatError::RateLimited { retry_after_ms: 250 }.to_event();
+    let Message::Binary(bytes) = ChatWireFormat::MessagePack
+        .encode(&event)
+        .expect("event should encode")
+    else {
+        panic!("msgpack events should be binary frames");
+    };
+
+    let json = serde_json::to_vec(&event).expect("event should serialize");
+    assert!(bytes.len() < json.len());
+    assert_eq!(rmp_serde::from_slice::<ChatEvent>(&bytes).expect("event should decode"), event);
+}
+
+#[test]
+fn commands_decode_from_either_frame_type() {
+    let packed = rmp_serde::to_vec_named(&send()).expect("command should encode");
+    assert_eq!(
+        ChatWireFormat::MessagePack
+            .decode(&Message::Binary(packed.clone().into()))
+            .expect("msgpack command should decode"),
+        send()
+    );
+    assert!(
+        ChatWireFormat::Json
+            .decode(&Message::Binary(packed.into()))
+            .is_err()
+    );
+
+    let text = serde_json::to_string(&send()).expect("command should serialize");
+    for format in [ChatWireFormat::Json, ChatWireFormat::MessagePack] {
+        assert_eq!(
+            format
+                .decode(&Message::Text(text.clone().into()))
+                .expect("json command should decode"),
+            send()
+        );
+    }
+    assert!(
+        ChatWireFormat::Json
+            .decode(&Message::Binary(text.into_bytes().into()))
+            .is_ok()
+    );
+    assert!(
+        ChatWireFormat::MessagePack
+            .decode(&Message::Ping(Default::default()))
+            .is_err()
+    );
+}
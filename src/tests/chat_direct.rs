@@ -0,0 +1,131 @@
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatError, ChatEvent, ChatState,
+        direct::{ChatDirectConversation, ChatDirectUnread, ChatDirectUnreadCounts},
+    },
+    tests::chat_support::drain,
+};
+
+fn send_direct(to_user_id: &str, content: &str) -> ChatCommand {
+    ChatCommand::SendDirectMessage {
+        to_user_id: to_user_id.to_string(),
+        content: content.to_string(),
+    }
+}
+
+fn unread(conversations: &[(&str, u64)]) -> ChatDirectUnread {
+    ChatDirectUnread {
+        conversations: conversations
+            .iter()
+            .map(|(user_id, unread_count)| ChatDirectConversation {
+                user_id: user_id.to_string(),
+                unread_count: *unread_count,
+            })
+            .collect(),
+    }
+}
+
+#[tokio::test]
+async fn direct_message_reaches_every_connection_of_the_recipient() {
+    let chat_state = ChatState::default();
+    let (sender, sender_outbound) = chat_state.register_connection("u1", "rc").await;
+    let (_, phone) = chat_state.register_connection("u2", "peer").await;
+    let (_, laptop) = chat_state.register_connection("u2", "peer").await;
+
+    chat_state
+        .process_message(&sender, send_direct(" u2 ", "  hi there "))
+        .await
+        .expect("direct message should be sent");
+
+    for outbound in [&phone, &laptop] {
+        let events = drain(outbound);
+        assert!(
+            matches!(
+                events.as_slice(),
+                [ChatEvent::DirectMessage(message)]
+                    if message.sender_id == "u1" && message.recipient_id == "u2"
+                        && message.content == "hi there"
+            ),
+            "unexpected events: {events:?}"
+        );
+    }
+    let events = drain(&sender_outbound);
+    assert!(
+        matches!(
+            events.as_slice(),
+            [ChatEvent::DirectMessageSent(ack)] if ack.to_user_id == "u2" && ack.delivered
+        ),
+        "unexpected events: {events:?}"
+    );
+}
+
+#[tokio::test]
+async fn offline_recipient_gets_unread_counts_on_connect() {
+    let chat_state = ChatState::default();
+    let (sender, sender_outbound) = chat_state.register_connection("u1", "rc").await;
+    let (other, _) = chat_state.register_connection("u3", "other").await;
+
+    for _ in 0..2 {
+        chat_state
+            .process_message(&sender, send_direct("u2", "ping"))
+            .await
+            .expect("direct message should be sent");
+    }
+    chat_state
+        .process_message(&other, send_direct("u2", "hello"))
+        .await
+        .expect("direct message should be sent");
+    assert!(
+        drain(&sender_outbound)
+            .iter()
+            .all(|event| matches!(event, ChatEvent::DirectMessageSent(ack) if !ack.delivered))
+    );
+
+    let (recipient, recipient_outbound) = chat_state.register_connection("u2", "peer").await;
+    chat_state
+        .send_direct_unread(&recipient)
+        .await
+        .expect("unread summary should be sent");
+    assert_eq!(
+        drain(&recipient_outbound),
+        vec![ChatEvent::DirectUnread(unread(&[("u1", 2), ("u3", 1)]))]
+    );
+
+    chat_state
+        .process_message(&recipient, ChatCommand::MarkDirectRead { user_id: "u1".to_string() })
+        .await
+        .expect("mark read should succeed");
+    assert_eq!(drain(&recipient_outbound), vec![ChatEvent::DirectUnread(unread(&[("u3", 1)]))]);
+}
+
+#[tokio::test]
+async fn direct_message_validates_recipient_and_content() {
+    let chat_state = ChatState::default();
+    let (sender, _) = chat_state.register_connection("u1", "rc").await;
+
+    for (command, expected) in [
+        (send_direct("  ", "hi"), ChatError::InvalidRecipient),
+        (send_direct("u1", "hi"), ChatError::InvalidRecipient),
+        (send_direct("u2", "   "), ChatError::EmptyContent),
+        (send_direct("u2", &"x".repeat(501)), ChatError::ContentTooLong { max_len: 500 }),
+    ] {
+        assert_eq!(
+            chat_state
+                .process_message(&sender, command)
+                .await
+                .expect_err("invalid direct message should fail"),
+            expected
+        );
+    }
+}
+
+#[test]
+fn unread_counts_drop_conversations_once_read() {
+    let mut counts = ChatDirectUnreadCounts::default();
+    counts.increment("u2", "u1");
+    counts.increment("u2", "u1");
+
+    assert_eq!(counts.summary("u2"), unread(&[("u1", 2)]));
+    counts.mark_read("u2", "u1");
+    assert_eq!(counts.summary("u2"), ChatDirectUnread::default());
+}
@@ -0,0 +1,172 @@
+use crate::{
+    config::ChatConfig,
+    handlers::chat::{
+        ChatCommand, ChatError, ChatEvent, ChatHub, ChatState, directory::normalized_reason,
+        outbound::ChatCloseReason,
+    },
+    tests::chat_support::{join, session_tab},
+};
+
+#[test]
+fn room_summaries_count_users_once() {
+    let mut hub = ChatHub::default();
+    hub.join_room(session_tab("u1", 1), "lobby")
+        .expect("alice joins");
+    hub.join_room(session_tab("u1", 2), "lobby")
+        .expect("alice's second tab joins");
+    hub.join_room(session_tab("u2", 1), "lobby")
+        .expect("bob joins");
+
+    let summaries = hub.room_summaries();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].member_count, 2);
+    assert!(!summaries[0].private);
+
+    let members = hub
+        .room_members("lobby")
+        .expect("lobby should have members");
+    assert_eq!(members.members.len(), 2);
+    assert!(hub.room_members("games").is_none());
+}
+
+#[test]
+fn closing_a_room_drops_it_and_its_memberships() {
+    let mut hub = ChatHub::default();
+    let alice = session_tab("u1", 1);
+    let joined = hub.join_room(alice.clone(), "lobby").expect("alice joins");
+
+    let (closed, connection_ids) = hub
+        .close_room("lobby", Some("maintenance".to_string()))
+        .expect("lobby should close");
+    assert_eq!(closed.version, joined.version + 1);
+    assert_eq!(closed.reason.as_deref(), Some("maintenance"));
+    assert_eq!(connection_ids, vec![alice.connection_id.clone()]);
+    assert!(hub.room_summaries().is_empty());
+    assert!(!hub.is_member(&alice.connection_id, "lobby"));
+    assert!(hub.close_room("lobby", None).is_none());
+
+    let rejoined = hub.join_room(alice, "lobby").expect("alice rejoins");
+    assert!(rejoined.version > closed.version);
+}
+
+#[tokio::test]
+async fn directory_hides_rooms_the_caller_cannot_join() {
+    let chat_state = ChatState::default();
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    let (bob, _) = chat_state.register_connection("u2", "bob").await;
+    chat_state
+        .process_message(&alice, join("lobby"))
+        .await
+        .expect("alice joins lobby");
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::CreateRoom { room_id: "staff".to_string(), private: true },
+        )
+        .await
+        .expect("alice creates staff");
+    chat_state
+        .process_message(&alice, join("staff"))
+        .await
+        .expect("alice joins staff");
+
+    let rooms = chat_state
+        .room_directory(&alice.user_id)
+        .await
+        .expect("alice lists rooms");
+    assert_eq!(rooms.len(), 2);
+    let rooms = chat_state
+        .room_directory(&bob.user_id)
+        .await
+        .expect("bob lists rooms");
+    assert_eq!(
+        rooms
+            .iter()
+            .map(|room| room.room_id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["lobby"]
+    );
+
+    let members = chat_state
+        .room_members(&bob.user_id, "lobby")
+        .await
+        .expect("bob sees lobby members");
+    assert_eq!(members.members[0].user_id, "u1");
+    assert!(
+        chat_state
+            .room_members(&bob.user_id, "staff")
+            .await
+            .is_err()
+    );
+    assert!(matches!(
+        chat_state.room_members(&bob.user_id, "games").await,
+        Err(ChatError::RoomNotFound { room_id }) if room_id == "games"
+    ));
+}
+
+#[tokio::test]
+async fn admins_close_connections_and_rooms() {
+    let chat_state = ChatState::default().with_config(ChatConfig {
+        admin_user_ids: vec!["admin".to_string()],
+        ..ChatConfig::default()
+    });
+    assert!(chat_state.is_admin("admin"));
+    assert!(!chat_state.is_admin("u1"));
+
+    let (alice, alice_outbound) = chat_state.register_connection("u1", "alice").await;
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    for user in [&alice, &bob] {
+        chat_state
+            .process_message(user, join("lobby"))
+            .await
+            .expect("join should succeed");
+    }
+    let connections = chat_state
+        .live_connections()
+        .await
+        .expect("connections should list");
+    assert_eq!(connections.len(), 2);
+    assert_eq!(connections[0].user_id, "u1");
+    std::iter::from_fn(|| bob_outbound.try_pop()).for_each(drop);
+
+    let closed = chat_state
+        .close_room("lobby", Some("maintenance".to_string()))
+        .await
+        .expect("lobby should close");
+    assert!(matches!(
+        bob_outbound.try_pop(),
+        Some(ChatEvent::RoomClosed(notice)) if notice == closed
+    ));
+    assert!(
+        chat_state
+            .room_directory(&bob.user_id)
+            .await
+            .expect("bob lists rooms")
+            .is_empty()
+    );
+
+    chat_state
+        .close_connection(&alice.connection_id, None)
+        .await
+        .expect("alice's connection should close");
+    assert!(std::iter::from_fn(|| alice_outbound.try_pop()).any(
+        |event| matches!(event, ChatEvent::ConnectionClosed(notice) if notice.reason.is_none())
+    ));
+    assert_eq!(alice_outbound.close_reason(), Some(ChatCloseReason::ClosedByAdmin));
+    assert!(matches!(
+        chat_state.close_connection("conn-missing", None).await,
+        Err(ChatError::ConnectionNotFound { .. })
+    ));
+}
+
+#[test]
+fn close_reasons_are_trimmed_and_bounded() {
+    assert_eq!(normalized_reason(None).expect("no reason is fine"), None);
+    assert_eq!(normalized_reason(Some("  ")).expect("blank reason is fine"), None);
+    assert_eq!(
+        normalized_reason(Some(" spam ")).expect("reason should be kept"),
+        Some("spam".to_string())
+    );
+    assert!(normalized_reason(Some(&"x".repeat(201))).is_err());
+    assert_eq!(ChatCloseReason::ClosedByAdmin.code(), 4004);
+}
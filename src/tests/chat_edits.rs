@@ -0,0 +1,251 @@
+use serde_json::json;
+
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatError, ChatEvent, ChatHub, ChatRoomMessage, ChatSessionUser, ChatState,
+        edits::{ChatMessageChange, ChatMessageDeleted, ChatMessageEdited},
+        sync::{ChatRoomSync, StoredRoomEvent},
+    },
+    tests::chat_support::lobby_with_message,
+};
+
+fn edit(content: &str) -> ChatMessageChange {
+    ChatMessageChange::Edit { content: content.to_string() }
+}
+
+fn recent_messages(hub: &ChatHub, session_user: &ChatSessionUser) -> Vec<ChatRoomMessage> {
+    match hub.room_sync(&session_user.connection_id, "lobby", None) {
+        Ok(ChatRoomSync::Snapshot(snapshot)) => snapshot.recent_messages,
+        other => panic!("expected a snapshot, got {other:?}"),
+    }
+}
+
+#[test]
+fn edit_and_delete_commands_deserialize() {
+    let command: ChatCommand = serde_json::from_value(json!({
+        "type": "edit_room_message",
+        "payload": { "room_id": "lobby", "message_id": "m1", "content": "fixed" }
+    }))
+    .expect("edit_room_message should deserialize");
+    assert_eq!(
+        command,
+        ChatCommand::EditRoomMessage {
+            room_id: "lobby".to_string(),
+            message_id: "m1".to_string(),
+            content: "fixed".to_string(),
+        }
+    );
+
+    let command: ChatCommand = serde_json::from_value(json!({
+        "type": "delete_room_message",
+        "payload": { "room_id": "lobby", "message_id": "m1" }
+    }))
+    .expect("delete_room_message should deserialize");
+    assert_eq!(command.event_type(), "delete_room_message");
+}
+
+#[test]
+fn sender_edit_bumps_version_and_updates_retained_message() {
+    let (mut hub, alice, _, message) = lobby_with_message();
+
+    let modified = hub
+        .modify_message(
+            &alice.connection_id,
+            "lobby",
+            &message.message_id,
+            &edit("hello"),
+            None,
+            42,
+        )
+        .expect("sender may edit");
+
+    assert_eq!(
+        modified.event,
+        ChatEvent::MessageEdited(ChatMessageEdited {
+            room_id: "lobby".to_string(),
+            message_id: message.message_id.clone(),
+            message_version: 3,
+            content: "hello".to_string(),
+            edited_at: 42,
+            version: 4,
+        })
+    );
+    let recent = recent_messages(&hub, &alice);
+    assert_eq!(recent[0].content, "hello");
+    assert_eq!(recent[0].edited_at, Some(42));
+    assert_eq!(recent[0].version, 3);
+}
+
+#[test]
+fn delete_leaves_tombstone_that_cannot_change_again() {
+    let (mut hub, alice, _, message) = lobby_with_message();
+
+    let modified = hub
+        .modify_message(
+            &alice.connection_id,
+            "lobby",
+            &message.message_id,
+            &ChatMessageChange::Delete,
+            None,
+            42,
+        )
+        .expect("sender may delete");
+    assert_eq!(
+        modified.event,
+        ChatEvent::MessageDeleted(ChatMessageDeleted {
+            room_id: "lobby".to_string(),
+            message_id: message.message_id.clone(),
+            message_version: 3,
+            deleted_at: 42,
+            version: 4,
+        })
+    );
+    let recent = recent_messages(&hub, &alice);
+    assert_eq!(recent[0].content, "");
+    assert_eq!(recent[0].deleted_at, Some(42));
+
+    assert_eq!(
+        hub.modify_message(
+            &alice.connection_id,
+            "lobby",
+            &message.message_id,
+            &edit("back"),
+            None,
+            43
+        ),
+        Err(ChatError::MessageNotFound { message_id: message.message_id.clone() })
+    );
+}
+
+#[test]
+fn only_the_sender_may_change_a_message() {
+    let (mut hub, _, bob, message) = lobby_with_message();
+
+    assert_eq!(
+        hub.modify_message(
+            &bob.connection_id,
+            "lobby",
+            &message.message_id,
+            &ChatMessageChange::Delete,
+            None,
+            42
+        ),
+        Err(ChatError::Forbidden)
+    );
+    assert_eq!(
+        hub.modify_message(&bob.connection_id, "lobby", "missing", &edit("x"), None, 42),
+        Err(ChatError::MessageNotFound { message_id: "missing".to_string() })
+    );
+}
+
+#[test]
+fn stored_messages_outside_the_window_can_be_changed() {
+    let (mut hub, alice, _, message) = lobby_with_message();
+    let stored = ChatRoomMessage { message_id: "old".to_string(), version: 1, ..message };
+
+    assert!(matches!(
+        hub.modify_message(&alice.connection_id, "lobby", "old", &edit("x"), None, 42),
+        Err(ChatError::MessageNotFound { .. })
+    ));
+    let modified = hub
+        .modify_message(&alice.connection_id, "lobby", "old", &edit("x"), Some(stored), 42)
+        .expect("stored message may be edited");
+    assert_eq!(modified.message.edited_at, Some(42));
+    assert_eq!(recent_messages(&hub, &alice)[0].content, "helo");
+}
+
+#[test]
+fn changes_are_replayed_in_deltas() {
+    let (mut hub, alice, _, message) = lobby_with_message();
+    hub.modify_message(
+        &alice.connection_id,
+        "lobby",
+        &message.message_id,
+        &edit("hello"),
+        None,
+        42,
+    )
+    .expect("sender may edit");
+
+    let Ok(ChatRoomSync::Delta(delta)) = hub.room_sync(&alice.connection_id, "lobby", Some(3))
+    else {
+        panic!("delta should be available");
+    };
+    assert!(matches!(
+        delta.events.as_slice(),
+        [ChatEvent::MessageEdited(edited)] if edited.version == 4 && edited.content == "hello"
+    ));
+
+    let (version, event) = serde_json::from_str::<StoredRoomEvent>(
+        r#"{"version":5,"deleted":{"room_id":"lobby","message_id":"m1","message_version":3,"deleted_at":42,"version":5}}"#,
+    )
+    .expect("stored entry should decode")
+    .into_event("lobby");
+    assert_eq!(version, 5);
+    assert!(matches!(event, ChatEvent::MessageDeleted(deleted) if deleted.message_version == 3));
+}
+
+#[tokio::test]
+async fn edits_are_broadcast_to_the_room() {
+    let chat_state = ChatState::default();
+    let (alice, alice_outbound) = chat_state.register_connection("u1", "alice").await;
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    for user in [&alice, &bob] {
+        chat_state
+            .process_message(
+                user,
+                ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+    }
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::SendRoomMessage {
+                room_id: "lobby".to_string(),
+                content: "helo".to_string(),
+                reply_to: None,
+            },
+        )
+        .await
+        .expect("send should succeed");
+    while alice_outbound.try_pop().is_some() {}
+    let Some(ChatEvent::RoomMessage(message)) =
+        std::iter::from_fn(|| bob_outbound.try_pop()).last()
+    else {
+        panic!("bob should receive the message");
+    };
+
+    assert_eq!(
+        chat_state
+            .process_message(
+                &bob,
+                ChatCommand::DeleteRoomMessage {
+                    room_id: "lobby".to_string(),
+                    message_id: message.message_id.clone(),
+                },
+            )
+            .await,
+        Err(ChatError::Forbidden)
+    );
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::EditRoomMessage {
+                room_id: "lobby".to_string(),
+                message_id: message.message_id.clone(),
+                content: "hello".to_string(),
+            },
+        )
+        .await
+        .expect("edit should succeed");
+
+    for outbound in [&alice_outbound, &bob_outbound] {
+        assert!(matches!(
+            outbound.try_pop(),
+            Some(ChatEvent::MessageEdited(edited))
+                if edited.message_id == message.message_id && edited.version == 4
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use futures_util::{FutureExt, future::BoxFuture};
+
+use crate::{
+    config::{ChatBannedWordAction, ChatConfig, ChatFilterConfig, ChatLinkPolicy},
+    handlers::chat::{
+        ChatCommand, ChatError, ChatEvent, ChatSessionUser, ChatState,
+        filters::{
+            ChatContentFilter, ChatContentFilters, ChatFilterAction, ChatFilterInput,
+            extract_mentions,
+        },
+    },
+};
+
+fn config(filters: ChatFilterConfig) -> ChatConfig {
+    ChatConfig { filters, ..ChatConfig::default() }
+}
+
+fn filters(config: ChatFilterConfig) -> ChatContentFilters {
+    let mut filters = ChatContentFilters::default();
+    filters.configure(&config);
+    filters
+}
+
+fn send(content: &str) -> ChatCommand {
+    ChatCommand::SendRoomMessage {
+        room_id: "lobby".to_string(),
+        content: content.to_string(),
+        reply_to: None,
+    }
+}
+
+/// Alice owns the lobby, bob is a member.
+async fn lobby(chat_state: &ChatState) -> (ChatSessionUser, ChatSessionUser) {
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    let (bob, _) = chat_state.register_connection("u2", "bob").await;
+    for user in [&alice, &bob] {
+        chat_state
+            .process_message(
+                user,
+                ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+    }
+    (alice, bob)
+}
+
+/// Rejects messages shouting in all caps.
+struct NoShouting;
+
+impl ChatContentFilter for NoShouting {
+    fn name(&self) -> &str {
+        "no_shouting"
+    }
+
+    fn check<'a>(&'a self, input: &'a ChatFilterInput) -> BoxFuture<'a, ChatFilterAction> {
+        async move {
+            if input.content.chars().any(char::is_lowercase) {
+                ChatFilterAction::Allow
+            } else {
+                ChatFilterAction::Reject(ChatError::ContentRejected {
+                    reason: "no shouting".to_string(),
+                })
+            }
+        }
+        .boxed()
+    }
+}
+
+#[test]
+fn mentions_are_distinct_and_skip_email_addresses() {
+    assert_eq!(
+        extract_mentions("hi @bob and @alice.smith, @bob again. mail rc@example.com or @carol."),
+        vec!["bob", "alice.smith", "carol"]
+    );
+    assert!(extract_mentions("@ nobody").is_empty());
+}
+
+#[tokio::test]
+async fn banned_words_are_masked_or_rejected() {
+    let masking = filters(ChatFilterConfig {
+        banned_words: vec!["Darn".to_string()],
+        ..ChatFilterConfig::default()
+    });
+    let filtered = masking
+        .run("lobby", "u1", "darn it, DARN! darnation".to_string())
+        .await
+        .expect("masked content should pass");
+    assert_eq!(filtered.content, "**** it, ****! darnation");
+    assert!(filtered.flags.is_empty());
+
+    let rejecting = filters(ChatFilterConfig {
+        banned_words: vec!["darn".to_string()],
+        banned_word_action: ChatBannedWordAction::Reject,
+        ..ChatFilterConfig::default()
+    });
+    let error = rejecting
+        .run("lobby", "u1", "oh darn".to_string())
+        .await
+        .expect_err("banned word should be rejected");
+    assert_eq!(error.code(), "banned_content");
+}
+
+#[tokio::test]
+async fn link_policy_allows_listed_domains_only() {
+    let links = filters(ChatFilterConfig {
+        link_policy: ChatLinkPolicy::Reject,
+        allowed_link_domains: vec!["example.com".to_string()],
+        ..ChatFilterConfig::default()
+    });
+    links
+        .run("lobby", "u1", "see https://docs.example.com/guide".to_string())
+        .await
+        .expect("subdomains of allowed domains should pass");
+    for content in ["(www.spam.test)", "http://badexample.com", "HTTPS://Spam.test:8080/x"] {
+        assert!(matches!(
+            links.run("lobby", "u1", content.to_string()).await,
+            Err(ChatError::LinkNotAllowed)
+        ));
+    }
+
+    let flagging = filters(ChatFilterConfig {
+        link_policy: ChatLinkPolicy::Flag,
+        ..ChatFilterConfig::default()
+    });
+    let filtered = flagging
+        .run("lobby", "u1", "go to https://spam.test".to_string())
+        .await
+        .expect("flagged links should pass");
+    assert_eq!(filtered.flags.len(), 1);
+    assert_eq!(filtered.flags[0].filter, "links");
+    assert_eq!(filtered.flags[0].reason, "links to spam.test");
+}
+
+#[tokio::test]
+async fn mention_limit_and_custom_filters_reject() {
+    let mut pipeline = filters(ChatFilterConfig { max_mentions: 2, ..ChatFilterConfig::default() });
+    pipeline.push(Arc::new(NoShouting));
+
+    let filtered = pipeline
+        .run("lobby", "u1", "hey @bob and @carol".to_string())
+        .await
+        .expect("two mentions should pass");
+    assert_eq!(filtered.mentions, vec!["bob", "carol"]);
+    assert!(matches!(
+        pipeline.run("lobby", "u1", "@a @b @c".to_string()).await,
+        Err(ChatError::TooManyMentions { max_mentions: 2 })
+    ));
+    let error = pipeline
+        .run("lobby", "u1", "HELLO".to_string())
+        .await
+        .expect_err("custom filter should reject");
+    assert_eq!(error.code(), "content_rejected");
+}
+
+#[tokio::test]
+async fn flagged_messages_are_sent_and_listed_for_moderators() {
+    let chat_state = ChatState::default().with_config(config(ChatFilterConfig {
+        banned_words: vec!["darn".to_string()],
+        banned_word_action: ChatBannedWordAction::Flag,
+        ..ChatFilterConfig::default()
+    }));
+    let (alice, bob) = lobby(&chat_state).await;
+
+    chat_state
+        .process_message(&bob, send("darn it @u1"))
+        .await
+        .expect("flagged message should still send");
+    let flagged = chat_state
+        .flagged_messages(&alice.user_id, "lobby")
+        .await
+        .expect("the owner may list flagged messages");
+    assert_eq!(flagged.messages.len(), 1);
+    assert_eq!(flagged.messages[0].message.content, "darn it @u1");
+    assert_eq!(flagged.messages[0].message.mentions, vec!["u1"]);
+    assert_eq!(flagged.messages[0].flags[0].filter, "banned_words");
+
+    assert!(matches!(
+        chat_state.flagged_messages(&bob.user_id, "lobby").await,
+        Err(ChatError::NotRoomModerator)
+    ));
+}
+
+#[tokio::test]
+async fn edits_go_through_the_filters() {
+    let chat_state = ChatState::default().with_config(config(ChatFilterConfig {
+        banned_words: vec!["darn".to_string()],
+        ..ChatFilterConfig::default()
+    }));
+    let (alice, alice_outbound) = chat_state.register_connection("u1", "alice").await;
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+        )
+        .await
+        .expect("alice joins");
+    chat_state
+        .process_message(&alice, send("hello"))
+        .await
+        .expect("message should send");
+    let message_id = std::iter::from_fn(|| alice_outbound.try_pop())
+        .find_map(|event| match event {
+            ChatEvent::RoomMessage(message) => Some(message.message_id),
+            _ => None,
+        })
+        .expect("alice should receive her message");
+
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::EditRoomMessage {
+                room_id: "lobby".to_string(),
+                message_id,
+                content: "well darn".to_string(),
+            },
+        )
+        .await
+        .expect("masked edit should apply");
+    assert!(matches!(
+        std::iter::from_fn(|| alice_outbound.try_pop()).last(),
+        Some(ChatEvent::MessageEdited(edited)) if edited.content == "well ****"
+    ));
+}
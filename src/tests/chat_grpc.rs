@@ -0,0 +1,209 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::{StreamExt, stream};
+use tokio::sync::mpsc;
+use tonic::{
+    Code, Request,
+    transport::{Channel, Endpoint, Server, server::TcpIncoming},
+};
+
+use crate::{
+    config::ChatConfig,
+    grpc::{
+        chat::{
+            self, CommandFrame, EventFrame, chat_client::ChatClient, command_frame::Command,
+            event_frame::Event,
+        },
+        chat_frames::command_from_frame,
+        chat_impl::{self, close_status},
+    },
+    handlers::chat::{
+        ChatCommand, ChatEmptyPayload, ChatEvent, ChatState, outbound::ChatCloseReason,
+        presence::ChatPresenceStatus,
+    },
+    tests::chat_support::bearer,
+};
+
+fn frame(command: Command) -> CommandFrame {
+    CommandFrame { command: Some(command) }
+}
+
+async fn serve(chat_state: Arc<ChatState>) -> ChatClient<Channel> {
+    let incoming = TcpIncoming::bind("127.0.0.1:0".parse().expect("address should parse"))
+        .expect("listener should bind");
+    let addr = incoming.local_addr().expect("listener has an address");
+    tokio::spawn(
+        Server::builder()
+            .add_service(chat_impl::router(chat_state))
+            .serve_with_incoming(incoming),
+    );
+    let channel = Endpoint::from_shared(format!("http://{addr}"))
+        .expect("endpoint should parse")
+        .connect()
+        .await
+        .expect("client should connect");
+    ChatClient::new(channel)
+}
+
+#[test]
+fn frames_mirror_websocket_commands_and_events() {
+    assert_eq!(
+        command_from_frame(frame(Command::SendRoomMessage(chat::SendRoomMessage {
+            room_id: "lobby".to_string(),
+            content: "hi".to_string(),
+            reply_to: None,
+        }))),
+        Some(ChatCommand::SendRoomMessage {
+            room_id: "lobby".to_string(),
+            content: "hi".to_string(),
+            reply_to: None
+        })
+    );
+    assert_eq!(
+        command_from_frame(frame(Command::SetStatus(chat::SetStatus {
+            status: chat::PresenceStatus::Away.into(),
+        }))),
+        Some(ChatCommand::SetStatus { status: ChatPresenceStatus::Away })
+    );
+    assert_eq!(
+        command_from_frame(frame(Command::Ping(chat::Empty {}))),
+        Some(ChatCommand::Ping(ChatEmptyPayload::default()))
+    );
+    assert_eq!(command_from_frame(CommandFrame { command: None }), None);
+    assert_eq!(command_from_frame(frame(Command::SetStatus(chat::SetStatus { status: 0 }))), None);
+
+    assert_eq!(
+        EventFrame::from(ChatEvent::Pong(ChatEmptyPayload::default())),
+        EventFrame { event: Some(Event::Pong(chat::Empty {})) }
+    );
+    assert_eq!(close_status(ChatCloseReason::SessionExpired).code(), Code::Unauthenticated);
+    assert_eq!(close_status(ChatCloseReason::RateLimited).code(), Code::ResourceExhausted);
+    assert_eq!(close_status(ChatCloseReason::IdleTimeout).code(), Code::DeadlineExceeded);
+}
+
+#[tokio::test]
+async fn grpc_and_websocket_clients_share_rooms() {
+    let chat_state = Arc::new(ChatState::default());
+    let mut client = serve(chat_state.clone()).await;
+
+    let unauthenticated = client
+        .connect(Request::new(stream::empty::<CommandFrame>()))
+        .await
+        .expect_err("connect without a token should fail");
+    assert_eq!(unauthenticated.code(), Code::Unauthenticated);
+
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    chat_state
+        .process_message(
+            &bob,
+            ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+        )
+        .await
+        .expect("join should succeed");
+
+    let (commands, receiver) = mpsc::unbounded_channel();
+    let mut request = Request::new(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|frame| (frame, receiver))
+    }));
+    request.metadata_mut().insert(
+        "authorization",
+        bearer("u1")
+            .parse()
+            .expect("token should be valid metadata"),
+    );
+    let mut events = client
+        .connect(request)
+        .await
+        .expect("connect should succeed")
+        .into_inner();
+    let mut next_event = async || {
+        events
+            .next()
+            .await
+            .expect("stream should stay open")
+            .expect("event should arrive")
+    };
+    assert!(matches!(next_event().await.event, Some(Event::Connected(_))));
+    assert!(matches!(next_event().await.event, Some(Event::DirectUnread(_))));
+
+    commands
+        .send(frame(Command::JoinRoom(chat::JoinRoom {
+            room_id: "lobby".to_string(),
+            since_version: None,
+        })))
+        .expect("command should send");
+    assert!(matches!(next_event().await.event, Some(Event::JoinedRoom(_))));
+    commands
+        .send(frame(Command::SendRoomMessage(chat::SendRoomMessage {
+            room_id: "lobby".to_string(),
+            content: "from grpc".to_string(),
+            reply_to: None,
+        })))
+        .expect("command should send");
+    assert!(matches!(next_event().await.event, Some(Event::RoomMessage(_))));
+
+    let from_grpc = std::iter::from_fn(|| bob_outbound.try_pop())
+        .find_map(|event| match event {
+            ChatEvent::RoomMessage(message) => Some(message),
+            _ => None,
+        })
+        .expect("bob should receive the grpc message");
+    assert_eq!(from_grpc.sender_id, "u1");
+    assert_eq!(from_grpc.content, "from grpc");
+
+    chat_state
+        .process_message(
+            &bob,
+            ChatCommand::SendRoomMessage {
+                room_id: "lobby".to_string(),
+                content: "from websocket".to_string(),
+                reply_to: None,
+            },
+        )
+        .await
+        .expect("send should succeed");
+    assert!(matches!(
+        next_event().await.event,
+        Some(Event::RoomMessage(message)) if message.content == "from websocket"
+    ));
+
+    drop(commands);
+    assert!(events.next().await.is_none());
+}
+
+#[tokio::test]
+async fn idle_grpc_sessions_end_with_deadline_exceeded() {
+    let chat_state = Arc::new(
+        ChatState::default()
+            .with_config(ChatConfig { idle_timeout_secs: 1, ..ChatConfig::default() }),
+    );
+    let mut client = serve(chat_state.clone()).await;
+    let mut request = Request::new(stream::pending::<CommandFrame>());
+    request.metadata_mut().insert(
+        "authorization",
+        bearer("u1")
+            .parse()
+            .expect("token should be valid metadata"),
+    );
+    let mut events = client
+        .connect(request)
+        .await
+        .expect("connect should succeed")
+        .into_inner();
+
+    let status = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match events
+                .next()
+                .await
+                .expect("stream should end with a status")
+            {
+                Ok(_) => continue,
+                Err(status) => break status,
+            }
+        }
+    })
+    .await
+    .expect("idle session should be closed");
+    assert_eq!(status.code(), Code::DeadlineExceeded);
+}
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{
+    config::ChatConfig,
+    handlers::chat::{
+        ChatCommand, ChatEvent, ChatPresenceChange, ChatState, ChatUserSummary,
+        heartbeat::ChatHeartbeat, outbound::ChatCloseReason, presence::ChatPresenceStatus,
+    },
+};
+
+fn config() -> ChatConfig {
+    ChatConfig {
+        heartbeat_interval_secs: 30,
+        pong_timeout_secs: 10,
+        idle_timeout_secs: 120,
+        ..ChatConfig::default()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn pings_are_due_every_interval() {
+    let heartbeat = ChatHeartbeat::new(&config());
+    let started = Instant::now();
+
+    heartbeat.ping_due().await;
+    assert_eq!(started.elapsed(), Duration::from_secs(30));
+
+    heartbeat.ping_sent();
+    heartbeat.pong_received();
+    heartbeat.ping_due().await;
+    assert_eq!(started.elapsed(), Duration::from_secs(60));
+}
+
+#[tokio::test(start_paused = true)]
+async fn unanswered_ping_expires_after_pong_timeout() {
+    let heartbeat = ChatHeartbeat::new(&config());
+    heartbeat.ping_due().await;
+    heartbeat.ping_sent();
+    let sent_at = Instant::now();
+
+    assert_eq!(heartbeat.expired().await, ChatCloseReason::HeartbeatTimeout);
+    assert_eq!(sent_at.elapsed(), Duration::from_secs(10));
+}
+
+#[tokio::test(start_paused = true)]
+async fn ping_sent_while_waiting_moves_the_deadline_forward() {
+    let heartbeat = std::sync::Arc::new(ChatHeartbeat::new(&config()));
+    let started = Instant::now();
+    let waiting = tokio::spawn({
+        let heartbeat = heartbeat.clone();
+        async move { heartbeat.expired().await }
+    });
+
+    tokio::time::sleep(Duration::from_secs(30)).await;
+    heartbeat.ping_sent();
+
+    let reason = waiting.await.expect("expiry task should not panic");
+    assert_eq!(reason, ChatCloseReason::HeartbeatTimeout);
+    assert_eq!(started.elapsed(), Duration::from_secs(40));
+}
+
+#[tokio::test(start_paused = true)]
+async fn pongs_keep_the_transport_alive_but_not_the_client() {
+    let heartbeat = ChatHeartbeat::new(&config());
+    let started = Instant::now();
+
+    for _ in 0..3 {
+        heartbeat.ping_due().await;
+        heartbeat.ping_sent();
+        tokio::time::advance(Duration::from_secs(5)).await;
+        heartbeat.pong_received();
+    }
+
+    assert_eq!(heartbeat.expired().await, ChatCloseReason::IdleTimeout);
+    assert_eq!(started.elapsed(), Duration::from_secs(120));
+}
+
+#[tokio::test(start_paused = true)]
+async fn client_activity_postpones_the_idle_timeout() {
+    let heartbeat = ChatHeartbeat::new(&config());
+    let started = Instant::now();
+
+    tokio::time::advance(Duration::from_secs(100)).await;
+    heartbeat.activity();
+
+    assert_eq!(heartbeat.expired().await, ChatCloseReason::IdleTimeout);
+    assert_eq!(started.elapsed(), Duration::from_secs(220));
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_connection_is_unregistered_and_peers_see_it_leave() {
+    let chat_state = ChatState::default().with_config(config());
+    let (idle_user, idle_outbound) = chat_state.register_connection("u1", "rc").await;
+    let (peer, peer_outbound) = chat_state.register_connection("u2", "peer").await;
+    for user in [&idle_user, &peer] {
+        chat_state
+            .process_message(
+                user,
+                ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+    }
+    while peer_outbound.try_pop().is_some() {}
+
+    let heartbeat = chat_state.heartbeat();
+    let peer_heartbeat = chat_state.heartbeat();
+    tokio::time::advance(Duration::from_secs(90)).await;
+    peer_heartbeat.activity();
+
+    let reason = chat_state
+        .expire_connection(&idle_user.connection_id, &idle_outbound, &heartbeat)
+        .await;
+
+    assert_eq!(reason, ChatCloseReason::IdleTimeout);
+    assert_eq!(idle_outbound.close_reason(), Some(ChatCloseReason::IdleTimeout));
+    assert_eq!(
+        peer_outbound.try_pop(),
+        Some(ChatEvent::PresenceChanged(ChatPresenceChange {
+            room_id: "lobby".to_string(),
+            joined_members: vec![],
+            left_members: vec![ChatUserSummary {
+                user_id: "u1".to_string(),
+                user_name: "rc".to_string(),
+                status: ChatPresenceStatus::Online,
+            }],
+            version: 3,
+        }))
+    );
+    assert!(
+        tokio::time::timeout(
+            Duration::from_secs(30),
+            chat_state.expire_connection(&peer.connection_id, &peer_outbound, &peer_heartbeat),
+        )
+        .await
+        .is_err(),
+        "active peer should outlive the idle connection"
+    );
+}
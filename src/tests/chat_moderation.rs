@@ -0,0 +1,349 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatError, ChatEvent, ChatHub, ChatState, chat_error_response,
+        edits::ChatMessageChange,
+        moderation::{ChatModerationAction, ChatModerationNotice, ChatRoomAccess},
+        sync::ChatRoomSync,
+    },
+    tests::chat_support::{lobby, session_user},
+};
+
+fn notice(moderator_id: &str, user_id: &str, action: ChatModerationAction) -> ChatModerationNotice {
+    ChatModerationNotice {
+        room_id: "lobby".to_string(),
+        action,
+        user_id: user_id.to_string(),
+        moderator_id: moderator_id.to_string(),
+        muted_until: None,
+    }
+}
+
+#[test]
+fn moderation_commands_deserialize() {
+    let command: ChatCommand = serde_json::from_value(json!({
+        "type": "create_room",
+        "payload": { "room_id": "staff" }
+    }))
+    .expect("create_room should deserialize");
+    assert_eq!(command, ChatCommand::CreateRoom { room_id: "staff".to_string(), private: false });
+
+    let command: ChatCommand = serde_json::from_value(json!({
+        "type": "mute_member",
+        "payload": { "room_id": "lobby", "user_id": "u2", "duration_secs": 60 }
+    }))
+    .expect("mute_member should deserialize");
+    assert_eq!(
+        command,
+        ChatCommand::MuteMember {
+            room_id: "lobby".to_string(),
+            user_id: "u2".to_string(),
+            duration_secs: Some(60),
+        }
+    );
+}
+
+#[test]
+fn only_the_owner_manages_moderators() {
+    let mut access = ChatRoomAccess::new("u1", false);
+
+    assert_eq!(
+        access.apply(&notice("u2", "u3", ChatModerationAction::AddModerator)),
+        Err(ChatError::NotRoomOwner)
+    );
+    assert_eq!(
+        access.apply(&notice("u2", "u3", ChatModerationAction::Kick)),
+        Err(ChatError::NotRoomModerator)
+    );
+    access
+        .apply(&notice("u1", "u2", ChatModerationAction::AddModerator))
+        .expect("owner appoints a moderator");
+    access
+        .apply(&notice("u1", "u3", ChatModerationAction::AddModerator))
+        .expect("owner appoints a moderator");
+
+    assert!(access.is_moderator("u2"));
+    assert_eq!(
+        access.apply(&notice("u2", "u3", ChatModerationAction::Ban)),
+        Err(ChatError::InvalidModerationTarget)
+    );
+    assert_eq!(
+        access.apply(&notice("u2", "u1", ChatModerationAction::Kick)),
+        Err(ChatError::InvalidModerationTarget)
+    );
+    access
+        .apply(&notice("u2", "u4", ChatModerationAction::Ban))
+        .expect("moderator bans a member");
+    assert_eq!(access.check_join("u4"), Err(ChatError::BannedFromRoom));
+}
+
+#[test]
+fn mutes_expire() {
+    let mut access = ChatRoomAccess::new("u1", false);
+    access
+        .apply(&ChatModerationNotice {
+            muted_until: Some(100),
+            ..notice("u1", "u2", ChatModerationAction::Mute)
+        })
+        .expect("owner mutes a member");
+
+    assert_eq!(access.check_send("u2", 99), Err(ChatError::Muted { until: 100 }));
+    assert_eq!(access.check_send("u2", 100), Ok(()));
+    assert_eq!(access.check_send("u3", 99), Ok(()));
+}
+
+#[test]
+fn first_member_owns_the_room_and_bans_outlive_it() {
+    let (mut hub, alice, bob) = lobby();
+    assert_eq!(
+        hub.room_access("lobby")
+            .map(|access| access.owner_id.as_str()),
+        Some("u1")
+    );
+
+    hub.moderate(&alice.connection_id, &notice("u1", "u2", ChatModerationAction::Ban))
+        .expect("owner bans bob");
+    let (presence, removed) = hub
+        .remove_user("lobby", "u2")
+        .expect("bob was in the lobby");
+    assert_eq!(removed, vec![bob.connection_id.clone()]);
+    assert_eq!(presence.version, 3);
+    assert!(!hub.is_member(&bob.connection_id, "lobby"));
+
+    hub.leave_room(&alice.connection_id, "lobby")
+        .expect("alice leaves lobby");
+    assert_eq!(hub.join_room(bob, "lobby"), Err(ChatError::BannedFromRoom));
+}
+
+#[test]
+fn emptied_rooms_keep_their_owner_and_forget_expired_mutes() {
+    let (mut hub, alice, bob) = lobby();
+    hub.moderate(
+        &alice.connection_id,
+        &ChatModerationNotice {
+            muted_until: Some(1),
+            ..notice("u1", "u2", ChatModerationAction::Mute)
+        },
+    )
+    .expect("owner mutes bob");
+    hub.leave_room(&alice.connection_id, "lobby")
+        .expect("alice leaves");
+    hub.leave_room(&bob.connection_id, "lobby")
+        .expect("bob leaves lobby");
+
+    assert!(
+        hub.room_access("lobby")
+            .is_some_and(|access| access.owner_id == "u1" && access.muted.is_empty())
+    );
+    hub.join_room(bob, "lobby").expect("bob rejoins lobby");
+    assert_eq!(
+        hub.room_access("lobby")
+            .map(|access| access.owner_id.as_str()),
+        Some("u1")
+    );
+    let rejoined = hub
+        .room_sync("conn-u2", "lobby", None)
+        .expect("bob syncs lobby");
+    assert!(matches!(rejoined, ChatRoomSync::Snapshot(snapshot) if snapshot.version == 5));
+}
+
+#[test]
+fn muted_members_cannot_send() {
+    let (mut hub, alice, bob) = lobby();
+    hub.moderate(
+        &alice.connection_id,
+        &ChatModerationNotice {
+            muted_until: Some(i64::MAX),
+            ..notice("u1", "u2", ChatModerationAction::Mute)
+        },
+    )
+    .expect("owner mutes bob");
+
+    assert_eq!(
+        hub.send_room_message(&bob.connection_id, "lobby", "hello"),
+        Err(ChatError::Muted { until: i64::MAX })
+    );
+    assert!(
+        hub.send_room_message(&alice.connection_id, "lobby", "hello")
+            .is_ok()
+    );
+}
+
+#[test]
+fn private_rooms_need_an_invite() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    let bob = session_user("u2", "bob");
+    hub.create_room("u1", "staff", true).expect("room is new");
+    assert_eq!(hub.create_room("u2", "staff", false), Err(ChatError::RoomExists));
+    hub.join_room(alice.clone(), "staff").expect("owner joins");
+
+    assert_eq!(hub.join_room(bob.clone(), "staff"), Err(ChatError::InviteRequired));
+    hub.moderate(
+        &alice.connection_id,
+        &ChatModerationNotice {
+            room_id: "staff".to_string(),
+            ..notice("u1", "u2", ChatModerationAction::Invite)
+        },
+    )
+    .expect("owner invites bob");
+    assert!(hub.join_room(bob, "staff").is_ok());
+}
+
+#[test]
+fn moderators_may_delete_but_not_edit_messages_of_others() {
+    let (mut hub, alice, bob) = lobby();
+    let sent = hub
+        .send_room_message(&bob.connection_id, "lobby", "spam")
+        .expect("send should succeed");
+
+    assert_eq!(
+        hub.modify_message(
+            &alice.connection_id,
+            "lobby",
+            &sent.message.message_id,
+            &ChatMessageChange::Edit { content: "ham".to_string() },
+            None,
+            42,
+        ),
+        Err(ChatError::Forbidden)
+    );
+    assert!(
+        hub.modify_message(
+            &alice.connection_id,
+            "lobby",
+            &sent.message.message_id,
+            &ChatMessageChange::Delete,
+            None,
+            42,
+        )
+        .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn kick_is_broadcast_and_removes_the_member() {
+    let chat_state = ChatState::default();
+    let (alice, alice_outbound) = chat_state.register_connection("u1", "alice").await;
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    for user in [&alice, &bob] {
+        chat_state
+            .process_message(
+                user,
+                ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+    }
+    while alice_outbound.try_pop().is_some() {}
+    while bob_outbound.try_pop().is_some() {}
+
+    assert_eq!(
+        chat_state
+            .process_message(
+                &bob,
+                ChatCommand::KickMember { room_id: "lobby".to_string(), user_id: "u1".to_string() },
+            )
+            .await,
+        Err(ChatError::NotRoomModerator)
+    );
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::KickMember { room_id: "lobby".to_string(), user_id: "u2".to_string() },
+        )
+        .await
+        .expect("owner kicks bob");
+
+    assert!(matches!(
+        bob_outbound.try_pop(),
+        Some(ChatEvent::RoomModeration(notice))
+            if notice.action == ChatModerationAction::Kick && notice.user_id == "u2"
+    ));
+    assert!(bob_outbound.try_pop().is_none());
+    assert!(matches!(alice_outbound.try_pop(), Some(ChatEvent::RoomModeration(_))));
+    assert!(matches!(
+        alice_outbound.try_pop(),
+        Some(ChatEvent::PresenceChanged(presence)) if presence.left_members.len() == 1
+    ));
+    assert_eq!(
+        chat_state
+            .process_message(
+                &bob,
+                ChatCommand::SendRoomMessage {
+                    room_id: "lobby".to_string(),
+                    content: "still here?".to_string(),
+                    reply_to: None,
+                },
+            )
+            .await,
+        Err(ChatError::NotInRoom { room_id: "lobby".to_string() })
+    );
+}
+
+#[tokio::test]
+async fn room_history_is_forbidden_without_an_invite() {
+    let chat_state = ChatState::default();
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::CreateRoom { room_id: "staff".to_string(), private: true },
+        )
+        .await
+        .expect("alice creates staff");
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::JoinRoom { room_id: "staff".to_string(), since_version: None },
+        )
+        .await
+        .expect("alice joins staff");
+
+    chat_state
+        .room_history(&alice.user_id, "staff", None, None)
+        .await
+        .expect("the moderator reads staff history");
+    let error = chat_state
+        .room_history("u2", "staff", None, None)
+        .await
+        .expect_err("an uninvited user should not read staff history");
+    assert_eq!(error, ChatError::InviteRequired);
+    assert_eq!(chat_error_response(error).status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn room_history_is_forbidden_to_banned_users() {
+    let chat_state = ChatState::default();
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    let (bob, _) = chat_state.register_connection("u2", "bob").await;
+    for user in [&alice, &bob] {
+        chat_state
+            .process_message(
+                user,
+                ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+    }
+    chat_state
+        .room_history(&bob.user_id, "lobby", None, None)
+        .await
+        .expect("bob reads lobby history");
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::BanMember { room_id: "lobby".to_string(), user_id: "u2".to_string() },
+        )
+        .await
+        .expect("owner bans bob");
+
+    let error = chat_state
+        .room_history(&bob.user_id, "lobby", None, None)
+        .await
+        .expect_err("a banned user should not read lobby history");
+    assert_eq!(error, ChatError::BannedFromRoom);
+    assert_eq!(chat_error_response(error).status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,106 @@
+use futures_util::FutureExt;
+
+use crate::{
+    config::SlowConsumerPolicy,
+    handlers::chat::{
+        ChatEmptyPayload, ChatEvent, ChatPresenceChange, ChatUserSummary,
+        outbound::{ChatCloseReason, ChatOutbound, PushOutcome, merge_presence},
+        presence::ChatPresenceStatus,
+    },
+    tests::chat_support::drain,
+};
+
+fn member(id: &str) -> ChatUserSummary {
+    ChatUserSummary {
+        user_id: id.to_string(),
+        user_name: id.to_string(),
+        status: ChatPresenceStatus::Online,
+    }
+}
+
+fn presence(room_id: &str, joined: &[&str], left: &[&str], version: u64) -> ChatPresenceChange {
+    ChatPresenceChange {
+        room_id: room_id.to_string(),
+        joined_members: joined.iter().map(|id| member(id)).collect(),
+        left_members: left.iter().map(|id| member(id)).collect(),
+        version,
+    }
+}
+
+fn pong() -> ChatEvent {
+    ChatEvent::Pong(ChatEmptyPayload::default())
+}
+
+#[test]
+fn drop_oldest_keeps_the_newest_events() {
+    let outbound = ChatOutbound::new(2, SlowConsumerPolicy::DropOldest);
+
+    assert_eq!(
+        outbound.push(ChatEvent::PresenceChanged(presence("lobby", &["u1"], &[], 1))),
+        PushOutcome::Queued
+    );
+    assert_eq!(outbound.push(pong()), PushOutcome::Queued);
+    assert_eq!(
+        outbound.push(ChatEvent::PresenceChanged(presence("lobby", &["u2"], &[], 2))),
+        PushOutcome::DroppedOldest
+    );
+
+    assert_eq!(outbound.depth(), 2);
+    assert_eq!(
+        drain(&outbound),
+        vec![pong(), ChatEvent::PresenceChanged(presence("lobby", &["u2"], &[], 2))]
+    );
+}
+
+#[test]
+fn coalesce_presence_merges_updates_for_the_same_room() {
+    let outbound = ChatOutbound::new(2, SlowConsumerPolicy::CoalescePresence);
+    outbound.push(ChatEvent::PresenceChanged(presence("lobby", &["u1"], &[], 1)));
+    outbound.push(pong());
+
+    assert_eq!(
+        outbound.push(ChatEvent::PresenceChanged(presence("lobby", &["u2"], &[], 2))),
+        PushOutcome::Coalesced
+    );
+    assert_eq!(
+        outbound.push(ChatEvent::PresenceChanged(presence("dev", &["u3"], &[], 1))),
+        PushOutcome::DroppedOldest
+    );
+
+    assert_eq!(
+        drain(&outbound),
+        vec![pong(), ChatEvent::PresenceChanged(presence("dev", &["u3"], &[], 1))]
+    );
+}
+
+#[test]
+fn merged_presence_cancels_join_then_leave() {
+    let mut merged = presence("lobby", &["u1", "u2"], &[], 3);
+    merge_presence(&mut merged, presence("lobby", &[], &["u1", "u3"], 5));
+
+    assert_eq!(merged, presence("lobby", &["u2"], &["u3"], 5));
+}
+
+#[test]
+fn disconnect_policy_closes_the_queue_on_overflow() {
+    let outbound = ChatOutbound::new(1, SlowConsumerPolicy::Disconnect);
+    outbound.push(pong());
+
+    assert_eq!(outbound.push(pong()), PushOutcome::Overflowed);
+    assert_eq!(outbound.push(pong()), PushOutcome::Closed);
+    assert_eq!(outbound.depth(), 0);
+    assert_eq!(outbound.close_reason(), Some(ChatCloseReason::SlowConsumer));
+    assert_eq!(ChatCloseReason::SlowConsumer.code(), 4008);
+    assert!(outbound.closed().now_or_never().is_some());
+}
+
+#[test]
+fn closed_queue_drains_before_ending() {
+    let outbound = ChatOutbound::new(4, SlowConsumerPolicy::DropOldest);
+    outbound.push(pong());
+    outbound.close(Some(ChatCloseReason::SessionExpired));
+
+    assert_eq!(outbound.pop().now_or_never(), Some(Some(pong())));
+    assert_eq!(outbound.pop().now_or_never(), Some(None));
+    assert_eq!(outbound.close_reason(), Some(ChatCloseReason::SessionExpired));
+}
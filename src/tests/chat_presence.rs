@@ -0,0 +1,183 @@
+use serde_json::json;
+
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatEvent, ChatHub, ChatState, ChatUserSummary,
+        presence::{ChatPresenceStatus, ChatStatusChange},
+        sync::{ChatRoomSync, StoredRoomEvent},
+    },
+    tests::chat_support::{join, session_tab},
+};
+
+#[test]
+fn second_tab_joins_and_leaves_without_presence_changes() {
+    let mut hub = ChatHub::default();
+    let bob = session_tab("u2", 1);
+    hub.join_room(bob.clone(), "lobby").expect("bob joins");
+    let first = hub
+        .join_room(session_tab("u1", 1), "lobby")
+        .expect("alice's first tab joins");
+    let second = hub
+        .join_room(session_tab("u1", 2), "lobby")
+        .expect("alice's second tab joins");
+
+    assert!(first.joined_newly);
+    assert!(!second.joined_newly);
+    assert_eq!(second.version, first.version);
+    assert_eq!(
+        second
+            .members
+            .iter()
+            .map(|member| member.user_id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["u1", "u2"]
+    );
+
+    let (notice, presence, _) = hub
+        .leave_room(&session_tab("u1", 1).connection_id, "lobby")
+        .expect("first tab leaves");
+    assert!(presence.is_none());
+    assert_eq!(notice.version, first.version);
+    let snapshot = hub
+        .sync_room_state(&bob.connection_id, "lobby")
+        .expect("bob still syncs");
+    assert_eq!(snapshot.members.len(), 2);
+
+    let changes = hub.disconnect(&session_tab("u1", 2).connection_id);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].left_members[0].user_id, "u1");
+    assert_eq!(changes[0].version, first.version + 1);
+}
+
+#[test]
+fn removing_a_user_lists_them_once() {
+    let mut hub = ChatHub::default();
+    hub.join_room(session_tab("u1", 1), "lobby")
+        .expect("owner joins");
+    hub.join_room(session_tab("u2", 1), "lobby")
+        .expect("bob joins");
+    hub.join_room(session_tab("u2", 2), "lobby")
+        .expect("bob's second tab joins");
+
+    let (presence, connection_ids) = hub
+        .remove_user("lobby", "u2")
+        .expect("bob should be removed");
+    assert_eq!(presence.left_members.len(), 1);
+    assert_eq!(connection_ids.len(), 2);
+}
+
+#[test]
+fn status_changes_are_versioned_and_apply_to_later_joins() {
+    let mut hub = ChatHub::default();
+    let alice = session_tab("u1", 1);
+    let joined = hub.join_room(alice.clone(), "lobby").expect("alice joins");
+
+    let changes = hub.set_status("u1", ChatPresenceStatus::Away);
+    assert_eq!(
+        changes,
+        vec![ChatStatusChange {
+            room_id: "lobby".to_string(),
+            user_id: "u1".to_string(),
+            status: ChatPresenceStatus::Away,
+            version: joined.version + 1,
+        }]
+    );
+    assert!(hub.set_status("u1", ChatPresenceStatus::Away).is_empty());
+
+    let games = hub
+        .join_room(alice.clone(), "games")
+        .expect("alice joins games");
+    assert_eq!(games.members[0].status, ChatPresenceStatus::Away);
+
+    let sync = hub
+        .room_sync(&alice.connection_id, "lobby", Some(joined.version))
+        .expect("delta should be available");
+    assert!(matches!(
+        sync,
+        ChatRoomSync::Delta(delta)
+            if matches!(delta.events.as_slice(), [ChatEvent::StatusChanged(change)]
+                if change.status == ChatPresenceStatus::Away)
+    ));
+}
+
+#[test]
+fn set_status_command_and_stored_status_event_parse() {
+    let command: ChatCommand = serde_json::from_value(json!({
+        "type": "set_status",
+        "payload": { "status": "away" }
+    }))
+    .expect("set_status should deserialize");
+    assert_eq!(command, ChatCommand::SetStatus { status: ChatPresenceStatus::Away });
+    assert_eq!(command.event_type(), "set_status");
+
+    let stored: StoredRoomEvent = serde_json::from_value(json!({
+        "version": 9,
+        "status": { "room_id": "lobby", "user_id": "u1", "status": "offline", "version": 9 }
+    }))
+    .expect("stored status event should deserialize");
+    assert!(matches!(
+        stored.into_event("lobby"),
+        (9, ChatEvent::StatusChanged(change)) if change.status == ChatPresenceStatus::Offline
+    ));
+
+    let member: ChatUserSummary =
+        serde_json::from_value(json!({ "user_id": "u1", "user_name": "alice" }))
+            .expect("summaries without a status should deserialize");
+    assert_eq!(member.status, ChatPresenceStatus::Online);
+}
+
+#[tokio::test]
+async fn peers_see_status_and_presence_per_user() {
+    let chat_state = ChatState::default();
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    let (alice_one, _) = chat_state.register_connection("u1", "alice").await;
+    let (alice_two, alice_two_outbound) = chat_state.register_connection("u1", "alice").await;
+    for user in [&bob, &alice_one, &alice_two] {
+        chat_state
+            .process_message(user, join("lobby"))
+            .await
+            .expect("join should succeed");
+    }
+    let joined = std::iter::from_fn(|| bob_outbound.try_pop())
+        .filter(|event| matches!(event, ChatEvent::PresenceChanged(_)))
+        .count();
+    assert_eq!(joined, 1);
+
+    chat_state
+        .process_message(&alice_one, ChatCommand::SetStatus { status: ChatPresenceStatus::Away })
+        .await
+        .expect("status should be set");
+    assert!(matches!(
+        bob_outbound.try_pop(),
+        Some(ChatEvent::StatusChanged(change))
+            if change.user_id == "u1" && change.status == ChatPresenceStatus::Away
+    ));
+    assert!(
+        std::iter::from_fn(|| alice_two_outbound.try_pop())
+            .any(|event| matches!(event, ChatEvent::UserStatus(update)
+                if update.status == ChatPresenceStatus::Away))
+    );
+
+    chat_state
+        .unregister_connection(&alice_one.connection_id)
+        .await;
+    assert!(bob_outbound.try_pop().is_none());
+    chat_state
+        .unregister_connection(&alice_two.connection_id)
+        .await;
+    assert!(matches!(
+        bob_outbound.try_pop(),
+        Some(ChatEvent::PresenceChanged(presence)) if presence.left_members[0].user_id == "u1"
+    ));
+
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    chat_state
+        .process_message(&alice, join("lobby"))
+        .await
+        .expect("alice rejoins");
+    assert!(matches!(
+        bob_outbound.try_pop(),
+        Some(ChatEvent::PresenceChanged(presence))
+            if presence.joined_members[0].status == ChatPresenceStatus::Online
+    ));
+}
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::{
+    config::{ChatConfig, ChatRateLimit, ChatRateLimitConfig},
+    handlers::chat::{ChatCommand, ChatEmptyPayload, ChatError, ChatEvent, ChatState},
+};
+
+fn config(messages: ChatRateLimit) -> ChatConfig {
+    ChatConfig {
+        rate_limit: ChatRateLimitConfig {
+            messages,
+            max_violations: 3,
+            ..ChatRateLimitConfig::default()
+        },
+        ..ChatConfig::default()
+    }
+}
+
+fn send() -> ChatCommand {
+    ChatCommand::SendRoomMessage {
+        room_id: "lobby".to_string(),
+        content: "hi".to_string(),
+        reply_to: None,
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn connection_bucket_refills_over_time() {
+    let chat_state = ChatState::default().with_config(config(ChatRateLimit {
+        connection_burst: 2,
+        connection_per_sec: 1.0,
+        user_burst: 100,
+        user_per_sec: 100.0,
+    }));
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    let mut limits = chat_state.rate_limits(&alice);
+
+    assert!(limits.check(&send()).is_ok());
+    assert!(limits.check(&send()).is_ok());
+    assert_eq!(limits.check(&send()), Err(ChatError::RateLimited { retry_after_ms: 1000 }));
+    assert!(
+        limits
+            .check(&ChatCommand::Ping(ChatEmptyPayload::default()))
+            .is_ok()
+    );
+
+    tokio::time::advance(Duration::from_millis(500)).await;
+    assert_eq!(limits.check(&send()), Err(ChatError::RateLimited { retry_after_ms: 500 }));
+    tokio::time::advance(Duration::from_millis(500)).await;
+    assert!(limits.check(&send()).is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn tiny_refill_rates_wait_forever_instead_of_overflowing() {
+    let chat_state = ChatState::default().with_config(config(ChatRateLimit {
+        connection_burst: 1,
+        connection_per_sec: 1e-20,
+        user_burst: 100,
+        user_per_sec: 100.0,
+    }));
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    let mut limits = chat_state.rate_limits(&alice);
+
+    assert!(limits.check(&send()).is_ok());
+    assert_eq!(limits.check(&send()), Err(ChatError::RateLimited { retry_after_ms: u64::MAX }));
+}
+
+#[tokio::test(start_paused = true)]
+async fn user_bucket_is_shared_by_connections() {
+    let chat_state = ChatState::default().with_config(config(ChatRateLimit {
+        connection_burst: 100,
+        connection_per_sec: 100.0,
+        user_burst: 2,
+        user_per_sec: 1.0,
+    }));
+    let (first, _) = chat_state.register_connection("u1", "alice").await;
+    let (second, _) = chat_state.register_connection("u1", "alice").await;
+    let (bob, _) = chat_state.register_connection("u2", "bob").await;
+    let mut first = chat_state.rate_limits(&first);
+    let mut second = chat_state.rate_limits(&second);
+
+    assert!(first.check(&send()).is_ok());
+    assert!(second.check(&send()).is_ok());
+    assert!(matches!(first.check(&send()), Err(ChatError::RateLimited { .. })));
+    assert!(chat_state.rate_limits(&bob).check(&send()).is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn repeated_violations_exhaust_the_connection() {
+    let chat_state = ChatState::default().with_config(config(ChatRateLimit {
+        connection_burst: 1,
+        connection_per_sec: 1.0,
+        user_burst: 0,
+        user_per_sec: 0.0,
+    }));
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    let mut limits = chat_state.rate_limits(&alice);
+
+    assert!(limits.check(&send()).is_ok());
+    for _ in 0..2 {
+        assert!(limits.check(&send()).is_err());
+    }
+    assert!(!limits.exhausted());
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert!(limits.check(&send()).is_ok());
+    for _ in 0..3 {
+        assert!(limits.check(&send()).is_err());
+    }
+    assert!(limits.exhausted());
+}
+
+#[test]
+fn rate_limited_error_carries_retry_after() {
+    let event = serde_json::to_value(ChatError::RateLimited { retry_after_ms: 250 }.to_event())
+        .expect("event should serialize");
+    assert_eq!(
+        event,
+        json!({
+            "type": "error",
+            "payload": {
+                "code": "rate_limited",
+                "message": "too many commands, retry in 250 ms",
+                "retry_after_ms": 250
+            }
+        })
+    );
+
+    let ChatEvent::Error(payload) = ChatError::EmptyContent.to_event() else {
+        panic!("errors become error events");
+    };
+    assert_eq!(payload.retry_after_ms, None);
+}
@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatError, ChatEvent, ChatHub, ChatState,
+        backplane::read_marks,
+        receipts::{ChatReadMark, ChatReadPosition, ChatReadReceipt, TYPING_THROTTLE},
+    },
+    tests::chat_support::session_user,
+};
+
+fn position(user_id: &str, version: u64) -> ChatReadPosition {
+    ChatReadPosition { user_id: user_id.to_string(), version }
+}
+
+#[test]
+fn typing_notices_are_throttled_per_connection() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    let bob = session_user("u2", "bob");
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice joins lobby");
+    hub.join_room(bob.clone(), "lobby")
+        .expect("bob joins lobby");
+    let now = Instant::now();
+
+    let notice = hub
+        .start_typing(&alice.connection_id, "lobby", now)
+        .expect("member can type")
+        .expect("first notice should be broadcast");
+    assert_eq!(notice.user_id, "u1");
+    assert_eq!(notice.expires_in_ms, 6_000);
+
+    assert_eq!(
+        hub.start_typing(&alice.connection_id, "lobby", now + Duration::from_secs(1)),
+        Ok(None)
+    );
+    assert!(
+        hub.start_typing(&bob.connection_id, "lobby", now + Duration::from_secs(1))
+            .expect("member can type")
+            .is_some()
+    );
+    assert!(
+        hub.start_typing(&alice.connection_id, "lobby", now + TYPING_THROTTLE)
+            .expect("member can type")
+            .is_some()
+    );
+
+    hub.send_room_message(&alice.connection_id, "lobby", "hi")
+        .expect("send should succeed");
+    assert!(
+        hub.start_typing(&alice.connection_id, "lobby", now + TYPING_THROTTLE)
+            .expect("member can type")
+            .is_some(),
+        "sending a message should reset the throttle"
+    );
+    assert_eq!(
+        hub.start_typing("conn-u3", "lobby", now),
+        Err(ChatError::NotInRoom { room_id: "lobby".to_string() })
+    );
+}
+
+#[test]
+fn read_positions_only_move_forward_and_drive_unread_counts() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    let bob = session_user("u2", "bob");
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice joins lobby");
+    hub.join_room(bob.clone(), "lobby")
+        .expect("bob joins lobby");
+    for content in ["one", "two", "three"] {
+        hub.send_room_message(&alice.connection_id, "lobby", content)
+            .expect("send should succeed");
+    }
+
+    let snapshot = hub
+        .sync_room_state(&bob.connection_id, "lobby")
+        .expect("bob is a member");
+    assert_eq!(snapshot.version, 5);
+    assert_eq!(snapshot.unread_count, 3);
+    assert_eq!(snapshot.read_positions, vec![position("u1", 5), position("u2", 2)]);
+
+    let receipt = hub
+        .mark_read(&bob.connection_id, "lobby", 4)
+        .expect("bob is a member");
+    assert_eq!(
+        receipt,
+        Some(ChatReadReceipt {
+            room_id: "lobby".to_string(),
+            user_id: "u2".to_string(),
+            version: 4
+        })
+    );
+    assert_eq!(hub.mark_read(&bob.connection_id, "lobby", 3), Ok(None));
+    assert_eq!(
+        hub.sync_room_state(&bob.connection_id, "lobby")
+            .expect("bob is a member")
+            .unread_count,
+        1
+    );
+
+    let receipt = hub
+        .mark_read(&bob.connection_id, "lobby", 99)
+        .expect("bob is a member")
+        .expect("read position should advance");
+    assert_eq!(receipt.version, 5, "positions are clamped to the room version");
+    assert_eq!(
+        hub.sync_room_state(&bob.connection_id, "lobby")
+            .expect("bob is a member")
+            .unread_count,
+        0
+    );
+}
+
+#[test]
+fn rejoining_member_sees_messages_sent_while_away() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    let bob = session_user("u2", "bob");
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice joins lobby");
+    hub.join_room(bob.clone(), "lobby")
+        .expect("bob joins lobby");
+    hub.leave_room(&bob.connection_id, "lobby")
+        .expect("bob leaves lobby");
+    hub.send_room_message(&alice.connection_id, "lobby", "missed you")
+        .expect("send should succeed");
+
+    let joined = hub
+        .join_room(bob.clone(), "lobby")
+        .expect("bob rejoins lobby");
+    assert_eq!(joined.unread_count, 1);
+    assert_eq!(joined.read_positions, vec![position("u1", 4), position("u2", 2)]);
+}
+
+#[tokio::test]
+async fn typing_and_read_receipts_reach_peers_but_not_the_sender() {
+    let chat_state = ChatState::default();
+    let (alice, alice_outbound) = chat_state.register_connection("u1", "alice").await;
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    for user in [&alice, &bob] {
+        chat_state
+            .process_message(
+                user,
+                ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+    }
+    while alice_outbound.try_pop().is_some() {}
+    while bob_outbound.try_pop().is_some() {}
+
+    chat_state
+        .process_message(&alice, ChatCommand::Typing { room_id: "lobby".to_string() })
+        .await
+        .expect("typing should succeed");
+    chat_state
+        .process_message(&alice, ChatCommand::MarkRead { room_id: "lobby".to_string(), version: 2 })
+        .await
+        .expect("mark read should succeed");
+
+    assert!(alice_outbound.try_pop().is_none());
+    assert!(
+        matches!(bob_outbound.try_pop(), Some(ChatEvent::Typing(notice)) if notice.user_id == "u1")
+    );
+    assert_eq!(
+        bob_outbound.try_pop(),
+        Some(ChatEvent::ReadReceipt(ChatReadReceipt {
+            room_id: "lobby".to_string(),
+            user_id: "u1".to_string(),
+            version: 2,
+        }))
+    );
+}
+
+#[test]
+fn stored_read_marks_skip_malformed_entries() {
+    let fields = ["u1", "7:3", "u2", "garbage", "u3", "9:4"].map(str::to_string);
+
+    let marks = read_marks(&fields);
+
+    assert_eq!(marks.len(), 2);
+    assert_eq!(marks["u1"], ChatReadMark { version: 7, message_count: 3 });
+    assert_eq!(marks["u3"], ChatReadMark { version: 9, message_count: 4 });
+}
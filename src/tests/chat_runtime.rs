@@ -0,0 +1,74 @@
+use crate::{
+    config::ChatConfig,
+    handlers::chat::{ChatCommand, ChatEvent, ChatState},
+    tests::chat_support::join,
+};
+
+#[tokio::test]
+async fn rooms_on_different_shards_share_connections() {
+    let chat_state =
+        ChatState::default().with_config(ChatConfig { runtime_shards: 4, ..ChatConfig::default() });
+    let (alice, alice_outbound) = chat_state.register_connection("u1", "alice").await;
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    let rooms = (0..12)
+        .map(|room| format!("room-{room}"))
+        .collect::<Vec<_>>();
+    for room_id in &rooms {
+        for user in [&alice, &bob] {
+            chat_state
+                .process_message(user, join(room_id))
+                .await
+                .expect("join should succeed");
+        }
+    }
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::SendRoomMessage {
+                room_id: " room-7 ".to_string(),
+                content: "hi".to_string(),
+                reply_to: None,
+            },
+        )
+        .await
+        .expect("send should reach the same shard as the join");
+    while alice_outbound.try_pop().is_some() {}
+    assert!(matches!(
+        std::iter::from_fn(|| bob_outbound.try_pop()).last(),
+        Some(ChatEvent::RoomMessage(message)) if message.room_id == "room-7"
+    ));
+
+    chat_state.unregister_connection(&bob.connection_id).await;
+    let mut left_rooms = std::iter::from_fn(|| alice_outbound.try_pop())
+        .filter_map(|event| match event {
+            ChatEvent::PresenceChanged(presence) if presence.left_members.len() == 1 => {
+                Some(presence.room_id)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    left_rooms.sort();
+    let mut expected = rooms;
+    expected.sort();
+    assert_eq!(left_rooms, expected);
+}
+
+#[tokio::test]
+async fn a_single_shard_still_serves_every_room() {
+    let chat_state =
+        ChatState::default().with_config(ChatConfig { runtime_shards: 0, ..ChatConfig::default() });
+    let (alice, alice_outbound) = chat_state.register_connection("u1", "alice").await;
+    for room_id in ["lobby", "games"] {
+        chat_state
+            .process_message(&alice, join(room_id))
+            .await
+            .expect("join should succeed");
+    }
+
+    assert_eq!(
+        std::iter::from_fn(|| alice_outbound.try_pop())
+            .filter(|event| matches!(event, ChatEvent::JoinedRoom(_)))
+            .count(),
+        2
+    );
+}
@@ -0,0 +1,128 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::{StreamExt, stream};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use tonic::{
+    Code, Request,
+    transport::{Endpoint, Server, server::TcpIncoming},
+};
+
+use crate::{
+    config::ChatConfig,
+    grpc::{
+        chat::{CommandFrame, chat_client::ChatClient, event_frame::Event},
+        chat_impl::{self, close_status},
+    },
+    handlers::chat::{ChatError, ChatState, outbound::ChatCloseReason},
+    tests::chat_support::bearer,
+};
+
+fn connect_request(user_id: &str) -> Request<stream::Pending<CommandFrame>> {
+    let mut request = Request::new(stream::pending::<CommandFrame>());
+    request.metadata_mut().insert(
+        "authorization",
+        bearer(user_id)
+            .parse()
+            .expect("token should be valid metadata"),
+    );
+    request
+}
+
+#[test]
+fn shutdown_closes_with_going_away_and_a_reconnect_hint() {
+    assert_eq!(ChatCloseReason::GoingAway.code(), 1001);
+    assert_eq!(close_status(ChatCloseReason::GoingAway).code(), Code::Unavailable);
+    assert_eq!(ChatError::ShuttingDown.code(), "shutting_down");
+
+    let chat_state = ChatState::default()
+        .with_config(ChatConfig { shutdown_reconnect_after_ms: 2500, ..ChatConfig::default() });
+    assert_eq!(
+        serde_json::to_value(chat_state.shutdown_notice()).expect("notice should serialize"),
+        json!({ "type": "server_shutdown", "payload": { "reconnect_after_ms": 2500 } })
+    );
+}
+
+#[tokio::test]
+async fn sessions_flush_a_notice_and_close_on_shutdown() {
+    let chat_state = Arc::new(ChatState::default());
+    let incoming = TcpIncoming::bind("127.0.0.1:0".parse().expect("address should parse"))
+        .expect("listener should bind");
+    let addr = incoming.local_addr().expect("listener has an address");
+    tokio::spawn(
+        Server::builder()
+            .add_service(chat_impl::router(chat_state.clone()))
+            .serve_with_incoming(incoming),
+    );
+    let channel = Endpoint::from_shared(format!("http://{addr}"))
+        .expect("endpoint should parse")
+        .connect()
+        .await
+        .expect("client should connect");
+    let mut client = ChatClient::new(channel);
+
+    let mut events = client
+        .connect(connect_request("u1"))
+        .await
+        .expect("connect should succeed")
+        .into_inner();
+    let mut welcome = Vec::new();
+    for _ in 0..2 {
+        let event = events
+            .next()
+            .await
+            .expect("stream should stay open")
+            .expect("event should arrive");
+        welcome.push(event.event);
+    }
+    assert!(matches!(
+        welcome.as_slice(),
+        [Some(Event::Connected(_)), Some(Event::DirectUnread(_))]
+    ));
+
+    let token = CancellationToken::new();
+    token.cancel();
+    tokio::time::timeout(Duration::from_secs(5), chat_state.drain_on_shutdown(token))
+        .await
+        .expect("sessions should drain before the window");
+    assert!(chat_state.is_shutting_down());
+
+    let notice = events
+        .next()
+        .await
+        .expect("stream should stay open")
+        .expect("notice should arrive");
+    assert!(matches!(
+        notice.event,
+        Some(Event::ServerShutdown(notice)) if notice.reconnect_after_ms > 0
+    ));
+    let status = events
+        .next()
+        .await
+        .expect("stream should end with a status")
+        .expect_err("stream should end with an error status");
+    assert_eq!(status.code(), Code::Unavailable);
+
+    let refused = client
+        .connect(connect_request("u2"))
+        .await
+        .expect_err("connects during shutdown should be refused");
+    assert_eq!(refused.code(), Code::Unavailable);
+}
+
+#[tokio::test(start_paused = true)]
+async fn sessions_left_after_the_drain_window_are_closed() {
+    let chat_state = ChatState::default()
+        .with_config(ChatConfig { shutdown_drain_secs: 3, ..ChatConfig::default() });
+    let (_, outbound) = chat_state.register_connection("u1", "alice").await;
+    // A session that ignores the shutdown signal.
+    let stuck = chat_state.track_session(std::future::pending::<()>());
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let started = tokio::time::Instant::now();
+    chat_state.drain_on_shutdown(token).await;
+    assert_eq!(started.elapsed(), Duration::from_secs(3));
+    assert_eq!(outbound.close_reason(), Some(ChatCloseReason::GoingAway));
+    drop(stuck);
+}
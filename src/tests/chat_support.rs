@@ -0,0 +1,69 @@
+//! Fixtures shared by the chat tests.
+
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatEvent, ChatHub, ChatRoomMessage, ChatSessionUser, outbound::ChatOutbound,
+    },
+    utils::jwt_auth::{self, Claims},
+};
+
+/// A user on a single connection, `conn-{id}`.
+pub fn session_user(id: &str, name: &str) -> ChatSessionUser {
+    ChatSessionUser {
+        connection_id: format!("conn-{id}"),
+        user_id: id.to_string(),
+        user_name: name.to_string(),
+    }
+}
+
+/// One of several connections of a user, `conn-{id}-{tab}`, named after the user.
+pub fn session_tab(id: &str, tab: u32) -> ChatSessionUser {
+    ChatSessionUser {
+        connection_id: format!("conn-{id}-{tab}"),
+        user_id: id.to_string(),
+        user_name: id.to_string(),
+    }
+}
+
+/// Events queued for a connection, in delivery order.
+pub fn drain(outbound: &ChatOutbound) -> Vec<ChatEvent> {
+    std::iter::from_fn(|| outbound.try_pop()).collect()
+}
+
+/// A hub with alice and bob in the lobby, so alice owns it.
+pub fn lobby() -> (ChatHub, ChatSessionUser, ChatSessionUser) {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    let bob = session_user("u2", "bob");
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice joins lobby");
+    hub.join_room(bob.clone(), "lobby")
+        .expect("bob joins lobby");
+    (hub, alice, bob)
+}
+
+/// The lobby with one message from alice at version 3.
+pub fn lobby_with_message() -> (ChatHub, ChatSessionUser, ChatSessionUser, ChatRoomMessage) {
+    let (mut hub, alice, bob) = lobby();
+    let sent = hub
+        .send_room_message(&alice.connection_id, "lobby", "helo")
+        .expect("send should succeed");
+    (hub, alice, bob, sent.message)
+}
+
+pub fn join(room_id: &str) -> ChatCommand {
+    ChatCommand::JoinRoom { room_id: room_id.to_string(), since_version: None }
+}
+
+/// An `authorization` value with a token for `user_id`, valid for five minutes.
+pub fn bearer(user_id: &str) -> String {
+    let claims = Claims {
+        sub: user_id.to_string(),
+        company: "axes".to_string(),
+        exp: (chrono::Utc::now().timestamp() + 300) as u64,
+    };
+    let token =
+        jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &jwt_auth::keys().encoding)
+            .expect("token should encode");
+    format!("Bearer {token}")
+}
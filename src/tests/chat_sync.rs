@@ -0,0 +1,213 @@
+use serde_json::json;
+
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatEvent, ChatHub, ChatPresenceChange, ChatState, ChatUserSummary,
+        presence::ChatPresenceStatus,
+        sync::{ChatRoomEventLog, ChatRoomSync, MAX_ROOM_EVENTS, StoredRoomEvent},
+    },
+    tests::chat_support::session_user,
+};
+
+fn member(id: &str, name: &str) -> ChatUserSummary {
+    ChatUserSummary {
+        user_id: id.to_string(),
+        user_name: name.to_string(),
+        status: ChatPresenceStatus::Online,
+    }
+}
+
+fn event_versions(events: &[ChatEvent]) -> Vec<u64> {
+    events
+        .iter()
+        .map(|event| match event {
+            ChatEvent::RoomMessage(message) => message.version,
+            ChatEvent::PresenceChanged(presence) => presence.version,
+            event => panic!("unexpected event in delta: {event:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn sync_commands_default_since_version() {
+    let command: ChatCommand = serde_json::from_value(json!({
+        "type": "sync_room_state",
+        "payload": { "room_id": "lobby" }
+    }))
+    .expect("sync_room_state should deserialize");
+    assert_eq!(
+        command,
+        ChatCommand::SyncRoomState { room_id: "lobby".to_string(), since_version: None }
+    );
+
+    let command: ChatCommand = serde_json::from_value(json!({
+        "type": "join_room",
+        "payload": { "room_id": "lobby", "since_version": 4 }
+    }))
+    .expect("join_room should deserialize");
+    assert_eq!(
+        command,
+        ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: Some(4) }
+    );
+}
+
+#[test]
+fn sync_returns_messages_and_presence_after_since_version() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    let bob = session_user("u2", "bob");
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice joins lobby");
+    hub.join_room(bob.clone(), "lobby")
+        .expect("bob joins lobby");
+    hub.send_room_message(&alice.connection_id, "lobby", "hello")
+        .expect("send should succeed");
+    hub.leave_room(&bob.connection_id, "lobby")
+        .expect("bob leaves lobby");
+
+    let ChatRoomSync::Delta(delta) = hub
+        .room_sync(&alice.connection_id, "lobby", Some(1))
+        .expect("alice is a member")
+    else {
+        panic!("delta should be available");
+    };
+    assert_eq!(delta.since_version, 1);
+    assert_eq!(delta.version, 4);
+    assert_eq!(event_versions(&delta.events), vec![2, 3, 4]);
+    assert_eq!(
+        delta.events[2],
+        ChatEvent::PresenceChanged(ChatPresenceChange {
+            room_id: "lobby".to_string(),
+            joined_members: vec![],
+            left_members: vec![member("u2", "bob")],
+            version: 4,
+        })
+    );
+
+    let ChatRoomSync::Delta(delta) = hub
+        .room_sync(&alice.connection_id, "lobby", Some(4))
+        .expect("alice is a member")
+    else {
+        panic!("delta should be available");
+    };
+    assert!(delta.events.is_empty());
+}
+
+#[test]
+fn sync_falls_back_to_flagged_snapshot_when_events_are_gone() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice joins lobby");
+    for index in 0..MAX_ROOM_EVENTS {
+        hub.send_room_message(&alice.connection_id, "lobby", &format!("message {index}"))
+            .expect("send should succeed");
+    }
+
+    let ChatRoomSync::Snapshot(snapshot) = hub
+        .room_sync(&alice.connection_id, "lobby", Some(0))
+        .expect("alice is a member")
+    else {
+        panic!("evicted events should force a snapshot");
+    };
+    assert!(snapshot.delta_unavailable);
+    assert_eq!(snapshot.version, MAX_ROOM_EVENTS as u64 + 1);
+
+    assert!(matches!(
+        hub.room_sync(&alice.connection_id, "lobby", Some(1)),
+        Ok(ChatRoomSync::Delta(delta)) if delta.events.len() == MAX_ROOM_EVENTS
+    ));
+    assert!(matches!(
+        hub.room_sync(&alice.connection_id, "lobby", Some(500)),
+        Ok(ChatRoomSync::Snapshot(snapshot)) if snapshot.delta_unavailable
+    ));
+    assert!(matches!(
+        hub.room_sync(&alice.connection_id, "lobby", None),
+        Ok(ChatRoomSync::Snapshot(snapshot)) if !snapshot.delta_unavailable
+    ));
+}
+
+#[test]
+fn recreated_room_cannot_replay_versions_from_before() {
+    let mut hub = ChatHub::default();
+    let alice = session_user("u1", "alice");
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice joins lobby");
+    hub.disconnect(&alice.connection_id);
+    hub.join_room(alice.clone(), "lobby")
+        .expect("alice rejoins lobby");
+
+    assert!(matches!(
+        hub.room_sync(&alice.connection_id, "lobby", Some(1)),
+        Ok(ChatRoomSync::Snapshot(snapshot)) if snapshot.delta_unavailable && snapshot.version == 3
+    ));
+    assert!(matches!(
+        hub.room_sync(&alice.connection_id, "lobby", Some(2)),
+        Ok(ChatRoomSync::Delta(delta)) if event_versions(&delta.events) == vec![3]
+    ));
+}
+
+#[test]
+fn stored_log_entries_rebuild_replayable_events() {
+    let entries = [
+        r#"{"version":5,"joined":[{"user_id":"u2","user_name":"bob"}]}"#,
+        r#"{"version":6,"message":{"room_id":"lobby","message_id":"m1","sender_id":"u2","sender_name":"bob","content":"hi","sent_at":1,"version":6}}"#,
+    ]
+    .iter()
+    .map(|entry| {
+        serde_json::from_str::<StoredRoomEvent>(entry)
+            .expect("stored entry should decode")
+            .into_event("lobby")
+    })
+    .collect::<Vec<_>>();
+    let log = ChatRoomEventLog::from_entries(entries, 6);
+
+    assert_eq!(log.since(3, 6), None);
+    assert_eq!(log.since(4, 6).map(|events| event_versions(&events)), Some(vec![5, 6]));
+    assert_eq!(ChatRoomEventLog::from_entries(Vec::new(), 6).since(5, 6), None);
+    assert_eq!(ChatRoomEventLog::from_entries(Vec::new(), 6).since(6, 6), Some(Vec::new()));
+}
+
+#[tokio::test]
+async fn rejoin_with_since_version_replies_with_delta() {
+    let chat_state = ChatState::default();
+    let (alice, _) = chat_state.register_connection("u1", "alice").await;
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    for user in [&alice, &bob] {
+        chat_state
+            .process_message(
+                user,
+                ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+    }
+    chat_state.unregister_connection(&bob.connection_id).await;
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::SendRoomMessage {
+                room_id: "lobby".to_string(),
+                content: "while you were away".to_string(),
+                reply_to: None,
+            },
+        )
+        .await
+        .expect("send should succeed");
+    while bob_outbound.try_pop().is_some() {}
+
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    chat_state
+        .process_message(
+            &bob,
+            ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: Some(2) },
+        )
+        .await
+        .expect("rejoin should succeed");
+
+    let Some(ChatEvent::RoomDelta(delta)) = bob_outbound.try_pop() else {
+        panic!("rejoin should reply with a delta");
+    };
+    assert_eq!(delta.version, 5);
+    assert_eq!(event_versions(&delta.events), vec![3, 4, 5]);
+}
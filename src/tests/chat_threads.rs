@@ -0,0 +1,367 @@
+use serde_json::json;
+
+use crate::{
+    handlers::chat::{
+        ChatCommand, ChatError, ChatEvent, ChatHub, ChatSessionUser, ChatState,
+        edits::ChatMessageChange,
+        reactions::{MAX_REACTIONS_PER_MESSAGE, normalized_emoji},
+        sync::{ChatRoomSync, StoredRoomEvent},
+        threads::ChatReplyTarget,
+    },
+    tests::chat_support::lobby,
+};
+
+fn send(hub: &mut ChatHub, user: &ChatSessionUser, content: &str) -> String {
+    hub.send_room_message(&user.connection_id, "lobby", content)
+        .expect("message should send")
+        .message
+        .message_id
+}
+
+/// Pushes enough messages to trim everything older out of the recent window.
+fn flood(hub: &mut ChatHub, user: &ChatSessionUser) {
+    for index in 0..25 {
+        send(hub, user, &format!("filler {index}"));
+    }
+}
+
+#[test]
+fn replies_join_the_root_thread_and_outlive_the_recent_window() {
+    let (mut hub, alice, bob) = lobby();
+    let root_id = send(&mut hub, &alice, "lunch?");
+    let first = hub
+        .send_room_reply(&bob.connection_id, "lobby", "yes", ChatReplyTarget::new(&root_id))
+        .expect("reply should send");
+    assert_eq!(first.message.reply_to.as_deref(), Some(root_id.as_str()));
+    // Replying to a reply stays in the root's thread.
+    let second = hub
+        .send_room_reply(
+            &alice.connection_id,
+            "lobby",
+            "noon",
+            ChatReplyTarget::new(&first.message.message_id),
+        )
+        .expect("reply to a reply should send");
+    assert_eq!(second.message.reply_to.as_deref(), Some(root_id.as_str()));
+
+    flood(&mut hub, &alice);
+    let snapshot = hub
+        .sync_room_state(&alice.connection_id, "lobby")
+        .expect("alice syncs");
+    assert!(
+        snapshot
+            .recent_messages
+            .iter()
+            .all(|message| message.message_id != root_id)
+    );
+    assert_eq!(snapshot.threads.len(), 1);
+    assert_eq!(snapshot.threads[0].root_message_id, root_id);
+    assert_eq!(snapshot.threads[0].reply_count, 2);
+    assert_eq!(snapshot.threads[0].last_reply, second.message);
+
+    let third = hub
+        .send_room_reply(&bob.connection_id, "lobby", "see you", ChatReplyTarget::new(&root_id))
+        .expect("summarized threads take replies after their root was trimmed");
+    assert_eq!(third.message.reply_to.as_deref(), Some(root_id.as_str()));
+    let snapshot = hub
+        .sync_room_state(&bob.connection_id, "lobby")
+        .expect("bob syncs");
+    assert_eq!(snapshot.threads[0].reply_count, 3);
+}
+
+#[test]
+fn replies_need_a_live_target() {
+    let (mut hub, alice, bob) = lobby();
+    let old_id = send(&mut hub, &alice, "old news");
+    let stored = hub
+        .sync_room_state(&alice.connection_id, "lobby")
+        .expect("alice syncs")
+        .recent_messages
+        .pop()
+        .expect("message should be retained");
+    flood(&mut hub, &alice);
+
+    assert!(matches!(
+        hub.send_room_reply(&bob.connection_id, "lobby", "what?", ChatReplyTarget::new(&old_id)),
+        Err(ChatError::MessageNotFound { message_id }) if message_id == old_id
+    ));
+    let reply = hub
+        .send_room_reply(
+            &bob.connection_id,
+            "lobby",
+            "what?",
+            ChatReplyTarget { message_id: old_id.clone(), stored: Some(stored) },
+        )
+        .expect("stored targets take replies");
+    assert_eq!(reply.message.reply_to, Some(old_id));
+
+    let deleted_id = send(&mut hub, &alice, "oops");
+    hub.modify_message(
+        &alice.connection_id,
+        "lobby",
+        &deleted_id,
+        &ChatMessageChange::Delete,
+        None,
+        1_700_000_000,
+    )
+    .expect("alice deletes her message");
+    assert!(matches!(
+        hub.send_room_reply(&bob.connection_id, "lobby", "?", ChatReplyTarget::new(&deleted_id)),
+        Err(ChatError::MessageNotFound { .. })
+    ));
+}
+
+#[test]
+fn edits_of_the_last_reply_update_the_thread_summary() {
+    let (mut hub, alice, bob) = lobby();
+    let root_id = send(&mut hub, &alice, "lunch?");
+    let reply = hub
+        .send_room_reply(&bob.connection_id, "lobby", "yse", ChatReplyTarget::new(&root_id))
+        .expect("reply should send");
+    hub.modify_message(
+        &bob.connection_id,
+        "lobby",
+        &reply.message.message_id,
+        &ChatMessageChange::Edit { content: "yes".to_string() },
+        None,
+        1_700_000_000,
+    )
+    .expect("bob edits his reply");
+
+    let snapshot = hub
+        .sync_room_state(&alice.connection_id, "lobby")
+        .expect("alice syncs");
+    assert_eq!(snapshot.threads[0].last_reply.content, "yes");
+    assert_eq!(snapshot.threads[0].last_reply.edited_at, Some(1_700_000_000));
+}
+
+#[test]
+fn reactions_aggregate_per_message_as_versioned_events() {
+    let (mut hub, alice, bob) = lobby();
+    let message_id = send(&mut hub, &alice, "ship it");
+    let since = hub
+        .sync_room_state(&alice.connection_id, "lobby")
+        .expect("alice syncs")
+        .version;
+
+    let first = hub
+        .react(&alice.connection_id, "lobby", &message_id, "👍", true, None)
+        .expect("alice reacts")
+        .expect("reaction should be new");
+    assert_eq!(first.version, since + 1);
+    let second = hub
+        .react(&bob.connection_id, "lobby", &message_id, " 👍 ", true, None)
+        .expect("bob reacts")
+        .expect("reaction should be new");
+    assert_eq!(second.reactions.len(), 1);
+    assert_eq!(second.reactions[0].count, 2);
+    assert_eq!(second.reactions[0].user_ids, vec!["u1", "u2"]);
+    assert!(
+        hub.react(&bob.connection_id, "lobby", &message_id, "👍", true, None)
+            .expect("repeated reaction is fine")
+            .is_none()
+    );
+
+    hub.react(&alice.connection_id, "lobby", &message_id, "👍", false, None)
+        .expect("alice unreacts");
+    let last = hub
+        .react(&bob.connection_id, "lobby", &message_id, "👍", false, None)
+        .expect("bob unreacts")
+        .expect("reaction should be removed");
+    assert!(!last.added);
+    assert!(last.reactions.is_empty());
+
+    let ChatRoomSync::Delta(delta) = hub
+        .room_sync(&alice.connection_id, "lobby", Some(since))
+        .expect("alice syncs")
+    else {
+        panic!("reaction events should replay as a delta");
+    };
+    assert_eq!(delta.events.len(), 4);
+    assert!(
+        delta
+            .events
+            .iter()
+            .all(|event| matches!(event, ChatEvent::ReactionChanged(_)))
+    );
+    assert!(
+        hub.sync_room_state(&alice.connection_id, "lobby")
+            .expect("alice syncs")
+            .reactions
+            .is_empty()
+    );
+}
+
+#[test]
+fn reactions_outlive_the_recent_window_and_go_with_deletes() {
+    let (mut hub, alice, bob) = lobby();
+    let message_id = send(&mut hub, &alice, "ship it");
+    let unreacted_id = send(&mut hub, &alice, "no reactions yet");
+    hub.react(&alice.connection_id, "lobby", &message_id, "🚀", true, None)
+        .expect("alice reacts");
+    flood(&mut hub, &alice);
+
+    let changed = hub
+        .react(&bob.connection_id, "lobby", &message_id, "🚀", true, None)
+        .expect("reacted messages take reactions after they were trimmed")
+        .expect("reaction should be new");
+    assert_eq!(changed.reactions[0].count, 2);
+    let snapshot = hub
+        .sync_room_state(&bob.connection_id, "lobby")
+        .expect("bob syncs");
+    assert_eq!(snapshot.reactions.len(), 1);
+    assert_eq!(snapshot.reactions[0].message_id, message_id);
+    assert!(matches!(
+        hub.react(&bob.connection_id, "lobby", &unreacted_id, "🚀", true, None),
+        Err(ChatError::MessageNotFound { .. })
+    ));
+
+    let fresh_id = send(&mut hub, &alice, "fresh");
+    hub.react(&bob.connection_id, "lobby", &fresh_id, "🎉", true, None)
+        .expect("bob reacts");
+    hub.modify_message(
+        &alice.connection_id,
+        "lobby",
+        &fresh_id,
+        &ChatMessageChange::Delete,
+        None,
+        1_700_000_000,
+    )
+    .expect("alice deletes her message");
+    let snapshot = hub
+        .sync_room_state(&bob.connection_id, "lobby")
+        .expect("bob syncs");
+    assert_eq!(snapshot.reactions.len(), 1);
+}
+
+#[test]
+fn reactions_are_short_tokens_with_a_per_message_limit() {
+    assert_eq!(normalized_emoji(" :+1: ").expect("shortcodes are fine"), ":+1:");
+    for emoji in ["", "  ", "thumbs up", &"x".repeat(33)] {
+        assert_eq!(normalized_emoji(emoji), Err(ChatError::InvalidReaction));
+    }
+
+    let (mut hub, alice, _) = lobby();
+    let message_id = send(&mut hub, &alice, "vote");
+    for index in 0..MAX_REACTIONS_PER_MESSAGE {
+        hub.react(&alice.connection_id, "lobby", &message_id, &format!("e{index}"), true, None)
+            .expect("reaction should fit");
+    }
+    let error = hub
+        .react(&alice.connection_id, "lobby", &message_id, "one-more", true, None)
+        .expect_err("reactions past the limit should be rejected");
+    assert_eq!(error.code(), "too_many_reactions");
+}
+
+#[tokio::test]
+async fn threads_load_and_reactions_broadcast_over_commands() {
+    let chat_state = ChatState::default();
+    let (alice, alice_outbound) = chat_state.register_connection("u1", "alice").await;
+    let (bob, bob_outbound) = chat_state.register_connection("u2", "bob").await;
+    for user in [&alice, &bob] {
+        chat_state
+            .process_message(
+                user,
+                ChatCommand::JoinRoom { room_id: "lobby".to_string(), since_version: None },
+            )
+            .await
+            .expect("join should succeed");
+    }
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::SendRoomMessage {
+                room_id: "lobby".to_string(),
+                content: "lunch?".to_string(),
+                reply_to: None,
+            },
+        )
+        .await
+        .expect("root should send");
+    let root_id = std::iter::from_fn(|| bob_outbound.try_pop())
+        .find_map(|event| match event {
+            ChatEvent::RoomMessage(message) => Some(message.message_id),
+            _ => None,
+        })
+        .expect("bob should receive the root");
+    chat_state
+        .process_message(
+            &bob,
+            ChatCommand::SendRoomMessage {
+                room_id: "lobby".to_string(),
+                content: "yes".to_string(),
+                reply_to: Some(root_id.clone()),
+            },
+        )
+        .await
+        .expect("reply should send");
+    chat_state
+        .process_message(
+            &bob,
+            ChatCommand::React {
+                room_id: "lobby".to_string(),
+                message_id: root_id.clone(),
+                emoji: "👍".to_string(),
+            },
+        )
+        .await
+        .expect("reaction should apply");
+    assert!(std::iter::from_fn(|| alice_outbound.try_pop()).any(|event| matches!(
+        event,
+        ChatEvent::ReactionChanged(changed) if changed.user_id == "u2" && changed.added
+    )));
+
+    chat_state
+        .process_message(
+            &alice,
+            ChatCommand::LoadThread { room_id: "lobby".to_string(), message_id: root_id.clone() },
+        )
+        .await
+        .expect("thread should load");
+    let Some(ChatEvent::Thread(thread)) = alice_outbound.try_pop() else {
+        panic!("alice should receive the thread");
+    };
+    assert_eq!(thread.root_message_id, root_id);
+    assert_eq!(thread.root.map(|root| root.content).as_deref(), Some("lunch?"));
+    assert_eq!(thread.replies.len(), 1);
+    assert_eq!(thread.replies[0].content, "yes");
+    assert_eq!(thread.reply_count, 1);
+
+    assert!(matches!(
+        chat_state
+            .process_message(
+                &alice,
+                ChatCommand::LoadThread {
+                    room_id: "lobby".to_string(),
+                    message_id: "missing".to_string(),
+                },
+            )
+            .await,
+        Err(ChatError::MessageNotFound { .. })
+    ));
+}
+
+#[test]
+fn stored_reaction_events_decode_without_reactions_left() {
+    // Shaped like the backplane script's log entry after the last reaction was removed.
+    let entry = json!({
+        "version": 9,
+        "reaction": {
+            "room_id": "lobby",
+            "message_id": "m1",
+            "message_version": 4,
+            "emoji": "👍",
+            "user_id": "u2",
+            "added": false,
+            "version": 9
+        }
+    });
+    let stored: StoredRoomEvent =
+        serde_json::from_value(entry).expect("stored reaction should decode");
+    let (version, event) = stored.into_event("lobby");
+    assert_eq!(version, 9);
+    assert!(matches!(
+        event,
+        ChatEvent::ReactionChanged(changed) if !changed.added && changed.reactions.is_empty()
+    ));
+}
@@ -1,4 +1,23 @@
 mod bakery;
 mod chat;
+mod chat_backplane;
+mod chat_codec;
+mod chat_direct;
+mod chat_directory;
+mod chat_edits;
+mod chat_filters;
+mod chat_grpc;
+mod chat_heartbeat;
+mod chat_moderation;
+mod chat_outbound;
+mod chat_presence;
+mod chat_rate_limit;
+mod chat_receipts;
+mod chat_runtime;
+mod chat_shutdown;
+mod chat_support;
+mod chat_sync;
+mod chat_threads;
 mod hot;
+mod order_stats;
 mod orders;
@@ -0,0 +1,46 @@
+use axum::http::StatusCode;
+use chrono::NaiveDate;
+
+use crate::handlers::stat::orders::{resolve_date_range, sanitized_sku_limit};
+
+fn day(value: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").expect("test date should parse")
+}
+
+#[test]
+fn date_range_defaults_to_last_week() {
+    let range = resolve_date_range(None, None, day("2026-03-08")).expect("range should resolve");
+
+    assert_eq!(range, (day("2026-03-02"), day("2026-03-08")));
+}
+
+#[test]
+fn date_range_defaults_from_relative_to_explicit_to() {
+    let range = resolve_date_range(None, Some("2026-01-31"), day("2026-03-08"))
+        .expect("range should resolve");
+
+    assert_eq!(range, (day("2026-01-25"), day("2026-01-31")));
+}
+
+#[test]
+fn date_range_rejects_invalid_input() {
+    let today = day("2026-03-08");
+
+    let malformed = resolve_date_range(Some("03/01/2026"), None, today)
+        .expect_err("malformed date should be rejected");
+    let reversed = resolve_date_range(Some("2026-03-08"), Some("2026-03-01"), today)
+        .expect_err("reversed range should be rejected");
+    let too_large = resolve_date_range(Some("2025-01-01"), Some("2026-03-08"), today)
+        .expect_err("oversized range should be rejected");
+
+    assert_eq!(malformed.status, StatusCode::BAD_REQUEST);
+    assert_eq!(reversed.status, StatusCode::BAD_REQUEST);
+    assert_eq!(too_large.status, StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn sku_limit_is_clamped() {
+    assert_eq!(sanitized_sku_limit(None), 20);
+    assert_eq!(sanitized_sku_limit(Some(0)), 1);
+    assert_eq!(sanitized_sku_limit(Some(1_000)), 100);
+}
@@ -1,11 +1,28 @@
+use std::time::Duration;
+
 use axum::http::StatusCode;
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::orders::{
-    CreateOrderRequest, InventoryProcessingOutcome, InventoryResultEvent, KafkaSettings,
-    OrderCreatedEvent, OrderStatus, PrecheckDecision, RedisPrecheckOutcome, apply_inventory_result,
-    decide_order_creation, determine_inventory_result, redis_stock_key,
+use crate::{
+    config::{KafkaConfig, env_overrides, legacy_kafka_env_source},
+    orders::{
+        CreateOrderRequest, INVENTORY_RESULT_EVENT_TYPE, InventoryProcessingOutcome,
+        InventoryResultEvent, KafkaTopic, ORDER_CREATED_EVENT_TYPE, ORDERS_WORKER_CONSUMER,
+        OrderCreatedEvent, OrderStatus, PrecheckDecision, RedisPrecheckOutcome, SagaStage,
+        admin::{PartitionLag, WorkerMetricsSnapshot, render_metrics},
+        apply_inventory_result, decide_order_creation, determine_inventory_result,
+        projections::{ProjectedEvent, Projection, SkuDailyDelta, confirmation_rate},
+        redis_stock_key,
+        retry::{
+            FailureKind, RedeliveryDecision, RedeliveryTracker, RetryDecision, RetryPolicy,
+            classify_error,
+        },
+        runtime::select_handler,
+        saga_elapsed_seconds,
+        store::{OutboxBacklog, StoreError},
+        worker::{consumer_client_config, producer_client_config},
+    },
 };
 
 #[test]
@@ -120,24 +137,53 @@ fn kafka_events_round_trip_through_json() {
 }
 
 #[test]
-fn kafka_settings_use_expected_defaults() {
-    let settings = KafkaSettings::from_map(&[]);
+fn kafka_producer_config_maps_tuning_and_passthrough_properties() {
+    let mut kafka =
+        KafkaConfig { client_id: Some("axes-orders".to_string()), ..Default::default() };
+    kafka.security.protocol = Some("SASL_SSL".to_string());
+    kafka.security.sasl_mechanism = Some("SCRAM-SHA-512".to_string());
+    kafka.producer.compression_type = Some("zstd".to_string());
+    kafka.producer.linger_ms = Some(10);
+    kafka
+        .producer
+        .properties
+        .insert("acks".to_string(), "1".to_string());
+    kafka
+        .producer
+        .properties
+        .insert("batch.size".to_string(), "65536".to_string());
+
+    let config = producer_client_config(&kafka);
 
-    assert_eq!(settings.brokers, "localhost:9092");
-    assert_eq!(settings.order_created_topic, "orders.created.v1");
-    assert_eq!(settings.inventory_result_topic, "inventory.result.v1");
+    assert_eq!(config.get("bootstrap.servers"), Some("localhost:9092"));
+    assert_eq!(config.get("client.id"), Some("axes-orders"));
+    assert_eq!(config.get("security.protocol"), Some("SASL_SSL"));
+    assert_eq!(config.get("sasl.mechanism"), Some("SCRAM-SHA-512"));
+    assert_eq!(config.get("sasl.password"), None);
+    assert_eq!(config.get("enable.idempotence"), Some("true"));
+    assert_eq!(config.get("compression.type"), Some("zstd"));
+    assert_eq!(config.get("linger.ms"), Some("10"));
+    assert_eq!(config.get("message.timeout.ms"), Some("5000"));
+    assert_eq!(config.get("acks"), Some("1"));
+    assert_eq!(config.get("batch.size"), Some("65536"));
 }
 
 #[test]
-fn kafka_settings_allow_overrides() {
-    let settings = KafkaSettings::from_map(&[
-        ("AXES_KAFKA_BROKERS", "kafka-1:9092"),
-        ("AXES_KAFKA_ORDER_CREATED_TOPIC", "orders.created.custom"),
-    ]);
+fn kafka_consumer_config_disables_auto_commit() {
+    let mut kafka = KafkaConfig::default();
+    kafka.consumer.auto_offset_reset = "latest".to_string();
+    kafka
+        .consumer
+        .properties
+        .insert("fetch.min.bytes".to_string(), "1024".to_string());
 
-    assert_eq!(settings.brokers, "kafka-1:9092");
-    assert_eq!(settings.order_created_topic, "orders.created.custom");
-    assert_eq!(settings.inventory_result_topic, "inventory.result.v1");
+    let config = consumer_client_config(&kafka, ORDERS_WORKER_CONSUMER);
+
+    assert_eq!(config.get("group.id"), Some(ORDERS_WORKER_CONSUMER));
+    assert_eq!(config.get("enable.auto.commit"), Some("false"));
+    assert_eq!(config.get("auto.offset.reset"), Some("latest"));
+    assert_eq!(config.get("fetch.min.bytes"), Some("1024"));
+    assert_eq!(config.get("acks"), None);
 }
 
 #[test]
@@ -161,3 +207,330 @@ fn inventory_processing_outcome_matches_failure_and_stock_cases() {
         }
     );
 }
+
+#[test]
+fn worker_metrics_render_prometheus_text() {
+    let rendered = render_metrics(&WorkerMetricsSnapshot {
+        worker: "orders-worker".to_string(),
+        outbox_table: "order_outbox_messages".to_string(),
+        outbox: Some(OutboxBacklog {
+            unpublished_count: 3,
+            oldest_unpublished_age_seconds: Some(12.5),
+        }),
+        consumer_lag: vec![PartitionLag {
+            topic: "inventory.result.v1".to_string(),
+            partition: 1,
+            lag: 7,
+        }],
+        messages_processed: 42,
+        messages_failed: 2,
+        messages_retried: 5,
+        messages_redelivered: 1,
+        messages_parked: 1,
+    });
+
+    assert!(rendered.contains(
+        "axes_outbox_unpublished_messages{worker=\"orders-worker\",table=\"order_outbox_messages\"} 3"
+    ));
+    assert!(rendered.contains(
+        "axes_outbox_oldest_unpublished_age_seconds{worker=\"orders-worker\",table=\"order_outbox_messages\"} 12.5"
+    ));
+    assert!(rendered.contains(
+        "axes_kafka_consumer_lag{worker=\"orders-worker\",topic=\"inventory.result.v1\",partition=\"1\"} 7"
+    ));
+    assert!(rendered.contains("axes_worker_messages_processed_total{worker=\"orders-worker\"} 42"));
+    assert!(rendered.contains("axes_worker_messages_failed_total{worker=\"orders-worker\"} 2"));
+    assert!(rendered.contains("axes_worker_messages_retried_total{worker=\"orders-worker\"} 5"));
+    assert!(
+        rendered.contains("axes_worker_messages_redelivered_total{worker=\"orders-worker\"} 1")
+    );
+    assert!(rendered.contains("axes_worker_messages_parked_total{worker=\"orders-worker\"} 1"));
+}
+
+#[test]
+fn worker_metrics_skip_outbox_gauges_when_backlog_is_unknown() {
+    let rendered = render_metrics(&WorkerMetricsSnapshot {
+        worker: "inventory-worker".to_string(),
+        outbox_table: "inventory_outbox_messages".to_string(),
+        outbox: None,
+        consumer_lag: Vec::new(),
+        messages_processed: 0,
+        messages_failed: 0,
+        messages_retried: 0,
+        messages_redelivered: 0,
+        messages_parked: 0,
+    });
+
+    assert!(!rendered.contains("axes_outbox_unpublished_messages{"));
+    assert!(
+        rendered.contains("axes_worker_messages_processed_total{worker=\"inventory-worker\"} 0")
+    );
+}
+
+#[test]
+fn kafka_topics_resolve_against_config() {
+    let kafka =
+        KafkaConfig { order_created_topic: "orders.custom".to_string(), ..Default::default() };
+
+    assert_eq!(KafkaTopic::OrderCreated.resolve(&kafka), "orders.custom");
+    assert_eq!(KafkaTopic::InventoryResult.resolve(&kafka), "inventory.result.v1");
+}
+
+#[test]
+fn new_kafka_env_variables_win_over_legacy_ones() {
+    let legacy = |key: &str| match key {
+        "AXES_KAFKA_BROKERS" => Some("legacy:9092".to_string()),
+        "AXES_KAFKA_ORDER_CREATED_TOPIC" => Some("orders.legacy".to_string()),
+        _ => None,
+    };
+    let env = [("AXES__KAFKA__BROKERS".to_string(), "kafka:9092".to_string())];
+
+    let config = config::Config::builder()
+        .add_source(legacy_kafka_env_source(legacy).expect("legacy variables should map"))
+        .add_source(env_overrides().source(Some(env.into_iter().collect())))
+        .build()
+        .expect("config should build");
+
+    assert_eq!(
+        config
+            .get_string("kafka.brokers")
+            .expect("brokers should be set"),
+        "kafka:9092"
+    );
+    assert_eq!(
+        config
+            .get_string("kafka.order_created_topic")
+            .expect("topic should be set"),
+        "orders.legacy"
+    );
+}
+
+#[test]
+fn worker_routes_messages_by_topic_and_event_type() {
+    let routes = vec![
+        ("orders.created.v1".to_string(), ORDER_CREATED_EVENT_TYPE),
+        ("inventory.result.v1".to_string(), INVENTORY_RESULT_EVENT_TYPE),
+        ("inventory.result.v1".to_string(), "InventoryAdjusted"),
+    ];
+
+    assert_eq!(
+        select_handler(&routes, "inventory.result.v1", Some(INVENTORY_RESULT_EVENT_TYPE)),
+        Some(1)
+    );
+    assert_eq!(select_handler(&routes, "inventory.result.v1", Some("InventoryAdjusted")), Some(2));
+    assert_eq!(select_handler(&routes, "inventory.result.v1", Some("Unknown")), None);
+    assert_eq!(select_handler(&routes, "payments.v1", Some(ORDER_CREATED_EVENT_TYPE)), None);
+}
+
+#[test]
+fn worker_routes_headerless_messages_to_first_topic_handler() {
+    let routes = vec![
+        ("inventory.result.v1".to_string(), INVENTORY_RESULT_EVENT_TYPE),
+        ("inventory.result.v1".to_string(), "InventoryAdjusted"),
+    ];
+
+    assert_eq!(select_handler(&routes, "inventory.result.v1", None), Some(0));
+}
+
+fn retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(250),
+        max_elapsed: Duration::from_secs(1),
+        max_redeliveries: 2,
+    }
+}
+
+#[test]
+fn retry_policy_backs_off_exponentially_up_to_the_cap() {
+    let policy = retry_policy();
+
+    assert_eq!(policy.backoff(1), Duration::from_millis(100));
+    assert_eq!(policy.backoff(2), Duration::from_millis(200));
+    assert_eq!(policy.backoff(3), Duration::from_millis(250));
+    assert_eq!(policy.backoff(64), Duration::from_millis(250));
+}
+
+#[test]
+fn retry_policy_stops_transient_retries_at_max_attempts() {
+    let policy = retry_policy();
+
+    assert_eq!(
+        policy.decide(1, FailureKind::Transient, Duration::ZERO),
+        RetryDecision::Retry { backoff: Duration::from_millis(100) }
+    );
+    assert_eq!(
+        policy.decide(2, FailureKind::Transient, Duration::ZERO),
+        RetryDecision::Retry { backoff: Duration::from_millis(200) }
+    );
+    assert_eq!(policy.decide(3, FailureKind::Transient, Duration::ZERO), RetryDecision::GiveUp);
+    assert_eq!(policy.decide(1, FailureKind::Permanent, Duration::ZERO), RetryDecision::GiveUp);
+}
+
+#[test]
+fn retry_policy_retries_pool_timeouts_until_the_deadline() {
+    let policy = retry_policy();
+
+    assert_eq!(
+        policy.decide(10, FailureKind::PoolTimeout, Duration::from_millis(500)),
+        RetryDecision::Retry { backoff: Duration::from_millis(250) }
+    );
+    assert_eq!(
+        policy.decide(11, FailureKind::PoolTimeout, Duration::from_millis(800)),
+        RetryDecision::GiveUp
+    );
+    assert_eq!(
+        policy.decide(2, FailureKind::Transient, Duration::from_millis(900)),
+        RetryDecision::GiveUp
+    );
+}
+
+#[test]
+fn redeliveries_pause_with_growing_backoff_then_park() {
+    let policy = retry_policy();
+    let mut redeliveries = RedeliveryTracker::default();
+
+    assert_eq!(
+        redeliveries.record(&policy, "inventory.result.v1", 0, 7),
+        RedeliveryDecision::Pause { backoff: Duration::from_millis(250) }
+    );
+    assert_eq!(
+        redeliveries.record(&policy, "inventory.result.v1", 1, 3),
+        RedeliveryDecision::Pause { backoff: Duration::from_millis(250) }
+    );
+    assert_eq!(
+        redeliveries.record(&policy, "inventory.result.v1", 0, 7),
+        RedeliveryDecision::Pause { backoff: Duration::from_millis(500) }
+    );
+    assert_eq!(redeliveries.record(&policy, "inventory.result.v1", 0, 7), RedeliveryDecision::Park);
+
+    redeliveries.clear("inventory.result.v1", 1);
+    assert_eq!(
+        redeliveries.record(&policy, "inventory.result.v1", 1, 4),
+        RedeliveryDecision::Pause { backoff: Duration::from_millis(250) }
+    );
+}
+
+#[test]
+fn handler_errors_are_classified_by_cause() {
+    let pool_timeout = anyhow::Error::from(sqlx::Error::PoolTimedOut).context("apply failed");
+    let connection_lost = anyhow::Error::from(sqlx::Error::Io(std::io::Error::other("reset")));
+    let missing_order = anyhow::Error::from(StoreError::OrderNotFound { order_id: Uuid::new_v4() });
+    let decode_failure = anyhow::Error::from(sqlx::Error::ColumnNotFound("status".to_string()));
+    let logic_error = anyhow::anyhow!("unexpected event payload");
+
+    assert_eq!(classify_error(&pool_timeout), FailureKind::PoolTimeout);
+    assert_eq!(classify_error(&connection_lost), FailureKind::Transient);
+    assert_eq!(classify_error(&missing_order), FailureKind::Transient);
+    assert_eq!(classify_error(&decode_failure), FailureKind::Permanent);
+    assert_eq!(classify_error(&logic_error), FailureKind::Permanent);
+}
+
+#[test]
+fn precheck_outcomes_and_decisions_have_metric_labels() {
+    assert_eq!(RedisPrecheckOutcome::Known { available: 3 }.as_str(), "known");
+    assert_eq!(RedisPrecheckOutcome::Missing.as_str(), "missing");
+    assert_eq!(RedisPrecheckOutcome::Unavailable.as_str(), "unavailable");
+    assert_eq!(PrecheckDecision::Allow.as_str(), "allow");
+    assert_eq!(
+        decide_order_creation(RedisPrecheckOutcome::Known { available: 0 }, 1).as_str(),
+        "reject"
+    );
+    assert_eq!(SagaStage::Completed.as_str(), "completed");
+}
+
+#[test]
+fn saga_elapsed_seconds_is_never_negative() {
+    let created_at = chrono::DateTime::parse_from_rfc3339("2026-03-08T00:00:00Z")
+        .expect("timestamp should parse")
+        .with_timezone(&chrono::Utc);
+    let later = created_at + chrono::Duration::milliseconds(1500);
+
+    assert_eq!(saga_elapsed_seconds(created_at, later), 1.5);
+    assert_eq!(saga_elapsed_seconds(later, created_at), 0.0);
+}
+
+#[test]
+fn projections_parse_by_name() {
+    assert_eq!(Projection::parse("order_sku_daily_stats"), Some(Projection::OrderSkuDailyStats));
+    assert_eq!(Projection::parse(" order_sku_daily_stats "), Some(Projection::OrderSkuDailyStats));
+    assert_eq!(Projection::parse("orders"), None);
+}
+
+#[test]
+fn order_created_event_counts_toward_its_own_day() {
+    let event = ProjectedEvent::OrderCreated(OrderCreatedEvent {
+        message_id: Uuid::new_v4(),
+        correlation_id: Uuid::new_v4(),
+        order_id: Uuid::new_v4(),
+        sku: "SKU-001".to_string(),
+        quantity: 3,
+        occurred_on_utc: "2026-03-08T23:59:59+00:00".to_string(),
+    });
+
+    let delta = event
+        .sku_daily_delta()
+        .expect("order created delta should build");
+
+    assert_eq!(
+        delta,
+        SkuDailyDelta {
+            sku: "SKU-001".to_string(),
+            day: chrono::NaiveDate::from_ymd_opt(2026, 3, 8).expect("date should be valid"),
+            orders_created: 1,
+            quantity_ordered: 3,
+            orders_confirmed: 0,
+            orders_rejected: 0,
+        }
+    );
+}
+
+#[test]
+fn inventory_result_event_counts_confirmation_or_rejection_in_utc() {
+    let result = |success| {
+        ProjectedEvent::InventoryResult(InventoryResultEvent {
+            message_id: Uuid::new_v4(),
+            correlation_id: Uuid::new_v4(),
+            order_id: Uuid::new_v4(),
+            sku: "SKU-001".to_string(),
+            quantity: 3,
+            success,
+            reason: None,
+            occurred_on_utc: "2026-03-09T01:30:00+08:00".to_string(),
+        })
+    };
+
+    let confirmed = result(true)
+        .sku_daily_delta()
+        .expect("confirmed delta should build");
+    let rejected = result(false)
+        .sku_daily_delta()
+        .expect("rejected delta should build");
+
+    let day = chrono::NaiveDate::from_ymd_opt(2026, 3, 8).expect("date should be valid");
+    assert_eq!((confirmed.day, confirmed.orders_confirmed, confirmed.orders_rejected), (day, 1, 0));
+    assert_eq!((rejected.day, rejected.orders_confirmed, rejected.orders_rejected), (day, 0, 1));
+    assert_eq!(confirmed.orders_created, 0);
+}
+
+#[test]
+fn projected_event_rejects_invalid_timestamps() {
+    let event = ProjectedEvent::OrderCreated(OrderCreatedEvent {
+        message_id: Uuid::new_v4(),
+        correlation_id: Uuid::new_v4(),
+        order_id: Uuid::new_v4(),
+        sku: "SKU-001".to_string(),
+        quantity: 1,
+        occurred_on_utc: "yesterday".to_string(),
+    });
+
+    assert!(event.sku_daily_delta().is_err());
+}
+
+#[test]
+fn confirmation_rate_needs_decided_orders() {
+    assert_eq!(confirmation_rate(0, 0), None);
+    assert_eq!(confirmation_rate(3, 1), Some(0.75));
+    assert_eq!(confirmation_rate(0, 2), Some(0.0));
+}
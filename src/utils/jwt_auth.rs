@@ -66,13 +66,17 @@ where
                 .await
                 .map_err(|_| AuthError::InvalidToken)?;
         // Decode the user data
-        let token_data = decode::<Claims>(bearer.token(), &keys().decoding, &Validation::default())
-            .map_err(|_| AuthError::InvalidToken)?;
-
-        Ok(token_data.claims)
+        Ok(decode_claims(bearer.token())?)
     }
 }
 
+/// Validates a token's signature and expiry with `keys()` and returns its claims.
+pub fn decode_claims(token: &str) -> Result<Claims, AuthError> {
+    decode::<Claims>(token, &keys().decoding, &Validation::default())
+        .map(|token_data| token_data.claims)
+        .map_err(|_| AuthError::InvalidToken)
+}
+
 #[derive(Debug, Serialize)]
 pub struct AuthBody {
     pub access_token: String,
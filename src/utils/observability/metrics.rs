@@ -13,6 +13,15 @@ struct MetricsInstruments {
     http_request_duration_seconds: opentelemetry::metrics::Histogram<f64>,
     grpc_requests_total: opentelemetry::metrics::Counter<u64>,
     grpc_request_duration_seconds: opentelemetry::metrics::Histogram<f64>,
+    orders_created_total: opentelemetry::metrics::Counter<u64>,
+    orders_confirmed_total: opentelemetry::metrics::Counter<u64>,
+    orders_rejected_total: opentelemetry::metrics::Counter<u64>,
+    redis_precheck_total: opentelemetry::metrics::Counter<u64>,
+    inventory_decrement_total: opentelemetry::metrics::Counter<u64>,
+    order_saga_duration_seconds: opentelemetry::metrics::Histogram<f64>,
+    chat_queue_depth: opentelemetry::metrics::Histogram<u64>,
+    chat_events_dropped_total: opentelemetry::metrics::Counter<u64>,
+    chat_slow_consumer_disconnects_total: opentelemetry::metrics::Counter<u64>,
 }
 
 impl MetricsInstruments {
@@ -38,6 +47,44 @@ impl MetricsInstruments {
                 .with_description("gRPC request latency in seconds.")
                 .with_unit("s")
                 .build(),
+            orders_created_total: meter
+                .u64_counter("orders.created.count")
+                .with_description("Orders accepted and written as Pending.")
+                .build(),
+            orders_confirmed_total: meter
+                .u64_counter("orders.confirmed.count")
+                .with_description("Orders confirmed after inventory was reserved.")
+                .build(),
+            orders_rejected_total: meter
+                .u64_counter("orders.rejected.count")
+                .with_description("Orders rejected, by saga stage and reason.")
+                .build(),
+            redis_precheck_total: meter
+                .u64_counter("orders.redis_precheck.count")
+                .with_description("Redis stock prechecks by outcome and decision.")
+                .build(),
+            inventory_decrement_total: meter
+                .u64_counter("inventory.decrement.count")
+                .with_description("Inventory decrement attempts by outcome.")
+                .build(),
+            order_saga_duration_seconds: meter
+                .f64_histogram("orders.saga.duration")
+                .with_description("Time from order CreatedAtUtc to each saga step, in seconds.")
+                .with_unit("s")
+                .build(),
+            chat_queue_depth: meter
+                .u64_histogram("chat.connection.queue.depth")
+                .with_description("Per-connection outbound chat queue depth, sampled on enqueue.")
+                .with_boundaries(vec![0.0, 1.0, 4.0, 16.0, 64.0, 128.0, 256.0, 512.0, 1024.0])
+                .build(),
+            chat_events_dropped_total: meter
+                .u64_counter("chat.connection.events.dropped")
+                .with_description("Chat events dropped or merged because a connection fell behind.")
+                .build(),
+            chat_slow_consumer_disconnects_total: meter
+                .u64_counter("chat.connection.slow_consumer.disconnects")
+                .with_description("Chat connections closed for not keeping up with their queue.")
+                .build(),
         }
     }
 }
@@ -105,3 +152,104 @@ pub(crate) fn record_grpc_metrics(service: &str, method: &str, code: Code, elaps
         .grpc_request_duration_seconds
         .record(elapsed_seconds, &attributes);
 }
+
+pub(crate) fn record_order_created() {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    metrics.orders_created_total.add(1, &[]);
+}
+
+pub(crate) fn record_order_confirmed() {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    metrics.orders_confirmed_total.add(1, &[]);
+}
+
+pub(crate) fn record_order_rejected(stage: &str, reason: &str) {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    let attributes = [
+        KeyValue::new("order.saga.stage", stage.to_string()),
+        KeyValue::new("order.rejection.reason", reason.to_string()),
+    ];
+
+    metrics.orders_rejected_total.add(1, &attributes);
+}
+
+pub(crate) fn record_redis_precheck(outcome: &str, decision: &str) {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    let attributes = [
+        KeyValue::new("precheck.outcome", outcome.to_string()),
+        KeyValue::new("precheck.decision", decision.to_string()),
+    ];
+
+    metrics.redis_precheck_total.add(1, &attributes);
+}
+
+pub(crate) fn record_inventory_decrement(success: bool, reason: Option<&str>) {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    let attributes = [
+        KeyValue::new("inventory.outcome", if success { "success" } else { "failure" }),
+        KeyValue::new("inventory.failure.reason", reason.unwrap_or("none").to_string()),
+    ];
+
+    metrics.inventory_decrement_total.add(1, &attributes);
+}
+
+pub(crate) fn record_order_saga_duration(stage: &str, outcome: &str, elapsed_seconds: f64) {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    let attributes = [
+        KeyValue::new("order.saga.stage", stage.to_string()),
+        KeyValue::new("order.saga.outcome", outcome.to_string()),
+    ];
+
+    metrics
+        .order_saga_duration_seconds
+        .record(elapsed_seconds.max(0.0), &attributes);
+}
+
+pub(crate) fn record_chat_queue_depth(policy: &str, depth: usize) {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    metrics
+        .chat_queue_depth
+        .record(depth as u64, &[KeyValue::new("chat.slow_consumer.policy", policy.to_string())]);
+}
+
+pub(crate) fn record_chat_event_dropped(policy: &str, reason: &str) {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    let attributes = [
+        KeyValue::new("chat.slow_consumer.policy", policy.to_string()),
+        KeyValue::new("chat.drop.reason", reason.to_string()),
+    ];
+
+    metrics.chat_events_dropped_total.add(1, &attributes);
+}
+
+pub(crate) fn record_chat_slow_consumer_disconnect() {
+    let Some(metrics) = METRICS.get() else {
+        return;
+    };
+
+    metrics.chat_slow_consumer_disconnects_total.add(1, &[]);
+}
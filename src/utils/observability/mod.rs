@@ -8,6 +8,11 @@ mod trace;
 
 pub use grpc::grpc_observability_layer;
 pub use http::http_observability;
+pub(crate) use metrics::{
+    record_chat_event_dropped, record_chat_queue_depth, record_chat_slow_consumer_disconnect,
+    record_inventory_decrement, record_order_confirmed, record_order_created,
+    record_order_rejected, record_order_saga_duration, record_redis_precheck,
+};
 use opentelemetry::{global, trace::TracerProvider as _};
 use opentelemetry_sdk::{
     metrics::SdkMeterProvider, propagation::TraceContextPropagator, trace::SdkTracerProvider,